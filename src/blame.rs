@@ -0,0 +1,202 @@
+use std::path::Path;
+
+use crate::diff::{diff, lines, Edit};
+use crate::repository::path_bytes;
+use crate::{GitSyncError, Object, ObjectType, Repository, Result, Signature};
+
+/// a line of a file and the commit that last changed it, created by
+/// [Repository::blame]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameLine {
+    pub commit: String,
+    /// the author of the commit
+    pub author: Signature,
+    /// the content of the line, including its `\n`
+    pub line: Vec<u8>,
+}
+
+/// attributes every line of the file `path` in the commit `start` to the
+/// commit that last changed it, see [Repository::blame]
+pub(crate) fn blame(repo: &Repository, start: &str, path: &Path) -> Result<Vec<BlameLine>> {
+    let name = path_bytes(path);
+    let Some(mut blob) = blob_at(repo, start, &name)? else {
+        return Err(GitSyncError::PathspecNotFound {
+            path: path.to_path_buf(),
+        });
+    };
+    let data = repo.read_blob(&blob)?;
+    let final_lines = lines(&data);
+
+    let mut blamed: Vec<Option<String>> = vec![None; final_lines.len()];
+    // the lines that are not blamed yet, as (line in the final file, line in
+    // the version of `commit`)
+    let mut pending: Vec<(usize, usize)> = (0..final_lines.len()).map(|i| (i, i)).collect();
+    let mut commit = start.to_owned();
+    while !pending.is_empty() {
+        let Object::Commit { parents, .. } = repo.load_object(&commit)? else {
+            return Err(GitSyncError::UnexpectedObjectType {
+                sha1: commit,
+                expected: ObjectType::Commit,
+            });
+        };
+
+        let mut parent_blobs = Vec::new();
+        for parent in parents {
+            let parent_blob = blob_at(repo, &parent, &name)?;
+            parent_blobs.push((parent, parent_blob));
+        }
+        // a parent with the same file passes all lines on unchanged
+        if let Some((parent, _)) = parent_blobs
+            .iter()
+            .find(|(_, parent_blob)| parent_blob.as_ref() == Some(&blob))
+        {
+            commit = parent.clone();
+            continue;
+        }
+
+        // otherwise lines that are not in the first parent were changed by
+        // this commit
+        let first = parent_blobs
+            .into_iter()
+            .next()
+            .and_then(|(parent, parent_blob)| Some((parent, parent_blob?)));
+        let Some((parent, parent_blob)) = first else {
+            for (line, _) in pending.drain(..) {
+                blamed[line] = Some(commit.clone());
+            }
+            break;
+        };
+
+        let current = repo.read_blob(&blob)?;
+        let previous = repo.read_blob(&parent_blob)?;
+        let mut in_parent = vec![None; lines(&current).len()];
+        for edit in diff(&lines(&previous), &lines(&current)) {
+            if let Edit::Equal { old, new } = edit {
+                in_parent[new] = Some(old);
+            }
+        }
+        pending.retain_mut(|(line, current_line)| match in_parent[*current_line] {
+            Some(parent_line) => {
+                *current_line = parent_line;
+                true
+            }
+            None => {
+                blamed[*line] = Some(commit.clone());
+                false
+            }
+        });
+        commit = parent;
+        blob = parent_blob;
+    }
+
+    let mut authors: Vec<(String, Signature)> = Vec::new();
+    let mut result = Vec::with_capacity(final_lines.len());
+    for (line, commit) in final_lines.into_iter().zip(blamed) {
+        let commit = commit.expect("all lines are blamed");
+        let author = match authors.iter().find(|(sha1, _)| *sha1 == commit) {
+            Some((_, author)) => author.clone(),
+            None => {
+                let Object::Commit { author, .. } = repo.load_object(&commit)? else {
+                    unreachable!("blame only follows commits");
+                };
+                authors.push((commit.clone(), author.clone()));
+                author
+            }
+        };
+        result.push(BlameLine {
+            commit,
+            author,
+            line: line.to_vec(),
+        });
+    }
+    Ok(result)
+}
+
+/// the sha1 of the blob at the `/` separated `path` in the tree of `commit`,
+/// if there is one
+fn blob_at(repo: &Repository, commit: &str, path: &[u8]) -> Result<Option<String>> {
+    let mut tree = commit.to_owned();
+    let mut components = path.split(|&b| b == b'/').peekable();
+    while let Some(name) = components.next() {
+        let Some(entry) = repo
+            .read_tree(&tree)?
+            .into_iter()
+            .find(|entry| entry.name == name)
+        else {
+            return Ok(None);
+        };
+        match (components.peek(), entry.is_tree()) {
+            (None, false) if entry.mode != "160000" => return Ok(Some(entry.sha1())),
+            (Some(_), true) => tree = entry.sha1(),
+            _ => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use test_dir::DirBuilder;
+
+    use crate::test_utils::test_dir;
+    use crate::{GitSyncError, Repository, Signature};
+
+    #[test]
+    fn blame_changed_lines() {
+        let repo_path = test_dir("blame_changed_lines");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+        let first_author = Signature::parse("First <first@example.com> 1700000000 +0100").unwrap();
+        let second_author =
+            Signature::parse("Second <second@example.com> 1700000100 +0100").unwrap();
+
+        fs::create_dir(root.join("dir")).unwrap();
+        fs::write(root.join("dir/file.txt"), "1\n2\n3\n4\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let first = repo
+            .commit("first\n", &first_author, &first_author)
+            .unwrap();
+
+        fs::write(root.join("dir/file.txt"), "one\n2\n3\n4\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let second = repo
+            .commit("second\n", &second_author, &second_author)
+            .unwrap();
+
+        // commits that don't change the file are skipped
+        fs::write(root.join("other.txt"), "other\n").unwrap();
+        repo.add(&["."]).unwrap();
+        repo.commit("other\n", &first_author, &first_author)
+            .unwrap();
+
+        fs::write(root.join("dir/file.txt"), "one\n2\nthree\n4\n5\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let fourth = repo
+            .commit("fourth\n", &first_author, &first_author)
+            .unwrap();
+
+        let head = repo.resolve_ref("HEAD").unwrap();
+        let blame = repo.blame(&head, "dir/file.txt").unwrap();
+        let commits: Vec<&str> = blame.iter().map(|line| line.commit.as_str()).collect();
+        assert_eq!(
+            commits,
+            [
+                second.as_str(),
+                first.as_str(),
+                fourth.as_str(),
+                first.as_str(),
+                fourth.as_str()
+            ]
+        );
+        assert_eq!(blame[0].author, second_author);
+        assert_eq!(blame[0].line, b"one\n");
+        assert_eq!(blame[1].author, first_author);
+
+        assert!(matches!(
+            repo.blame(&head, "missing.txt"),
+            Err(GitSyncError::PathspecNotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::Object;
+
+/// a least recently used cache of decoded objects, see
+/// [Repository::with_cache](crate::Repository::with_cache)
+#[derive(Debug)]
+pub(crate) struct ObjectCache {
+    /// the maximum total size of the cached objects
+    capacity: usize,
+    size: usize,
+    /// incremented on every access, so that older entries have smaller ticks
+    tick: u64,
+    objects: HashMap<String, CachedObject>,
+    /// the sha1 of every cached object by the tick of its last access
+    by_tick: BTreeMap<u64, String>,
+}
+
+#[derive(Debug)]
+struct CachedObject {
+    object: Object,
+    /// size of the object's content
+    size: usize,
+    tick: u64,
+}
+
+impl ObjectCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            size: 0,
+            tick: 0,
+            objects: HashMap::new(),
+            by_tick: BTreeMap::new(),
+        }
+    }
+
+    /// the cached object `sha1`, which becomes the most recently used one
+    pub(crate) fn get(&mut self, sha1: &str) -> Option<&Object> {
+        self.tick += 1;
+        let cached = self.objects.get_mut(sha1)?;
+        let sha1 = self.by_tick.remove(&cached.tick).unwrap();
+        cached.tick = self.tick;
+        self.by_tick.insert(self.tick, sha1);
+        Some(&cached.object)
+    }
+
+    /// caches `object`, whose content is `size` bytes, evicting the least
+    /// recently used objects until it fits. Objects larger than the capacity
+    /// are not cached.
+    pub(crate) fn insert(&mut self, sha1: &str, object: Object, size: usize) {
+        if size > self.capacity {
+            return;
+        }
+        self.remove(sha1);
+        while self.size + size > self.capacity {
+            let (_, oldest) = self.by_tick.pop_first().unwrap();
+            self.size -= self.objects.remove(&oldest).unwrap().size;
+        }
+
+        self.tick += 1;
+        self.size += size;
+        self.by_tick.insert(self.tick, sha1.to_owned());
+        self.objects.insert(
+            sha1.to_owned(),
+            CachedObject {
+                object,
+                size,
+                tick: self.tick,
+            },
+        );
+    }
+
+    fn remove(&mut self, sha1: &str) {
+        if let Some(cached) = self.objects.remove(sha1) {
+            self.by_tick.remove(&cached.tick);
+            self.size -= cached.size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ObjectCache;
+    use crate::Object;
+
+    fn blob(content: &str) -> Object {
+        Object::Blob {
+            data: content.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let mut cache = ObjectCache::new(10);
+        cache.insert("a", blob("aaaa"), 4);
+        cache.insert("b", blob("bbbb"), 4);
+        assert!(cache.get("a").is_some());
+
+        // "b" is the least recently used object
+        cache.insert("c", blob("cccc"), 4);
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+
+        cache.insert("d", blob("too large to cache"), 18);
+        assert!(cache.get("d").is_none());
+        assert!(cache.get("a").is_some());
+    }
+}
@@ -0,0 +1,631 @@
+use std::{fmt::Display, fs, iter::Peekable, path::Path, str::Chars, str::FromStr};
+
+use crate::{GitSyncError, Result};
+
+/// a single `key = value` entry of a [Config]
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    /// lowercase section name
+    section: String,
+    /// case sensitive subsection name
+    subsection: Option<String>,
+    /// lowercase key name
+    key: String,
+    value: String,
+}
+
+impl Entry {
+    fn matches(&self, section: &str, subsection: Option<&str>, key: &str) -> bool {
+        self.section.eq_ignore_ascii_case(section)
+            && self.subsection.as_deref() == subsection
+            && self.key.eq_ignore_ascii_case(key)
+    }
+}
+
+/// git config file, e.g. `.git/config`.
+///
+/// Values are addressed by `(section, subsection, key)`. Section and key
+/// names are case insensitive, subsection names are not. A key can have
+/// multiple values, in which case the last one wins for single value lookups.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// all entries in file order
+    entries: Vec<Entry>,
+}
+
+impl Config {
+    /// reads and parses the config file at `path`
+    pub fn parse(path: impl AsRef<Path>) -> Result<Self> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// writes the config to the file at `path`, replacing its content
+    pub fn write_to_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// the last value of `key`
+    pub fn get_str(&self, section: &str, subsection: Option<&str>, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|entry| entry.matches(section, subsection, key))
+            .map(|entry| entry.value.as_str())
+    }
+
+    /// all values of the multi-valued `key`, in file order
+    pub fn get_all<'a>(
+        &'a self,
+        section: &'a str,
+        subsection: Option<&'a str>,
+        key: &'a str,
+    ) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.matches(section, subsection, key))
+            .map(|entry| entry.value.as_str())
+    }
+
+    /// all `(key, value)` pairs in the given section, in file order
+    pub fn section<'a>(
+        &'a self,
+        section: &'a str,
+        subsection: Option<&'a str>,
+    ) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        self.entries
+            .iter()
+            .filter(move |entry| {
+                entry.section.eq_ignore_ascii_case(section)
+                    && entry.subsection.as_deref() == subsection
+            })
+            .map(|entry| (entry.key.as_str(), entry.value.as_str()))
+    }
+
+    /// the last value of `key` interpreted as a git boolean.
+    ///
+    /// `true`, `yes`, `on` and `1` are true, `false`, `no`, `off`, `0` and the
+    /// empty string are false.
+    pub fn get_bool(
+        &self,
+        section: &str,
+        subsection: Option<&str>,
+        key: &str,
+    ) -> Result<Option<bool>> {
+        let Some(value) = self.get_str(section, subsection, key) else {
+            return Ok(None);
+        };
+        match value.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" => Ok(Some(true)),
+            "false" | "no" | "off" | "0" | "" => Ok(Some(false)),
+            _ => Err(GitSyncError::InvalidConfig(format!(
+                "{value:?} is not a valid boolean for {section}.{key}"
+            ))),
+        }
+    }
+
+    /// the last value of `key` interpreted as an integer.
+    ///
+    /// The suffixes `k`, `m` and `g` scale the value by 1024, 1024^2 and
+    /// 1024^3.
+    pub fn get_int(
+        &self,
+        section: &str,
+        subsection: Option<&str>,
+        key: &str,
+    ) -> Result<Option<i64>> {
+        let Some(value) = self.get_str(section, subsection, key) else {
+            return Ok(None);
+        };
+        let invalid = || {
+            GitSyncError::InvalidConfig(format!(
+                "{value:?} is not a valid integer for {section}.{key}"
+            ))
+        };
+
+        let (number, factor) = match value.as_bytes().last() {
+            Some(b'k' | b'K') => (&value[..value.len() - 1], 1024),
+            Some(b'm' | b'M') => (&value[..value.len() - 1], 1024 * 1024),
+            Some(b'g' | b'G') => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+            _ => (value, 1),
+        };
+        let number: i64 = number.parse().map_err(|_| invalid())?;
+        number.checked_mul(factor).map(Some).ok_or_else(invalid)
+    }
+
+    /// sets `key` to `value`, replacing all existing values.
+    ///
+    /// New keys are added to the end of their section.
+    pub fn set(&mut self, section: &str, subsection: Option<&str>, key: &str, value: &str) {
+        let existing = self
+            .entries
+            .iter()
+            .position(|entry| entry.matches(section, subsection, key));
+        let entry = Entry {
+            section: section.to_ascii_lowercase(),
+            subsection: subsection.map(str::to_owned),
+            key: key.to_ascii_lowercase(),
+            value: value.to_owned(),
+        };
+
+        if let Some(index) = existing {
+            self.unset(section, subsection, key);
+            self.entries.insert(index, entry);
+        } else {
+            self.push(entry);
+        }
+    }
+
+    /// adds another value to the multi-valued `key`
+    pub fn add(&mut self, section: &str, subsection: Option<&str>, key: &str, value: &str) {
+        self.push(Entry {
+            section: section.to_ascii_lowercase(),
+            subsection: subsection.map(str::to_owned),
+            key: key.to_ascii_lowercase(),
+            value: value.to_owned(),
+        });
+    }
+
+    /// removes all values of `key`
+    pub fn unset(&mut self, section: &str, subsection: Option<&str>, key: &str) {
+        self.entries
+            .retain(|entry| !entry.matches(section, subsection, key));
+    }
+
+    /// inserts `entry` after the last entry of its section
+    fn push(&mut self, entry: Entry) {
+        let last_in_section = self.entries.iter().rposition(|other| {
+            other.section == entry.section && other.subsection == entry.subsection
+        });
+        match last_in_section {
+            Some(index) => self.entries.insert(index + 1, entry),
+            None => self.entries.push(entry),
+        }
+    }
+}
+
+impl FromStr for Config {
+    type Err = GitSyncError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Parser {
+            chars: s.chars().peekable(),
+            line: 1,
+        }
+        .parse()
+    }
+}
+
+impl Display for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut current: Option<(&str, Option<&str>)> = None;
+        for entry in &self.entries {
+            let section = (entry.section.as_str(), entry.subsection.as_deref());
+            if current != Some(section) {
+                match section.1 {
+                    Some(subsection) => {
+                        let subsection = subsection.replace('\\', "\\\\").replace('"', "\\\"");
+                        writeln!(f, "[{} \"{subsection}\"]", section.0)?
+                    }
+                    None => writeln!(f, "[{}]", section.0)?,
+                }
+                current = Some(section);
+            }
+            writeln!(f, "\t{} = {}", entry.key, quote_value(&entry.value))?;
+        }
+        Ok(())
+    }
+}
+
+/// escapes `value` and quotes it if it would not survive parsing otherwise
+fn quote_value(value: &str) -> String {
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';']);
+
+    let mut quoted = String::with_capacity(value.len() + 2);
+    if needs_quotes {
+        quoted.push('"');
+    }
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\u{8}' => quoted.push_str("\\b"),
+            c => quoted.push(c),
+        }
+    }
+    if needs_quotes {
+        quoted.push('"');
+    }
+    quoted
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn parse(mut self) -> Result<Config> {
+        let mut config = Config::default();
+        let mut section: Option<(String, Option<String>)> = None;
+
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                None => break,
+                Some('\n') => {
+                    self.next();
+                }
+                Some('#' | ';') => self.skip_line(),
+                Some('[') => {
+                    self.next();
+                    section = Some(self.parse_section_header()?);
+                }
+                Some(c) if c.is_ascii_alphabetic() => {
+                    let Some((section, subsection)) = &section else {
+                        return Err(self.error("key outside of a section"));
+                    };
+                    let key = self.parse_key();
+                    let value = self.parse_value()?;
+                    config.entries.push(Entry {
+                        section: section.clone(),
+                        subsection: subsection.clone(),
+                        key,
+                        value,
+                    });
+                }
+                Some(c) => {
+                    let c = *c;
+                    return Err(self.error(&format!("unexpected character {c:?}")));
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    fn error(&self, reason: &str) -> GitSyncError {
+        GitSyncError::InvalidConfig(format!("line {}: {reason}", self.line))
+    }
+
+    /// skips whitespace, but not newlines
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .next_if(|c| c.is_whitespace() && *c != '\n')
+            .is_some()
+        {}
+    }
+
+    /// skips until the end of the line, including the newline
+    fn skip_line(&mut self) {
+        while let Some(c) = self.next() {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    /// parses `section]`, `section "subsection"]` or the deprecated
+    /// `section.subsection]`
+    fn parse_section_header(&mut self) -> Result<(String, Option<String>)> {
+        let mut name = String::new();
+        while let Some(c) = self
+            .chars
+            .next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.'))
+        {
+            name.push(c.to_ascii_lowercase());
+        }
+        if name.is_empty() {
+            return Err(self.error("empty section name"));
+        }
+
+        let subsection = if let Some((section, subsection)) = name.split_once('.') {
+            let subsection = subsection.to_owned();
+            name.truncate(section.len());
+            Some(subsection)
+        } else {
+            self.skip_whitespace();
+            if self.chars.next_if_eq(&'"').is_some() {
+                Some(self.parse_subsection()?)
+            } else {
+                None
+            }
+        };
+
+        if self.chars.next_if_eq(&']').is_none() {
+            return Err(self.error("invalid section header"));
+        }
+        Ok((name, subsection))
+    }
+
+    /// parses the quoted subsection name, after the opening quote
+    fn parse_subsection(&mut self) -> Result<String> {
+        let mut subsection = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(subsection),
+                Some('\\') => match self.next() {
+                    Some('\n') | None => break,
+                    Some(c) => subsection.push(c),
+                },
+                Some('\n') | None => break,
+                Some(c) => subsection.push(c),
+            }
+        }
+        Err(self.error("unterminated subsection name"))
+    }
+
+    fn parse_key(&mut self) -> String {
+        let mut key = String::new();
+        while let Some(c) = self
+            .chars
+            .next_if(|c| c.is_ascii_alphanumeric() || *c == '-')
+        {
+            key.push(c.to_ascii_lowercase());
+        }
+        key
+    }
+
+    /// parses ` = value` up to and including the end of the line.
+    ///
+    /// A key without a value is true.
+    fn parse_value(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            None | Some('\n' | '#' | ';') => {
+                self.skip_line();
+                return Ok("true".to_owned());
+            }
+            Some('=') => {
+                self.next();
+            }
+            Some(_) => return Err(self.error("expected '=' after key")),
+        }
+        self.skip_whitespace();
+
+        let mut value = String::new();
+        // unquoted whitespace is only kept if more content follows it
+        let mut pending_whitespace = String::new();
+        let mut quoted = false;
+        while let Some(c) = self.next() {
+            match c {
+                '\n' if quoted => return Err(self.error("unterminated quoted value")),
+                '\n' => break,
+                '#' | ';' if !quoted => {
+                    self.skip_line();
+                    break;
+                }
+                '"' => quoted = !quoted,
+                '\\' => {
+                    let escaped = match self.next() {
+                        // line continuation
+                        Some('\n') => continue,
+                        Some('\\') => '\\',
+                        Some('"') => '"',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    value.push_str(&pending_whitespace);
+                    pending_whitespace.clear();
+                    value.push(escaped);
+                }
+                c if c.is_whitespace() && !quoted => pending_whitespace.push(c),
+                c => {
+                    value.push_str(&pending_whitespace);
+                    pending_whitespace.clear();
+                    value.push(c);
+                }
+            }
+        }
+        if quoted {
+            return Err(self.error("unterminated quoted value"));
+        }
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+    use test_dir::DirBuilder;
+
+    use crate::test_utils::existing_test_repo;
+    use crate::Repository;
+
+    const REMOTE_CONFIG: &str = r#"# a comment
+[core]
+	repositoryformatversion = 0
+	bare = false ; trailing comment
+	worktree = "C:\\Program Files\\my repo"
+[remote "origin"]
+	url = https://example.com/repo.git
+	fetch = +refs/heads/*:refs/remotes/origin/*
+	fetch = +refs/tags/*:refs/tags/*
+[Branch "Main"]
+	remote = origin
+"#;
+
+    #[test]
+    fn parse_remote_subsection() {
+        let config: Config = REMOTE_CONFIG.parse().unwrap();
+
+        assert_eq!(
+            config.get_str("remote", Some("origin"), "url"),
+            Some("https://example.com/repo.git")
+        );
+        assert_eq!(
+            config
+                .get_all("remote", Some("origin"), "fetch")
+                .collect::<Vec<_>>(),
+            vec![
+                "+refs/heads/*:refs/remotes/origin/*",
+                "+refs/tags/*:refs/tags/*"
+            ]
+        );
+        assert_eq!(
+            config.get_str("remote", Some("origin"), "FETCH"),
+            Some("+refs/tags/*:refs/tags/*")
+        );
+        // section names are case insensitive, subsection names are not
+        assert_eq!(
+            config.get_str("branch", Some("Main"), "remote"),
+            Some("origin")
+        );
+        assert_eq!(config.get_str("branch", Some("main"), "remote"), None);
+        assert_eq!(config.get_str("remote", None, "url"), None);
+    }
+
+    #[test]
+    fn parse_quoted_path() {
+        let config: Config = REMOTE_CONFIG.parse().unwrap();
+        assert_eq!(
+            config.get_str("core", None, "worktree"),
+            Some(r"C:\Program Files\my repo")
+        );
+    }
+
+    #[test]
+    fn parse_values() {
+        let config: Config = "[test]\n\
+            spaces =   inner   spaces   \n\
+            quoted = \" keep \"  # comment\n\
+            escapes = a\\tb\\nc\\\"d\n\
+            continued = first \\\n  second\n\
+            valueless\n\
+            empty =\n\
+            [legacy.Sub]\n\
+            key = value"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            config.get_str("test", None, "spaces"),
+            Some("inner   spaces")
+        );
+        assert_eq!(config.get_str("test", None, "quoted"), Some(" keep "));
+        assert_eq!(config.get_str("test", None, "escapes"), Some("a\tb\nc\"d"));
+        assert_eq!(
+            config.get_str("test", None, "continued"),
+            Some("first   second")
+        );
+        assert_eq!(
+            config.get_bool("test", None, "valueless").unwrap(),
+            Some(true)
+        );
+        assert_eq!(config.get_bool("test", None, "empty").unwrap(), Some(false));
+        assert_eq!(config.get_str("legacy", Some("sub"), "key"), Some("value"));
+    }
+
+    #[test]
+    fn booleans_and_integers() {
+        let config: Config = "[b]\n\
+            a = true\n b = Yes\n c = on\n d = 1\n\
+            e = false\n f = no\n g = OFF\n h = 0\n\
+            invalid = maybe\n\
+            [i]\n small = 42\n neg = -3\n kilo = 2k\n mega = 1m\n giga = 1g\n bad = 12x"
+            .parse()
+            .unwrap();
+
+        for key in ["a", "b", "c", "d"] {
+            assert_eq!(
+                config.get_bool("b", None, key).unwrap(),
+                Some(true),
+                "{key}"
+            );
+        }
+        for key in ["e", "f", "g", "h"] {
+            assert_eq!(
+                config.get_bool("b", None, key).unwrap(),
+                Some(false),
+                "{key}"
+            );
+        }
+        assert!(config.get_bool("b", None, "invalid").is_err());
+        assert_eq!(config.get_bool("b", None, "missing").unwrap(), None);
+
+        assert_eq!(config.get_int("i", None, "small").unwrap(), Some(42));
+        assert_eq!(config.get_int("i", None, "neg").unwrap(), Some(-3));
+        assert_eq!(config.get_int("i", None, "kilo").unwrap(), Some(2048));
+        assert_eq!(
+            config.get_int("i", None, "mega").unwrap(),
+            Some(1024 * 1024)
+        );
+        assert_eq!(
+            config.get_int("i", None, "giga").unwrap(),
+            Some(1024 * 1024 * 1024)
+        );
+        assert!(config.get_int("i", None, "bad").is_err());
+    }
+
+    #[test]
+    fn invalid_configs() {
+        for invalid in [
+            "key = outside section",
+            "[core\nkey = value",
+            "[remote \"origin]\nurl = x",
+            "[core]\nkey = \"unterminated",
+            "[core]\nkey = bad\\escape",
+            "[core]\nkey value",
+        ] {
+            assert!(invalid.parse::<Config>().is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn set_and_roundtrip() {
+        let mut config: Config = REMOTE_CONFIG.parse().unwrap();
+        config.set("core", None, "bare", "true");
+        config.set(
+            "remote",
+            Some("origin"),
+            "fetch",
+            "+refs/heads/main:refs/remotes/origin/main",
+        );
+        config.set("core", None, "compression", "9");
+        config.add(
+            "remote",
+            Some("upstream"),
+            "url",
+            "path with \"quotes\" # and hash",
+        );
+        config.unset("branch", Some("Main"), "remote");
+
+        assert_eq!(config.get_bool("core", None, "bare").unwrap(), Some(true));
+        assert_eq!(config.get_all("remote", Some("origin"), "fetch").count(), 1);
+
+        let serialized = config.to_string();
+        assert!(serialized.starts_with(
+            "[core]\n\trepositoryformatversion = 0\n\tbare = true\n\tworktree = C:\\\\Program Files\\\\my repo\n\tcompression = 9\n[remote \"origin\"]\n"
+        ));
+        assert!(!serialized.contains("[branch"));
+
+        let reparsed: Config = serialized.parse().unwrap();
+        assert_eq!(reparsed, config);
+    }
+
+    #[test]
+    fn repository_config() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let config = repo.config();
+        assert_eq!(config.get_str("user", None, "name"), Some("Test User"));
+        assert_eq!(config.get_bool("core", None, "bare").unwrap(), Some(false));
+    }
+}
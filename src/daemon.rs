@@ -0,0 +1,490 @@
+use std::{
+    env,
+    fmt::Write,
+    fs,
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::{quote_path, Change, GitSyncError, Object, ObjectType, Repository, Result, Signature};
+
+/// periodically commits all changes in the worktree of a repository, the
+/// autocommits gitsync is built around
+#[derive(Debug)]
+pub struct Daemon<'r> {
+    repo: &'r Repository,
+    interval: Duration,
+    /// name and email of the autocommits, the time is set for each commit
+    author: Signature,
+    shutdown: Shutdown,
+}
+
+/// stops a running [Daemon] when signaled. Clones signal the same daemon
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    signaled: Arc<(Mutex<bool>, Condvar)>,
+}
+
+/// a commit created by [Daemon::tick]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutoCommit {
+    pub sha1: String,
+    pub message: String,
+    /// the paths that changed since the last commit
+    pub changes: Vec<(Vec<u8>, Change)>,
+}
+
+impl<'r> Daemon<'r> {
+    /// creates a daemon that commits the worktree of `repo` every
+    /// `interval` as `author`
+    pub fn new(repo: &'r Repository, interval: Duration, author: Signature) -> Self {
+        Self {
+            repo,
+            interval,
+            author,
+            shutdown: Shutdown::default(),
+        }
+    }
+
+    /// a handle that stops [Self::run], e.g. from another thread
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    /// commits the changes every interval, starting right away, until the
+    /// daemon is shut down. `on_commit` is called with every new commit and
+    /// `on_error` with the error of a failed commit, which is retried in the
+    /// next interval.
+    pub fn run(
+        &self,
+        mut on_commit: impl FnMut(&AutoCommit),
+        mut on_error: impl FnMut(&GitSyncError),
+    ) {
+        while !self.shutdown.is_signaled() {
+            match self.tick() {
+                Ok(Some(commit)) => on_commit(&commit),
+                Ok(None) => {}
+                Err(err) => on_error(&err),
+            }
+            if self.shutdown.wait(self.interval) {
+                break;
+            }
+        }
+    }
+
+    /// stages all changes in the worktree and commits them on top of `HEAD`.
+    /// Returns `None` without committing if nothing changed.
+    pub fn tick(&self) -> Result<Option<AutoCommit>> {
+        self.repo.add(&["."])?;
+        let changes = self.repo.status()?.staged;
+        if changes.is_empty() {
+            return Ok(None);
+        }
+
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as i64);
+        let author = Signature {
+            time: now,
+            ..self.author.clone()
+        };
+        let template = self
+            .repo
+            .config()
+            .get_str("gitsync", Some("autocommit"), "template");
+        let message = autocommit_message(template, &author, &changes);
+        let sha1 = self.repo.commit(&message, &author, &author)?;
+        Ok(Some(AutoCommit {
+            sha1,
+            message,
+            changes,
+        }))
+    }
+}
+
+impl Shutdown {
+    /// stops the daemon after its current commit
+    pub fn signal(&self) {
+        let (signaled, condvar) = &*self.signaled;
+        *signaled.lock().unwrap() = true;
+        condvar.notify_all();
+    }
+
+    pub fn is_signaled(&self) -> bool {
+        *self.signaled.0.lock().unwrap()
+    }
+
+    /// waits for `timeout` or until the shutdown is signaled. Returns whether
+    /// it was signaled
+    fn wait(&self, timeout: Duration) -> bool {
+        let (signaled, condvar) = &*self.signaled;
+        let guard = signaled.lock().unwrap();
+        let (guard, _) = condvar
+            .wait_timeout_while(guard, timeout, |signaled| !*signaled)
+            .unwrap();
+        *guard
+    }
+}
+
+/// rewrites the first parent history of `HEAD` so that consecutive commits
+/// before `cutoff` collapse into one commit per hour, see
+/// [Repository::squash_autocommits]
+pub(crate) fn squash_autocommits(repo: &Repository, cutoff: i64) -> Result<String> {
+    /// the time span that is collapsed into one snapshot
+    const SNAPSHOT_SECS: i64 = 60 * 60;
+
+    let head = repo.resolve_ref("HEAD")?;
+    // merges are kept with their history, only the commits after the newest
+    // merge are rewritten
+    let mut base = None;
+    let mut chain = Vec::new();
+    let mut next = Some(head.clone());
+    while let Some(sha1) = next {
+        let commit = repo.load_object(&sha1)?;
+        let Object::Commit { parents, .. } = &commit else {
+            return Err(GitSyncError::UnexpectedObjectType {
+                sha1,
+                expected: ObjectType::Commit,
+            });
+        };
+        if parents.len() > 1 {
+            base = Some(sha1);
+            break;
+        }
+        next = parents.first().cloned();
+        chain.push((sha1, commit));
+    }
+    chain.reverse();
+
+    let snapshot = |commit: &Object| match commit {
+        Object::Commit { committer, .. } if committer.time < cutoff => {
+            Some(committer.time.div_euclid(SNAPSHOT_SECS))
+        }
+        _ => None,
+    };
+    let mut groups: Vec<Vec<(String, Object)>> = Vec::new();
+    for (sha1, commit) in chain {
+        match groups.last_mut() {
+            Some(group)
+                if snapshot(&commit).is_some() && snapshot(&group[0].1) == snapshot(&commit) =>
+            {
+                group.push((sha1, commit))
+            }
+            _ => groups.push(vec![(sha1, commit)]),
+        }
+    }
+
+    let mut parent = base;
+    for mut group in groups {
+        let count = group.len();
+        let (sha1, commit) = group.pop().unwrap();
+        let Object::Commit {
+            tree,
+            parents,
+            author,
+            committer,
+            encoding,
+            extra_headers,
+            message,
+            ..
+        } = commit
+        else {
+            unreachable!("only commits are collected");
+        };
+        let new_parents: Vec<String> = parent.iter().cloned().collect();
+        // already squashed commits are kept as they are
+        if count == 1 && parents == new_parents {
+            parent = Some(sha1);
+            continue;
+        }
+
+        let message = if count == 1 {
+            message
+        } else {
+            let message = String::from_utf8_lossy(&message);
+            let subject = message.lines().next().unwrap_or_default();
+            format!("{subject}\n\nsquashes {count} autocommits\n").into_bytes()
+        };
+        let squashed = Object::Commit {
+            tree,
+            parents: new_parents,
+            author,
+            committer,
+            encoding,
+            extra_headers,
+            // a signature is invalid once the parents change
+            gpgsig: None,
+            message,
+        };
+        parent = Some(squashed.save(repo)?);
+    }
+
+    let new_head = parent.unwrap_or_else(|| head.clone());
+    if new_head != head {
+        repo.update_ref_with_message("HEAD", &new_head, Some(&head), "autosync: squash")?;
+    }
+    Ok(new_head)
+}
+
+/// the message of an autocommit: a subject followed by the changed paths.
+///
+/// The subject is created from `gitsync.autocommit.template` if it is set,
+/// otherwise it contains the time and number of changed files.
+fn autocommit_message(
+    template: Option<&str>,
+    author: &Signature,
+    changes: &[(Vec<u8>, Change)],
+) -> String {
+    let datetime = format_datetime(author.time, author.tz_offset);
+    let mut message = match template {
+        Some(template) => expand_template(template, &datetime, changes.len()),
+        None => {
+            let files = if changes.len() == 1 { "file" } else { "files" };
+            format!("autosync {datetime}, {} {files} changed", changes.len())
+        }
+    };
+    message.push_str("\n\n");
+    for (path, change) in changes {
+        let change = match change {
+            Change::Added => 'A',
+            Change::Modified => 'M',
+            Change::Deleted => 'D',
+        };
+        writeln!(message, "{change} {}", quote_path(path)).unwrap();
+    }
+    message
+}
+
+/// replaces the `{hostname}`, `{datetime}` and `{changed}` placeholders in
+/// `template`. Unknown placeholders are kept as they are
+fn expand_template(template: &str, datetime: &str, changed: usize) -> String {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        match &rest[1..end] {
+            "hostname" => expanded.push_str(&hostname()),
+            "datetime" => expanded.push_str(datetime),
+            "changed" => expanded.push_str(&changed.to_string()),
+            _ => expanded.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// the name of this computer, `localhost` if it is unknown
+fn hostname() -> String {
+    let from_env = ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .find_map(|var| env::var(var).ok());
+    from_env
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_owned())
+}
+
+/// formats a unix time stamp in the time zone `tz_offset`, in minutes east
+/// of UTC, like `2023-11-14 23:13:20 +0100`
+fn format_datetime(time: i64, tz_offset: i32) -> String {
+    let local = time + tz_offset as i64 * 60;
+    let (days, secs) = (local.div_euclid(86400), local.rem_euclid(86400));
+
+    // converts days since 1970-01-01 to a date in the proleptic gregorian
+    // calendar, see http://howardhinnant.github.io/date_algorithms.html
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    let sign = if tz_offset < 0 { '-' } else { '+' };
+    let offset = tz_offset.abs();
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} {sign}{:02}{:02}",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+        offset / 60,
+        offset % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs,
+        time::{Duration, SystemTime},
+    };
+
+    use test_dir::DirBuilder;
+
+    use super::{expand_template, format_datetime, Daemon};
+    use crate::test_utils::{set_env, test_dir};
+    use crate::{Change, Object, Repository, Signature};
+
+    #[test]
+    fn format_dates() {
+        assert_eq!(format_datetime(0, 0), "1970-01-01 00:00:00 +0000");
+        assert_eq!(format_datetime(1700000000, 60), "2023-11-14 23:13:20 +0100");
+        assert_eq!(
+            format_datetime(1709210096, -510),
+            "2024-02-29 04:04:56 -0830"
+        );
+    }
+
+    #[test]
+    fn expand_placeholders() {
+        let _env = set_env(&[("HOSTNAME", Some("laptop".as_ref()))]);
+        assert_eq!(
+            expand_template(
+                "sync {hostname} {datetime}: {changed} {unknown} {",
+                "now",
+                3
+            ),
+            "sync laptop now: 3 {unknown} {"
+        );
+    }
+
+    #[test]
+    fn autocommit_with_template() {
+        let _env = set_env(&[("HOSTNAME", Some("laptop".as_ref()))]);
+        let repo_path = test_dir("autocommit_with_template");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let mut config = repo.config().clone();
+        config.set(
+            "gitsync",
+            Some("autocommit"),
+            "template",
+            "autosync {hostname}: {changed} changed",
+        );
+        config.write_to_file(repo.path("config")).unwrap();
+        let repo = Repository::new(repo_path.root()).unwrap();
+
+        fs::write(repo_path.root().join("a.txt"), "a\n").unwrap();
+        fs::write(repo_path.root().join("b.txt"), "b\n").unwrap();
+        let author = Signature::parse("Sync <sync@example.com> 0 +0000").unwrap();
+        let daemon = Daemon::new(&repo, Duration::from_secs(60), author);
+        let commit = daemon.tick().unwrap().unwrap();
+        assert_eq!(
+            commit.message,
+            "autosync laptop: 2 changed\n\nA a.txt\nA b.txt\n"
+        );
+        let Object::Commit { message, .. } = repo.load_object(&commit.sha1).unwrap() else {
+            panic!("expected a commit");
+        };
+        assert!(message.starts_with(b"autosync laptop: 2 changed\n"));
+    }
+
+    #[test]
+    fn squash_old_autocommits() {
+        let repo_path = test_dir("squash_old_autocommits");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let commit_at = |time: i64, content: &str| {
+            fs::write(repo_path.root().join("notes.txt"), content).unwrap();
+            repo.add(&["."]).unwrap();
+            let signature = Signature {
+                time,
+                ..Signature::parse("Sync <sync@example.com> 0 +0000").unwrap()
+            };
+            repo.commit(&format!("sync {content}\n"), &signature, &signature)
+                .unwrap()
+        };
+        let count_commits = |head: &str| repo.walk_commits(head).count();
+
+        // five rapid commits within the same hour
+        for i in 0..5 {
+            commit_at(1700000000 + i, &format!("old {i}"));
+        }
+        let old_tree = repo.read_tree(&repo.resolve_ref("HEAD").unwrap()).unwrap();
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let recent = commit_at(now, "recent");
+        assert_eq!(count_commits(&recent), 6);
+
+        let head = repo
+            .squash_autocommits(Duration::from_secs(60 * 60))
+            .unwrap();
+        assert_eq!(repo.resolve_ref("HEAD").unwrap(), head);
+        assert_eq!(count_commits(&head), 2);
+        assert_eq!(
+            repo.read_tree(&head).unwrap(),
+            repo.read_tree(&recent).unwrap()
+        );
+        let Object::Commit { parents, .. } = repo.load_object(&head).unwrap() else {
+            panic!("expected a commit");
+        };
+        let Object::Commit {
+            parents: snapshot_parents,
+            message,
+            ..
+        } = repo.load_object(&parents[0]).unwrap()
+        else {
+            panic!("expected a commit");
+        };
+        assert!(snapshot_parents.is_empty());
+        assert_eq!(message, b"sync old 4\n\nsquashes 5 autocommits\n");
+        assert_eq!(repo.read_tree(&parents[0]).unwrap(), old_tree);
+
+        // squashing again changes nothing
+        assert_eq!(
+            repo.squash_autocommits(Duration::from_secs(60 * 60))
+                .unwrap(),
+            head
+        );
+    }
+
+    #[test]
+    fn autocommit_changes() {
+        let repo_path = test_dir("autocommit_changes");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+        let author = Signature::parse("Sync <sync@example.com> 0 +0000").unwrap();
+        let daemon = Daemon::new(&repo, Duration::from_secs(60), author);
+
+        // nothing to commit in an empty worktree
+        assert_eq!(daemon.tick().unwrap(), None);
+
+        fs::write(root.join("notes.txt"), "first\n").unwrap();
+        let first = daemon.tick().unwrap().unwrap();
+        assert_eq!(first.changes, [(b"notes.txt".to_vec(), Change::Added)]);
+        assert!(first.message.starts_with("autosync "));
+        assert!(first.message.ends_with(", 1 file changed\n\nA notes.txt\n"));
+        assert_eq!(repo.resolve_ref("HEAD").unwrap(), first.sha1);
+        assert_eq!(daemon.tick().unwrap(), None);
+
+        fs::write(root.join("notes.txt"), "second\n").unwrap();
+        let second = daemon.tick().unwrap().unwrap();
+        assert_eq!(second.changes, [(b"notes.txt".to_vec(), Change::Modified)]);
+        let Object::Commit { parents, .. } = repo.load_object(&second.sha1).unwrap() else {
+            panic!("expected a commit");
+        };
+        assert_eq!(parents, [first.sha1]);
+
+        // a signaled daemon stops without committing
+        fs::write(root.join("notes.txt"), "third\n").unwrap();
+        daemon.shutdown_handle().signal();
+        daemon.run(
+            |_| panic!("no commit expected"),
+            |err| panic!("no error expected: {err}"),
+        );
+        assert_eq!(repo.resolve_ref("HEAD").unwrap(), second.sha1);
+    }
+}
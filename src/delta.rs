@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use crate::{GitSyncError, Result};
+
+/// the number of bytes of the base [create_delta] indexes together. Shorter
+/// matches are not found
+const BLOCK_LEN: usize = 16;
+/// the largest size a single copy instruction can encode
+const MAX_COPY: usize = 0xff_ffff;
+/// the largest size a single insert instruction can encode
+const MAX_INSERT: usize = 0x7f;
+
+/// reconstructs an object from its `base` and a git `delta`.
+///
+/// A delta starts with the sizes of the base and the result, followed by
+/// instructions that either copy a range of the base or insert new data.
+pub(crate) fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut pos = 0;
+    let base_size = read_size(delta, &mut pos)?;
+    let result_size = read_size(delta, &mut pos)?;
+    if base_size != base.len() {
+        return Err(invalid(&format!(
+            "delta expects a base of {base_size} bytes, but the base has {}",
+            base.len()
+        )));
+    }
+
+    // the declared size is untrusted, copies of the base make results
+    // larger than this rare
+    let mut result = Vec::with_capacity(result_size.min(base.len() + delta.len()));
+    while let Some(&instruction) = delta.get(pos) {
+        pos += 1;
+        if instruction & 0x80 != 0 {
+            // the lower 4 bits select which offset bytes follow, the next 3
+            // bits which size bytes follow. Missing bytes are 0
+            let mut read_le = |bits: u8, count: usize| {
+                let mut value = 0usize;
+                for i in 0..count {
+                    if bits & (1 << i) != 0 {
+                        let byte = *delta.get(pos).ok_or_else(|| invalid("truncated copy"))?;
+                        pos += 1;
+                        value |= (byte as usize) << (8 * i);
+                    }
+                }
+                Ok::<usize, GitSyncError>(value)
+            };
+            let offset = read_le(instruction & 0x0f, 4)?;
+            let size = match read_le((instruction >> 4) & 0x07, 3)? {
+                0 => 0x10000,
+                size => size,
+            };
+            let copy = offset
+                .checked_add(size)
+                .and_then(|end| base.get(offset..end))
+                .ok_or_else(|| invalid("copy is out of bounds"))?;
+            result.extend_from_slice(copy);
+        } else if instruction != 0 {
+            let size = instruction as usize;
+            let insert = delta
+                .get(pos..pos + size)
+                .ok_or_else(|| invalid("truncated insert"))?;
+            pos += size;
+            result.extend_from_slice(insert);
+        } else {
+            return Err(invalid("reserved instruction 0"));
+        }
+        if result.len() > result_size {
+            return Err(invalid(&format!(
+                "delta should produce {result_size} bytes, but produces more"
+            )));
+        }
+    }
+
+    if result.len() != result_size {
+        return Err(invalid(&format!(
+            "delta should produce {result_size} bytes, but produced {}",
+            result.len()
+        )));
+    }
+    Ok(result)
+}
+
+/// creates a delta that turns `base` into `target`, the inverse of
+/// [apply_delta].
+///
+/// The base is indexed in blocks of [BLOCK_LEN] bytes. Wherever a block
+/// appears in the target, the match is extended in both directions and
+/// copied from the base. Everything else is inserted.
+pub(crate) fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    write_size(&mut delta, base.len());
+    write_size(&mut delta, target.len());
+
+    let mut blocks: HashMap<&[u8], usize> = HashMap::new();
+    for (i, block) in base.chunks_exact(BLOCK_LEN).enumerate() {
+        blocks.entry(block).or_insert(i * BLOCK_LEN);
+    }
+
+    // the start of the bytes that are not copied yet
+    let mut insert_start = 0;
+    let mut pos = 0;
+    while pos + BLOCK_LEN <= target.len() {
+        let Some(&offset) = blocks.get(&target[pos..pos + BLOCK_LEN]) else {
+            pos += 1;
+            continue;
+        };
+        let (mut start, mut target_start) = (offset, pos);
+        while start > 0
+            && target_start > insert_start
+            && base[start - 1] == target[target_start - 1]
+        {
+            start -= 1;
+            target_start -= 1;
+        }
+        let mut len = pos + BLOCK_LEN - target_start;
+        while start + len < base.len()
+            && target_start + len < target.len()
+            && base[start + len] == target[target_start + len]
+        {
+            len += 1;
+        }
+
+        push_insert(&mut delta, &target[insert_start..target_start]);
+        push_copy(&mut delta, start, len);
+        pos = target_start + len;
+        insert_start = pos;
+    }
+    push_insert(&mut delta, &target[insert_start..]);
+    delta
+}
+
+/// appends instructions copying `len` bytes of the base at `offset`
+fn push_copy(delta: &mut Vec<u8>, mut offset: usize, mut len: usize) {
+    while len > 0 {
+        let size = len.min(MAX_COPY);
+        let instruction_pos = delta.len();
+        let mut instruction = 0x80;
+        delta.push(instruction);
+        // only the bytes that are not 0 are stored
+        for i in 0..4 {
+            let byte = (offset >> (8 * i)) as u8;
+            if byte != 0 {
+                instruction |= 1 << i;
+                delta.push(byte);
+            }
+        }
+        for i in 0..3 {
+            let byte = (size >> (8 * i)) as u8;
+            if byte != 0 {
+                instruction |= 0x10 << i;
+                delta.push(byte);
+            }
+        }
+        delta[instruction_pos] = instruction;
+        offset += size;
+        len -= size;
+    }
+}
+
+/// appends instructions inserting `data`
+fn push_insert(delta: &mut Vec<u8>, data: &[u8]) {
+    for chunk in data.chunks(MAX_INSERT) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+}
+
+/// the inverse of [read_size]
+fn write_size(delta: &mut Vec<u8>, mut size: usize) {
+    while size >= 0x80 {
+        delta.push((size & 0x7f) as u8 | 0x80);
+        size >>= 7;
+    }
+    delta.push(size as u8);
+}
+
+/// the size of the object `delta` creates, read from its header. Only the
+/// first [MAX_DELTA_HEADER] bytes of the delta are needed
+pub(crate) fn delta_result_size(delta: &[u8]) -> Result<usize> {
+    let mut pos = 0;
+    read_size(delta, &mut pos)?;
+    read_size(delta, &mut pos)
+}
+
+/// the longest header of a delta, two sizes of at most 10 bytes each
+pub(crate) const MAX_DELTA_HEADER: usize = 20;
+
+/// reads a little endian size with 7 bits per byte
+fn read_size(delta: &[u8], pos: &mut usize) -> Result<usize> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *delta
+            .get(*pos)
+            .ok_or_else(|| invalid("truncated delta header"))?;
+        *pos += 1;
+        if shift > usize::BITS - 7 {
+            return Err(invalid("delta size is too large"));
+        }
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+fn invalid(reason: &str) -> GitSyncError {
+    GitSyncError::InvalidDelta(reason.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{apply_delta, create_delta};
+
+    #[test]
+    fn copy_and_insert() {
+        // copy "hello", insert ", my", copy " world"
+        let delta = [11, 15, 0x90, 5, 4, b',', b' ', b'm', b'y', 0x91, 5, 6];
+        assert_eq!(
+            apply_delta(b"hello world", &delta).unwrap(),
+            b"hello, my world"
+        );
+    }
+
+    #[test]
+    fn create_and_apply() {
+        let base: Vec<u8> = (0..20_000u32).flat_map(|i| i.to_le_bytes()).collect();
+        let mut target = base.clone();
+        target[100] = 0xff;
+        target.splice(40_000..40_000, b"inserted".iter().copied());
+        target.truncate(70_000);
+
+        let delta = create_delta(&base, &target);
+        assert!(delta.len() < 100, "delta is {} bytes", delta.len());
+        assert_eq!(apply_delta(&base, &delta).unwrap(), target);
+
+        // nothing in common
+        let delta = create_delta(b"abc", b"xyz");
+        assert_eq!(apply_delta(b"abc", &delta).unwrap(), b"xyz");
+        let delta = create_delta(b"", &base);
+        assert_eq!(apply_delta(b"", &delta).unwrap(), base);
+    }
+
+    #[test]
+    fn reject_invalid_deltas() {
+        // wrong base size
+        assert!(apply_delta(b"abc", &[4, 1, 1, b'x']).is_err());
+        // copy past the end of the base
+        assert!(apply_delta(b"abc", &[3, 4, 0x91, 0, 4]).is_err());
+        assert!(apply_delta(b"abc", &[3, 1, 0]).is_err());
+        // produces fewer bytes than announced
+        assert!(apply_delta(b"abc", &[3, 2, 1, b'x']).is_err());
+        // produces more bytes than announced
+        assert!(apply_delta(b"abc", &[3, 1, 2, b'x', b'y']).is_err());
+        // announces a result too large to allocate
+        let huge = [
+            3, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f, 1, b'x',
+        ];
+        assert!(apply_delta(b"abc", &huge).is_err());
+    }
+}
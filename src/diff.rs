@@ -0,0 +1,329 @@
+use std::{fmt::Write, ops::Range};
+
+use crate::Change;
+
+/// a path that differs between two trees, created by
+/// [Repository::diff_trees](crate::Repository::diff_trees)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeChange {
+    /// the path relative to the root of the trees
+    pub path: Vec<u8>,
+    pub change: Change,
+    /// the mode and sha1 in the first tree, unless the path was added
+    pub old: Option<(String, String)>,
+    /// the mode and sha1 in the second tree, unless the path was deleted
+    pub new: Option<(String, String)>,
+}
+
+/// one step of the edit script between two sequences, created by [diff]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Edit {
+    /// `a[old] == b[new]`
+    Equal { old: usize, new: usize },
+    /// `a[old]` is not in `b`
+    Delete { old: usize },
+    /// `b[new]` is not in `a`
+    Insert { new: usize },
+}
+
+/// lines of context around the changes of a hunk, like git's default
+const CONTEXT: usize = 3;
+
+/// the shortest edit script that turns `a` into `b`, using the linear space
+/// variant of Myers' algorithm
+pub(crate) fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Edit> {
+    let max = (a.len() + b.len()).div_ceil(2) as isize + 1;
+    let mut myers = Myers {
+        a,
+        b,
+        forward: vec![0; 2 * max as usize + 1],
+        backward: vec![0; 2 * max as usize + 1],
+        offset: max,
+        edits: Vec::with_capacity(a.len().max(b.len())),
+    };
+    myers.diff(0..a.len(), 0..b.len());
+
+    // like git, the deletions of a change come before its insertions
+    let mut edits = myers.edits;
+    for change in edits.split_mut(|edit| matches!(edit, Edit::Equal { .. })) {
+        change.sort_by_key(|edit| matches!(edit, Edit::Insert { .. }));
+    }
+    edits
+}
+
+/// the state of [diff]. Only the furthest reaching paths of the current
+/// round are kept, so the memory stays linear in the length of the input
+struct Myers<'a, T> {
+    a: &'a [T],
+    b: &'a [T],
+    /// `forward[k]` is the furthest x reached on diagonal k = x - y from
+    /// the start of the ranges
+    forward: Vec<isize>,
+    /// `backward[k]` is the furthest distance from the end of the ranges
+    /// reached on the reversed diagonal k
+    backward: Vec<isize>,
+    /// the index of diagonal 0 in `forward` and `backward`
+    offset: isize,
+    edits: Vec<Edit>,
+}
+
+impl<T: PartialEq> Myers<'_, T> {
+    /// appends the edits that turn `a[old]` into `b[new]`
+    fn diff(&mut self, mut old: Range<usize>, mut new: Range<usize>) {
+        while !old.is_empty() && !new.is_empty() && self.a[old.start] == self.b[new.start] {
+            self.edits.push(Edit::Equal {
+                old: old.start,
+                new: new.start,
+            });
+            old.start += 1;
+            new.start += 1;
+        }
+        let mut suffix = 0;
+        while suffix < old.len()
+            && suffix < new.len()
+            && self.a[old.end - suffix - 1] == self.b[new.end - suffix - 1]
+        {
+            suffix += 1;
+        }
+        old.end -= suffix;
+        new.end -= suffix;
+
+        if old.is_empty() {
+            self.edits
+                .extend(new.clone().map(|new| Edit::Insert { new }));
+        } else if new.is_empty() {
+            self.edits
+                .extend(old.clone().map(|old| Edit::Delete { old }));
+        } else {
+            let (start, end) = self.middle_snake(old.clone(), new.clone());
+            self.diff(old.start..start.0, new.start..start.1);
+            self.edits.extend(
+                (start.0..end.0)
+                    .zip(start.1..end.1)
+                    .map(|(old, new)| Edit::Equal { old, new }),
+            );
+            self.diff(end.0..old.end, end.1..new.end);
+        }
+
+        self.edits.extend(
+            (old.end..old.end + suffix)
+                .zip(new.end..new.end + suffix)
+                .map(|(old, new)| Edit::Equal { old, new }),
+        );
+    }
+
+    /// the start and end of the snake in the middle of a shortest edit
+    /// script of `a[old]` and `b[new]`. It is found by searching from both
+    /// ends at once until the paths overlap
+    fn middle_snake(
+        &mut self,
+        old: Range<usize>,
+        new: Range<usize>,
+    ) -> ((usize, usize), (usize, usize)) {
+        let (a, b) = (&self.a[old.clone()], &self.b[new.clone()]);
+        let (n, m) = (a.len() as isize, b.len() as isize);
+        let delta = n - m;
+        let odd = delta % 2 != 0;
+        let offset = self.offset;
+        let index = |k: isize| (k + offset) as usize;
+        let (forward, backward) = (&mut self.forward, &mut self.backward);
+        forward[index(1)] = 0;
+        backward[index(1)] = 0;
+
+        for d in 0..=(n + m + 1) / 2 {
+            for k in (-d..=d).step_by(2) {
+                let mut x = if k == -d || (k != d && forward[index(k - 1)] < forward[index(k + 1)])
+                {
+                    forward[index(k + 1)]
+                } else {
+                    forward[index(k - 1)] + 1
+                };
+                let (start_x, start_y) = (x, x - k);
+                let mut y = start_y;
+                while x < n && y < m && a[x as usize] == b[y as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                forward[index(k)] = x;
+                // the backward search has done d - 1 rounds
+                let reversed = delta - k;
+                if odd
+                    && (-(d - 1)..=d - 1).contains(&reversed)
+                    && x + backward[index(reversed)] >= n
+                {
+                    return (
+                        (old.start + start_x as usize, new.start + start_y as usize),
+                        (old.start + x as usize, new.start + y as usize),
+                    );
+                }
+            }
+
+            for k in (-d..=d).step_by(2) {
+                let mut x =
+                    if k == -d || (k != d && backward[index(k - 1)] < backward[index(k + 1)]) {
+                        backward[index(k + 1)]
+                    } else {
+                        backward[index(k - 1)] + 1
+                    };
+                let (end_x, end_y) = (x, x - k);
+                let mut y = end_y;
+                while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                    x += 1;
+                    y += 1;
+                }
+                backward[index(k)] = x;
+                let forward_k = delta - k;
+                if !odd && (-d..=d).contains(&forward_k) && x + forward[index(forward_k)] >= n {
+                    return (
+                        (old.start + (n - x) as usize, new.start + (m - y) as usize),
+                        (
+                            old.start + (n - end_x) as usize,
+                            new.start + (m - end_y) as usize,
+                        ),
+                    );
+                }
+            }
+        }
+        unreachable!("the searches meet after at most half the edits")
+    }
+}
+
+/// splits `data` into lines that keep their `\n`
+pub(crate) fn lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&b| b == b'\n').collect()
+}
+
+/// a unified diff from `a` to `b` with the file names `a_name` and `b_name`.
+/// Returns an empty string if they are equal.
+pub(crate) fn unified_diff(a: &[u8], b: &[u8], a_name: &str, b_name: &str) -> String {
+    let (a, b) = (lines(a), lines(b));
+    let edits = diff(&a, &b);
+
+    let mut out = String::new();
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Equal { .. }))
+        .map(|(i, _)| i)
+        .collect();
+    if changes.is_empty() {
+        return out;
+    }
+    writeln!(out, "--- {a_name}").unwrap();
+    writeln!(out, "+++ {b_name}").unwrap();
+
+    // changes that are close enough to share their context form one hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &change in &changes {
+        match hunks.last_mut() {
+            Some((_, end)) if change - *end - 1 <= 2 * CONTEXT => *end = change,
+            _ => hunks.push((change, change)),
+        }
+    }
+
+    for (first, last) in hunks {
+        let start = first.saturating_sub(CONTEXT);
+        let end = (last + CONTEXT + 1).min(edits.len());
+        let hunk = &edits[start..end];
+
+        // the position of the hunk is where its first line is or would be
+        let (old_start, new_start) = match edits[start] {
+            Edit::Equal { old, new } => (old, new),
+            Edit::Delete { old } => (old, new_position(&edits[..start])),
+            Edit::Insert { new } => (old_position(&edits[..start]), new),
+        };
+        let old_len = hunk
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Insert { .. }))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Delete { .. }))
+            .count();
+        writeln!(
+            out,
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_len),
+            hunk_range(new_start, new_len)
+        )
+        .unwrap();
+
+        for edit in hunk {
+            let (prefix, line) = match *edit {
+                Edit::Equal { old, .. } => (' ', a[old]),
+                Edit::Delete { old } => ('-', a[old]),
+                Edit::Insert { new } => ('+', b[new]),
+            };
+            out.push(prefix);
+            out.push_str(&String::from_utf8_lossy(line));
+            if !line.ends_with(b"\n") {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+    }
+    out
+}
+
+/// the number of lines of `a` that `edits` consumed
+fn old_position(edits: &[Edit]) -> usize {
+    edits
+        .iter()
+        .filter(|edit| !matches!(edit, Edit::Insert { .. }))
+        .count()
+}
+
+/// the number of lines of `b` that `edits` consumed
+fn new_position(edits: &[Edit]) -> usize {
+    edits
+        .iter()
+        .filter(|edit| !matches!(edit, Edit::Delete { .. }))
+        .count()
+}
+
+/// formats the 0 based `start` and `len` of a hunk like git. Empty ranges
+/// point to the line before them and a length of 1 is omitted.
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{diff, unified_diff, Edit};
+
+    #[test]
+    fn shortest_edit_script() {
+        let a: Vec<char> = "abcabba".chars().collect();
+        let b: Vec<char> = "cbabac".chars().collect();
+        let edits = diff(&a, &b);
+        // the edit distance of Myers' example is 5
+        let changes = edits
+            .iter()
+            .filter(|edit| !matches!(edit, Edit::Equal { .. }))
+            .count();
+        assert_eq!(changes, 5);
+
+        assert!(diff::<u8>(&[], &[]).is_empty());
+        assert_eq!(diff(&[1], &[]), [Edit::Delete { old: 0 }]);
+    }
+
+    #[test]
+    fn unified_hunks() {
+        let a = b"1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let b = b"1\n2\n3\n4\nfive\n6\n7\n8\n9\n10\n11\n12\n13";
+        assert_eq!(
+            unified_diff(a, b, "a/x", "b/x"),
+            "--- a/x\n+++ b/x\n\
+            @@ -2,7 +2,7 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n\
+            @@ -10,3 +10,4 @@\n 10\n 11\n 12\n+13\n\\ No newline at end of file\n"
+        );
+        assert_eq!(unified_diff(a, a, "a/x", "b/x"), "");
+        assert_eq!(
+            unified_diff(b"", b"new\n", "a/x", "b/x"),
+            "--- a/x\n+++ b/x\n@@ -0,0 +1 @@\n+new\n"
+        );
+    }
+}
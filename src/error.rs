@@ -0,0 +1,190 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::ObjectType;
+
+pub type Result<T> = std::result::Result<T, GitSyncError>;
+
+#[derive(Debug, Error)]
+pub enum GitSyncError {
+    #[error("object {sha1} not found")]
+    ObjectNotFound { sha1: String },
+
+    #[error("short sha1 {prefix} is ambiguous")]
+    AmbiguousObject { prefix: String },
+
+    #[error("{name:?} is not a valid object name")]
+    InvalidObjectName { name: String },
+
+    #[error("invalid object header: {0}")]
+    InvalidObjectHeader(String),
+
+    #[error("unknown object type {0:?}")]
+    UnknownObjectType(String),
+
+    #[error("invalid object size {0:?}")]
+    InvalidObjectSize(String),
+
+    #[error("invalid {typ} object: {reason}")]
+    InvalidObject { typ: ObjectType, reason: String },
+
+    #[error("object {sha1} is not a {expected}")]
+    UnexpectedObjectType { sha1: String, expected: ObjectType },
+
+    #[error("invalid signature {0:?}")]
+    InvalidSignature(String),
+
+    #[error(
+        "{role} identity unknown: set user.{key} in the config or the GIT_{}_{} environment variable",
+        role.to_ascii_uppercase(),
+        key.to_ascii_uppercase()
+    )]
+    MissingIdentity { role: String, key: String },
+
+    #[error("invalid date {0:?}")]
+    InvalidDate(String),
+
+    #[error("invalid hex string {0:?}")]
+    InvalidHex(String),
+
+    #[error("Expected to read object of size {expected} but got {actual} instead")]
+    SizeMismatch { expected: usize, actual: usize },
+
+    #[error("object ended after {actual} of {expected} bytes")]
+    TruncatedObject { expected: usize, actual: usize },
+
+    #[error("unknown revision {spec:?}")]
+    UnknownRevision { spec: String },
+
+    #[error("no names found, cannot describe {commit}")]
+    NoTagFound { commit: String },
+
+    #[error("ref {name:?} not found")]
+    RefNotFound { name: String },
+
+    #[error("invalid ref {name:?}: {reason}")]
+    InvalidRef { name: String, reason: String },
+
+    #[error("ref {name:?} has no reflog")]
+    NoReflog { name: String },
+
+    #[error("the reflog of {name:?} only has {len} entries, {index} is out of range")]
+    ReflogIndexOutOfRange {
+        name: String,
+        index: usize,
+        len: usize,
+    },
+
+    #[error("the reflog of {name:?} does not go back to {date:?}")]
+    ReflogTooShort { name: String, date: String },
+
+    #[error("ref {name:?} is locked by another process")]
+    RefLocked { name: String },
+
+    #[error("ref {name:?} is at {actual:?} but expected {expected}")]
+    RefChanged {
+        name: String,
+        expected: String,
+        actual: Option<String>,
+    },
+
+    #[error("cannot delete branch {name:?}, it is checked out")]
+    BranchCheckedOut { name: String },
+
+    #[error("local changes to {path:?} would be overwritten")]
+    WouldOverwrite { path: String },
+
+    #[error("{path:?} is not a git repository")]
+    NotARepository { path: PathBuf },
+
+    #[error("{path:?} is a bare repository without a worktree")]
+    NotAWorktree { path: PathBuf },
+
+    #[error("{path:?} is outside of the worktree")]
+    OutsideWorktree { path: PathBuf },
+
+    #[error("{path:?} already exists")]
+    AlreadyExists { path: PathBuf },
+
+    #[error("{path:?} is not a directory")]
+    NotADirectory { path: PathBuf },
+
+    #[error("invalid pack: {0}")]
+    InvalidPack(String),
+
+    #[error("invalid delta: {0}")]
+    InvalidDelta(String),
+
+    #[error("invalid index: {0}")]
+    InvalidIndex(String),
+
+    #[error("the index is locked by another process, {path:?} exists")]
+    IndexLocked { path: PathBuf },
+
+    #[error("pathspec {path:?} did not match any files")]
+    PathspecNotFound { path: PathBuf },
+
+    #[error("path {path:?} is unmerged")]
+    UnmergedPath { path: String },
+
+    #[error("the {name} hook failed with {status}")]
+    HookFailed {
+        name: String,
+        status: std::process::ExitStatus,
+    },
+
+    #[error("could not verify the signature: {0}")]
+    Gpg(String),
+
+    #[error("a merge is in progress, commit the resolved conflicts first")]
+    MergeInProgress,
+
+    #[error("a cherry-pick is in progress, commit the resolved conflicts first")]
+    CherryPickInProgress,
+
+    #[error("a revert is in progress, commit the resolved conflicts first")]
+    RevertInProgress,
+
+    #[error("cannot revert {commit}: {reason}")]
+    InvalidMainline { commit: String, reason: String },
+
+    #[error("refusing to merge unrelated histories")]
+    UnrelatedHistories,
+
+    #[error("invalid config: {0}")]
+    InvalidConfig(String),
+
+    #[error("invalid repository format {version}. Only 0 and 1 are supported")]
+    UnsupportedRepositoryFormat { version: i64 },
+
+    #[error("unsupported repository extension {name:?}")]
+    UnsupportedExtension { name: String },
+
+    #[error("remote {name:?} is not configured")]
+    RemoteNotFound { name: String },
+
+    #[error("updating {name:?} is not a fast-forward, fetch first or force the push")]
+    NonFastForward { name: String },
+
+    #[error("transport error: {0}")]
+    Transport(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl GitSyncError {
+    /// unwraps errors that were wrapped in an [std::io::Error] to pass them
+    /// through a [Read](std::io::Read) implementation
+    pub(crate) fn from_io(err: std::io::Error) -> Self {
+        if err
+            .get_ref()
+            .is_some_and(|inner| inner.is::<GitSyncError>())
+        {
+            *err.into_inner().unwrap().downcast().unwrap()
+        } else {
+            GitSyncError::Io(err)
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    sync::atomic::{self, AtomicUsize},
+};
+
+use crate::{GitSyncError, Result};
+
+/// counts the signature files written by this process, to name them uniquely
+static SIGNATURE_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// the result of [Repository::verify_signature]
+///
+/// [Repository::verify_signature]: crate::Repository::verify_signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// the object has no signature
+    NotSigned,
+    /// the signature is valid. `key` is the id of the signing key and
+    /// `signer` its user id, e.g. `Test User <test@example.com>`
+    Good { key: String, signer: String },
+    /// the signature does not match the object
+    Bad { key: String, signer: String },
+    /// the signature can't be checked, usually because the public key is
+    /// missing
+    Unverifiable { key: String },
+}
+
+/// verifies `signature` of `payload` by running `program --verify`, which
+/// is `gpg` unless `gpg.program` is configured. The signature is written to
+/// a temporary file in `temp_dir`, usually the git dir
+pub(crate) fn verify(
+    program: &str,
+    payload: &[u8],
+    signature: &str,
+    temp_dir: &Path,
+) -> Result<SignatureStatus> {
+    // gpg reads a detached signature only from a file
+    let (signature_file, mut file) = create_signature_file(temp_dir)?;
+    let output = (|| {
+        file.write_all(signature.as_bytes())?;
+        drop(file);
+        let mut child = Command::new(program)
+            .args(["--status-fd=1", "--verify"])
+            .arg(&signature_file)
+            .arg("-")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        match stdin.write_all(payload) {
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => {}
+            result => result?,
+        }
+        drop(stdin);
+        child.wait_with_output()
+    })();
+    let _ = fs::remove_file(&signature_file);
+    let output = output?;
+
+    parse_status(&String::from_utf8_lossy(&output.stdout)).ok_or_else(|| {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        GitSyncError::Gpg(stderr.trim().to_owned())
+    })
+}
+
+/// creates a new, empty file for a signature in `dir`. Existing files are
+/// never opened, so another process can't redirect the write with a
+/// symlink, and their names are skipped
+fn create_signature_file(dir: &Path) -> Result<(PathBuf, File)> {
+    loop {
+        let path = dir.join(format!(
+            "gitsync_sig_{}_{}",
+            process::id(),
+            SIGNATURE_FILES.fetch_add(1, atomic::Ordering::Relaxed)
+        ));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            file => return Ok((path, file?)),
+        }
+    }
+}
+
+/// reads the result from the `[GNUPG:]` status lines gpg prints with
+/// `--status-fd`
+fn parse_status(status: &str) -> Option<SignatureStatus> {
+    for line in status.lines() {
+        let Some(line) = line.strip_prefix("[GNUPG:] ") else {
+            continue;
+        };
+        let (keyword, args) = line.split_once(' ').unwrap_or((line, ""));
+        let (key, signer) = args.split_once(' ').unwrap_or((args, ""));
+        let (key, signer) = (key.to_owned(), signer.to_owned());
+        return Some(match keyword {
+            "GOODSIG" => SignatureStatus::Good { key, signer },
+            // expired or revoked keys made a good signature, but git doesn't
+            // trust it either
+            "BADSIG" | "EXPKEYSIG" | "REVKEYSIG" | "EXPSIG" => SignatureStatus::Bad { key, signer },
+            "ERRSIG" => SignatureStatus::Unverifiable { key },
+            _ => continue,
+        });
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, process, sync::atomic};
+
+    use test_dir::DirBuilder;
+
+    use super::{create_signature_file, parse_status, SignatureStatus, SIGNATURE_FILES};
+    use crate::test_utils::test_dir;
+
+    #[test]
+    fn skip_existing_signature_files() {
+        let dir = test_dir("skip_existing_signature_files");
+        let next = SIGNATURE_FILES.load(atomic::Ordering::Relaxed);
+        let taken = dir
+            .root()
+            .join(format!("gitsync_sig_{}_{next}", process::id()));
+        fs::write(&taken, "planted").unwrap();
+
+        let (path, _) = create_signature_file(dir.root()).unwrap();
+        assert_ne!(path, taken);
+        assert_eq!(fs::read_to_string(&taken).unwrap(), "planted");
+    }
+
+    #[test]
+    fn parse_gpg_status() {
+        let good = "[GNUPG:] NEWSIG\n\
+            [GNUPG:] KEY_CONSIDERED 0123456789ABCDEF0123456789ABCDEF01234567 0\n\
+            [GNUPG:] GOODSIG 89ABCDEF01234567 Test User <test@example.com>\n\
+            [GNUPG:] VALIDSIG 0123456789ABCDEF0123456789ABCDEF01234567 2023-11-14\n";
+        assert_eq!(
+            parse_status(good),
+            Some(SignatureStatus::Good {
+                key: "89ABCDEF01234567".to_owned(),
+                signer: "Test User <test@example.com>".to_owned(),
+            })
+        );
+
+        let bad =
+            "[GNUPG:] NEWSIG\n[GNUPG:] BADSIG 89ABCDEF01234567 Test User <test@example.com>\n";
+        assert!(matches!(
+            parse_status(bad),
+            Some(SignatureStatus::Bad { key, .. }) if key == "89ABCDEF01234567"
+        ));
+
+        let missing_key = "[GNUPG:] ERRSIG 89ABCDEF01234567 1 8 00 1700000000 9 -\n\
+            [GNUPG:] NO_PUBKEY 89ABCDEF01234567\n";
+        assert_eq!(
+            parse_status(missing_key),
+            Some(SignatureStatus::Unverifiable {
+                key: "89ABCDEF01234567".to_owned()
+            })
+        );
+
+        assert_eq!(parse_status("gpg: invalid armor\n"), None);
+    }
+}
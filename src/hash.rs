@@ -0,0 +1,91 @@
+use std::{fmt::Display, io::Write};
+
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use crate::{Config, GitSyncError, Result};
+
+/// the hash function used to name objects, set by `extensions.objectFormat`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// reads the algorithm from the repository config
+    pub fn from_config(config: &Config) -> Result<Self> {
+        match config.get_str("extensions", None, "objectformat") {
+            None => Ok(Self::Sha1),
+            Some(format) if format.eq_ignore_ascii_case("sha1") => Ok(Self::Sha1),
+            Some(format) if format.eq_ignore_ascii_case("sha256") => Ok(Self::Sha256),
+            Some(_) => Err(GitSyncError::UnsupportedExtension {
+                name: "objectformat".to_owned(),
+            }),
+        }
+    }
+
+    /// the name used by git, e.g. in `extensions.objectFormat`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    /// length of the raw digest in bytes
+    pub fn raw_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha1 => 20,
+            HashAlgorithm::Sha256 => 32,
+        }
+    }
+
+    /// length of the hex encoded digest
+    pub fn hex_len(&self) -> usize {
+        self.raw_len() * 2
+    }
+
+    pub(crate) fn hasher(&self) -> Hasher {
+        match self {
+            HashAlgorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+}
+
+impl Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// incremental hasher for any [HashAlgorithm]
+pub(crate) enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub(crate) fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Sha1(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Hasher::Sha1(hasher) => hasher.update(buf),
+            Hasher::Sha256(hasher) => hasher.update(buf),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
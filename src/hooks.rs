@@ -0,0 +1,145 @@
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+};
+
+use crate::{Repository, Result};
+
+/// runs the hook `name`, e.g. `pre-commit`, with `args` and `stdin` and
+/// waits for it to exit.
+///
+/// Hooks are read from `core.hooksPath` or `hooks` in the git dir and run in
+/// the root of the worktree, or the git dir of a bare repository. A missing
+/// or non-executable hook is skipped and counts as a success.
+pub fn run_hook(
+    repo: &Repository,
+    name: &str,
+    args: &[&OsStr],
+    stdin: Option<&[u8]>,
+) -> Result<ExitStatus> {
+    let path = hooks_dir(repo).join(name);
+    if !is_executable(&path) {
+        return Ok(ExitStatus::default());
+    }
+
+    let current_dir = match repo.worktree_root() {
+        Ok(root) => root.to_path_buf(),
+        Err(_) => repo.path(""),
+    };
+    let mut child = Command::new(&path)
+        .args(args)
+        .current_dir(current_dir)
+        .env("GIT_DIR", repo.path(""))
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .spawn()?;
+
+    if let Some(input) = stdin {
+        // dropping the pipe closes it, so the hook sees the end of its input
+        let mut pipe = child.stdin.take().expect("stdin is piped");
+        match pipe.write_all(input) {
+            // the hook exited without reading all of its input
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => {}
+            result => result?,
+        }
+    }
+    Ok(child.wait()?)
+}
+
+fn hooks_dir(repo: &Repository) -> PathBuf {
+    match repo.config().get_str("core", None, "hooksPath") {
+        Some(path) => match repo.worktree_root() {
+            Ok(root) => root.join(path),
+            Err(_) => repo.path("").join(path),
+        },
+        None => repo.path("hooks"),
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+// the hooks in the tests are shell scripts
+#[cfg(all(test, unix))]
+mod test {
+    use std::{ffi::OsStr, fs};
+
+    use super::run_hook;
+    use crate::test_utils::test_dir;
+    use crate::{CommitOptions, GitSyncError, Object, Repository, Signature};
+
+    fn write_hook(repo: &Repository, name: &str, script: &str) {
+        use std::os::unix::fs::PermissionsExt;
+        let path = repo.path("hooks").join(name);
+        fs::create_dir_all(repo.path("hooks")).unwrap();
+        fs::write(&path, format!("#!/bin/sh\n{script}\n")).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn run_hooks() {
+        let repo_path = test_dir("run_hooks");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+
+        // missing hooks succeed
+        assert!(run_hook(&repo, "pre-commit", &[], None).unwrap().success());
+
+        write_hook(
+            &repo,
+            "check",
+            r#"test "$1" = arg && test "$(cat)" = input"#,
+        );
+        let status = run_hook(&repo, "check", &[OsStr::new("arg")], Some(b"input")).unwrap();
+        assert!(status.success());
+        let status = run_hook(&repo, "check", &[OsStr::new("other")], Some(b"input")).unwrap();
+        assert!(!status.success());
+    }
+
+    #[test]
+    fn commit_hooks() {
+        let repo_path = test_dir("commit_hooks");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        fs::write(repo_path.root().join("foo.txt"), "hello\n").unwrap();
+        repo.add(&["foo.txt"]).unwrap();
+        let signature = Signature::parse("Test User <test@example.com> 1700000000 +0100").unwrap();
+
+        write_hook(&repo, "pre-commit", "exit 1");
+        let err = repo
+            .commit_with("first\n", &signature, &signature, &CommitOptions::default())
+            .unwrap_err();
+        assert!(matches!(err, GitSyncError::HookFailed { name, .. } if name == "pre-commit"));
+        assert!(repo.resolve_ref("HEAD").is_err());
+
+        write_hook(&repo, "pre-commit", "exit 0");
+        write_hook(&repo, "commit-msg", r#"echo "rewritten" > "$1""#);
+        let commit = repo
+            .commit_with("first\n", &signature, &signature, &CommitOptions::default())
+            .unwrap();
+        let Object::Commit { message, .. } = repo.load_object(&commit).unwrap() else {
+            panic!("expected a commit");
+        };
+        assert_eq!(message, b"rewritten\n");
+
+        // --no-verify skips the hooks
+        write_hook(&repo, "pre-commit", "exit 1");
+        let options = CommitOptions { no_verify: true };
+        let second = repo
+            .commit_with("second\n", &signature, &signature, &options)
+            .unwrap();
+        assert_eq!(repo.resolve_ref("HEAD").unwrap(), second);
+    }
+}
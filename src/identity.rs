@@ -0,0 +1,136 @@
+use std::time::SystemTime;
+
+use crate::{time, Config, GitSyncError, Result, Signature};
+
+/// who authored or committed something, see [Repository::author_identity]
+/// and [Repository::committer_identity]
+///
+/// [Repository::author_identity]: crate::Repository::author_identity
+/// [Repository::committer_identity]: crate::Repository::committer_identity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub name: String,
+    pub email: String,
+    /// the fixed date from `GIT_AUTHOR_DATE` or `GIT_COMMITTER_DATE` as
+    /// seconds since the unix epoch and timezone offset in minutes
+    pub date: Option<(i64, i32)>,
+}
+
+impl Identity {
+    /// a signature of this identity at its fixed date, or the current time
+    pub fn signature(&self) -> Signature {
+        let (time, tz_offset) = self.date.unwrap_or_else(|| {
+            // TODO use the local timezone
+            (now(), 0)
+        });
+        Signature {
+            name: self.name.clone(),
+            email: self.email.clone(),
+            time,
+            tz_offset,
+            raw_time: None,
+        }
+    }
+}
+
+/// resolves the identity for `role` (`AUTHOR` or `COMMITTER`) from the
+/// `GIT_<role>_NAME`, `GIT_<role>_EMAIL` and `GIT_<role>_DATE` variables
+/// returned by `var`, falling back to `user.name` and `user.email` in
+/// `config`
+pub(crate) fn resolve(
+    config: &Config,
+    role: &str,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<Identity> {
+    let lookup = |key: &str| {
+        var(&format!("GIT_{role}_{}", key.to_ascii_uppercase()))
+            .or_else(|| config.get_str("user", None, key).map(str::to_owned))
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| GitSyncError::MissingIdentity {
+                role: role.to_ascii_lowercase(),
+                key: key.to_owned(),
+            })
+    };
+    let name = lookup("name")?;
+    let email = lookup("email")?;
+
+    let date = match var(&format!("GIT_{role}_DATE")) {
+        Some(date) => Some(time::parse_human_date(&date)?),
+        None => None,
+    };
+    Ok(Identity { name, email, date })
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{resolve, Identity};
+    use crate::{Config, GitSyncError};
+
+    #[test]
+    fn resolve_identity() {
+        let config: Config = "[user]\n\tname = Config User\n\temail = config@example.com\n"
+            .parse()
+            .unwrap();
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |key: &str| {
+                vars.iter()
+                    .find(|(name, _)| *name == key)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+
+        // the config is the fallback
+        assert_eq!(
+            resolve(&config, "AUTHOR", env(&[])).unwrap(),
+            Identity {
+                name: "Config User".to_owned(),
+                email: "config@example.com".to_owned(),
+                date: None,
+            }
+        );
+
+        // the variables of the role win over the config
+        let vars = env(&[
+            ("GIT_AUTHOR_NAME", "Env Author"),
+            ("GIT_AUTHOR_DATE", "1700000000 -0830"),
+            ("GIT_COMMITTER_EMAIL", "committer@example.com"),
+        ]);
+        let author = resolve(&config, "AUTHOR", &vars).unwrap();
+        assert_eq!(author.name, "Env Author");
+        assert_eq!(author.email, "config@example.com");
+        assert_eq!(author.date, Some((1_700_000_000, -510)));
+        assert_eq!(
+            author.signature().to_string(),
+            "Env Author <config@example.com> 1700000000 -0830"
+        );
+        let committer = resolve(&config, "COMMITTER", &vars).unwrap();
+        assert_eq!(committer.name, "Config User");
+        assert_eq!(committer.email, "committer@example.com");
+        assert_eq!(committer.date, None);
+
+        let vars = env(&[("GIT_AUTHOR_DATE", "2023-11-14T22:13:20Z")]);
+        assert_eq!(
+            resolve(&config, "AUTHOR", vars).unwrap().date,
+            Some((1_700_000_000, 0))
+        );
+        let vars = env(&[("GIT_AUTHOR_DATE", "last tuesday")]);
+        assert!(matches!(
+            resolve(&config, "AUTHOR", vars),
+            Err(GitSyncError::InvalidDate(_))
+        ));
+
+        let empty = Config::default();
+        let vars = env(&[("GIT_COMMITTER_NAME", "Env Committer")]);
+        let err = resolve(&empty, "COMMITTER", vars).unwrap_err();
+        assert!(
+            matches!(&err, GitSyncError::MissingIdentity { role, key } if role == "committer" && key == "email")
+        );
+        assert!(err.to_string().contains("user.email"));
+    }
+}
@@ -0,0 +1,335 @@
+use std::{fs, io::ErrorKind, path::Path};
+
+use crate::{index::is_below, repository::os_str_bytes, RepoKind, Repository, Result};
+
+/// the patterns of `.gitignore` files and `.git/info/exclude`, which decide
+/// what untracked files are hidden
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    /// in order of increasing precedence
+    patterns: Vec<Pattern>,
+}
+
+/// a single line of an ignore file
+#[derive(Debug, Clone)]
+struct Pattern {
+    /// the directory of the ignore file the pattern is from, relative to the
+    /// worktree root. Empty for the root
+    base: Vec<u8>,
+    glob: Vec<u8>,
+    /// `!` patterns re-include paths
+    negated: bool,
+    /// patterns with a trailing `/` only match directories
+    dir_only: bool,
+    /// patterns containing a `/` are matched against the path relative to
+    /// `base`, all others against the last path component
+    anchored: bool,
+}
+
+impl IgnoreRules {
+    /// reads `.git/info/exclude` and every `.gitignore` in the worktree of
+    /// `repo`.
+    ///
+    /// `.gitignore` files in ignored directories are not read, like in git.
+    pub fn load(repo: &Repository) -> Result<Self> {
+        let mut rules = Self::default();
+        if let Some(exclude) = read_optional(&repo.gitdir_root().join("info/exclude"))? {
+            rules.add_patterns(b"", &exclude);
+        }
+        rules.load_dir(repo.worktree_root()?, &[])?;
+        Ok(rules)
+    }
+
+    /// reads the `.gitignore` of `dir`, whose path relative to the worktree
+    /// root is `name`, and of all directories below it
+    fn load_dir(&mut self, dir: &Path, name: &[u8]) -> Result<()> {
+        if let Some(gitignore) = read_optional(&dir.join(".gitignore"))? {
+            self.add_patterns(name, &gitignore);
+        }
+
+        let mut subdirs = Vec::new();
+        for dir_entry in fs::read_dir(dir)? {
+            let dir_entry = dir_entry?;
+            if RepoKind::is_dir_name(&dir_entry.file_name()) || !dir_entry.file_type()?.is_dir() {
+                continue;
+            }
+            subdirs.push((os_str_bytes(&dir_entry.file_name()), dir_entry.path()));
+        }
+        subdirs.sort();
+
+        for (file_name, path) in subdirs {
+            let child = join(name, &file_name);
+            if !self.matches(&child, true) {
+                self.load_dir(&path, &child)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// adds the patterns of an ignore file in the directory `base`, which is
+    /// relative to the worktree root. Later patterns take precedence.
+    pub fn add_patterns(&mut self, base: &[u8], content: &[u8]) {
+        for line in content.split(|&b| b == b'\n') {
+            if let Some(pattern) = Pattern::parse(base, line) {
+                self.patterns.push(pattern);
+            }
+        }
+    }
+
+    /// whether `path`, relative to the worktree root, is ignored.
+    ///
+    /// The last matching pattern decides, so `!` patterns can re-include
+    /// paths. Paths in ignored directories are always ignored.
+    pub fn matches(&self, path: &[u8], is_dir: bool) -> bool {
+        let mut parent_end = 0;
+        while let Some(slash) = path[parent_end..].iter().position(|&b| b == b'/') {
+            parent_end += slash;
+            if self.matches_self(&path[..parent_end], true) {
+                return true;
+            }
+            parent_end += 1;
+        }
+        self.matches_self(path, is_dir)
+    }
+
+    /// whether the last pattern that matches `path` ignores it, without
+    /// looking at its parents
+    fn matches_self(&self, path: &[u8], is_dir: bool) -> bool {
+        self.patterns
+            .iter()
+            .rev()
+            .find(|pattern| pattern.matches(path, is_dir))
+            .is_some_and(|pattern| !pattern.negated)
+    }
+}
+
+impl Pattern {
+    /// parses one line of an ignore file. Empty lines and `#` comments
+    /// contain no pattern
+    fn parse(base: &[u8], line: &[u8]) -> Option<Self> {
+        let mut line = line.strip_suffix(b"\r").unwrap_or(line);
+        // trailing spaces are ignored unless they are escaped
+        while line.ends_with(b" ") && !line.ends_with(b"\\ ") {
+            line = &line[..line.len() - 1];
+        }
+        if line.is_empty() || line.starts_with(b"#") {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix(b"!") {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix(b"/") {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let anchored = line.contains(&b'/');
+        let glob = line.strip_prefix(b"/").unwrap_or(line);
+        if glob.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            base: base.to_vec(),
+            glob: glob.to_vec(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &[u8], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let relative = if self.base.is_empty() {
+            path
+        } else if is_below(path, &self.base) {
+            &path[self.base.len() + 1..]
+        } else {
+            return false;
+        };
+
+        if self.anchored {
+            glob_match(&self.glob, relative)
+        } else {
+            let name_start = relative
+                .iter()
+                .rposition(|&b| b == b'/')
+                .map_or(0, |slash| slash + 1);
+            glob_match(&self.glob, &relative[name_start..])
+        }
+    }
+}
+
+/// matches `path` against a glob like git's wildmatch.
+///
+/// `*` and `?` don't match `/`, `**/` matches any number of directories and a
+/// trailing `/**` everything below a directory.
+fn glob_match(glob: &[u8], path: &[u8]) -> bool {
+    match glob {
+        [] => path.is_empty(),
+        [b'*', b'*'] => true,
+        [b'*', b'*', b'/', rest @ ..] => {
+            glob_match(rest, path)
+                || path
+                    .iter()
+                    .enumerate()
+                    .any(|(i, &b)| b == b'/' && glob_match(rest, &path[i + 1..]))
+        }
+        [b'*', rest @ ..] => {
+            let rest = if rest.starts_with(b"*") {
+                &rest[1..]
+            } else {
+                rest
+            };
+            for i in 0..=path.len() {
+                if glob_match(rest, &path[i..]) {
+                    return true;
+                }
+                if path.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        [b'?', rest @ ..] => {
+            matches!(path.first(), Some(&b) if b != b'/') && glob_match(rest, &path[1..])
+        }
+        [b'[', rest @ ..] => match (path.first(), match_class(rest, path.first())) {
+            (Some(_), Some((true, rest))) => glob_match(rest, &path[1..]),
+            (_, Some((false, _))) => false,
+            // an unclosed `[` is matched literally
+            (_, None) => path.first() == Some(&b'[') && glob_match(rest, &path[1..]),
+            (None, _) => false,
+        },
+        [b'\\', c, rest @ ..] => path.first() == Some(c) && glob_match(rest, &path[1..]),
+        [c, rest @ ..] => path.first() == Some(c) && glob_match(rest, &path[1..]),
+    }
+}
+
+/// matches `byte` against the class after a `[`, e.g. `a-z]` or `!0-9]`.
+/// Returns whether it matched and the rest of the glob after the class,
+/// `None` if the class is not closed.
+fn match_class<'g>(class: &'g [u8], byte: Option<&u8>) -> Option<(bool, &'g [u8])> {
+    let (negated, mut class) = match class {
+        [b'!' | b'^', rest @ ..] => (true, rest),
+        _ => (false, class),
+    };
+    let byte = byte.copied().filter(|&b| b != b'/');
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        match class {
+            [] => return None,
+            // a `]` at the start is part of the class
+            [b']', rest @ ..] if !first => {
+                return Some((byte.is_some() && matched != negated, rest))
+            }
+            [start, b'-', end, rest @ ..] if *end != b']' => {
+                matched |= byte.is_some_and(|b| (*start..=*end).contains(&b));
+                class = rest;
+            }
+            [c, rest @ ..] => {
+                matched |= byte == Some(*c);
+                class = rest;
+            }
+        }
+        first = false;
+    }
+}
+
+fn join(name: &[u8], file_name: &[u8]) -> Vec<u8> {
+    if name.is_empty() {
+        file_name.to_vec()
+    } else {
+        [name, b"/", file_name].concat()
+    }
+}
+
+/// the content of the file at `path`, `None` if it doesn't exist
+fn read_optional(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use test_dir::DirBuilder;
+
+    use super::{glob_match, IgnoreRules};
+    use crate::test_utils::test_dir;
+    use crate::Repository;
+
+    #[test]
+    fn match_globs() {
+        assert!(glob_match(b"*.log", b"debug.log"));
+        assert!(!glob_match(b"*.log", b"dir/debug.log"));
+        assert!(glob_match(b"debug?.txt", b"debug1.txt"));
+        assert!(!glob_match(b"debug?.txt", b"debug/.txt"));
+        assert!(glob_match(b"**/tmp", b"tmp"));
+        assert!(glob_match(b"**/tmp", b"a/b/tmp"));
+        assert!(glob_match(b"a/**/b", b"a/b"));
+        assert!(glob_match(b"a/**/b", b"a/x/y/b"));
+        assert!(glob_match(b"a/**", b"a/x/y"));
+        assert!(glob_match(b"[a-c]at", b"bat"));
+        assert!(!glob_match(b"[!a-c]at", b"bat"));
+        assert!(glob_match(b"\\*", b"*"));
+        assert!(!glob_match(b"\\*", b"a"));
+    }
+
+    #[test]
+    fn ignore_patterns() {
+        let mut rules = IgnoreRules::default();
+        rules.add_patterns(
+            b"",
+            b"# logs\n*.log\n!keep.log\nbuild/\n**/tmp\n/root-only\n",
+        );
+
+        assert!(rules.matches(b"debug.log", false));
+        assert!(rules.matches(b"dir/debug.log", false));
+        assert!(!rules.matches(b"keep.log", false));
+        assert!(!rules.matches(b"dir/keep.log", false));
+        assert!(rules.matches(b"build", true));
+        assert!(!rules.matches(b"build", false));
+        assert!(rules.matches(b"src/build/out.o", false));
+        assert!(rules.matches(b"tmp", true));
+        assert!(rules.matches(b"a/b/tmp/file", false));
+        assert!(rules.matches(b"root-only", false));
+        assert!(!rules.matches(b"dir/root-only", false));
+        assert!(!rules.matches(b"main.rs", false));
+
+        // files in ignored directories can't be re-included
+        rules.add_patterns(b"", b"!build/keep.log\n");
+        assert!(rules.matches(b"build/keep.log", false));
+    }
+
+    #[test]
+    fn nested_gitignore_overrides_parent() {
+        let repo_path = test_dir("nested_gitignore_overrides_parent");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+
+        fs::write(root.join(".gitignore"), "*.log\nignored/\n").unwrap();
+        fs::create_dir_all(root.join("logs")).unwrap();
+        fs::write(root.join("logs/.gitignore"), "!*.log\n").unwrap();
+        fs::create_dir_all(root.join("ignored")).unwrap();
+        fs::write(root.join("ignored/.gitignore"), "!*\n").unwrap();
+        fs::create_dir_all(repo.path("info")).unwrap();
+        fs::write(repo.path("info/exclude"), "*.secret\n").unwrap();
+
+        let rules = IgnoreRules::load(&repo).unwrap();
+        assert!(rules.matches(b"debug.log", false));
+        assert!(!rules.matches(b"logs/debug.log", false));
+        assert!(rules.matches(b"other/debug.log", false));
+        assert!(rules.matches(b"ignored/file", false));
+        assert!(rules.matches(b"logs/key.secret", false));
+    }
+}
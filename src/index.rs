@@ -0,0 +1,650 @@
+use std::{
+    fmt::{self, Display},
+    fs::{self, Metadata},
+    io::Write,
+    ops::Range,
+    path::Path,
+};
+
+use crate::{quote_path, to_hex, GitSyncError, HashAlgorithm, Result};
+
+/// the staging area, stored in `.git/index`.
+///
+/// Only versions 2 and 3 of the format are supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Index {
+    pub version: u32,
+    /// entries sorted by path and stage
+    pub entries: Vec<IndexEntry>,
+    /// extensions like the cached tree, kept as they were read
+    pub extensions: Vec<IndexExtension>,
+}
+
+/// a file tracked by the [Index]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub ctime: IndexTime,
+    pub mtime: IndexTime,
+    pub dev: u32,
+    pub ino: u32,
+    /// the file mode, e.g. `0o100644`
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// the file size, truncated to 32 bits
+    pub size: u32,
+    /// the binary sha1 of the blob
+    pub sha: Vec<u8>,
+    /// the flags as stored in the index, including the name length
+    pub flags: u16,
+    /// the additional flags of version 3 entries
+    pub extended_flags: Option<u16>,
+    /// the path relative to the worktree root, separated by `/`
+    pub path: Vec<u8>,
+}
+
+/// a time stamp as stored in the index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IndexTime {
+    pub secs: u32,
+    pub nanos: u32,
+}
+
+/// an optional section after the entries, e.g. `TREE`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexExtension {
+    pub signature: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+
+impl Default for Index {
+    fn default() -> Self {
+        Self {
+            version: 2,
+            entries: Vec::new(),
+            extensions: Vec::new(),
+        }
+    }
+}
+
+impl Index {
+    /// reads the index file at `path` of a sha1 repository
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        Self::read_with(path, HashAlgorithm::Sha1)
+    }
+
+    /// reads the index file at `path`, which uses `hash` for object names
+    /// and its checksum
+    pub fn read_with(path: impl AsRef<Path>, hash: HashAlgorithm) -> Result<Self> {
+        Self::parse(&fs::read(path)?, hash)
+    }
+
+    /// parses the content of an index file
+    pub fn parse(data: &[u8], hash: HashAlgorithm) -> Result<Self> {
+        if data.len() < 12 + hash.raw_len() {
+            return Err(invalid("file is too short"));
+        }
+        let (content, checksum) = data.split_at(data.len() - hash.raw_len());
+        let mut hasher = hash.hasher();
+        hasher.write_all(content)?;
+        if hasher.finalize() != checksum {
+            return Err(invalid("checksum does not match"));
+        }
+
+        let mut reader = Reader { data: content };
+        if reader.take(4)? != SIGNATURE {
+            return Err(invalid("missing DIRC signature"));
+        }
+        let version = reader.u32()?;
+        if !matches!(version, 2 | 3) {
+            return Err(invalid(&format!("unsupported version {version}")));
+        }
+        let count = reader.u32()?;
+
+        let entries = (0..count)
+            .map(|_| reader.entry(version, hash))
+            .collect::<Result<_>>()?;
+
+        let mut extensions = Vec::new();
+        while !reader.data.is_empty() {
+            let signature = reader.take(4)?.try_into().unwrap();
+            let len = reader.u32()? as usize;
+            let data = reader.take(len)?.to_vec();
+            extensions.push(IndexExtension { signature, data });
+        }
+
+        Ok(Self {
+            version,
+            entries,
+            extensions,
+        })
+    }
+
+    /// writes the index file of a sha1 repository to `path`
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.write_with(path, HashAlgorithm::Sha1)
+    }
+
+    /// writes the index file to `path`, using `hash` for the checksum
+    pub fn write_with(&self, path: impl AsRef<Path>, hash: HashAlgorithm) -> Result<()> {
+        fs::write(path, self.serialize(hash))?;
+        Ok(())
+    }
+
+    /// serializes the index in the format git uses on disk.
+    ///
+    /// Entries are written sorted by path and stage. Version 3 is used if any
+    /// entry has extended flags.
+    pub fn serialize(&self, hash: HashAlgorithm) -> Vec<u8> {
+        let mut entries: Vec<&IndexEntry> = self.entries.iter().collect();
+        entries.sort_by(|a, b| (&a.path, a.stage()).cmp(&(&b.path, b.stage())));
+
+        let extended = entries.iter().any(|entry| entry.extended_flags.is_some());
+        let version = if extended {
+            self.version.max(3)
+        } else {
+            self.version
+        };
+
+        let mut data = Vec::new();
+        data.extend_from_slice(SIGNATURE);
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+
+        for entry in entries {
+            entry.serialize(&mut data);
+        }
+
+        for extension in &self.extensions {
+            data.extend_from_slice(&extension.signature);
+            data.extend_from_slice(&(extension.data.len() as u32).to_be_bytes());
+            data.extend_from_slice(&extension.data);
+        }
+
+        let mut hasher = hash.hasher();
+        hasher.write_all(&data).unwrap();
+        data.extend_from_slice(&hasher.finalize());
+        data
+    }
+
+    /// the entry for `path` at stage 0
+    pub fn entry(&self, path: &[u8]) -> Option<&IndexEntry> {
+        self.entries[self.path_range(path)]
+            .first()
+            .filter(|entry| entry.stage() == 0)
+    }
+
+    /// adds or replaces the entry for its path.
+    ///
+    /// Conflicting stages of the path are resolved and entries that would
+    /// collide with it, like a file where `entry` needs a directory, are
+    /// removed.
+    pub fn add(&mut self, entry: IndexEntry) {
+        let path = &entry.path;
+        for (end, _) in path.iter().enumerate().filter(|(_, &b)| b == b'/') {
+            let parent = self.path_range(&path[..end]);
+            self.entries.drain(parent);
+        }
+        let below = self.dir_range(path);
+        self.entries.drain(below);
+        let stages = self.path_range(path);
+        self.entries.drain(stages.clone());
+        self.entries.insert(stages.start, entry);
+        self.invalidate_cached_tree();
+    }
+
+    /// removes all entries for `path` or inside the directory `path`.
+    ///
+    /// Returns whether any entry was removed.
+    pub fn remove(&mut self, path: &[u8]) -> bool {
+        let len = self.entries.len();
+        let below = self.dir_range(path);
+        self.entries.drain(below);
+        let stages = self.path_range(path);
+        self.entries.drain(stages);
+        let removed = self.entries.len() != len;
+        if removed {
+            self.invalidate_cached_tree();
+        }
+        removed
+    }
+
+    /// whether there is an entry for `path` at any stage
+    pub(crate) fn contains(&self, path: &[u8]) -> bool {
+        !self.path_range(path).is_empty()
+    }
+
+    /// whether there are entries inside the directory `dir`
+    pub(crate) fn contains_below(&self, dir: &[u8]) -> bool {
+        !self.dir_range(dir).is_empty()
+    }
+
+    /// the entries for `path` at all stages. The entries are sorted by path
+    /// and stage, so they are found by binary search
+    fn path_range(&self, path: &[u8]) -> Range<usize> {
+        let start = self
+            .entries
+            .partition_point(|entry| entry.path.as_slice() < path);
+        let len = self.entries[start..].partition_point(|entry| entry.path == path);
+        start..start + len
+    }
+
+    /// the entries inside the directory `dir`, which all start with `dir/`
+    /// and so follow each other
+    fn dir_range(&self, dir: &[u8]) -> Range<usize> {
+        let prefix = [dir, b"/"].concat();
+        let start = self.entries.partition_point(|entry| entry.path < prefix);
+        let len = self.entries[start..].partition_point(|entry| entry.path.starts_with(&prefix));
+        start..start + len
+    }
+
+    /// drops the `TREE` extension, which is outdated once entries change
+    fn invalidate_cached_tree(&mut self) {
+        self.extensions
+            .retain(|extension| &extension.signature != b"TREE");
+    }
+}
+
+impl IndexEntry {
+    /// creates an entry without stat data, which is never assumed to match
+    /// the file in the worktree
+    pub fn new(path: Vec<u8>, sha: Vec<u8>, mode: u32) -> Self {
+        let flags = path.len().min(FLAG_NAME_MASK as usize) as u16;
+        Self {
+            ctime: IndexTime::default(),
+            mtime: IndexTime::default(),
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            sha,
+            flags,
+            extended_flags: None,
+            path,
+        }
+    }
+
+    /// creates an entry for the file `path` using the stat data of `metadata`
+    pub fn from_metadata(path: Vec<u8>, sha: Vec<u8>, mode: u32, metadata: &Metadata) -> Self {
+        let stat = Stat::from(metadata);
+        let flags = path.len().min(FLAG_NAME_MASK as usize) as u16;
+        Self {
+            ctime: stat.ctime,
+            mtime: stat.mtime,
+            dev: stat.dev,
+            ino: stat.ino,
+            mode,
+            uid: stat.uid,
+            gid: stat.gid,
+            size: stat.size,
+            sha,
+            flags,
+            extended_flags: None,
+            path,
+        }
+    }
+
+    /// whether the stat data of the entry matches `metadata`, in which case
+    /// the file is assumed to be unchanged
+    pub fn matches_metadata(&self, metadata: &Metadata) -> bool {
+        let stat = Stat::from(metadata);
+        self.ctime == stat.ctime
+            && self.mtime == stat.mtime
+            && self.dev == stat.dev
+            && self.ino == stat.ino
+            && self.uid == stat.uid
+            && self.gid == stat.gid
+            && self.size == stat.size
+    }
+
+    /// the merge stage, 0 unless the entry is conflicted
+    pub fn stage(&self) -> u16 {
+        (self.flags & FLAG_STAGE_MASK) >> 12
+    }
+
+    /// marks the entry as one side of a conflict, 1 for the merge base, 2
+    /// for ours and 3 for theirs
+    pub fn set_stage(&mut self, stage: u16) {
+        self.flags = (self.flags & !FLAG_STAGE_MASK) | ((stage << 12) & FLAG_STAGE_MASK);
+    }
+
+    pub fn sha1(&self) -> String {
+        to_hex(&self.sha)
+    }
+
+    /// the mode formatted like in a tree, e.g. `100644`
+    pub fn mode_str(&self) -> String {
+        format!("{:o}", self.mode)
+    }
+
+    fn serialize(&self, data: &mut Vec<u8>) {
+        let start = data.len();
+
+        for value in [
+            self.ctime.secs,
+            self.ctime.nanos,
+            self.mtime.secs,
+            self.mtime.nanos,
+            self.dev,
+            self.ino,
+            self.mode,
+            self.uid,
+            self.gid,
+            self.size,
+        ] {
+            data.extend_from_slice(&value.to_be_bytes());
+        }
+        data.extend_from_slice(&self.sha);
+
+        // the lower 12 bits store the path length, capped for long paths
+        let name_len = self.path.len().min(FLAG_NAME_MASK as usize) as u16;
+        let mut flags = (self.flags & !(FLAG_NAME_MASK | FLAG_EXTENDED)) | name_len;
+        if self.extended_flags.is_some() {
+            flags |= FLAG_EXTENDED;
+        }
+        data.extend_from_slice(&flags.to_be_bytes());
+        if let Some(extended_flags) = self.extended_flags {
+            data.extend_from_slice(&extended_flags.to_be_bytes());
+        }
+
+        data.extend_from_slice(&self.path);
+        let padding = 8 - (data.len() - start) % 8;
+        data.resize(data.len() + padding, 0);
+    }
+}
+
+impl Display for IndexEntry {
+    /// formats the entry like `git ls-files -s`, e.g.
+    /// `100644 <sha1> 0\tdir/file.txt`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}\t{}",
+            self.mode_str(),
+            self.sha1(),
+            self.stage(),
+            quote_path(&self.path)
+        )
+    }
+}
+
+/// the stat data stored in an entry, truncated to 32 bits like git does
+struct Stat {
+    ctime: IndexTime,
+    mtime: IndexTime,
+    dev: u32,
+    ino: u32,
+    uid: u32,
+    gid: u32,
+    size: u32,
+}
+
+impl From<&Metadata> for Stat {
+    #[cfg(unix)]
+    fn from(metadata: &Metadata) -> Self {
+        use std::os::unix::fs::MetadataExt;
+        Self {
+            ctime: IndexTime {
+                secs: metadata.ctime() as u32,
+                nanos: metadata.ctime_nsec() as u32,
+            },
+            mtime: IndexTime {
+                secs: metadata.mtime() as u32,
+                nanos: metadata.mtime_nsec() as u32,
+            },
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.size() as u32,
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn from(metadata: &Metadata) -> Self {
+        let time = |time: std::io::Result<std::time::SystemTime>| {
+            time.ok()
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or_else(IndexTime::default, |time| IndexTime {
+                    secs: time.as_secs() as u32,
+                    nanos: time.subsec_nanos(),
+                })
+        };
+        Self {
+            ctime: time(metadata.created()),
+            mtime: time(metadata.modified()),
+            dev: 0,
+            ino: 0,
+            uid: 0,
+            gid: 0,
+            size: metadata.len() as u32,
+        }
+    }
+}
+
+/// whether `path` is inside the directory `dir`
+pub(crate) fn is_below(path: &[u8], dir: &[u8]) -> bool {
+    path.len() > dir.len() && path.starts_with(dir) && path[dir.len()] == b'/'
+}
+
+fn invalid(reason: &str) -> GitSyncError {
+    GitSyncError::InvalidIndex(reason.to_owned())
+}
+
+struct Reader<'d> {
+    data: &'d [u8],
+}
+
+impl<'d> Reader<'d> {
+    fn take(&mut self, len: usize) -> Result<&'d [u8]> {
+        if self.data.len() < len {
+            return Err(invalid("unexpected end of file"));
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn time(&mut self) -> Result<IndexTime> {
+        Ok(IndexTime {
+            secs: self.u32()?,
+            nanos: self.u32()?,
+        })
+    }
+
+    fn entry(&mut self, version: u32, hash: HashAlgorithm) -> Result<IndexEntry> {
+        let start = self.data.len();
+
+        let ctime = self.time()?;
+        let mtime = self.time()?;
+        let dev = self.u32()?;
+        let ino = self.u32()?;
+        let mode = self.u32()?;
+        let uid = self.u32()?;
+        let gid = self.u32()?;
+        let size = self.u32()?;
+        let sha = self.take(hash.raw_len())?.to_vec();
+        let flags = self.u16()?;
+        let extended_flags = if flags & FLAG_EXTENDED != 0 {
+            if version < 3 {
+                return Err(invalid("extended flags require version 3"));
+            }
+            Some(self.u16()?)
+        } else {
+            None
+        };
+
+        let path_len = self
+            .data
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| invalid("entry path is not terminated"))?;
+        let path = self.take(path_len)?.to_vec();
+
+        // entries are padded with 1 to 8 NUL bytes to a multiple of 8
+        let len = start - self.data.len();
+        let padding = 8 - len % 8;
+        if self.take(padding)?.iter().any(|&b| b != 0) {
+            return Err(invalid("entry padding is not NUL"));
+        }
+
+        Ok(IndexEntry {
+            ctime,
+            mtime,
+            dev,
+            ino,
+            mode,
+            uid,
+            gid,
+            size,
+            sha,
+            flags,
+            extended_flags,
+            path,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_dir::DirBuilder;
+
+    use crate::test_utils::existing_test_repo;
+    use crate::{GitSyncError, HashAlgorithm, Index, IndexEntry, Repository};
+
+    #[test]
+    fn read_fixture_index() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let index = repo.read_index().unwrap();
+        assert_eq!(index.version, 2);
+
+        let entries: Vec<(&[u8], String)> = index
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_slice(), entry.sha1()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (
+                    &b"a.txt"[..],
+                    "06fcdd77c9348567c50638b30d406500f521c304".to_owned()
+                ),
+                (
+                    b"b.txt",
+                    "61780798228d17af2d34fce4cfbdf35556832472".to_owned()
+                ),
+                (
+                    b"dir/c.txt",
+                    "79c53955ef856f16f2107446bc721c8879a1bd2e".to_owned()
+                ),
+            ]
+        );
+
+        let a = index.entry(b"a.txt").unwrap();
+        assert_eq!(a.mode_str(), "100644");
+        assert_eq!(a.size, 23);
+        assert_eq!(a.stage(), 0);
+
+        // git writes the cached tree extension
+        assert_eq!(index.extensions.len(), 1);
+        assert_eq!(&index.extensions[0].signature, b"TREE");
+    }
+
+    #[test]
+    fn write_is_byte_identical() {
+        let test_dir = existing_test_repo("linear_history");
+        let path = test_dir.root().join(".git/index");
+        let original = std::fs::read(&path).unwrap();
+
+        let index = Index::read(&path).unwrap();
+        assert_eq!(index.serialize(HashAlgorithm::Sha1), original);
+
+        // entries are sorted when written
+        let mut reversed = index.clone();
+        reversed.entries.reverse();
+        let copy = test_dir.root().join("index.copy");
+        reversed.write(&copy).unwrap();
+        assert_eq!(std::fs::read(&copy).unwrap(), original);
+    }
+
+    #[test]
+    fn add_replaces_colliding_entries() {
+        let test_dir = existing_test_repo("linear_history");
+        let mut index = Index::read(&test_dir.root().join(".git/index")).unwrap();
+        let paths = |index: &Index| -> Vec<(Vec<u8>, u16)> {
+            index
+                .entries
+                .iter()
+                .map(|entry| (entry.path.clone(), entry.stage()))
+                .collect()
+        };
+
+        for stage in [3, 1, 2] {
+            let mut entry = IndexEntry::new(b"b.txt".to_vec(), vec![0; 20], 0o100644);
+            entry.set_stage(stage);
+            index
+                .entries
+                .retain(|other| other.path != b"b.txt" || other.stage() != 0);
+            let pos = index
+                .entries
+                .partition_point(|other| (&other.path, other.stage()) < (&entry.path, stage));
+            index.entries.insert(pos, entry);
+        }
+        assert!(index.entry(b"b.txt").is_none());
+        assert!(index.contains(b"b.txt"));
+
+        index.add(IndexEntry::new(b"b.txt".to_vec(), vec![0; 20], 0o100644));
+        index.add(IndexEntry::new(b"a.txt/x".to_vec(), vec![0; 20], 0o100644));
+        index.add(IndexEntry::new(b"dir".to_vec(), vec![0; 20], 0o100644));
+        assert_eq!(
+            paths(&index),
+            [
+                (b"a.txt/x".to_vec(), 0),
+                (b"b.txt".to_vec(), 0),
+                (b"dir".to_vec(), 0),
+            ]
+        );
+        assert!(index.contains_below(b"a.txt"));
+        assert!(!index.contains_below(b"dir"));
+
+        assert!(index.remove(b"a.txt"));
+        assert!(!index.remove(b"a.txt"));
+        assert_eq!(
+            paths(&index),
+            [(b"b.txt".to_vec(), 0), (b"dir".to_vec(), 0)]
+        );
+    }
+
+    #[test]
+    fn reject_bad_checksum() {
+        let test_dir = existing_test_repo("linear_history");
+        let path = test_dir.root().join(".git/index");
+
+        let mut data = std::fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+
+        assert!(matches!(
+            Index::parse(&data, HashAlgorithm::Sha1),
+            Err(GitSyncError::InvalidIndex(_))
+        ));
+    }
+}
@@ -12,12 +12,59 @@
 
 // #![deny(missing_docs)]
 // TODO reenable missing docs as error
-// TODO change out anyhow for thiserror
 
 #[cfg(test)]
 pub mod test_utils;
 
+mod blame;
+pub use blame::BlameLine;
+mod cache;
+mod config;
+pub use config::Config;
+mod daemon;
+pub use daemon::{AutoCommit, Daemon, Shutdown};
+mod delta;
+mod diff;
+pub use diff::TreeChange;
+mod error;
+pub use error::{GitSyncError, Result};
+mod gpg;
+pub use gpg::SignatureStatus;
+mod hash;
+pub use hash::HashAlgorithm;
+mod hooks;
+pub use hooks::run_hook;
+mod identity;
+pub use identity::Identity;
+mod ignore;
+pub use ignore::IgnoreRules;
+mod index;
+pub use index::{Index, IndexEntry, IndexExtension, IndexTime};
+mod merge;
+pub use merge::MergeOutcome;
 mod object;
-pub use object::{Object, ObjectType};
+pub use object::{from_hex, quote_path, to_hex, Object, ObjectType, Signature, TreeEntry};
+mod pack;
+pub use pack::{
+    write_delta_pack, write_pack, DeltaOptions, Pack, PackEntries, PackEntry, PackEntryKind,
+    PackObjectType, PackVerifyError, VerifiedEntry,
+};
+mod pack_index;
+pub use pack_index::{PackIndex, PackIndexEntry};
+mod packed_refs;
+pub use packed_refs::{PackedRef, PackedRefs};
+mod reflog;
+pub use reflog::ReflogEntry;
 mod repository;
-pub use repository::Repository;
+pub use repository::{
+    CommitOptions, DescribeOptions, DiscoverOptions, Discovered, FsckError, InitOptions, RepoKind,
+    Repository, ResetMode,
+};
+mod status;
+pub use status::{Change, StatusReport};
+mod time;
+pub use time::{format_git_time, parse_git_time, parse_human_date};
+mod transport;
+pub use transport::{FetchedRef, HttpTransport, LocalTransport, RemoteRef, Transport};
+mod walk;
+pub use walk::{CommitWalk, WorktreeWalk};
@@ -1,12 +1,18 @@
 use std::{
-    fs::{File, OpenOptions},
-    io::Read,
+    collections::BTreeMap,
+    fs::File,
+    io::{self, Read, Write},
     path::PathBuf,
+    time::Duration,
 };
 
 use clap::{Args, Parser, Subcommand};
 
-use gitsync::{Object, ObjectType, Repository};
+use gitsync::{
+    quote_path, Change, CommitOptions, Daemon, DescribeOptions, GitSyncError, InitOptions,
+    MergeOutcome, Object, ObjectType, Pack, PackIndex, RepoKind, Repository, ResetMode, Signature,
+    SignatureStatus,
+};
 
 // TODO error handling
 
@@ -29,33 +35,579 @@ enum Command {
 
     /// Hashes the given object and prints the sha1-hash
     HashObject(HashObjectArgs),
+
+    /// Counts the loose objects and their size on disk
+    CountObjects,
+
+    /// Verifies the integrity of all loose objects
+    Fsck(FsckArgs),
+
+    /// Shows the commit history
+    Log(LogArgs),
+
+    /// Lists the entries of a tree
+    LsTree(LsTreeArgs),
+
+    /// Lists the files in the index
+    LsFiles(LsFilesArgs),
+
+    /// Replaces the index with the files of a tree
+    ReadTree(ReadTreeArgs),
+
+    /// Stores a directory as a tree object and prints its sha1-hash
+    WriteTree(WriteTreeArgs),
+
+    /// Creates a commit of a tree and prints its sha1-hash
+    CommitTree(CommitTreeArgs),
+
+    /// Stages files in the index
+    Add(AddArgs),
+
+    /// Shows the staged, unstaged and untracked changes
+    Status,
+
+    /// Commits the staged changes
+    Commit(CommitArgs),
+
+    /// Points a ref to an object
+    UpdateRef(UpdateRefArgs),
+
+    /// Prints or changes the ref a symbolic ref like HEAD points to
+    SymbolicRef(SymbolicRefArgs),
+
+    /// Lists all refs and the objects they point to
+    ShowRef(ShowRefArgs),
+
+    /// Prints the sha1-hash a revision like `HEAD~2` resolves to
+    RevParse(RevParseArgs),
+
+    /// Checks the objects and checksums of a pack against its index
+    VerifyPack(VerifyPackArgs),
+
+    /// Shows the changes between two blobs or the changed files between two
+    /// trees or commits
+    Diff(DiffArgs),
+
+    /// Lists, creates or deletes branches
+    Branch(BranchArgs),
+
+    /// Lists, creates or deletes tags
+    Tag(TagArgs),
+
+    /// Checks out a branch or commit, updating HEAD, the index and the
+    /// worktree
+    #[command(alias = "checkout")]
+    Switch(SwitchArgs),
+
+    /// Commits all changes in the worktree to the `.gitsync` repository
+    /// every interval
+    Daemon(DaemonArgs),
+
+    /// Clones a remote repository into a new directory
+    Clone(CloneArgs),
+
+    /// Updates a branch of a remote repository with the local branch
+    Push(PushArgs),
+
+    /// Downloads the branches of a remote into its remote-tracking branches
+    Fetch(FetchArgs),
+
+    /// Merges a commit into HEAD, fast-forwarding if possible
+    Merge(MergeArgs),
+
+    /// Applies the changes of a commit on top of HEAD
+    CherryPick(CherryPickArgs),
+
+    /// Undoes the changes of a commit in a new commit on top of HEAD
+    Revert(RevertArgs),
+
+    /// Moves the current branch to a commit and resets the index and
+    /// worktree
+    Reset(ResetArgs),
+
+    /// Deletes loose objects that are not reachable from any ref
+    Prune(PruneArgs),
+
+    /// Moves the loose objects into a new pack
+    Repack(RepackArgs),
+
+    /// Prints the best common ancestor of two commits
+    MergeBase(MergeBaseArgs),
+
+    /// Shows the commit that last changed each line of a file
+    Blame(BlameArgs),
+
+    /// Lists the commits reachable from the given commits, newest first
+    RevList(RevListArgs),
+
+    /// Shows the previous values of a ref, newest first
+    Reflog(ReflogArgs),
+
+    /// Names a commit after the nearest tag it is reachable from
+    Describe(DescribeArgs),
+
+    /// Checks the gpg signature of a commit
+    VerifyCommit(VerifyArgs),
+
+    /// Checks the gpg signature of a tag
+    VerifyTag(VerifyArgs),
 }
 
 #[derive(Debug, Args)]
 struct InitArgs {
     path: PathBuf,
+
+    /// Name of the branch HEAD points to
+    #[arg(short = 'b', long, default_value = "main")]
+    initial_branch: String,
+
+    /// Creates a repository without a worktree
+    #[arg(long)]
+    bare: bool,
+
+    /// Creates the autocommit repository in `.gitsync` instead of `.git`
+    #[arg(long, conflicts_with = "bare")]
+    gitsync: bool,
 }
 
 #[derive(Debug, Args)]
 struct CatFileArgs {
-    #[arg(value_enum, name = "type")]
-    typ: ObjectType,
+    #[arg(
+        value_enum,
+        name = "type",
+        required_unless_present_any([
+            "show_type",
+            "size",
+            "pretty",
+            "stdin",
+            "batch",
+            "batch_check"
+        ])
+    )]
+    typ: Option<ObjectType>,
+
+    #[arg(required_unless_present_any([
+        "show_type",
+        "size",
+        "pretty",
+        "stdin",
+        "batch",
+        "batch_check"
+    ]))]
+    object: Option<String>,
+
+    /// Prints the type of the object instead of its content
+    #[arg(short = 't', value_name = "object", conflicts_with_all(["type", "object"]))]
+    show_type: Option<String>,
+
+    /// Prints the size of the object instead of its content
+    #[arg(
+        short = 's',
+        long,
+        value_name = "object",
+        conflicts_with_all(["type", "object", "show_type"])
+    )]
+    size: Option<String>,
+
+    /// Prints the object in a readable form depending on its type: trees
+    /// are listed like `ls-tree`, all other objects are printed as stored
+    #[arg(
+        short = 'p',
+        value_name = "object",
+        conflicts_with_all(["type", "object", "show_type", "size"])
+    )]
+    pretty: Option<String>,
+
+    /// Decodes a zlib compressed object file read from stdin, without a
+    /// repository
+    #[arg(
+        long,
+        conflicts_with_all(["type", "object", "show_type", "size", "pretty"])
+    )]
+    stdin: bool,
+
+    /// Reads object names from stdin, one per line, and prints the sha1-hash,
+    /// type, size and content of each
+    #[arg(
+        long,
+        conflicts_with_all(["type", "object", "show_type", "size", "pretty", "stdin"])
+    )]
+    batch: bool,
+
+    /// Like `--batch`, but only prints the sha1-hash, type and size
+    #[arg(
+        long,
+        conflicts_with_all([
+            "type",
+            "object",
+            "show_type",
+            "size",
+            "pretty",
+            "stdin",
+            "batch"
+        ])
+    )]
+    batch_check: bool,
+}
+
+#[derive(Debug, Args)]
+struct FsckArgs {
+    /// Also lists the loose and packed objects that are not reachable from
+    /// any ref
+    #[arg(long)]
+    unreachable: bool,
+}
+
+#[derive(Debug, Args)]
+struct LogArgs {
+    /// Commit to start at
+    #[arg(default_value = "HEAD")]
+    start: String,
+
+    /// Only follows the first parent of merge commits
+    #[arg(long)]
+    first_parent: bool,
+}
+
+#[derive(Debug, Args)]
+struct LsTreeArgs {
+    /// Tree, commit or tag whose tree is listed
+    tree: String,
+
+    /// Recurses into subtrees
+    #[arg(short = 'r')]
+    recursive: bool,
+}
+
+#[derive(Debug, Args)]
+struct LsFilesArgs {
+    /// Shows the mode, sha1-hash and stage of each entry
+    #[arg(short = 's', long)]
+    stage: bool,
+
+    /// Only lists the files that are missing in the worktree
+    #[arg(short = 'd', long)]
+    deleted: bool,
+}
+
+#[derive(Debug, Args)]
+struct ReadTreeArgs {
+    /// Tree, commit or tag whose tree is read
+    tree: String,
+}
+
+#[derive(Debug, Args)]
+struct WriteTreeArgs {
+    /// Directory to store, defaults to the root of the worktree
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct CommitTreeArgs {
+    tree: String,
+
+    /// Parent commits, can be given multiple times
+    #[arg(short = 'p', value_name = "parent")]
+    parents: Vec<String>,
+
+    /// Commit message, read from stdin if not given
+    #[arg(short = 'm')]
+    message: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct AddArgs {
+    /// Files or directories to stage
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct CommitArgs {
+    /// Commit message
+    #[arg(short = 'm')]
+    message: String,
+
+    /// Skips the pre-commit and commit-msg hooks
+    #[arg(short = 'n', long)]
+    no_verify: bool,
+}
+
+#[derive(Debug, Args)]
+struct UpdateRefArgs {
+    /// Full name of the ref, e.g. `refs/heads/main`
+    name: String,
+
+    new_value: String,
+
+    /// Only updates the ref if it still points to this object
+    old_value: Option<String>,
+
+    /// The reason for the update recorded in the reflog
+    #[arg(short = 'm')]
+    message: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct SymbolicRefArgs {
+    /// Name of the symbolic ref, e.g. `HEAD`
+    name: String,
+
+    /// The ref to point to, e.g. `refs/heads/main`. Prints the current
+    /// target if not given
+    target: Option<String>,
+}
+
+#[derive(Debug, Args)]
+struct ShowRefArgs {
+    /// Only shows branches
+    #[arg(long)]
+    heads: bool,
+
+    /// Only shows tags
+    #[arg(long)]
+    tags: bool,
+}
+
+#[derive(Debug, Args)]
+struct RevParseArgs {
+    revision: String,
+}
+
+#[derive(Debug, Args)]
+struct VerifyPackArgs {
+    /// the `.pack` or `.idx` file
+    pack: PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct DiffArgs {
+    a: String,
+    b: String,
+}
+
+#[derive(Debug, Args)]
+struct BranchArgs {
+    /// Branch to create or delete, lists all branches if not given
+    name: Option<String>,
+
+    /// Commit the new branch points to
+    #[arg(default_value = "HEAD", conflicts_with = "delete")]
+    start: String,
+
+    /// Deletes the branch
+    #[arg(short = 'd', long, requires = "name")]
+    delete: bool,
+}
+
+#[derive(Debug, Args)]
+struct TagArgs {
+    /// Tag to create or delete, lists all tags if not given
+    name: Option<String>,
+
+    /// Object the new tag points to
+    #[arg(default_value = "HEAD", conflicts_with = "delete")]
+    object: String,
+
+    /// Creates an annotated tag object
+    #[arg(short = 'a', requires = "message")]
+    annotate: bool,
+
+    /// Message of the annotated tag, implies `-a`
+    #[arg(short = 'm', requires = "name")]
+    message: Option<String>,
+
+    /// Deletes the tag
+    #[arg(short = 'd', long, requires = "name", conflicts_with = "message")]
+    delete: bool,
+}
+
+#[derive(Debug, Args)]
+struct SwitchArgs {
+    /// Branch to switch to. Other revisions are checked out as a detached
+    /// HEAD
+    target: String,
+
+    /// Discards local changes that would be overwritten
+    #[arg(short = 'f', long)]
+    force: bool,
+}
+
+#[derive(Debug, Args)]
+struct DaemonArgs {
+    /// Time between commits, e.g. `30s`, `5m` or `1h`
+    #[arg(long, default_value = "5m", value_parser = parse_interval)]
+    interval: Duration,
+}
+
+#[derive(Debug, Args)]
+struct CloneArgs {
+    /// Url of the remote repository
+    url: String,
+
+    /// Directory to clone into
+    dir: PathBuf,
+}
+
+#[derive(Debug, Args)]
+struct PushArgs {
+    /// Name of a configured remote or a url
+    remote: String,
+
+    /// Branch to push
+    branch: String,
+
+    /// Updates the remote branch even if it is not a fast-forward
+    #[arg(short = 'f', long)]
+    force: bool,
+}
+
+#[derive(Debug, Args)]
+struct MergeArgs {
+    /// The branch or commit to merge
+    commit: String,
+}
+
+#[derive(Debug, Args)]
+struct CherryPickArgs {
+    /// The commit whose changes are applied
+    commit: String,
+}
+
+#[derive(Debug, Args)]
+struct ResetArgs {
+    #[arg(default_value = "HEAD")]
+    commit: String,
+
+    /// Only moves the branch, keeps the index and worktree
+    #[arg(long, conflicts_with_all(["mixed", "hard"]))]
+    soft: bool,
+
+    /// Also resets the index, the default
+    #[arg(long, conflicts_with = "hard")]
+    mixed: bool,
+
+    /// Also resets the worktree, discarding all local changes
+    #[arg(long)]
+    hard: bool,
+}
+
+#[derive(Debug, Args)]
+struct RevertArgs {
+    /// The commit whose changes are undone
+    commit: String,
+
+    /// The parent of a merge commit whose side is kept, starting at 1
+    #[arg(short, long)]
+    mainline: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+struct PruneArgs {
+    /// Only lists the objects that would be deleted
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+
+    /// Keeps unreachable objects that are newer than this, e.g. `12h` or
+    /// `2w`
+    #[arg(long, default_value = "2w", value_parser = parse_interval)]
+    expire: Duration,
+}
+
+#[derive(Debug, Args)]
+struct RepackArgs {
+    /// Deletes the loose objects once they are packed
+    #[arg(short = 'd')]
+    delete: bool,
+}
+
+#[derive(Debug, Args)]
+struct MergeBaseArgs {
+    a: String,
+    b: String,
+
+    /// Prints all best common ancestors instead of one
+    #[arg(long)]
+    all: bool,
+}
+
+#[derive(Debug, Args)]
+struct BlameArgs {
+    path: PathBuf,
+
+    /// Commit whose version of the file is shown
+    #[arg(long, default_value = "HEAD")]
+    rev: String,
+}
+
+#[derive(Debug, Args)]
+struct RevListArgs {
+    /// Commits to start at. Commits reachable from commits prefixed with `^`
+    /// are left out
+    #[arg(required = true)]
+    commits: Vec<String>,
+
+    /// Stops after this many commits
+    #[arg(short = 'n', long)]
+    max_count: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+struct ReflogArgs {
+    /// Full name of the ref, e.g. `refs/heads/main`
+    #[arg(default_value = "HEAD")]
+    name: String,
+}
+
+#[derive(Debug, Args)]
+struct DescribeArgs {
+    #[arg(default_value = "HEAD")]
+    commit: String,
+
+    /// Also uses lightweight tags
+    #[arg(long)]
+    tags: bool,
+
+    /// Prints the abbreviated sha1 if no tag is found
+    #[arg(long)]
+    always: bool,
+}
+
+#[derive(Debug, Args)]
+struct VerifyArgs {
+    /// The signed object, e.g. `HEAD` or a tag name
     object: String,
 }
 
+#[derive(Debug, Args)]
+struct FetchArgs {
+    /// Name of the configured remote
+    #[arg(default_value = "origin")]
+    remote: String,
+}
+
 #[derive(Debug, Args)]
 struct HashObjectArgs {
-    #[arg(required_unless_present("stdin"))]
+    #[arg(required_unless_present_any(["stdin", "tree"]))]
     file: Option<PathBuf>,
 
     #[arg(long)]
     stdin: bool,
 
+    /// Hashes a directory recursively and prints the sha1-hash of its tree
+    #[arg(long, conflicts_with_all(["file", "stdin"]))]
+    tree: Option<PathBuf>,
+
     #[arg(value_enum, name = "type", long, short, default_value = "blob")]
     typ: ObjectType,
 
     #[arg(long, short)]
     write: bool,
+
+    /// Doesn't write the object, but appends `(exists)` to the sha1-hash if
+    /// the repository already contains it
+    #[arg(long, conflicts_with = "write")]
+    check: bool,
 }
 
 fn main() {
@@ -66,37 +618,142 @@ fn main() {
         Command::Find => find(),
         Command::CatFile(args) => cat_file(args),
         Command::HashObject(args) => hash_object(args),
+        Command::CountObjects => count_objects(),
+        Command::Fsck(args) => fsck(args),
+        Command::Log(args) => log(args),
+        Command::LsTree(args) => ls_tree(args),
+        Command::LsFiles(args) => ls_files(args),
+        Command::ReadTree(args) => read_tree(args),
+        Command::WriteTree(args) => write_tree(args),
+        Command::CommitTree(args) => commit_tree(args),
+        Command::Add(args) => add(args),
+        Command::Status => status(),
+        Command::Commit(args) => commit(args),
+        Command::UpdateRef(args) => update_ref(args),
+        Command::SymbolicRef(args) => symbolic_ref(args),
+        Command::ShowRef(args) => show_ref(args),
+        Command::RevParse(args) => rev_parse(args),
+        Command::VerifyPack(args) => verify_pack(args),
+        Command::Diff(args) => diff(args),
+        Command::Branch(args) => branch(args),
+        Command::Tag(args) => tag(args),
+        Command::Switch(args) => switch(args),
+        Command::Daemon(args) => {
+            if let Err(err) = daemon(args) {
+                eprintln!("fatal: {err}");
+                std::process::exit(128);
+            }
+        }
+        Command::Clone(args) => clone(args),
+        Command::Push(args) => push(args),
+        Command::Fetch(args) => fetch(args),
+        Command::Merge(args) => merge(args),
+        Command::CherryPick(args) => cherry_pick(args),
+        Command::Revert(args) => revert(args),
+        Command::Reset(args) => reset(args),
+        Command::Prune(args) => prune(args),
+        Command::Repack(args) => repack(args),
+        Command::MergeBase(args) => merge_base(args),
+        Command::Blame(args) => blame(args),
+        Command::RevList(args) => rev_list(args),
+        Command::Reflog(args) => reflog(args),
+        Command::Describe(args) => describe(args),
+        Command::VerifyCommit(args) => verify(args, ObjectType::Commit),
+        Command::VerifyTag(args) => verify(args, ObjectType::Tag),
     }
 }
 
 fn find_repo() -> Repository {
-    Repository::find().unwrap()
+    Repository::find(RepoKind::Git).unwrap()
 }
 
 fn init(args: InitArgs) {
-    Repository::create_at(args.path).unwrap();
+    let options = InitOptions {
+        initial_branch: args.initial_branch,
+        bare: args.bare,
+        kind: if args.gitsync {
+            RepoKind::GitSync
+        } else {
+            RepoKind::Git
+        },
+    };
+    Repository::create_with(args.path, &options).unwrap();
 }
 
 fn find() {
     let repo = find_repo();
-    println!("Git repository at: {}", repo.worktree_root().display());
+    match repo.worktree_root() {
+        Ok(worktree) => println!("Git repository at: {}", worktree.display()),
+        Err(_) => println!("Bare git repository at: {}", repo.gitdir_root().display()),
+    }
 }
 
 fn cat_file(args: CatFileArgs) {
+    if args.stdin {
+        let object = match Object::deserialize_zlib_read(io::stdin().lock()) {
+            Ok(object) => object,
+            Err(err) => {
+                eprintln!("fatal: could not decode object from stdin: {err}");
+                std::process::exit(128);
+            }
+        };
+        object.serialize(&mut io::stdout().lock()).unwrap();
+        return;
+    }
+
     let repo = find_repo();
-    let sha1 = repo.find_object_sha1(&args.object, None, true);
-    let obj_path = Repository::sha1_to_object(&sha1);
 
-    let mut open_opts = OpenOptions::new();
-    open_opts.read(true);
+    if args.batch {
+        repo.cat_file_batch(io::stdin().lock(), &mut io::stdout().lock())
+            .unwrap();
+        return;
+    }
+
+    if args.batch_check {
+        repo.cat_file_batch_check(io::stdin().lock(), &mut io::stdout().lock())
+            .unwrap();
+        return;
+    }
 
-    let file = repo.file(obj_path, &open_opts, false).unwrap();
+    if let Some(object) = args.show_type {
+        let sha1 = repo.rev_parse(&object).unwrap();
+        println!("{}", repo.object_type(&sha1).unwrap());
+        return;
+    }
 
-    let obj = Object::deserialize_zlib_read(file).unwrap();
-    obj.serialize(&mut std::io::stdout()).unwrap();
+    if let Some(object) = args.size {
+        let sha1 = repo.rev_parse(&object).unwrap();
+        println!("{}", repo.object_size(&sha1).unwrap());
+        return;
+    }
+
+    if let Some(object) = args.pretty {
+        let sha1 = repo.rev_parse(&object).unwrap();
+        repo.pretty_print(&sha1, &mut io::stdout().lock()).unwrap();
+        return;
+    }
+
+    let name = args.object.unwrap();
+    let (_, object) = repo.read_object(&name).unwrap();
+    if Some(object.object_type()) != args.typ {
+        eprintln!("fatal: git cat-file {name}: bad file");
+        std::process::exit(128);
+    }
+    object.serialize(&mut io::stdout().lock()).unwrap();
 }
 
 fn hash_object(args: HashObjectArgs) {
+    if let Some(dir) = args.tree {
+        let repo = find_repo();
+        let sha1 = if args.write {
+            repo.write_tree_from_dir(dir)
+        } else {
+            repo.hash_tree_from_dir(dir)
+        };
+        println!("{}", sha1.unwrap());
+        return;
+    }
+
     let mut input: Box<dyn Read> = if args.stdin {
         Box::new(std::io::stdin())
     } else {
@@ -106,10 +763,745 @@ fn hash_object(args: HashObjectArgs) {
     let obj = Object::deserialize_read(args.typ, &mut input).unwrap();
 
     if args.write {
-        let repo = Repository::find().unwrap();
+        let repo = Repository::find(RepoKind::Git).unwrap();
         let sha1 = obj.save(&repo).unwrap();
         println!("{}", sha1);
+    } else if args.check {
+        let repo = find_repo();
+        let sha1 = obj.hash(repo.hash_algorithm());
+        if repo.has_object(&sha1) {
+            println!("{sha1} (exists)");
+        } else {
+            println!("{sha1}");
+        }
     } else {
         println!("{}", obj.sha1());
     }
 }
+
+fn count_objects() {
+    let repo = find_repo();
+    let (count, size) = repo.count_loose_objects().unwrap();
+    println!("count: {count}, size: {}", size / 1024);
+}
+
+fn fsck(args: FsckArgs) {
+    let repo = find_repo();
+    let errors = repo.fsck().unwrap();
+    for error in &errors {
+        println!("{error}");
+    }
+    if args.unreachable {
+        let reachable = repo.reachable_objects().unwrap();
+        let mut objects: Vec<String> = repo.loose_objects().unwrap().collect();
+        objects.extend(repo.packed_objects().unwrap());
+        objects.sort();
+        objects.dedup();
+        for sha1 in objects.iter().filter(|sha1| !reachable.contains(*sha1)) {
+            println!("unreachable {} {sha1}", repo.object_type(sha1).unwrap());
+        }
+    }
+    if !errors.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn log(args: LogArgs) {
+    let repo = find_repo();
+    let start = repo.rev_parse(&args.start).unwrap();
+
+    let mut walk = repo.walk_commits(&start);
+    if args.first_parent {
+        walk = walk.first_parent();
+    }
+
+    for commit in walk {
+        let (sha1, commit) = commit.unwrap();
+        let Object::Commit {
+            author, message, ..
+        } = commit
+        else {
+            unreachable!("walk_commits only yields commits");
+        };
+
+        println!("commit {sha1}");
+        println!("Author: {} <{}>", author.name, author.email);
+        println!("Date:   {}", author.time_str());
+        println!();
+        let message = String::from_utf8_lossy(&message);
+        println!("    {}", message.lines().next().unwrap_or_default());
+        println!();
+    }
+}
+
+fn ls_tree(args: LsTreeArgs) {
+    let repo = find_repo();
+    let sha1 = repo.rev_parse(&args.tree).unwrap();
+
+    let entries = if args.recursive {
+        repo.read_tree_recursive(&sha1).unwrap()
+    } else {
+        repo.read_tree(&sha1).unwrap()
+    };
+
+    for entry in entries {
+        println!("{entry}");
+    }
+}
+
+fn ls_files(args: LsFilesArgs) {
+    let repo = find_repo();
+    for entry in repo.ls_files(args.deleted).unwrap() {
+        if args.stage {
+            println!("{entry}");
+        } else {
+            println!("{}", quote_path(&entry.path));
+        }
+    }
+}
+
+fn read_tree(args: ReadTreeArgs) {
+    let repo = find_repo();
+    let sha1 = repo.rev_parse(&args.tree).unwrap();
+    repo.read_tree_into_index(&sha1).unwrap();
+}
+
+fn write_tree(args: WriteTreeArgs) {
+    let repo = find_repo();
+    let path = match args.path {
+        Some(path) => path,
+        None => repo.worktree_root().unwrap().to_path_buf(),
+    };
+    println!("{}", repo.write_tree_from_dir(path).unwrap());
+}
+
+fn commit_tree(args: CommitTreeArgs) {
+    let repo = find_repo();
+    let tree = repo.find_object_sha1(&args.tree, None, true).unwrap();
+    let parents: Vec<String> = args
+        .parents
+        .iter()
+        .map(|parent| repo.find_object_sha1(parent, None, true).unwrap())
+        .collect();
+
+    let mut message = match args.message {
+        Some(message) => message,
+        None => {
+            let mut message = String::new();
+            io::stdin().read_to_string(&mut message).unwrap();
+            message
+        }
+    };
+    if !message.ends_with('\n') {
+        message.push('\n');
+    }
+
+    let author = signature(&repo, "AUTHOR");
+    let committer = signature(&repo, "COMMITTER");
+    let sha1 = repo
+        .commit_tree(&tree, &parents, &author, &committer, &message)
+        .unwrap();
+    println!("{sha1}");
+}
+
+/// the signature for `role` (`AUTHOR` or `COMMITTER`) from the `GIT_<role>_*`
+/// environment variables, falling back to `user.name`, `user.email` and the
+/// current time. Exits if no identity is set
+fn signature(repo: &Repository, role: &str) -> Signature {
+    let identity = match role {
+        "AUTHOR" => repo.author_identity(),
+        _ => repo.committer_identity(),
+    };
+    match identity {
+        Ok(identity) => identity.signature(),
+        Err(err) => {
+            eprintln!("fatal: {err}");
+            std::process::exit(128);
+        }
+    }
+}
+
+fn add(args: AddArgs) {
+    let repo = find_repo();
+    let paths: Vec<PathBuf> = args
+        .paths
+        .iter()
+        .map(|path| repo.to_workdir_relative(path).unwrap())
+        .collect();
+    repo.add(&paths).unwrap();
+}
+
+fn status() {
+    let repo = find_repo();
+    match repo.read_symbolic_ref("HEAD").unwrap() {
+        Some(head) => println!(
+            "On branch {}",
+            head.strip_prefix("refs/heads/").unwrap_or(&head)
+        ),
+        None => {
+            let head = repo.resolve_ref("HEAD").unwrap();
+            println!("HEAD detached at {}", &head[..7]);
+        }
+    }
+
+    let report = repo.status().unwrap();
+    let print_changes = |title: &str, changes: &[(Vec<u8>, Change)]| {
+        if changes.is_empty() {
+            return;
+        }
+        println!("{title}");
+        for (path, change) in changes {
+            let change = match change {
+                Change::Added => "new file:",
+                Change::Modified => "modified:",
+                Change::Deleted => "deleted:",
+            };
+            println!("\t{change:<12}{}", quote_path(path));
+        }
+        println!();
+    };
+    print_changes("Changes to be committed:", &report.staged);
+    print_changes("Changes not staged for commit:", &report.unstaged);
+
+    if !report.untracked.is_empty() {
+        println!("Untracked files:");
+        for path in &report.untracked {
+            println!("\t{}", quote_path(path));
+        }
+        println!();
+    }
+
+    if report.is_clean() {
+        println!("nothing to commit, working tree clean");
+    }
+}
+
+fn commit(args: CommitArgs) {
+    let repo = find_repo();
+    let mut message = args.message;
+    if !message.ends_with('\n') {
+        message.push('\n');
+    }
+
+    let author = signature(&repo, "AUTHOR");
+    let committer = signature(&repo, "COMMITTER");
+    let options = CommitOptions {
+        no_verify: args.no_verify,
+    };
+    let sha1 = match repo.commit_with(&message, &author, &committer, &options) {
+        Err(err @ GitSyncError::HookFailed { .. }) => {
+            eprintln!("fatal: {err}");
+            std::process::exit(1);
+        }
+        result => result.unwrap(),
+    };
+    // the commit-msg hook may have changed the message
+    let Object::Commit { message, .. } = repo.load_object(&sha1).unwrap() else {
+        unreachable!("commit creates a commit");
+    };
+    let message = String::from_utf8_lossy(&message);
+    let summary = message.lines().next().unwrap_or_default();
+    println!("[{}] {summary}", &sha1[..7]);
+}
+
+fn update_ref(args: UpdateRefArgs) {
+    let repo = find_repo();
+    let new_value = repo.find_object_sha1(&args.new_value, None, true).unwrap();
+    let old_value = args.old_value.map(|old_value| {
+        if old_value.chars().all(|c| c == '0') {
+            old_value
+        } else {
+            repo.find_object_sha1(&old_value, None, true).unwrap()
+        }
+    });
+    let message = args.message.unwrap_or_default();
+    repo.update_ref_with_message(&args.name, &new_value, old_value.as_deref(), &message)
+        .unwrap();
+}
+
+fn symbolic_ref(args: SymbolicRefArgs) {
+    let repo = find_repo();
+    if let Some(target) = args.target {
+        repo.write_symbolic_ref(&args.name, &target).unwrap();
+        return;
+    }
+    match repo.read_symbolic_ref(&args.name).unwrap() {
+        Some(target) => println!("{target}"),
+        None => {
+            eprintln!("fatal: ref {} is not a symbolic ref", args.name);
+            std::process::exit(128);
+        }
+    }
+}
+
+fn show_ref(args: ShowRefArgs) {
+    let repo = find_repo();
+    let filtered = args.heads || args.tags;
+    for (name, sha1) in repo.refs().unwrap() {
+        let shown = !filtered
+            || (args.heads && name.starts_with("refs/heads/"))
+            || (args.tags && name.starts_with("refs/tags/"));
+        if shown {
+            println!("{sha1} {name}");
+        }
+    }
+}
+
+fn rev_parse(args: RevParseArgs) {
+    let repo = find_repo();
+    println!("{}", repo.rev_parse(&args.revision).unwrap());
+}
+
+fn verify_pack(args: VerifyPackArgs) {
+    // packs can be verified outside of a repository
+    let hash = Repository::find(RepoKind::Git)
+        .map(|repo| repo.hash_algorithm())
+        .unwrap_or_default();
+    let pack = Pack::open(args.pack.with_extension("pack"), hash).unwrap();
+    let index = PackIndex::open(args.pack.with_extension("idx"), hash).unwrap();
+
+    let (entries, errors) = pack.verify(&index);
+    let mut chain_lengths = BTreeMap::new();
+    for entry in &entries {
+        print!(
+            "{} {:<6} {} {} {}",
+            entry.sha1,
+            entry.typ.as_str(),
+            entry.size,
+            entry.packed_size,
+            entry.offset
+        );
+        match &entry.delta {
+            Some((depth, base)) => {
+                println!(" {depth} {base}");
+                *chain_lengths.entry(*depth).or_insert(0) += 1;
+            }
+            None => println!(),
+        }
+    }
+
+    let non_delta = entries.iter().filter(|entry| entry.delta.is_none()).count();
+    println!("non delta: {non_delta} objects");
+    for (depth, count) in chain_lengths {
+        let objects = if count == 1 { "object" } else { "objects" };
+        println!("chain length = {depth}: {count} {objects}");
+    }
+
+    for error in &errors {
+        eprintln!("{error}");
+    }
+    if errors.is_empty() {
+        println!("{}: ok", args.pack.with_extension("pack").display());
+    } else {
+        println!("{}: bad", args.pack.with_extension("pack").display());
+        std::process::exit(1);
+    }
+}
+
+fn diff(args: DiffArgs) {
+    let repo = find_repo();
+    let a = repo.rev_parse(&args.a).unwrap();
+    let b = repo.rev_parse(&args.b).unwrap();
+
+    let is_blob = |sha1: &str| repo.object_type(sha1).unwrap() == ObjectType::Blob;
+    if is_blob(&a) && is_blob(&b) {
+        print!("{}", repo.diff_blobs(&a, &b).unwrap());
+        return;
+    }
+
+    for change in repo.diff_trees(&a, &b).unwrap() {
+        let status = match change.change {
+            Change::Added => 'A',
+            Change::Modified => 'M',
+            Change::Deleted => 'D',
+        };
+        println!("{status}\t{}", quote_path(&change.path));
+    }
+}
+
+fn branch(args: BranchArgs) {
+    let repo = find_repo();
+    let Some(name) = args.name else {
+        let current = repo.current_branch().unwrap();
+        for (name, _) in repo.list_branches().unwrap() {
+            let marker = if current.as_ref() == Some(&name) {
+                '*'
+            } else {
+                ' '
+            };
+            println!("{marker} {name}");
+        }
+        return;
+    };
+
+    if args.delete {
+        let sha1 = repo.resolve_ref(&format!("refs/heads/{name}")).unwrap();
+        repo.delete_branch(&name).unwrap();
+        println!("Deleted branch {name} (was {}).", &sha1[..7]);
+    } else {
+        repo.create_branch(&name, &args.start).unwrap();
+    }
+}
+
+fn tag(args: TagArgs) {
+    let repo = find_repo();
+    let Some(name) = args.name else {
+        for (name, _) in repo.list_tags().unwrap() {
+            println!("{name}");
+        }
+        return;
+    };
+
+    if args.delete {
+        let sha1 = repo.resolve_ref(&format!("refs/tags/{name}")).unwrap();
+        repo.delete_tag(&name).unwrap();
+        println!("Deleted tag '{name}' (was {})", &sha1[..7]);
+        return;
+    }
+
+    if args.annotate || args.message.is_some() {
+        let mut message = args.message.unwrap_or_default();
+        if !message.ends_with('\n') {
+            message.push('\n');
+        }
+        let tagger = signature(&repo, "COMMITTER");
+        repo.create_tag(&name, &args.object, Some((&tagger, &message)))
+            .unwrap();
+    } else {
+        repo.create_tag(&name, &args.object, None).unwrap();
+    }
+}
+
+fn switch(args: SwitchArgs) {
+    let repo = find_repo();
+    repo.switch(&args.target, args.force).unwrap();
+    match repo.current_branch().unwrap() {
+        Some(branch) => println!("Switched to branch '{branch}'"),
+        None => {
+            let head = repo.resolve_ref("HEAD").unwrap();
+            println!("HEAD is now at {}", &head[..7]);
+        }
+    }
+}
+
+fn daemon(args: DaemonArgs) -> gitsync::Result<()> {
+    let repo = Repository::find(RepoKind::GitSync)?;
+    let author = signature(&repo, "AUTHOR");
+    let daemon = Daemon::new(&repo, args.interval, author);
+
+    // Ctrl-C and SIGTERM stop the daemon after its current commit
+    let shutdown = daemon.shutdown_handle();
+    ctrlc::set_handler(move || shutdown.signal()).map_err(io::Error::other)?;
+
+    daemon.run(
+        |commit| {
+            let summary = commit.message.lines().next().unwrap_or_default();
+            println!("[{}] {summary}", &commit.sha1[..7]);
+        },
+        |err| eprintln!("error: {err}"),
+    );
+    Ok(())
+}
+
+fn clone(args: CloneArgs) {
+    println!("Cloning into '{}'...", args.dir.display());
+    Repository::clone_remote(&args.url, &args.dir).unwrap();
+}
+
+fn push(args: PushArgs) {
+    let repo = find_repo();
+    let new = repo
+        .resolve_ref(&format!("refs/heads/{}", args.branch))
+        .unwrap();
+    match repo.push(&args.remote, &args.branch, args.force).unwrap() {
+        Some(old) if old == new => println!("Everything up-to-date"),
+        Some(old) => println!("   {}..{}  {}", &old[..7], &new[..7], args.branch),
+        None => println!(" * [new branch]      {}", args.branch),
+    }
+}
+
+fn fetch(args: FetchArgs) {
+    let repo = find_repo();
+    for fetched in repo.fetch(&args.remote).unwrap() {
+        let tracking = fetched
+            .name
+            .strip_prefix("refs/remotes/")
+            .unwrap_or(&fetched.name);
+        let branch = tracking
+            .strip_prefix(&format!("{}/", args.remote))
+            .unwrap_or(tracking);
+        match fetched.old {
+            Some(old) => println!(
+                "   {}..{}  {branch} -> {tracking}",
+                &old[..7],
+                &fetched.new[..7]
+            ),
+            None => println!(" * [new branch]      {branch} -> {tracking}"),
+        }
+    }
+}
+
+fn merge(args: MergeArgs) {
+    let repo = find_repo();
+    let head = repo.resolve_ref("HEAD").unwrap();
+    let author = signature(&repo, "AUTHOR");
+    let committer = signature(&repo, "COMMITTER");
+    match repo.merge(&args.commit, &author, &committer).unwrap() {
+        MergeOutcome::UpToDate => println!("Already up to date."),
+        MergeOutcome::FastForward { commit } => {
+            println!("Updating {}..{}", &head[..7], &commit[..7]);
+            println!("Fast-forward");
+        }
+        MergeOutcome::Merged { commit } => println!("Merge made as {}", &commit[..7]),
+        MergeOutcome::Conflicts { paths } => {
+            for path in paths {
+                println!("CONFLICT: Merge conflict in {}", quote_path(&path));
+            }
+            println!("Automatic merge failed; fix conflicts and then commit the result.");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn cherry_pick(args: CherryPickArgs) {
+    let repo = find_repo();
+    let committer = signature(&repo, "COMMITTER");
+    let commit = repo.rev_parse(&format!("{}^0", args.commit)).unwrap();
+    let Object::Commit { message, .. } = repo.load_object(&commit).unwrap() else {
+        unreachable!("peeled to a commit");
+    };
+    let message = String::from_utf8_lossy(&message);
+    let summary = message.lines().next().unwrap_or_default();
+    match repo.cherry_pick(&commit, &committer).unwrap() {
+        MergeOutcome::Merged { commit } => println!("[{}] {summary}", &commit[..7]),
+        MergeOutcome::UpToDate | MergeOutcome::FastForward { .. } => {
+            println!("The cherry-pick is empty, HEAD already contains its changes.");
+            std::process::exit(1);
+        }
+        MergeOutcome::Conflicts { paths } => {
+            for path in paths {
+                println!("CONFLICT: Merge conflict in {}", quote_path(&path));
+            }
+            println!(
+                "error: could not apply {}... {summary}\nfix conflicts and then commit the result.",
+                &commit[..7]
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+fn revert(args: RevertArgs) {
+    let repo = find_repo();
+    let author = signature(&repo, "AUTHOR");
+    let committer = signature(&repo, "COMMITTER");
+    let commit = repo.rev_parse(&format!("{}^0", args.commit)).unwrap();
+    let outcome = match repo.revert(&commit, args.mainline, &author, &committer) {
+        Err(err @ GitSyncError::InvalidMainline { .. }) => {
+            eprintln!("error: {err}");
+            std::process::exit(128);
+        }
+        result => result.unwrap(),
+    };
+    match outcome {
+        MergeOutcome::Merged { commit } => {
+            let Object::Commit { message, .. } = repo.load_object(&commit).unwrap() else {
+                unreachable!("revert creates a commit");
+            };
+            let message = String::from_utf8_lossy(&message);
+            let summary = message.lines().next().unwrap_or_default();
+            println!("[{}] {summary}", &commit[..7]);
+        }
+        MergeOutcome::UpToDate | MergeOutcome::FastForward { .. } => {
+            println!("The revert is empty, HEAD doesn't contain the changes.");
+            std::process::exit(1);
+        }
+        MergeOutcome::Conflicts { paths } => {
+            for path in paths {
+                println!("CONFLICT: Merge conflict in {}", quote_path(&path));
+            }
+            println!(
+                "error: could not revert {}\nfix conflicts and then commit the result.",
+                &commit[..7]
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+fn reset(args: ResetArgs) {
+    let repo = find_repo();
+    let mode = if args.soft {
+        ResetMode::Soft
+    } else if args.hard {
+        ResetMode::Hard
+    } else {
+        ResetMode::Mixed
+    };
+    let commit = repo.reset(&args.commit, mode).unwrap();
+    if mode == ResetMode::Hard {
+        let Object::Commit { message, .. } = repo.load_object(&commit).unwrap() else {
+            unreachable!("reset to a commit");
+        };
+        let message = String::from_utf8_lossy(&message);
+        let summary = message.lines().next().unwrap_or_default();
+        println!("HEAD is now at {} {summary}", &commit[..7]);
+    }
+}
+
+fn prune(args: PruneArgs) {
+    let repo = find_repo();
+    for sha1 in repo.prune(args.expire, args.dry_run).unwrap() {
+        println!(
+            "{sha1} {}",
+            if args.dry_run {
+                "would be pruned"
+            } else {
+                "pruned"
+            }
+        );
+    }
+}
+
+fn repack(args: RepackArgs) {
+    let repo = find_repo();
+    match repo.repack(args.delete).unwrap() {
+        Some(checksum) => println!("pack-{checksum}"),
+        None => println!("Nothing new to pack."),
+    }
+}
+
+fn merge_base(args: MergeBaseArgs) {
+    let repo = find_repo();
+    // `^0` peels tags to their commits
+    let a = repo.rev_parse(&format!("{}^0", args.a)).unwrap();
+    let b = repo.rev_parse(&format!("{}^0", args.b)).unwrap();
+    let mut bases = repo.merge_bases(&a, &b).unwrap();
+    if !args.all {
+        bases.truncate(1);
+    }
+    if bases.is_empty() {
+        std::process::exit(1);
+    }
+    for base in bases {
+        println!("{base}");
+    }
+}
+
+fn blame(args: BlameArgs) {
+    let repo = find_repo();
+    let path = repo.to_workdir_relative(&args.path).unwrap();
+    let commit = repo.rev_parse(&format!("{}^0", args.rev)).unwrap();
+
+    let mut out = io::stdout().lock();
+    for line in repo.blame(&commit, &path).unwrap() {
+        write!(
+            out,
+            "{} ({} {}) ",
+            &line.commit[..7],
+            line.author.name,
+            line.author.time_str()
+        )
+        .unwrap();
+        out.write_all(&line.line).unwrap();
+        if !line.line.ends_with(b"\n") {
+            writeln!(out).unwrap();
+        }
+    }
+}
+
+fn rev_list(args: RevListArgs) {
+    let repo = find_repo();
+    let (mut includes, mut excludes) = (Vec::new(), Vec::new());
+    for spec in &args.commits {
+        match spec.strip_prefix('^') {
+            Some(spec) => excludes.push(repo.rev_parse(&format!("{spec}^0")).unwrap()),
+            None => includes.push(repo.rev_parse(&format!("{spec}^0")).unwrap()),
+        }
+    }
+
+    let commits = repo.rev_list(&includes, &excludes).unwrap();
+    let max_count = args.max_count.unwrap_or(commits.len());
+    for sha1 in commits.iter().take(max_count) {
+        println!("{sha1}");
+    }
+}
+
+fn reflog(args: ReflogArgs) {
+    let repo = find_repo();
+    let entries = repo.read_reflog(&args.name).unwrap();
+    for (n, entry) in entries.iter().rev().enumerate() {
+        println!(
+            "{} {}@{{{n}}}: {}",
+            &entry.new[..7],
+            args.name,
+            entry.message
+        );
+    }
+}
+
+fn describe(args: DescribeArgs) {
+    let repo = find_repo();
+    let commit = repo.rev_parse(&format!("{}^0", args.commit)).unwrap();
+    let options = DescribeOptions {
+        tags: args.tags,
+        always: args.always,
+    };
+    let name = match repo.describe(&commit, &options) {
+        Err(err @ GitSyncError::NoTagFound { .. }) => {
+            eprintln!("fatal: {err}");
+            std::process::exit(128);
+        }
+        result => result.unwrap(),
+    };
+    println!("{name}");
+}
+
+fn verify(args: VerifyArgs, expected: ObjectType) {
+    let repo = find_repo();
+    let sha1 = repo.rev_parse(&args.object).unwrap();
+    let typ = repo.object_type(&sha1).unwrap();
+    if typ != expected {
+        eprintln!("error: {sha1}: cannot verify a non-{expected} object of type {typ}.");
+        std::process::exit(1);
+    }
+
+    match repo.verify_signature(&sha1).unwrap() {
+        SignatureStatus::Good { key, signer } => {
+            println!("Good signature from \"{signer}\" with key {key}");
+        }
+        SignatureStatus::Bad { key, signer } => {
+            eprintln!("BAD signature from \"{signer}\" with key {key}");
+            std::process::exit(1);
+        }
+        SignatureStatus::Unverifiable { key } => {
+            eprintln!("Can't check signature: no public key {key}");
+            std::process::exit(1);
+        }
+        SignatureStatus::NotSigned => {
+            eprintln!("error: no signature found");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// parses a duration like `90`, `30s`, `5m`, `1h`, `3d` or `2w`. Plain
+/// numbers are seconds
+fn parse_interval(interval: &str) -> Result<Duration, String> {
+    let (number, unit) = match interval.find(|c: char| !c.is_ascii_digit()) {
+        Some(split) => interval.split_at(split),
+        None => (interval, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid interval {interval:?}"))?;
+    let secs = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        "d" => number * 60 * 60 * 24,
+        "w" => number * 60 * 60 * 24 * 7,
+        _ => return Err(format!("unknown unit {unit:?}, expected s, m, h, d or w")),
+    };
+    Ok(Duration::from_secs(secs))
+}
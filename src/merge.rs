@@ -0,0 +1,121 @@
+use crate::diff::{diff, lines, Edit};
+
+/// the result of [Repository::merge](crate::Repository::merge) and
+/// [Repository::cherry_pick](crate::Repository::cherry_pick)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeOutcome {
+    /// the commit is already part of the history of `HEAD`, or its changes
+    /// are already in `HEAD` for a cherry-pick
+    UpToDate,
+    /// `HEAD` was moved forward to the commit
+    FastForward { commit: String },
+    /// a merge commit with `HEAD` and the commit as parents was created, or
+    /// the new commit on top of `HEAD` for a cherry-pick
+    Merged { commit: String },
+    /// the paths changed on both sides and could not be merged.
+    ///
+    /// They are left in the index at stages 1 to 3 and text conflicts are
+    /// marked in the worktree. Committing after they are added finishes the
+    /// merge.
+    Conflicts { paths: Vec<Vec<u8>> },
+}
+
+/// merges the changes from `base` to `ours` and from `base` to `theirs` line
+/// by line. Returns the merged text and whether it contains conflicts.
+///
+/// Conflicting lines are written between `<<<<<<< our_label`, `=======` and
+/// `>>>>>>> their_label` markers.
+pub(crate) fn merge_text(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    our_label: &str,
+    their_label: &str,
+) -> (Vec<u8>, bool) {
+    let (base, ours, theirs) = (lines(base), lines(ours), lines(theirs));
+    let in_ours = matching_lines(&base, &ours);
+    let in_theirs = matching_lines(&base, &theirs);
+
+    let mut out = Vec::new();
+    let mut conflict = false;
+    let (mut b, mut o, mut t) = (0, 0, 0);
+    loop {
+        // the next line of the base that both sides kept, where the three
+        // versions line up again
+        let next = (b..base.len()).find_map(|i| Some((i, in_ours[i]?, in_theirs[i]?)));
+        let (next_b, next_o, next_t) = next.unwrap_or((base.len(), ours.len(), theirs.len()));
+        let base_chunk = &base[b..next_b];
+        let our_chunk = &ours[o..next_o];
+        let their_chunk = &theirs[t..next_t];
+
+        if our_chunk == base_chunk {
+            out.extend(their_chunk.concat());
+        } else if their_chunk == base_chunk || our_chunk == their_chunk {
+            out.extend(our_chunk.concat());
+        } else {
+            conflict = true;
+            push_marker(&mut out, &format!("<<<<<<< {our_label}"));
+            out.extend(our_chunk.concat());
+            push_marker(&mut out, "=======");
+            out.extend(their_chunk.concat());
+            push_marker(&mut out, &format!(">>>>>>> {their_label}"));
+        }
+
+        if next.is_none() {
+            break;
+        }
+        out.extend_from_slice(base[next_b]);
+        (b, o, t) = (next_b + 1, next_o + 1, next_t + 1);
+    }
+    (out, conflict)
+}
+
+/// for every line of `a`, the line of `b` it is kept as, if any
+fn matching_lines(a: &[&[u8]], b: &[&[u8]]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; a.len()];
+    for edit in diff(a, b) {
+        if let Edit::Equal { old, new } = edit {
+            matches[old] = Some(new);
+        }
+    }
+    matches
+}
+
+/// appends a conflict marker line, starting a new line if needed
+fn push_marker(out: &mut Vec<u8>, marker: &str) {
+    if !out.is_empty() && !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+    out.extend_from_slice(marker.as_bytes());
+    out.push(b'\n');
+}
+
+#[cfg(test)]
+mod test {
+    use super::merge_text;
+
+    #[test]
+    fn merge_lines() {
+        let base = b"1\n2\n3\n4\n5\n";
+        let ours = b"one\n2\n3\n4\n5\n";
+        let theirs = b"1\n2\n3\n4\nfive\n6\n";
+        assert_eq!(
+            merge_text(base, ours, theirs, "HEAD", "other"),
+            (b"one\n2\n3\n4\nfive\n6\n".to_vec(), false)
+        );
+        // the same change on both sides is not a conflict
+        assert_eq!(
+            merge_text(base, ours, ours, "HEAD", "other"),
+            (ours.to_vec(), false)
+        );
+
+        let theirs = b"uno\n2\n3\n4\n5";
+        assert_eq!(
+            merge_text(base, ours, theirs, "HEAD", "other"),
+            (
+                b"<<<<<<< HEAD\none\n=======\nuno\n>>>>>>> other\n2\n3\n4\n5".to_vec(),
+                true
+            )
+        );
+    }
+}
@@ -1,16 +1,17 @@
 use std::{
-    fs::OpenOptions,
-    io::{empty, BufRead, BufReader, ErrorKind, Read, Write},
+    cmp::Ordering,
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::{self, empty, BufRead, BufReader, ErrorKind, Read, Write},
+    path::Path,
+    sync::atomic::{self, AtomicUsize},
 };
 
-use anyhow::{bail, Context, Result};
+use crate::{format_git_time, parse_git_time, GitSyncError, HashAlgorithm, Repository, Result};
 use clap::ValueEnum;
 use flate2::{bufread::ZlibDecoder, write::ZlibEncoder, Compression};
-use sha1::{Digest, Sha1};
 
-use crate::Repository;
-
-#[derive(Debug, Clone, Copy, ValueEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum ObjectType {
     Blob,
     Commit,
@@ -18,12 +19,198 @@ pub enum ObjectType {
     Tag,
 }
 
-#[derive(Debug)]
+impl ObjectType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ObjectType::Blob => "blob",
+            ObjectType::Commit => "commit",
+            ObjectType::Tree => "tree",
+            ObjectType::Tag => "tag",
+        }
+    }
+
+    /// the type code of a pack entry that stores the whole object, see
+    /// [PackObjectType](crate::PackObjectType) for the delta codes
+    pub fn to_pack_code(self) -> u8 {
+        match self {
+            ObjectType::Commit => 1,
+            ObjectType::Tree => 2,
+            ObjectType::Blob => 3,
+            ObjectType::Tag => 4,
+        }
+    }
+
+    /// the inverse of [ObjectType::to_pack_code]. Delta and unknown codes
+    /// are `None`
+    pub fn from_pack_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(ObjectType::Commit),
+            2 => Some(ObjectType::Tree),
+            3 => Some(ObjectType::Blob),
+            4 => Some(ObjectType::Tag),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ObjectType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The identity and time stamp stored in a commit's `author`/`committer` line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    /// seconds since the unix epoch
+    pub time: i64,
+    /// timezone offset in minutes east of UTC
+    pub tz_offset: i32,
+    /// the time stamp as it was stored if it differs from how `time` and
+    /// `tz_offset` are formatted, e.g. `1700000000 -0000`. It is written
+    /// instead as long as it still matches them, so parsed signatures keep
+    /// their exact bytes
+    pub raw_time: Option<String>,
+}
+
+impl Signature {
+    /// parses a signature of the form `Name <email> 1700000000 +0100`
+    pub fn parse(line: &str) -> Result<Self> {
+        let invalid = || GitSyncError::InvalidSignature(line.to_owned());
+
+        let email_start = line.find('<').ok_or_else(invalid)?;
+        let email_end = line[email_start..].find('>').ok_or_else(invalid)? + email_start;
+
+        let name = line[..email_start].trim_end().to_owned();
+        let email = line[email_start + 1..email_end].to_owned();
+
+        let stamp = line[email_end + 1..].trim();
+        let (time, tz_offset) = Self::parse_time(stamp).ok_or_else(invalid)?;
+        let raw_time = (stamp != format_git_time(time, tz_offset)).then(|| stamp.to_owned());
+
+        Ok(Self {
+            name,
+            email,
+            time,
+            tz_offset,
+            raw_time,
+        })
+    }
+
+    /// parses a time stamp of the form `1700000000 +0100`, as used in
+    /// signatures, see [parse_git_time]
+    pub fn parse_time(time: &str) -> Option<(i64, i32)> {
+        parse_git_time(time.trim()).ok()
+    }
+
+    /// the time stamp as stored by git, e.g. `1700000000 +0100`
+    pub fn time_str(&self) -> String {
+        match &self.raw_time {
+            Some(raw) if Self::parse_time(raw) == Some((self.time, self.tz_offset)) => raw.clone(),
+            _ => format_git_time(self.time, self.tz_offset),
+        }
+    }
+}
+
+impl Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} <{}> {}", self.name, self.email, self.time_str())
+    }
+}
+
+/// A single record of a tree object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    /// the octal mode as stored by git, e.g. `100644` or `40000`
+    pub mode: String,
+    /// the file name. Git does not require names to be valid utf-8
+    pub name: Vec<u8>,
+    /// the binary sha1 of the referenced object
+    pub sha: Vec<u8>,
+}
+
+/// formats the entry like `git ls-tree`, e.g.
+/// `100644 blob <sha1>\tfile.txt`, with the name quoted by [quote_path]
+impl Display for TreeEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:0>6} {} {}\t{}",
+            self.mode,
+            self.object_type(),
+            self.sha1(),
+            quote_path(&self.name)
+        )
+    }
+}
+
+impl TreeEntry {
+    pub fn is_tree(&self) -> bool {
+        self.mode == "40000"
+    }
+
+    /// the type of the object this entry points to
+    pub fn object_type(&self) -> ObjectType {
+        match self.mode.as_str() {
+            "40000" => ObjectType::Tree,
+            // submodules are stored as the commit they point to
+            "160000" => ObjectType::Commit,
+            _ => ObjectType::Blob,
+        }
+    }
+
+    pub fn sha1(&self) -> String {
+        to_hex(&self.sha)
+    }
+
+    /// compares entries the way git sorts them within a tree.
+    ///
+    /// Names are compared bytewise, but subtrees are compared as if their
+    /// name had a trailing `/`. E.g. `foo.txt` sorts before the directory
+    /// `foo`, but after the file `foo`.
+    pub fn cmp_git(&self, other: &TreeEntry) -> Ordering {
+        let a = self.name.iter().chain(self.is_tree().then_some(&b'/'));
+        let b = other.name.iter().chain(other.is_tree().then_some(&b'/'));
+        a.cmp(b)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum Object {
-    Blob { data: Vec<u8> },
-    Commit,
-    Tree,
-    Tag,
+    Blob {
+        data: Vec<u8>,
+    },
+    Commit {
+        tree: String,
+        parents: Vec<String>,
+        author: Signature,
+        committer: Signature,
+        encoding: Option<String>,
+        /// the headers after `committer` that have no field, e.g.
+        /// `mergetag`, in the order they were read. They are written after
+        /// `encoding` and before `gpgsig`, where git puts them
+        extra_headers: Vec<(String, String)>,
+        gpgsig: Option<String>,
+        /// in the charset named by `encoding`, utf-8 if it is missing, but
+        /// not necessarily valid
+        message: Vec<u8>,
+    },
+    Tree {
+        entries: Vec<TreeEntry>,
+    },
+    Tag {
+        object: String,
+        typ: ObjectType,
+        tag: String,
+        tagger: Option<Signature>,
+        message: String,
+        /// signature appended to the message, starting at the
+        /// `-----BEGIN PGP SIGNATURE-----` line
+        gpgsig: Option<String>,
+    },
 }
 
 impl Object {
@@ -32,72 +219,168 @@ impl Object {
     }
 
     pub fn serialize_zlib_comp(&self, comp: Compression) -> (String, Vec<u8>) {
+        self.serialize_zlib_with(HashAlgorithm::Sha1, comp)
+    }
+
+    /// compresses the object and hashes it with `hash`
+    pub fn serialize_zlib_with(&self, hash: HashAlgorithm, comp: Compression) -> (String, Vec<u8>) {
         let mut data = Vec::new();
         let mut encoder = ZlibEncoder::new(&mut data, comp);
-        let hash = self.serialize_with_header(&mut encoder).unwrap();
+        let hash = self.serialize_with_header(&mut encoder, hash).unwrap();
         drop(encoder);
         (hash, data)
     }
 
-    fn serialize_with_header(&self, write: &mut impl Write) -> Result<String> {
-        let mut hasher = Sha1::new();
+    fn serialize_with_header(&self, write: &mut impl Write, hash: HashAlgorithm) -> Result<String> {
+        let mut hasher = hash.hasher();
         let mut write = SplitWrite(write, &mut hasher);
 
-        write!(write, "{} ", self.type_str())?;
+        write!(write, "{} {}\0", self.object_type(), self.serialized_len())?;
+        self.serialize(&mut write)?;
+        drop(write);
+
+        Ok(to_hex(&hasher.finalize()))
+    }
 
+    /// the number of bytes [Object::serialize] writes, computed without
+    /// buffering the serialized object
+    pub fn serialized_len(&self) -> u64 {
         match self {
-            Object::Blob { data } => {
-                write!(write, "{}\0", data.len())?;
-                write.write_all(&data)?;
+            Object::Blob { data } => data.len() as u64,
+            Object::Tree { entries } => entries
+                .iter()
+                .map(|entry| (entry.mode.len() + 1 + entry.name.len() + 1 + entry.sha.len()) as u64)
+                .sum(),
+            Object::Commit { .. } | Object::Tag { .. } => {
+                let mut count = ByteCount(0);
+                self.serialize(&mut count)
+                    .expect("counting bytes can't fail");
+                count.0
             }
-            _ => {
-                let mut data = Vec::new();
-                self.serialize(&mut data)?;
-                write!(write, "{}\0", data.len())?;
-                write.write_all(&data)?;
-            }
-        };
-        drop(write);
-
-        let mut hash = Vec::with_capacity(20);
-        for c in hasher.finalize() {
-            let _ = write!(hash, "{:0>2x}", c);
         }
-        Ok(String::from_utf8(hash).unwrap())
     }
 
     pub fn serialize(&self, write: &mut impl Write) -> Result<()> {
         match self {
             Object::Blob { data } => write.write_all(&data)?,
-            Object::Commit => todo!(),
-            Object::Tree => todo!(),
-            Object::Tag => todo!(),
+            Object::Commit {
+                tree,
+                parents,
+                author,
+                committer,
+                encoding,
+                extra_headers,
+                gpgsig,
+                message,
+            } => {
+                writeln!(write, "tree {tree}")?;
+                for parent in parents {
+                    writeln!(write, "parent {parent}")?;
+                }
+                writeln!(write, "author {author}")?;
+                writeln!(write, "committer {committer}")?;
+                if let Some(encoding) = encoding {
+                    writeln!(write, "encoding {encoding}")?;
+                }
+                for (key, value) in extra_headers {
+                    write_header(write, key, value)?;
+                }
+                if let Some(gpgsig) = gpgsig {
+                    write_header(write, "gpgsig", gpgsig)?;
+                }
+                writeln!(write)?;
+                write.write_all(message)?;
+            }
+            Object::Tree { entries } => {
+                let mut sorted: Vec<&TreeEntry> = entries.iter().collect();
+                sorted.sort_by(|a, b| a.cmp_git(b));
+                for entry in sorted {
+                    write!(write, "{} ", entry.mode)?;
+                    write.write_all(&entry.name)?;
+                    write.write_all(&[0])?;
+                    write.write_all(&entry.sha)?;
+                }
+            }
+            Object::Tag {
+                object,
+                typ,
+                tag,
+                tagger,
+                message,
+                gpgsig,
+            } => {
+                writeln!(write, "object {object}")?;
+                writeln!(write, "type {typ}")?;
+                writeln!(write, "tag {tag}")?;
+                if let Some(tagger) = tagger {
+                    writeln!(write, "tagger {tagger}")?;
+                }
+                write!(write, "\n{message}")?;
+                if let Some(gpgsig) = gpgsig {
+                    write!(write, "{gpgsig}")?;
+                }
+            }
         }
         Ok(())
     }
 
+    /// writes the object to the repository, compressed with
+    /// [Repository::compression_level], and returns its hash.
+    ///
+    /// Objects are named by their content, so nothing is written if the
+    /// object already exists, only an empty loose file is replaced. The file
+    /// is written atomically, so no partial object is left if writing fails.
     pub fn save(&self, repo: &Repository) -> Result<String> {
-        let (sha1, data) = self.serialize_zlib();
-
-        let path = Repository::sha1_to_object(&sha1);
-        let mut file = repo
-            .file(path, OpenOptions::new().create(true).write(true), true)
-            .context("save object")?;
+        let hash = self.hash(repo.hash_algorithm());
+        let path = repo.loose_object_path(&hash);
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.len() > 0 => return Ok(hash),
+            Ok(_) => {}
+            // a pack written by another process since the packs were loaded
+            // is not checked, at worst the object is stored twice
+            Err(_) if repo.has_object_no_reload(&hash) => return Ok(hash),
+            Err(_) => {}
+        }
 
-        file.write_all(&data).context("save object")?;
-        Ok(sha1)
+        let (_, data) = self.serialize_zlib_with(repo.hash_algorithm(), repo.compression_level());
+        write_loose_object(&path, &data, || Ok(()))?;
+        Ok(hash)
     }
 
     pub fn sha1(&self) -> String {
-        self.serialize_with_header(&mut empty()).unwrap()
+        self.hash(HashAlgorithm::Sha1)
     }
 
-    fn type_str(&self) -> &'static str {
+    /// the signature of a signed commit or tag and the payload it signs,
+    /// which is the serialized object without the signature. `None` if the
+    /// object is not signed
+    pub fn signed_payload(&self) -> Option<(Vec<u8>, &str)> {
+        let signature = match self {
+            Object::Commit { gpgsig, .. } | Object::Tag { gpgsig, .. } => gpgsig.as_deref()?,
+            Object::Blob { .. } | Object::Tree { .. } => return None,
+        };
+        let mut unsigned = self.clone();
+        if let Object::Commit { gpgsig, .. } | Object::Tag { gpgsig, .. } = &mut unsigned {
+            *gpgsig = None;
+        }
+        let mut payload = Vec::new();
+        unsigned
+            .serialize(&mut payload)
+            .expect("writing to a vec can't fail");
+        Some((payload, signature))
+    }
+
+    /// the object's name when hashed with `hash`
+    pub fn hash(&self, hash: HashAlgorithm) -> String {
+        self.serialize_with_header(&mut empty(), hash).unwrap()
+    }
+
+    pub fn object_type(&self) -> ObjectType {
         match self {
-            Object::Blob { data: _ } => "blob",
-            Object::Commit => "commit",
-            Object::Tree => "tree",
-            Object::Tag => "tag",
+            Object::Blob { data: _ } => ObjectType::Blob,
+            Object::Commit { .. } => ObjectType::Commit,
+            Object::Tree { .. } => ObjectType::Tree,
+            Object::Tag { .. } => ObjectType::Tag,
         }
     }
 
@@ -107,41 +390,88 @@ impl Object {
     }
 
     pub fn deserialize_zlib(data: impl BufRead) -> Result<Self> {
+        Self::deserialize_zlib_with(data, HashAlgorithm::Sha1)
+    }
+
+    /// decodes an object of a repository that uses `hash`
+    pub fn deserialize_zlib_with(data: impl BufRead, hash: HashAlgorithm) -> Result<Self> {
         let decoder = ZlibDecoder::new(data);
         let mut decoder = BufReader::new(decoder);
 
+        let (obj_type, size) = Self::read_header(&mut decoder)?;
+
+        let mut data = Vec::new();
+        SizedReader::new(decoder, size)
+            .read_to_end(&mut data)
+            .map_err(GitSyncError::from_io)?;
+
+        Self::deserialize_with(obj_type, data, hash)
+    }
+
+    /// decompresses a zlib stream, e.g. a loose object file, without parsing
+    /// it. The result of a loose object still starts with its header
+    pub fn inflate(reader: impl Read) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        ZlibDecoder::new(BufReader::new(reader)).read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// compresses `bytes` as a zlib stream with `comp`, the inverse of
+    /// [Object::inflate]
+    pub fn deflate(bytes: &[u8], comp: Compression) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), comp);
+        encoder
+            .write_all(bytes)
+            .expect("writing to a vec can't fail");
+        encoder.finish().expect("writing to a vec can't fail")
+    }
+
+    /// reads the `<type> <size>\0` header from a decompressed object.
+    ///
+    /// The header must be exactly as git writes it: a lowercase type, a
+    /// single space and a decimal size without leading zeros. `reader` is
+    /// left at the start of the object's content.
+    pub fn read_header(reader: &mut impl BufRead) -> Result<(ObjectType, usize)> {
+        let invalid = |msg: &str| GitSyncError::InvalidObjectHeader(msg.to_owned());
+
         let mut buf = Vec::new();
-        decoder
-            .read_until(b' ', &mut buf)
-            .context("failed to read type")?;
+        reader.read_until(b' ', &mut buf)?;
+        if buf.contains(&0) {
+            return Err(invalid("Expected b' ' between object type and size"));
+        }
         if buf.pop() != Some(b' ') {
-            bail!("Expected b' ' after object type but got EOF instead");
+            return Err(invalid(
+                "Expected b' ' after object type but got EOF instead",
+            ));
         }
-        let typ = String::from_utf8(buf).context(format!("Could not parse type"))?;
-        let obj_type = match ObjectType::from_str(&typ, true) {
-            Ok(typ) => typ,
-            Err(msg) => bail!("Invalid blob type:\n{msg}"),
-        };
+        let obj_type = std::str::from_utf8(&buf)
+            .ok()
+            .and_then(|typ| ObjectType::from_str(typ, false).ok())
+            .ok_or_else(|| {
+                GitSyncError::UnknownObjectType(String::from_utf8_lossy(&buf).into_owned())
+            })?;
 
         let mut buf = Vec::new();
-        decoder
-            .read_until(0, &mut buf)
-            .context("failed to read type")?;
+        reader.read_until(0, &mut buf)?;
         if buf.pop() != Some(0) {
-            bail!("Expected 0 after object size but got EOF instead");
+            return Err(invalid("Expected 0 after object size but got EOF instead"));
         }
-        let size = String::from_utf8(buf).context(format!("Could not parse size"))?;
-        let size: usize = size.parse().context("could not parse size")?;
-
-        let mut data = Vec::new();
-        let real_size = decoder
-            .read_to_end(&mut data)
-            .context("could not read data")?;
-        if real_size != size {
-            bail!("Expected to read object of size {size} but got {real_size} instead");
+        let invalid_size =
+            || GitSyncError::InvalidObjectSize(String::from_utf8_lossy(&buf).into_owned());
+        // `parse` would accept a sign and leading zeros
+        let canonical = match buf.as_slice() {
+            [] | [b'0', _, ..] => false,
+            digits => digits.iter().all(u8::is_ascii_digit),
+        };
+        if !canonical {
+            return Err(invalid_size());
         }
+        let size = std::str::from_utf8(&buf)
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(invalid_size)?;
 
-        Self::deserialize(obj_type, data)
+        Ok((obj_type, size))
     }
 
     pub fn deserialize_read(typ: ObjectType, reader: &mut impl Read) -> Result<Object> {
@@ -152,27 +482,384 @@ impl Object {
     }
 
     pub fn deserialize(typ: ObjectType, data: Vec<u8>) -> Result<Object> {
+        Self::deserialize_with(typ, data, HashAlgorithm::Sha1)
+    }
+
+    /// parses the content of an object of a repository that uses `hash`.
+    ///
+    /// The hash only matters for trees, which store the raw hash of each
+    /// entry.
+    pub fn deserialize_with(typ: ObjectType, data: Vec<u8>, hash: HashAlgorithm) -> Result<Object> {
         match typ {
             ObjectType::Blob => Ok(Self::Blob { data }),
-            ObjectType::Commit => todo!(),
-            ObjectType::Tree => todo!(),
-            ObjectType::Tag => todo!(),
+            ObjectType::Commit => Self::deserialize_commit(data),
+            ObjectType::Tree => Self::deserialize_tree(data, hash),
+            ObjectType::Tag => Self::deserialize_tag(data),
         }
     }
+
+    fn deserialize_tag(data: Vec<u8>) -> Result<Object> {
+        let invalid = |reason: &str| invalid_object(ObjectType::Tag, reason);
+
+        let data = String::from_utf8(data).map_err(|_| invalid("not valid utf-8"))?;
+        let (headers, message) = data
+            .split_once("\n\n")
+            .ok_or_else(|| invalid("missing the empty line before the message"))?;
+
+        let mut object = None;
+        let mut typ = None;
+        let mut tag = None;
+        let mut tagger = None;
+
+        for (key, value) in parse_headers(ObjectType::Tag, headers)? {
+            match key {
+                "object" => object = Some(value),
+                "type" => {
+                    typ = Some(
+                        ObjectType::from_str(&value, false)
+                            .map_err(|msg| invalid(&format!("Invalid tag type:\n{msg}")))?,
+                    )
+                }
+                "tag" => tag = Some(value),
+                "tagger" => tagger = Some(Signature::parse(&value)?),
+                _ => return Err(invalid(&format!("unsupported header {key:?}"))),
+            }
+        }
+
+        let (message, gpgsig) = split_signature(message);
+
+        Ok(Object::Tag {
+            object: object.ok_or_else(|| invalid("missing object"))?,
+            typ: typ.ok_or_else(|| invalid("missing type"))?,
+            tag: tag.ok_or_else(|| invalid("missing tag name"))?,
+            tagger,
+            message: message.to_owned(),
+            gpgsig: gpgsig.map(str::to_owned),
+        })
+    }
+
+    fn deserialize_tree(data: Vec<u8>, hash: HashAlgorithm) -> Result<Object> {
+        let invalid = |reason: &str| invalid_object(ObjectType::Tree, reason);
+
+        let mut entries = Vec::new();
+        let mut rest = data.as_slice();
+
+        // entries are stored as `<mode> <name>\0<raw hash>` in git's sort
+        // order, so we keep them in the order we read them.
+        while !rest.is_empty() {
+            let space = rest
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or_else(|| invalid("entry is missing its mode"))?;
+            let mode = String::from_utf8(rest[..space].to_vec())
+                .map_err(|_| invalid("entry mode is not valid ascii"))?;
+            rest = &rest[space + 1..];
+
+            let nul = rest
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| invalid("entry name is not terminated"))?;
+            let name = rest[..nul].to_vec();
+            rest = &rest[nul + 1..];
+
+            let hash_len = hash.raw_len();
+            if rest.len() < hash_len {
+                return Err(invalid("entry hash is truncated"));
+            }
+            let sha = rest[..hash_len].to_vec();
+            rest = &rest[hash_len..];
+
+            entries.push(TreeEntry { mode, name, sha });
+        }
+
+        Ok(Object::Tree { entries })
+    }
+
+    fn deserialize_commit(mut data: Vec<u8>) -> Result<Object> {
+        let invalid = |reason: &str| invalid_object(ObjectType::Commit, reason);
+
+        // the message is in the commit's encoding, only the headers are utf-8
+        let headers_end = data
+            .windows(2)
+            .position(|window| window == b"\n\n")
+            .ok_or_else(|| invalid("missing the empty line before the message"))?;
+        let message = data.split_off(headers_end + 2);
+        data.truncate(headers_end);
+        let headers = String::from_utf8(data).map_err(|_| invalid("not valid utf-8"))?;
+
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        let mut encoding = None;
+        let mut extra_headers = Vec::new();
+        let mut gpgsig = None;
+
+        for (key, value) in parse_headers(ObjectType::Commit, &headers)? {
+            match key {
+                "tree" => tree = Some(value),
+                "parent" => parents.push(value),
+                "author" => author = Some(Signature::parse(&value)?),
+                "committer" => committer = Some(Signature::parse(&value)?),
+                "encoding" => encoding = Some(value),
+                "gpgsig" => gpgsig = Some(value),
+                key => extra_headers.push((key.to_owned(), value)),
+            }
+        }
+
+        Ok(Object::Commit {
+            tree: tree.ok_or_else(|| invalid("missing tree"))?,
+            parents,
+            author: author.ok_or_else(|| invalid("missing author"))?,
+            committer: committer.ok_or_else(|| invalid("missing committer"))?,
+            encoding,
+            extra_headers,
+            gpgsig,
+            message,
+        })
+    }
 }
 
-struct SplitWrite<'l, A, B>(&'l mut A, &'l mut B);
+/// formats `bytes` as lowercase hex, e.g. to display a binary sha1
+pub fn to_hex(bytes: &[u8]) -> String {
+    use std::fmt::Write as _;
 
-impl<'l, A: Write, B: Write> Write for SplitWrite<'l, A, B> {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let size_a = self.0.write(buf)?;
-        let size_b = self.1.write(buf)?;
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{byte:0>2x}");
+    }
+    hex
+}
+
+/// quotes a path the way git prints it, e.g. in `ls-tree`.
+///
+/// Paths containing control characters, `"`, `\` or non-ascii bytes are
+/// wrapped in quotes with those bytes escaped. Other paths are returned as is.
+pub fn quote_path(path: &[u8]) -> String {
+    let needs_quotes = path
+        .iter()
+        .any(|&b| b < 0x20 || b == b'"' || b == b'\\' || b >= 0x7f);
+    if !needs_quotes {
+        return String::from_utf8_lossy(path).into_owned();
+    }
+
+    let mut quoted = String::with_capacity(path.len() + 2);
+    quoted.push('"');
+    for &b in path {
+        match b {
+            0x07 => quoted.push_str("\\a"),
+            0x08 => quoted.push_str("\\b"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\n' => quoted.push_str("\\n"),
+            0x0b => quoted.push_str("\\v"),
+            0x0c => quoted.push_str("\\f"),
+            b'\r' => quoted.push_str("\\r"),
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            b if !(0x20..0x7f).contains(&b) => quoted.push_str(&format!("\\{b:03o}")),
+            b => quoted.push(b as char),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// parses a hex string, e.g. a sha1, into its bytes
+pub fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    let invalid = || GitSyncError::InvalidHex(hex.to_owned());
 
-        if size_a != size_b {
-            let err = std::io::Error::new(ErrorKind::Other, "write did not match in size");
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid()))
+        .collect()
+}
+
+/// counts the temporary files of [write_loose_object], so threads of one
+/// process never share one
+static TEMP_OBJECTS: AtomicUsize = AtomicUsize::new(0);
+
+/// writes the compressed object `data` to the loose object file `path`.
+///
+/// The data is written to a temporary file in the same directory, which is
+/// renamed to `path` once it is complete. `before_rename` runs in between,
+/// which lets tests inject failures.
+fn write_loose_object(
+    path: &Path,
+    data: &[u8],
+    before_rename: impl FnOnce() -> Result<()>,
+) -> Result<()> {
+    let temp = path.with_file_name(format!(
+        "tmp_obj_{}_{}",
+        std::process::id(),
+        TEMP_OBJECTS.fetch_add(1, atomic::Ordering::Relaxed)
+    ));
+    let result = (|| -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp)?;
+        file.write_all(data)?;
+        drop(file);
+        before_rename()?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    })();
+
+    if let Err(err) = result {
+        let _ = fs::remove_file(&temp);
+        // replacing a file fails on windows while it is open, e.g. because
+        // another process just wrote the same object and is reading it
+        let written = fs::metadata(path).is_ok_and(|metadata| metadata.len() > 0);
+        if !written {
             return Err(err);
         }
-        Ok(size_a)
+    }
+    Ok(())
+}
+
+/// reads the content of an object, failing as soon as it is longer than the
+/// size declared in its header or when it ends early.
+///
+/// The errors are [GitSyncError::SizeMismatch] and
+/// [GitSyncError::TruncatedObject] wrapped in an [io::Error].
+pub(crate) struct SizedReader<R> {
+    inner: R,
+    expected: usize,
+    read: usize,
+}
+
+impl<R: BufRead> SizedReader<R> {
+    pub(crate) fn new(inner: R, expected: usize) -> Self {
+        Self {
+            inner,
+            expected,
+            read: 0,
+        }
+    }
+}
+
+impl<R: BufRead> BufRead for SizedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let remaining = self.expected - self.read;
+        let (expected, read) = (self.expected, self.read);
+        let buf = self.inner.fill_buf()?;
+        if buf.is_empty() && remaining > 0 {
+            let err = GitSyncError::TruncatedObject {
+                expected,
+                actual: read,
+            };
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, err));
+        }
+        if !buf.is_empty() && remaining == 0 {
+            let err = GitSyncError::SizeMismatch {
+                expected,
+                actual: read + buf.len(),
+            };
+            return Err(io::Error::new(ErrorKind::InvalidData, err));
+        }
+        Ok(&buf[..buf.len().min(remaining)])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.read += amt;
+        self.inner.consume(amt);
+    }
+}
+
+impl<R: BufRead> Read for SizedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+fn invalid_object(typ: ObjectType, reason: &str) -> GitSyncError {
+    GitSyncError::InvalidObject {
+        typ,
+        reason: reason.to_owned(),
+    }
+}
+
+/// splits a signature appended to a tag message from the message itself
+fn split_signature(message: &str) -> (&str, Option<&str>) {
+    const SIGNATURE_START: [&str; 2] = [
+        "-----BEGIN PGP SIGNATURE-----",
+        "-----BEGIN SSH SIGNATURE-----",
+    ];
+
+    let mut line_start = 0;
+    for line in message.split_inclusive('\n') {
+        if SIGNATURE_START.iter().any(|start| line.starts_with(start)) {
+            return (&message[..line_start], Some(&message[line_start..]));
+        }
+        line_start += line.len();
+    }
+    (message, None)
+}
+
+/// writes a header, continuing multi-line values on lines starting with a space
+fn write_header(write: &mut impl Write, key: &str, value: &str) -> Result<()> {
+    writeln!(write, "{key} {}", value.replace('\n', "\n "))?;
+    Ok(())
+}
+
+/// splits the header section of a commit or tag into `(key, value)` pairs.
+///
+/// Multi-line values (e.g. `gpgsig`) are continued on lines starting with a
+/// space. The leading space is stripped and the lines are joined with `\n`.
+fn parse_headers(typ: ObjectType, headers: &str) -> Result<Vec<(&str, String)>> {
+    let mut fields: Vec<(&str, String)> = Vec::new();
+    for line in headers.split('\n') {
+        if let Some(continuation) = line.strip_prefix(' ') {
+            let (_, value) = fields
+                .last_mut()
+                .ok_or_else(|| invalid_object(typ, "header continuation without a header"))?;
+            value.push('\n');
+            value.push_str(continuation);
+        } else {
+            let (key, value) = line
+                .split_once(' ')
+                .ok_or_else(|| invalid_object(typ, "invalid header line"))?;
+            fields.push((key, value.to_owned()));
+        }
+    }
+    Ok(fields)
+}
+
+/// a writer that only counts the bytes written to it
+struct ByteCount(u64);
+
+impl Write for ByteCount {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// writes everything to `A` and mirrors exactly the bytes `A` accepted to
+/// `B`, usually a hasher
+struct SplitWrite<'l, A, B>(&'l mut A, &'l mut B);
+
+impl<'l, A: Write, B: Write> Write for SplitWrite<'l, A, B> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let size = self.0.write(buf)?;
+        // a short write of `A` is retried by the caller with the rest, so
+        // `B` must not see more than `A` took
+        self.1.write_all(&buf[..size])?;
+        Ok(size)
     }
 
     fn flush(&mut self) -> std::io::Result<()> {
@@ -190,12 +877,28 @@ impl<'l, A: Write, B: Write> Write for SplitWrite<'l, A, B> {
 
 #[cfg(test)]
 mod test {
-    use std::{fs::OpenOptions, io::Read};
+    use std::{
+        fs::OpenOptions,
+        io::{BufReader, ErrorKind, Read, Write},
+    };
 
-    use flate2::{bufread::ZlibDecoder, Compression};
+    use flate2::{bufread::ZlibDecoder, write::ZlibEncoder, Compression};
     use test_dir::DirBuilder;
 
-    use crate::{test_utils, Object, ObjectType, Repository};
+    use super::{from_hex, quote_path, write_loose_object, SizedReader, SplitWrite};
+    use crate::{
+        test_utils, to_hex, GitSyncError, HashAlgorithm, Object, ObjectType, Repository, TreeEntry,
+    };
+
+    const MERGE_COMMIT: &[u8] = b"tree 3d8a457a40a1b21da38ebcb0ab009e4e997a5bb9\n\
+parent 09ed861f6cd57e64a23811b30686bb4919a41674\n\
+parent 25a7b65d7c4c638b508f306909cb719db11cb7c2\n\
+author Test User <test@example.com> 1700000000 +0100\n\
+committer Other <other@example.com> 1700000300 +0000\n\
+encoding ISO-8859-1\n\
+gpgsig -----BEGIN PGP SIGNATURE-----\n \n iQEzBAABCAAdFiEE\n -----END PGP SIGNATURE-----\n\
+\n\
+merge\n";
 
     #[test]
     fn read_blob_object() {
@@ -205,7 +908,7 @@ mod test {
 
         let repo = Repository::new(test_dir.root()).unwrap();
 
-        let path = Repository::sha1_to_object(BLOB_SHA1);
+        let path = Repository::hash_to_object(BLOB_SHA1);
 
         let obj_file = repo
             .file(path, OpenOptions::new().read(true), false)
@@ -219,6 +922,365 @@ mod test {
         }
     }
 
+    #[test]
+    fn read_commit_object() {
+        const COMMIT_SHA1: &str = "1e3e965f92c57b26cb86b4313edca25125678074";
+
+        let test_dir = test_utils::existing_test_repo("linear_history");
+
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let path = Repository::hash_to_object(COMMIT_SHA1);
+
+        let obj_file = repo
+            .file(path, OpenOptions::new().read(true), false)
+            .unwrap();
+
+        let obj = Object::deserialize_zlib_read(obj_file).unwrap();
+
+        match obj {
+            Object::Commit {
+                tree,
+                parents,
+                author,
+                committer,
+                message,
+                ..
+            } => {
+                assert_eq!(tree, "3d8a457a40a1b21da38ebcb0ab009e4e997a5bb9");
+                assert_eq!(parents, vec!["09ed861f6cd57e64a23811b30686bb4919a41674"]);
+                assert_eq!(author.name, "Test User");
+                assert_eq!(author.email, "test@example.com");
+                assert_eq!(author.time, 1700000200);
+                assert_eq!(author.tz_offset, -(8 * 60 + 30));
+                assert_eq!(committer, author);
+                assert_eq!(message, b"third commit\n");
+            }
+            _ => panic!("expected commit!"),
+        }
+    }
+
+    #[test]
+    fn parse_root_and_merge_commits() {
+        const ROOT_COMMIT: &[u8] = b"tree 01304958cafbf532dc719599cbf542855ec8c15f\n\
+author Test User <test@example.com> 1700000000 +0100\n\
+committer Test User <test@example.com> 1700000000 +0100\n\
+\n\
+first commit\n";
+
+        match Object::deserialize(ObjectType::Commit, ROOT_COMMIT.into()).unwrap() {
+            Object::Commit {
+                parents, gpgsig, ..
+            } => {
+                assert!(parents.is_empty());
+                assert_eq!(gpgsig, None);
+            }
+            _ => panic!("expected commit!"),
+        }
+
+        match Object::deserialize(ObjectType::Commit, MERGE_COMMIT.into()).unwrap() {
+            Object::Commit {
+                parents,
+                committer,
+                encoding,
+                gpgsig,
+                message,
+                ..
+            } => {
+                assert_eq!(
+                    parents,
+                    vec![
+                        "09ed861f6cd57e64a23811b30686bb4919a41674",
+                        "25a7b65d7c4c638b508f306909cb719db11cb7c2"
+                    ]
+                );
+                assert_eq!(committer.name, "Other");
+                assert_eq!(committer.tz_offset, 0);
+                assert_eq!(encoding.as_deref(), Some("ISO-8859-1"));
+                assert_eq!(
+                    gpgsig.as_deref(),
+                    Some("-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEE\n-----END PGP SIGNATURE-----")
+                );
+                assert_eq!(message, b"merge\n");
+            }
+            _ => panic!("expected commit!"),
+        }
+    }
+
+    #[test]
+    fn roundtrip_commit() {
+        const COMMIT_SHA1: &str = "1e3e965f92c57b26cb86b4313edca25125678074";
+
+        let test_dir = test_utils::existing_test_repo("linear_history");
+
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let path = Repository::hash_to_object(COMMIT_SHA1);
+
+        let mut obj_file = repo
+            .file(path, OpenOptions::new().read(true), false)
+            .unwrap();
+
+        let mut zlib = Vec::new();
+        obj_file.read_to_end(&mut zlib).unwrap();
+
+        let obj = Object::deserialize_zlib(zlib.as_slice()).unwrap();
+
+        let mut result = Vec::new();
+        let sha1 = obj
+            .serialize_with_header(&mut result, HashAlgorithm::Sha1)
+            .unwrap();
+        assert_eq!(sha1, COMMIT_SHA1);
+
+        let mut expected = Vec::new();
+        let mut decoder = ZlibDecoder::new(zlib.as_slice());
+        decoder.read_to_end(&mut expected).unwrap();
+
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn roundtrip_signed_merge_commit() {
+        const MERGE_SHA1: &str = "d6619dbc365fbfcd07f8f7400d33b6798e29e948";
+
+        let obj = Object::deserialize(ObjectType::Commit, MERGE_COMMIT.into()).unwrap();
+
+        let mut result = Vec::new();
+        obj.serialize(&mut result).unwrap();
+
+        assert_eq!(result, MERGE_COMMIT);
+        assert_eq!(obj.sha1(), MERGE_SHA1);
+    }
+
+    #[test]
+    fn roundtrip_latin1_commit() {
+        const LATIN1_COMMIT: &[u8] = b"tree 01304958cafbf532dc719599cbf542855ec8c15f\n\
+author Test User <test@example.com> 1700000000 +0100\n\
+committer Test User <test@example.com> 1700000000 +0100\n\
+encoding ISO-8859-1\n\
+\n\
+caf\xe9\n";
+
+        let obj = Object::deserialize(ObjectType::Commit, LATIN1_COMMIT.into()).unwrap();
+        let Object::Commit { message, .. } = &obj else {
+            panic!("expected commit!");
+        };
+        assert_eq!(message, b"caf\xe9\n");
+
+        let mut result = Vec::new();
+        obj.serialize(&mut result).unwrap();
+        assert_eq!(result, LATIN1_COMMIT);
+    }
+
+    #[test]
+    fn roundtrip_commit_with_extra_headers() {
+        const EXTRA_HEADERS_COMMIT: &[u8] = b"tree 01304958cafbf532dc719599cbf542855ec8c15f\n\
+parent 09ed861f6cd57e64a23811b30686bb4919a41674\n\
+parent 25a7b65d7c4c638b508f306909cb719db11cb7c2\n\
+author Test User <test@example.com> 1700000000 +0100\n\
+committer Test User <test@example.com> 1700000000 +0100\n\
+mergetag object 25a7b65d7c4c638b508f306909cb719db11cb7c2\n type commit\n tag v1.0\n \n release\n\
+gpgsig-sha256 -----BEGIN PGP SIGNATURE-----\n \n iQEzBAABCAAdFiEE\n -----END PGP SIGNATURE-----\n\
+gpgsig -----BEGIN PGP SIGNATURE-----\n \n iQEzBAABCAAdFiEE\n -----END PGP SIGNATURE-----\n\
+\n\
+merge v1.0\n";
+
+        let obj = Object::deserialize(ObjectType::Commit, EXTRA_HEADERS_COMMIT.into()).unwrap();
+        let Object::Commit {
+            extra_headers,
+            gpgsig,
+            ..
+        } = &obj
+        else {
+            panic!("expected commit!");
+        };
+        let keys: Vec<&str> = extra_headers.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["mergetag", "gpgsig-sha256"]);
+        assert_eq!(
+            extra_headers[0].1,
+            "object 25a7b65d7c4c638b508f306909cb719db11cb7c2\ntype commit\ntag v1.0\n\nrelease"
+        );
+        assert!(gpgsig.is_some());
+
+        let mut result = Vec::new();
+        obj.serialize(&mut result).unwrap();
+        assert_eq!(result, EXTRA_HEADERS_COMMIT);
+    }
+
+    #[test]
+    fn roundtrip_negative_zero_timezone() {
+        const UTC_COMMIT: &[u8] = b"tree 01304958cafbf532dc719599cbf542855ec8c15f\n\
+author Test User <test@example.com> 1700000000 -0000\n\
+committer Test User <test@example.com> 1700000000 +0000\n\
+\n\
+first commit\n";
+
+        let obj = Object::deserialize(ObjectType::Commit, UTC_COMMIT.into()).unwrap();
+        let Object::Commit {
+            author, committer, ..
+        } = &obj
+        else {
+            panic!("expected commit!");
+        };
+        assert_eq!((author.time, author.tz_offset), (1700000000, 0));
+        assert_eq!(author.time_str(), "1700000000 -0000");
+        assert_eq!(committer.raw_time, None);
+
+        let mut result = Vec::new();
+        obj.serialize(&mut result).unwrap();
+        assert_eq!(result, UTC_COMMIT);
+    }
+
+    #[test]
+    fn read_tree_object() {
+        const TREE_SHA1: &str = "3d8a457a40a1b21da38ebcb0ab009e4e997a5bb9";
+
+        let test_dir = test_utils::existing_test_repo("linear_history");
+
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let path = Repository::hash_to_object(TREE_SHA1);
+
+        let obj_file = repo
+            .file(path, OpenOptions::new().read(true), false)
+            .unwrap();
+
+        let obj = Object::deserialize_zlib_read(obj_file).unwrap();
+
+        match obj {
+            Object::Tree { entries } => {
+                assert_eq!(entries.len(), 3);
+
+                let blob = &entries[0];
+                assert_eq!(blob.mode, "100644");
+                assert_eq!(blob.name, b"a.txt");
+                assert_eq!(blob.sha1(), "06fcdd77c9348567c50638b30d406500f521c304");
+                assert!(!blob.is_tree());
+
+                let subtree = &entries[2];
+                assert_eq!(subtree.mode, "40000");
+                assert_eq!(subtree.name, b"dir");
+                assert_eq!(subtree.sha1(), "1fb43f1b1d0e9e6f60f6b41185b21cd30319d0db");
+                assert!(subtree.is_tree());
+            }
+            _ => panic!("expected tree!"),
+        }
+    }
+
+    #[test]
+    fn tree_sha1_uses_git_order() {
+        const BLOB_SHA1: &str = "2bb09523ce4baf1940ee8fef49f6cade5afe3d03";
+        const SUBTREE_SHA1: &str = "1fb43f1b1d0e9e6f60f6b41185b21cd30319d0db";
+
+        let entry = |mode: &str, name: &str, sha1: &str| TreeEntry {
+            mode: mode.to_owned(),
+            name: name.as_bytes().to_vec(),
+            sha: from_hex(sha1).unwrap(),
+        };
+
+        let tree = Object::Tree {
+            entries: vec![
+                entry("100644", "foo.txt", BLOB_SHA1),
+                entry("100644", "foo", BLOB_SHA1),
+                entry("40000", "bar", SUBTREE_SHA1),
+            ],
+        };
+        // as computed by `git mktree`
+        assert_eq!(tree.sha1(), "418f6d74e7161bbb62e7d64ecb5ecc7ec039194e");
+
+        let tree = Object::Tree {
+            entries: vec![
+                entry("40000", "foo", SUBTREE_SHA1),
+                entry("100644", "foo.txt", BLOB_SHA1),
+            ],
+        };
+        assert_eq!(tree.sha1(), "f2404b1b0acb91a127ac29a941b62eb2191b68d4");
+    }
+
+    #[test]
+    fn quote_paths_like_git() {
+        assert_eq!(quote_path(b"dir/a.txt"), "dir/a.txt");
+        assert_eq!(quote_path(b"with space"), "with space");
+        assert_eq!(quote_path(b"tab\there"), "\"tab\\there\"");
+        assert_eq!(quote_path(b"quo\"te"), "\"quo\\\"te\"");
+        assert_eq!(quote_path("ü.txt".as_bytes()), "\"\\303\\274.txt\"");
+        assert_eq!(quote_path(b"\xff"), "\"\\377\"");
+    }
+
+    #[test]
+    fn roundtrip_tag() {
+        const TAG_SHA1: &str = "28d0e451b11758598f5e97c62bd8aa8c1061cba5";
+
+        let test_dir = test_utils::existing_test_repo("linear_history");
+
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let path = Repository::hash_to_object(TAG_SHA1);
+
+        let obj_file = repo
+            .file(path, OpenOptions::new().read(true), false)
+            .unwrap();
+
+        let obj = Object::deserialize_zlib_read(obj_file).unwrap();
+
+        match &obj {
+            Object::Tag {
+                object,
+                typ,
+                tag,
+                tagger,
+                message,
+                gpgsig,
+            } => {
+                assert_eq!(object, "25a7b65d7c4c638b508f306909cb719db11cb7c2");
+                assert!(matches!(typ, ObjectType::Commit));
+                assert_eq!(tag, "v1.0");
+                assert_eq!(tagger.as_ref().unwrap().time, 1700000050);
+                assert_eq!(message, "version 1.0\n");
+                assert_eq!(gpgsig, &None);
+            }
+            _ => panic!("expected tag!"),
+        }
+
+        assert_eq!(obj.sha1(), TAG_SHA1);
+    }
+
+    #[test]
+    fn roundtrip_signed_tag() {
+        const SIGNED_TAG: &[u8] = b"object 25a7b65d7c4c638b508f306909cb719db11cb7c2\n\
+type commit\n\
+tag v1.1\n\
+tagger Test User <test@example.com> 1700000050 +0100\n\
+\n\
+signed version\n\
+-----BEGIN PGP SIGNATURE-----\n\
+\n\
+iQEzBAABCAAdFiEE\n\
+-----END PGP SIGNATURE-----\n";
+        const SIGNED_TAG_SHA1: &str = "02f0e67ae2b98f6bc61d09cc49a99e1fa8da6752";
+
+        let obj = Object::deserialize(ObjectType::Tag, SIGNED_TAG.into()).unwrap();
+
+        match &obj {
+            Object::Tag {
+                message, gpgsig, ..
+            } => {
+                assert_eq!(message, "signed version\n");
+                assert!(gpgsig
+                    .as_ref()
+                    .unwrap()
+                    .starts_with("-----BEGIN PGP SIGNATURE-----\n"));
+            }
+            _ => panic!("expected tag!"),
+        }
+
+        let mut result = Vec::new();
+        obj.serialize(&mut result).unwrap();
+
+        assert_eq!(result, SIGNED_TAG);
+        assert_eq!(obj.sha1(), SIGNED_TAG_SHA1);
+    }
+
     #[test]
     fn blob_sha1() {
         const BLOB_DATA: &[u8] = b"this is a simple test blob\n";
@@ -229,6 +1291,238 @@ mod test {
         assert_eq!(obj.sha1(), BLOB_SHA1);
     }
 
+    #[test]
+    fn blob_sha256() {
+        const BLOB_DATA: &[u8] = b"this is a simple test blob\n";
+        // git hash-object with --object-format=sha256
+        const BLOB_SHA256: &str =
+            "9a6d0189d45d52076b31cf0f0188d2672c1c3ac3cfa55bc378ee0bdd22492d8a";
+
+        let obj = Object::deserialize(ObjectType::Blob, BLOB_DATA.into()).unwrap();
+
+        assert_eq!(obj.hash(HashAlgorithm::Sha256), BLOB_SHA256);
+    }
+
+    /// accepts at most 3 bytes per write
+    struct Trickle(Vec<u8>);
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let size = buf.len().min(3);
+            self.0.extend_from_slice(&buf[..size]);
+            Ok(size)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn mirror_short_writes() {
+        let blob = Object::Blob {
+            data: b"hello world\n".to_vec(),
+        };
+        let serialized = b"blob 12\0hello world\n";
+
+        let mut trickle = Trickle(Vec::new());
+        let mut hasher = HashAlgorithm::Sha1.hasher();
+        let mut write = SplitWrite(&mut trickle, &mut hasher);
+        let mut rest = &serialized[..];
+        while !rest.is_empty() {
+            let size = write.write(rest).unwrap();
+            assert!(size <= 3);
+            rest = &rest[size..];
+        }
+        assert_eq!(trickle.0, serialized);
+        assert_eq!(to_hex(&hasher.finalize()), blob.sha1());
+
+        let mut trickle = Trickle(Vec::new());
+        let hash = blob
+            .serialize_with_header(&mut trickle, HashAlgorithm::Sha1)
+            .unwrap();
+        assert_eq!(trickle.0, serialized);
+        assert_eq!(hash, blob.sha1());
+    }
+
+    #[test]
+    fn serialized_len() {
+        let test_dir = test_utils::existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        for sha1 in [
+            // tree
+            "3d8a457a40a1b21da38ebcb0ab009e4e997a5bb9",
+            // commit
+            "1e3e965f92c57b26cb86b4313edca25125678074",
+            // blob
+            "06fcdd77c9348567c50638b30d406500f521c304",
+        ] {
+            let object = repo.load_object(sha1).unwrap();
+            let mut data = Vec::new();
+            object.serialize(&mut data).unwrap();
+            assert_eq!(object.serialized_len(), data.len() as u64, "{sha1}");
+            assert_eq!(repo.object_size(sha1).unwrap(), data.len());
+            assert_eq!(object.sha1(), sha1);
+        }
+
+        let merge = Object::deserialize(ObjectType::Commit, MERGE_COMMIT.into()).unwrap();
+        assert_eq!(merge.serialized_len(), MERGE_COMMIT.len() as u64);
+    }
+
+    #[test]
+    fn inflate_deflate_round_trip() {
+        let mut random = 0x2545_f491_u32;
+        let noise: Vec<u8> = (0..10_000)
+            .map(|_| {
+                random ^= random << 13;
+                random ^= random >> 17;
+                random ^= random << 5;
+                random as u8
+            })
+            .collect();
+        for bytes in [&b""[..], b"blob 0\0", b"\0\xff\x78\x9c", &noise] {
+            for comp in [Compression::none(), Compression::best()] {
+                let compressed = Object::deflate(bytes, comp);
+                assert_eq!(Object::inflate(compressed.as_slice()).unwrap(), bytes);
+            }
+        }
+
+        // a loose object inflates to its header and content
+        let (_, zlib) = Object::Blob {
+            data: b"hello\n".to_vec(),
+        }
+        .serialize_zlib();
+        assert_eq!(
+            Object::inflate(zlib.as_slice()).unwrap(),
+            b"blob 6\0hello\n"
+        );
+
+        assert!(Object::inflate(&b"not zlib"[..]).is_err());
+    }
+
+    #[test]
+    fn signed_payload() {
+        let commit = Object::deserialize(ObjectType::Commit, MERGE_COMMIT.into()).unwrap();
+        let (payload, signature) = commit.signed_payload().unwrap();
+        assert_eq!(
+            signature,
+            "-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEE\n-----END PGP SIGNATURE-----"
+        );
+        assert_eq!(
+            payload,
+            b"tree 3d8a457a40a1b21da38ebcb0ab009e4e997a5bb9\n\
+parent 09ed861f6cd57e64a23811b30686bb4919a41674\n\
+parent 25a7b65d7c4c638b508f306909cb719db11cb7c2\n\
+author Test User <test@example.com> 1700000000 +0100\n\
+committer Other <other@example.com> 1700000300 +0000\n\
+encoding ISO-8859-1\n\
+\n\
+merge\n"
+        );
+
+        let tag = b"object 25a7b65d7c4c638b508f306909cb719db11cb7c2\n\
+type commit\n\
+tag v1.1\n\
+tagger Test User <test@example.com> 1700000050 +0100\n\
+\n\
+signed version\n\
+-----BEGIN PGP SIGNATURE-----\n\
+\n\
+iQEzBAABCAAdFiEE\n\
+-----END PGP SIGNATURE-----\n";
+        let tag = Object::deserialize(ObjectType::Tag, tag.to_vec()).unwrap();
+        let (payload, signature) = tag.signed_payload().unwrap();
+        assert_eq!(
+            signature,
+            "-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEE\n-----END PGP SIGNATURE-----\n"
+        );
+        assert!(payload.ends_with(
+            b"tagger Test User <test@example.com> 1700000050 +0100\n\nsigned version\n"
+        ));
+
+        let blob = Object::Blob {
+            data: b"-----BEGIN PGP SIGNATURE-----\n".to_vec(),
+        };
+        assert!(blob.signed_payload().is_none());
+    }
+
+    #[test]
+    fn empty_objects() {
+        let blob = Object::Blob { data: Vec::new() };
+        let tree = Object::Tree {
+            entries: Vec::new(),
+        };
+        for (obj, header, sha1, sha256) in [
+            (
+                &blob,
+                &b"blob 0\0"[..],
+                "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391",
+                "473a0f4c3be8a93681a267e3b1e9a7dcda1185436fe141f7749120a303721813",
+            ),
+            (
+                &tree,
+                b"tree 0\0",
+                "4b825dc642cb6eb9a060e54bf8d69288fbee4904",
+                "6ef19b41225c5369f1c104d45d8d85efa9b057b53b14b4b9b939dd74decc5321",
+            ),
+        ] {
+            assert_eq!(obj.sha1(), sha1);
+            assert_eq!(obj.hash(HashAlgorithm::Sha256), sha256);
+
+            let mut raw = Vec::new();
+            obj.serialize_with_header(&mut raw, HashAlgorithm::Sha1)
+                .unwrap();
+            assert_eq!(raw, header);
+
+            let (hash, zlib) = obj.serialize_zlib();
+            assert_eq!(hash, sha1);
+            let decoded = Object::deserialize_zlib(zlib.as_slice()).unwrap();
+            assert_eq!(decoded.object_type(), obj.object_type());
+            assert_eq!(decoded.sha1(), sha1);
+            let mut reader = BufReader::new(ZlibDecoder::new(zlib.as_slice()));
+            assert_eq!(
+                Object::read_header(&mut reader).unwrap(),
+                (obj.object_type(), 0)
+            );
+        }
+
+        // content after an empty body is still rejected
+        let too_long = zlib_object("blob 0\0", b"x");
+        assert!(matches!(
+            Object::deserialize_zlib(too_long.as_slice()),
+            Err(GitSyncError::SizeMismatch {
+                expected: 0,
+                actual: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn roundtrip_sha256_tree() {
+        const BLOB_SHA256: &str =
+            "9a6d0189d45d52076b31cf0f0188d2672c1c3ac3cfa55bc378ee0bdd22492d8a";
+
+        let tree = Object::Tree {
+            entries: vec![TreeEntry {
+                mode: "100644".to_owned(),
+                name: b"blob.txt".to_vec(),
+                sha: from_hex(BLOB_SHA256).unwrap(),
+            }],
+        };
+        let mut data = Vec::new();
+        tree.serialize(&mut data).unwrap();
+
+        let parsed = Object::deserialize_with(ObjectType::Tree, data, HashAlgorithm::Sha256);
+        match parsed.unwrap() {
+            Object::Tree { entries } => {
+                assert_eq!(entries.len(), 1);
+                assert_eq!(entries[0].sha1(), BLOB_SHA256);
+            }
+            obj => panic!("expected tree, got {obj:?}"),
+        }
+    }
+
     #[test]
     #[ignore = "cant reproduce gits zlib compression."]
     fn zlib_simple_blob() {
@@ -239,7 +1533,7 @@ mod test {
 
         let repo = Repository::new(test_dir.root()).unwrap();
 
-        let path = Repository::sha1_to_object(BLOB_SHA1);
+        let path = Repository::hash_to_object(BLOB_SHA1);
 
         let mut obj_file = repo
             .file(path, OpenOptions::new().read(true), false)
@@ -263,7 +1557,7 @@ mod test {
 
         let repo = Repository::new(test_dir.root()).unwrap();
 
-        let path = Repository::sha1_to_object(BLOB_SHA1);
+        let path = Repository::hash_to_object(BLOB_SHA1);
 
         let mut obj_file = repo
             .file(path, OpenOptions::new().read(true), false)
@@ -275,7 +1569,9 @@ mod test {
         let obj = Object::deserialize_zlib(zlib.as_slice()).unwrap();
 
         let mut result = Vec::new();
-        let sha1 = obj.serialize_with_header(&mut result).unwrap();
+        let sha1 = obj
+            .serialize_with_header(&mut result, HashAlgorithm::Sha1)
+            .unwrap();
         assert_eq!(sha1, BLOB_SHA1);
 
         let mut expected = Vec::new();
@@ -284,4 +1580,148 @@ mod test {
 
         assert_eq!(result, expected);
     }
+
+    /// compresses an object file with `header` and `content`
+    fn zlib_object(header: &str, content: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(header.as_bytes()).unwrap();
+        encoder.write_all(content).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn reject_wrong_content_size() {
+        let too_long = zlib_object("blob 5\0", b"hello world");
+        assert!(matches!(
+            Object::deserialize_zlib(too_long.as_slice()),
+            Err(GitSyncError::SizeMismatch { expected: 5, .. })
+        ));
+
+        let too_short = zlib_object("blob 20\0", b"hello");
+        assert!(matches!(
+            Object::deserialize_zlib(too_short.as_slice()),
+            Err(GitSyncError::TruncatedObject {
+                expected: 20,
+                actual: 5
+            })
+        ));
+    }
+
+    #[test]
+    fn reject_malformed_headers() {
+        let header = |data: &[u8]| Object::read_header(&mut &data[..]);
+
+        assert_eq!(header(b"blob 0\0").unwrap(), (ObjectType::Blob, 0));
+        assert_eq!(header(b"tree 10\0").unwrap(), (ObjectType::Tree, 10));
+
+        for size in [
+            &b"blob 05\0"[..],
+            b"blob 00\0",
+            b"blob \0",
+            b"blob  5\0",
+            b"blob +5\0",
+            b"blob -5\0",
+            b"blob 5 \0",
+            b"blob 5x\0",
+            b"blob 99999999999999999999999999\0",
+        ] {
+            assert!(
+                matches!(header(size), Err(GitSyncError::InvalidObjectSize(_))),
+                "{}",
+                size.escape_ascii()
+            );
+        }
+
+        for (typ, echoed) in [
+            (&b"BLOB 5\0"[..], "BLOB"),
+            (b"blub 5\0", "blub"),
+            (b" blob 5\0", ""),
+            (b"bl\xffb 5\0", "bl\u{fffd}b"),
+        ] {
+            match header(typ) {
+                Err(GitSyncError::UnknownObjectType(actual)) => assert_eq!(actual, echoed),
+                other => panic!("{}: {other:?}", typ.escape_ascii()),
+            }
+        }
+
+        for missing in [
+            &b"blob5\0hello world"[..],
+            b"blob5\0",
+            b"blob",
+            b"blob 5",
+            b"",
+        ] {
+            assert!(
+                matches!(header(missing), Err(GitSyncError::InvalidObjectHeader(_))),
+                "{}",
+                missing.escape_ascii()
+            );
+        }
+
+        let leading_zero = zlib_object("blob 05\0", b"hello");
+        assert!(matches!(
+            Object::deserialize_zlib(leading_zero.as_slice()),
+            Err(GitSyncError::InvalidObjectSize(size)) if size == "05"
+        ));
+    }
+
+    #[test]
+    fn save_atomically() {
+        let test_dir = test_utils::test_dir("save_atomically");
+        let repo = Repository::create_at(test_dir.root()).unwrap();
+        let blob = Object::Blob {
+            data: b"hello\n".to_vec(),
+        };
+        let (sha1, data) = blob.serialize_zlib();
+        let path = repo.loose_object_path(&sha1);
+        let dir = path.parent().unwrap();
+        let files = || -> Vec<_> {
+            std::fs::read_dir(dir)
+                .map(|entries| entries.map(|entry| entry.unwrap().file_name()).collect())
+                .unwrap_or_default()
+        };
+
+        let err = write_loose_object(&path, &data, || {
+            Err(std::io::Error::other("injected").into())
+        })
+        .unwrap_err();
+        assert!(matches!(err, GitSyncError::Io(_)));
+        assert!(files().is_empty(), "{:?}", files());
+        assert!(!repo.has_object(&sha1));
+
+        assert_eq!(blob.save(&repo).unwrap(), sha1);
+        assert_eq!(files(), [path.file_name().unwrap().to_owned()]);
+        assert_eq!(repo.load_object(&sha1).unwrap().sha1(), sha1);
+    }
+
+    #[test]
+    fn stop_streaming_at_declared_size() {
+        let mut reader = SizedReader::new(&b"hello world"[..], 5);
+        let mut buf = [0; 5];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        // fails before the rest of the content is read
+        let err = reader.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        let mut reader = SizedReader::new(&b"hello"[..], 20);
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn decode_piped_object() {
+        let piped = zlib_object("blob 12\0", b"hello world\n");
+        let object = Object::deserialize_zlib_read(piped.as_slice()).unwrap();
+        let mut content = Vec::new();
+        object.serialize(&mut content).unwrap();
+        assert_eq!(content, b"hello world\n");
+
+        // partial and garbage input
+        assert!(Object::deserialize_zlib_read(&piped[..piped.len() / 2]).is_err());
+        assert!(Object::deserialize_zlib_read(&b"not an object"[..]).is_err());
+        assert!(Object::deserialize_zlib_read(&[][..]).is_err());
+        let no_header = zlib_object("", b"hello world\n");
+        assert!(Object::deserialize_zlib_read(no_header.as_slice()).is_err());
+    }
 }
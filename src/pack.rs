@@ -0,0 +1,1093 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::{bufread::ZlibDecoder, write::ZlibEncoder, Compression, Crc};
+use memmap2::Mmap;
+use thiserror::Error;
+
+use crate::{
+    delta::{apply_delta, create_delta, delta_result_size, MAX_DELTA_HEADER},
+    pack_index::PackIndexEntry,
+    to_hex, GitSyncError, HashAlgorithm, ObjectType, PackIndex, Result,
+};
+
+/// a memory mapped `.pack` file.
+///
+/// Objects in a pack are addressed by their offset in the file.
+#[derive(Debug)]
+pub struct Pack {
+    path: PathBuf,
+    data: Mmap,
+    hash: HashAlgorithm,
+    object_count: u32,
+}
+
+/// the header of an object stored in a [Pack]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackEntry {
+    /// offset of the entry in the pack
+    pub offset: u64,
+    pub kind: PackEntryKind,
+    /// size of the decompressed data. For deltas this is the size of the
+    /// delta, not of the object
+    pub size: usize,
+    /// offset of the compressed data
+    data_offset: u64,
+}
+
+/// how the data of a [PackEntry] is stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackEntryKind {
+    /// the full object
+    Object(ObjectType),
+    /// a delta against the entry at this offset in the same pack
+    OfsDelta { base_offset: u64 },
+    /// a delta against the object with this sha1
+    RefDelta { base: String },
+}
+
+/// the type code in the header of a [PackEntry]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackObjectType {
+    Object(ObjectType),
+    OfsDelta,
+    RefDelta,
+}
+
+impl PackObjectType {
+    pub fn to_pack_code(self) -> u8 {
+        match self {
+            PackObjectType::Object(typ) => typ.to_pack_code(),
+            PackObjectType::OfsDelta => 6,
+            PackObjectType::RefDelta => 7,
+        }
+    }
+
+    /// the type of the code, `None` for the unused codes 0 and 5
+    pub fn from_pack_code(code: u8) -> Option<Self> {
+        match code {
+            6 => Some(PackObjectType::OfsDelta),
+            7 => Some(PackObjectType::RefDelta),
+            code => ObjectType::from_pack_code(code).map(PackObjectType::Object),
+        }
+    }
+}
+
+/// an object checked by [Pack::verify], with the columns of
+/// `git verify-pack -v`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedEntry {
+    pub sha1: String,
+    pub typ: ObjectType,
+    /// size of the decompressed data. For deltas this is the size of the
+    /// delta, not of the object
+    pub size: usize,
+    /// size of the entry in the pack, including its header
+    pub packed_size: u64,
+    pub offset: u64,
+    /// the length of the delta chain and the sha1 of the direct base
+    pub delta: Option<(usize, String)>,
+}
+
+/// a problem found by [Pack::verify]
+#[derive(Debug, Error)]
+pub enum PackVerifyError {
+    /// the trailing checksum does not match the content of the pack
+    #[error("pack checksum mismatch: trailer is {expected}, content hashes to {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+
+    /// the index was written for a pack with a different checksum
+    #[error("index belongs to pack {index}, not {pack}")]
+    WrongIndex { index: String, pack: String },
+
+    /// the index and the pack header disagree on the number of objects
+    #[error("index lists {index} objects, but the pack contains {pack}")]
+    CountMismatch { index: usize, pack: usize },
+
+    /// the entry could not be read or its deltas could not be applied
+    #[error("corrupt entry at offset {offset}: {error}")]
+    Corrupt { offset: u64, error: GitSyncError },
+
+    /// the entry hashes to an object that is not in the index
+    #[error("object {sha1} at offset {offset} is not in the index")]
+    NotInIndex { sha1: String, offset: u64 },
+
+    /// the index points to another offset for the object
+    #[error("index lists {sha1} at offset {expected}, but it is stored at {actual}")]
+    OffsetMismatch {
+        sha1: String,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+/// how [write_delta_pack] looks for delta bases
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeltaOptions {
+    /// how many of the previously written objects are tried as base for
+    /// each object, like git's `pack.window`
+    pub window: usize,
+    /// the longest chain of deltas an object can be at the end of, like
+    /// git's `pack.depth`
+    pub depth: usize,
+}
+
+impl Default for DeltaOptions {
+    fn default() -> Self {
+        Self {
+            window: 10,
+            depth: 50,
+        }
+    }
+}
+
+/// the type and content of an object
+type RawObject = (ObjectType, Vec<u8>);
+
+const SIGNATURE: &[u8; 4] = b"PACK";
+const HEADER_LEN: usize = 12;
+/// the longest delta chain that is resolved. Git itself never writes chains
+/// this long, so anything longer is most likely a corrupt pack
+pub(crate) const MAX_DELTA_DEPTH: usize = 4096;
+/// the most memory reserved up front for the data of an entry. Larger
+/// entries grow while they are decompressed, so a corrupt size in an entry
+/// header can't exhaust the memory
+const MAX_PREALLOCATION: usize = 1 << 20;
+
+impl Pack {
+    /// opens and validates the header of the pack at `path`, whose objects
+    /// are named by `hash`
+    pub fn open(path: impl Into<PathBuf>, hash: HashAlgorithm) -> Result<Self> {
+        let path = path.into();
+        let file = File::open(&path)?;
+        if file.metadata()?.len() < (HEADER_LEN + hash.raw_len()) as u64 {
+            return Err(invalid("file is too short"));
+        }
+        // SAFETY: git never modifies a pack once it is written, it only
+        // replaces packs with new files.
+        let data = unsafe { Mmap::map(&file)? };
+
+        if &data[..4] != SIGNATURE {
+            return Err(invalid("missing PACK signature"));
+        }
+        let version = u32::from_be_bytes(data[4..8].try_into().unwrap());
+        if !matches!(version, 2 | 3) {
+            return Err(invalid(&format!("unsupported version {version}")));
+        }
+        let object_count = u32::from_be_bytes(data[8..12].try_into().unwrap());
+
+        Ok(Self {
+            path,
+            data,
+            hash,
+            object_count,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// the number of objects the header declares
+    pub fn object_count(&self) -> u32 {
+        self.object_count
+    }
+
+    /// the entries after the header up to the trailing checksum
+    fn content(&self) -> &[u8] {
+        &self.data[..self.data.len() - self.hash.raw_len()]
+    }
+
+    /// reads the header of the entry at `offset`
+    pub fn entry(&self, offset: u64) -> Result<PackEntry> {
+        let content = self.content();
+        let mut pos = usize::try_from(offset)
+            .ok()
+            .filter(|&pos| pos >= HEADER_LEN && pos < content.len())
+            .ok_or_else(|| invalid(&format!("offset {offset} is out of bounds")))?;
+        let mut next_byte = || {
+            let byte = *content
+                .get(pos)
+                .ok_or_else(|| invalid("entry header is truncated"))?;
+            pos += 1;
+            Ok::<u8, GitSyncError>(byte)
+        };
+
+        // the first byte stores the type and the lowest 4 bits of the size,
+        // followed by 7 bits of the size per byte while the msb is set
+        let mut byte = next_byte()?;
+        let type_code = (byte >> 4) & 0b111;
+        let mut size = (byte & 0b1111) as usize;
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            byte = next_byte()?;
+            if shift > usize::BITS - 7 {
+                return Err(invalid("entry size is too large"));
+            }
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+        }
+
+        let typ = PackObjectType::from_pack_code(type_code)
+            .ok_or_else(|| invalid(&format!("unknown object type {type_code}")))?;
+        let kind = match typ {
+            PackObjectType::Object(typ) => PackEntryKind::Object(typ),
+            PackObjectType::OfsDelta => {
+                // big endian with 7 bits per byte, where every continuation
+                // adds 1 so that there is only one encoding per offset
+                let mut byte = next_byte()?;
+                let mut distance = (byte & 0x7f) as u64;
+                while byte & 0x80 != 0 {
+                    byte = next_byte()?;
+                    distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+                }
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .filter(|_| distance != 0)
+                    .ok_or_else(|| invalid("delta base offset is out of bounds"))?;
+                PackEntryKind::OfsDelta { base_offset }
+            }
+            PackObjectType::RefDelta => {
+                let base = content
+                    .get(pos..pos + self.hash.raw_len())
+                    .ok_or_else(|| invalid("delta base is truncated"))?;
+                pos += self.hash.raw_len();
+                PackEntryKind::RefDelta { base: to_hex(base) }
+            }
+        };
+
+        Ok(PackEntry {
+            offset,
+            kind,
+            size,
+            data_offset: pos as u64,
+        })
+    }
+
+    /// decompresses the data of `entry` and returns it along with the length
+    /// of the compressed data
+    fn inflate(&self, entry: &PackEntry) -> Result<(Vec<u8>, u64)> {
+        let compressed = &self.content()[entry.data_offset as usize..];
+        let mut decoder = ZlibDecoder::new(compressed);
+        let mut data = Vec::with_capacity(entry.size.min(MAX_PREALLOCATION));
+        // one byte more than declared is enough to detect a size mismatch
+        (&mut decoder)
+            .take((entry.size as u64).saturating_add(1))
+            .read_to_end(&mut data)
+            .map_err(|err| invalid(&format!("entry at {}: {err}", entry.offset)))?;
+        if data.len() != entry.size {
+            return Err(GitSyncError::SizeMismatch {
+                expected: entry.size,
+                actual: data.len(),
+            });
+        }
+        Ok((data, decoder.total_in()))
+    }
+
+    /// the type and size of the object stored at `offset`, without
+    /// reconstructing it.
+    ///
+    /// Only the entry headers of the delta chain and the start of the first
+    /// delta are decompressed. `base_type` returns the type of the bases of
+    /// ref deltas.
+    pub fn object_info(
+        &self,
+        offset: u64,
+        base_type: &mut dyn FnMut(&str) -> Result<ObjectType>,
+    ) -> Result<(ObjectType, usize)> {
+        let mut entry = self.entry(offset)?;
+        let size = match &entry.kind {
+            PackEntryKind::Object(typ) => return Ok((*typ, entry.size)),
+            _ => delta_result_size(&self.inflate_prefix(&entry, MAX_DELTA_HEADER)?)?,
+        };
+
+        for _ in 0..=MAX_DELTA_DEPTH {
+            match &entry.kind {
+                PackEntryKind::Object(typ) => return Ok((*typ, size)),
+                PackEntryKind::OfsDelta { base_offset } => entry = self.entry(*base_offset)?,
+                PackEntryKind::RefDelta { base } => return Ok((base_type(base)?, size)),
+            }
+        }
+        Err(invalid(&format!(
+            "delta chain of entry at {offset} is too deep"
+        )))
+    }
+
+    /// decompresses at most the first `len` bytes of the data of `entry`
+    fn inflate_prefix(&self, entry: &PackEntry, len: usize) -> Result<Vec<u8>> {
+        let compressed = &self.content()[entry.data_offset as usize..];
+        let mut prefix = Vec::with_capacity(len);
+        ZlibDecoder::new(compressed)
+            .take(len as u64)
+            .read_to_end(&mut prefix)
+            .map_err(|err| invalid(&format!("entry at {}: {err}", entry.offset)))?;
+        Ok(prefix)
+    }
+
+    /// reads the object stored at `offset`.
+    ///
+    /// Deltas against other entries of this pack are resolved, ref deltas
+    /// against objects outside of it fail with [GitSyncError::ObjectNotFound].
+    /// Use [Pack::read_object_with] to resolve those.
+    pub fn read_object(&self, offset: u64) -> Result<(ObjectType, Vec<u8>)> {
+        self.read_object_with(offset, &mut |base| {
+            Err(GitSyncError::ObjectNotFound {
+                sha1: base.to_owned(),
+            })
+        })
+    }
+
+    /// reads the object stored at `offset`, using `resolve_base` to read the
+    /// bases of ref deltas
+    pub fn read_object_with(
+        &self,
+        offset: u64,
+        resolve_base: &mut dyn FnMut(&str) -> Result<RawObject>,
+    ) -> Result<(ObjectType, Vec<u8>)> {
+        // follow the chain down to the base and apply the deltas on the way
+        // back up. The base of an ofs delta is always stored before it, so the
+        // chain can't contain cycles
+        let mut deltas = Vec::new();
+        let mut entry = self.entry(offset)?;
+        let (typ, mut data) = loop {
+            if deltas.len() > MAX_DELTA_DEPTH {
+                return Err(invalid(&format!(
+                    "delta chain of entry at {offset} is too deep"
+                )));
+            }
+            match &entry.kind {
+                PackEntryKind::Object(typ) => break (*typ, self.inflate(&entry)?.0),
+                PackEntryKind::OfsDelta { base_offset } => {
+                    let base_offset = *base_offset;
+                    deltas.push(self.inflate(&entry)?.0);
+                    entry = self.entry(base_offset)?;
+                }
+                PackEntryKind::RefDelta { base } => {
+                    deltas.push(self.inflate(&entry)?.0);
+                    break resolve_base(base)?;
+                }
+            }
+        };
+
+        for delta in deltas.iter().rev() {
+            data = apply_delta(&data, delta)?;
+        }
+        Ok((typ, data))
+    }
+
+    /// the checksum at the end of the pack
+    pub fn checksum(&self) -> String {
+        to_hex(&self.data[self.content().len()..])
+    }
+
+    /// checks the pack and every entry in it against `index`.
+    ///
+    /// Every object is reconstructed and rehashed. Returns the objects in the
+    /// order they are stored along with all problems that were found.
+    pub fn verify(&self, index: &PackIndex) -> (Vec<VerifiedEntry>, Vec<PackVerifyError>) {
+        let mut errors = Vec::new();
+
+        let mut hasher = self.hash.hasher();
+        hasher.write_all(self.content()).unwrap();
+        let actual = to_hex(&hasher.finalize());
+        if actual != self.checksum() {
+            errors.push(PackVerifyError::ChecksumMismatch {
+                expected: self.checksum(),
+                actual,
+            });
+        }
+        if index.pack_checksum() != self.checksum() {
+            errors.push(PackVerifyError::WrongIndex {
+                index: index.pack_checksum(),
+                pack: self.checksum(),
+            });
+        }
+        if index.object_count() != self.object_count as usize {
+            errors.push(PackVerifyError::CountMismatch {
+                index: index.object_count(),
+                pack: self.object_count as usize,
+            });
+        }
+
+        let mut headers = Vec::new();
+        let mut entries = self.entries();
+        while let Some(entry) = entries.next() {
+            match entry {
+                Ok(entry) => headers.push(entry),
+                // entries are only found by decompressing the previous one,
+                // so nothing after this can be read
+                Err(error) => errors.push(PackVerifyError::Corrupt {
+                    offset: entries.offset,
+                    error,
+                }),
+            }
+        }
+
+        // sha1 and delta depth by offset. The bases of ofs deltas are stored
+        // before them, so they are always known
+        let mut known: HashMap<u64, (String, usize)> = HashMap::new();
+        let mut depths: HashMap<String, usize> = HashMap::new();
+        let mut verified = Vec::new();
+        for (i, entry) in headers.iter().enumerate() {
+            let (typ, data) = match self
+                .read_object_with(entry.offset, &mut |base| self.resolve_in(index, base, 1))
+            {
+                Ok(object) => object,
+                Err(error) => {
+                    errors.push(PackVerifyError::Corrupt {
+                        offset: entry.offset,
+                        error,
+                    });
+                    continue;
+                }
+            };
+            let sha1 = object_hash(self.hash, typ, &data);
+
+            match index.find(&sha1) {
+                None => errors.push(PackVerifyError::NotInIndex {
+                    sha1: sha1.clone(),
+                    offset: entry.offset,
+                }),
+                Some(expected) if expected != entry.offset => {
+                    errors.push(PackVerifyError::OffsetMismatch {
+                        sha1: sha1.clone(),
+                        expected,
+                        actual: entry.offset,
+                    })
+                }
+                Some(_) => {}
+            }
+
+            let delta = match &entry.kind {
+                PackEntryKind::Object(_) => None,
+                PackEntryKind::OfsDelta { base_offset } => known
+                    .get(base_offset)
+                    .map(|(base, depth)| (depth + 1, base.clone())),
+                PackEntryKind::RefDelta { base } => {
+                    Some((depths.get(base).map_or(1, |depth| depth + 1), base.clone()))
+                }
+            };
+            let depth = delta.as_ref().map_or(0, |(depth, _)| *depth);
+            known.insert(entry.offset, (sha1.clone(), depth));
+            depths.insert(sha1.clone(), depth);
+
+            let end = headers
+                .get(i + 1)
+                .map_or(self.content().len() as u64, |next| next.offset);
+            verified.push(VerifiedEntry {
+                sha1,
+                typ,
+                size: entry.size,
+                packed_size: end - entry.offset,
+                offset: entry.offset,
+                delta,
+            });
+        }
+
+        (verified, errors)
+    }
+
+    /// reads the object `sha1` through `index`, resolving ref deltas against
+    /// other objects in this pack
+    fn resolve_in(&self, index: &PackIndex, sha1: &str, depth: usize) -> Result<RawObject> {
+        if depth > MAX_DELTA_DEPTH {
+            return Err(invalid(&format!("delta chain of {sha1} is too deep")));
+        }
+        let offset = index
+            .find(sha1)
+            .ok_or_else(|| GitSyncError::ObjectNotFound {
+                sha1: sha1.to_owned(),
+            })?;
+        self.read_object_with(offset, &mut |base| self.resolve_in(index, base, depth + 1))
+    }
+
+    /// hashes every object in the pack to build its index, without needing
+    /// an existing index.
+    ///
+    /// Ref deltas against objects of this pack are resolved in any order,
+    /// `resolve_base` reads the bases that are not part of it.
+    pub fn index_entries(
+        &self,
+        resolve_base: &mut dyn FnMut(&str) -> Result<RawObject>,
+    ) -> Result<Vec<PackIndexEntry>> {
+        let headers = self.entries().collect::<Result<Vec<_>>>()?;
+
+        // ref delta bases can be stored after the delta, so entries whose
+        // base is not known yet are retried once the other entries are hashed
+        let mut known: HashMap<String, u64> = HashMap::new();
+        let mut indexed = Vec::with_capacity(headers.len());
+        let mut pending: Vec<usize> = (0..headers.len()).collect();
+        while !pending.is_empty() {
+            let mut deferred = Vec::new();
+            let mut last_error = None;
+            for &i in &pending {
+                let entry = &headers[i];
+                let object = self.read_object_with(entry.offset, &mut |base| {
+                    self.resolve_known(&known, base, resolve_base, 1)
+                });
+                let (typ, data) = match object {
+                    Ok(object) => object,
+                    Err(err @ GitSyncError::ObjectNotFound { .. }) => {
+                        deferred.push(i);
+                        last_error = Some(err);
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                };
+                let sha1 = object_hash(self.hash, typ, &data);
+
+                let end = headers
+                    .get(i + 1)
+                    .map_or(self.content().len() as u64, |next| next.offset);
+                let mut crc = Crc::new();
+                crc.update(&self.data[entry.offset as usize..end as usize]);
+
+                known.insert(sha1.clone(), entry.offset);
+                indexed.push(PackIndexEntry {
+                    sha1,
+                    offset: entry.offset,
+                    crc32: crc.sum(),
+                });
+            }
+            if deferred.len() == pending.len() {
+                return Err(last_error.unwrap());
+            }
+            pending = deferred;
+        }
+        Ok(indexed)
+    }
+
+    /// reads the object `sha1`, which is the entry at `known[sha1]` or read
+    /// by `resolve_base` from outside of the pack
+    fn resolve_known(
+        &self,
+        known: &HashMap<String, u64>,
+        sha1: &str,
+        resolve_base: &mut dyn FnMut(&str) -> Result<RawObject>,
+        depth: usize,
+    ) -> Result<RawObject> {
+        if depth > MAX_DELTA_DEPTH {
+            return Err(invalid(&format!("delta chain of {sha1} is too deep")));
+        }
+        match known.get(sha1) {
+            Some(&offset) => self.read_object_with(offset, &mut |base| {
+                self.resolve_known(known, base, resolve_base, depth + 1)
+            }),
+            None => resolve_base(sha1),
+        }
+    }
+
+    /// iterates over the headers of all entries in the order they are stored
+    pub fn entries(&self) -> PackEntries<'_> {
+        PackEntries {
+            pack: self,
+            offset: HEADER_LEN as u64,
+            remaining: self.object_count,
+        }
+    }
+}
+
+/// iterator over the entries of a [Pack], created by [Pack::entries]
+pub struct PackEntries<'p> {
+    pack: &'p Pack,
+    offset: u64,
+    remaining: u32,
+}
+
+impl Iterator for PackEntries<'_> {
+    type Item = Result<PackEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        // the next entry starts after the compressed data of this one
+        let entry = self.pack.entry(self.offset).and_then(|entry| {
+            let (_, compressed_len) = self.pack.inflate(&entry)?;
+            self.offset = entry.data_offset + compressed_len;
+            Ok(entry)
+        });
+        if entry.is_err() {
+            self.remaining = 0;
+        }
+        Some(entry)
+    }
+}
+
+/// serializes `objects` as a version 2 pack, with every object stored in
+/// full. Objects are written in the given order
+pub fn write_pack(objects: &[(ObjectType, Vec<u8>)], hash: HashAlgorithm) -> Vec<u8> {
+    let mut pack = Vec::new();
+    pack.extend_from_slice(SIGNATURE);
+    pack.extend_from_slice(&2u32.to_be_bytes());
+    pack.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+
+    for (typ, data) in objects {
+        write_entry_header(&mut pack, typ.to_pack_code(), data.len());
+        write_compressed(&mut pack, data);
+    }
+
+    let mut hasher = hash.hasher();
+    hasher.write_all(&pack).unwrap();
+    pack.extend_from_slice(&hasher.finalize());
+    pack
+}
+
+/// serializes `objects` as a version 2 pack, storing objects as deltas
+/// against similar objects where that is smaller.
+///
+/// Like git, objects are sorted by type and size, largest first, and each
+/// object is compared to the `window` objects before it. The smallest
+/// delta is stored as an offset delta, if it saves at least half of the
+/// object. No chain of deltas is longer than `depth`.
+pub fn write_delta_pack(
+    objects: &[(ObjectType, Vec<u8>)],
+    hash: HashAlgorithm,
+    options: &DeltaOptions,
+) -> Vec<u8> {
+    let mut order: Vec<&(ObjectType, Vec<u8>)> = objects.iter().collect();
+    order.sort_by_key(|(typ, data)| (typ.to_pack_code(), Reverse(data.len())));
+
+    let mut pack = Vec::new();
+    pack.extend_from_slice(SIGNATURE);
+    pack.extend_from_slice(&2u32.to_be_bytes());
+    pack.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+
+    // the offset and delta depth of every written entry
+    let mut written: Vec<(usize, usize)> = Vec::with_capacity(order.len());
+    for (n, (typ, data)) in order.iter().enumerate() {
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        for base in n.saturating_sub(options.window)..n {
+            let (base_typ, base_data) = order[base];
+            if base_typ != typ || written[base].1 >= options.depth {
+                continue;
+            }
+            let delta = create_delta(base_data, data);
+            let limit = best.as_ref().map_or(data.len() / 2, |(_, best)| best.len());
+            if delta.len() < limit {
+                best = Some((base, delta));
+            }
+        }
+
+        let offset = pack.len();
+        match best {
+            Some((base, delta)) => {
+                let (base_offset, base_depth) = written[base];
+                write_entry_header(
+                    &mut pack,
+                    PackObjectType::OfsDelta.to_pack_code(),
+                    delta.len(),
+                );
+                write_base_offset(&mut pack, offset - base_offset);
+                write_compressed(&mut pack, &delta);
+                written.push((offset, base_depth + 1));
+            }
+            None => {
+                write_entry_header(&mut pack, typ.to_pack_code(), data.len());
+                write_compressed(&mut pack, data);
+                written.push((offset, 0));
+            }
+        }
+    }
+
+    let mut hasher = hash.hasher();
+    hasher.write_all(&pack).unwrap();
+    pack.extend_from_slice(&hasher.finalize());
+    pack
+}
+
+fn write_compressed(out: &mut Vec<u8>, data: &[u8]) {
+    let mut encoder = ZlibEncoder::new(out, Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap();
+}
+
+/// the distance from an offset delta back to its base. Every byte but the
+/// last has the high bit set and adds 1 before the shift, so no number has
+/// two encodings
+fn write_base_offset(out: &mut Vec<u8>, mut distance: usize) {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance != 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    out.extend_from_slice(&bytes);
+}
+
+/// the inverse of the header parsing in [Pack::entry]: the type and the
+/// lowest 4 bits of the size, followed by 7 bits of the size per byte
+fn write_entry_header(out: &mut Vec<u8>, type_code: u8, size: usize) {
+    let mut byte = (type_code << 4) | (size & 0b1111) as u8;
+    let mut size = size >> 4;
+    while size != 0 {
+        out.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    out.push(byte);
+}
+
+/// the name of an object with the given type and content
+fn object_hash(hash: HashAlgorithm, typ: ObjectType, data: &[u8]) -> String {
+    let mut hasher = hash.hasher();
+    write!(hasher, "{typ} {}\0", data.len()).unwrap();
+    hasher.write_all(data).unwrap();
+    to_hex(&hasher.finalize())
+}
+
+fn invalid(reason: &str) -> GitSyncError {
+    GitSyncError::InvalidPack(reason.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use test_dir::DirBuilder;
+
+    use std::{fs, io::Read};
+
+    use super::{
+        object_hash, write_compressed, write_delta_pack, write_entry_header, write_pack,
+        DeltaOptions, Pack, PackEntryKind, PackObjectType, PackVerifyError,
+    };
+    use crate::test_utils::{existing_test_repo, test_dir};
+    use crate::{GitSyncError, HashAlgorithm, ObjectType, PackIndex, Repository, Result};
+
+    const PACK: &str = ".git/objects/pack/pack-3af1e45a1a333b30392b7a6a1906476cd1f15f6c.pack";
+
+    #[test]
+    fn pack_type_codes() {
+        for typ in [
+            ObjectType::Commit,
+            ObjectType::Tree,
+            ObjectType::Blob,
+            ObjectType::Tag,
+        ] {
+            assert_eq!(ObjectType::from_pack_code(typ.to_pack_code()), Some(typ));
+        }
+
+        let mut valid = Vec::new();
+        for code in 0..=u8::MAX {
+            if let Some(typ) = PackObjectType::from_pack_code(code) {
+                assert_eq!(typ.to_pack_code(), code);
+                valid.push(code);
+            }
+            assert_eq!(
+                ObjectType::from_pack_code(code).map(PackObjectType::Object),
+                PackObjectType::from_pack_code(code).filter(|typ| !matches!(
+                    typ,
+                    PackObjectType::OfsDelta | PackObjectType::RefDelta
+                ))
+            );
+        }
+        assert_eq!(valid, [1, 2, 3, 4, 6, 7]);
+        assert_eq!(
+            PackObjectType::from_pack_code(3),
+            Some(PackObjectType::Object(ObjectType::Blob))
+        );
+        assert_eq!(
+            PackObjectType::from_pack_code(6),
+            Some(PackObjectType::OfsDelta)
+        );
+        assert_eq!(
+            PackObjectType::from_pack_code(7),
+            Some(PackObjectType::RefDelta)
+        );
+    }
+
+    #[test]
+    fn read_pack_entries() {
+        let test_dir = existing_test_repo("packed_objects");
+        let pack = Pack::open(test_dir.root().join(PACK), HashAlgorithm::Sha1).unwrap();
+        assert_eq!(pack.object_count(), 12);
+
+        let entries: Vec<_> = pack.entries().map(|entry| entry.unwrap()).collect();
+        assert_eq!(entries.len(), 12);
+        // offsets as listed by `git verify-pack -v`
+        assert_eq!(entries[0].offset, 12);
+        assert_eq!(entries[0].kind, PackEntryKind::Object(ObjectType::Commit));
+        assert_eq!(entries[8].offset, 794);
+        assert_eq!(entries[8].kind, PackEntryKind::Object(ObjectType::Blob));
+        assert_eq!(entries[8].size, 23);
+
+        let (typ, data) = pack.read_object(794).unwrap();
+        assert_eq!(typ, ObjectType::Blob);
+        assert_eq!(data.len(), 23);
+        // inside the header
+        assert!(pack.read_object(4).is_err());
+    }
+
+    const DELTA_PACK: &str = ".git/objects/pack/pack-9d9506ca781239bd0307c9f2bf8a402b45c27a65.pack";
+    const REF_DELTA_PACK: &str =
+        ".git/objects/pack/refdelta-ffc5d4a1c5198a8f8fe9bd3f6e62eba733b45564.pack";
+
+    /// the content of the loose object `sha1`
+    fn loose_content(repo: &Repository, sha1: &str) -> Vec<u8> {
+        let mut content = Vec::new();
+        repo.open_object_reader(sha1)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        content
+    }
+
+    #[test]
+    fn resolve_ofs_deltas() {
+        let test_dir = existing_test_repo("delta_pack");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let pack = Pack::open(test_dir.root().join(DELTA_PACK), HashAlgorithm::Sha1).unwrap();
+
+        // offsets as listed by `git verify-pack -v`, the blobs are deltas
+        // with depth 1 and 2
+        let entry = pack.entry(1129).unwrap();
+        assert!(matches!(entry.kind, PackEntryKind::OfsDelta { .. }));
+        for (offset, sha1) in [
+            (1102, "c583a4c3eb2eb9a262cce546d6f4037c186628a5"),
+            (1129, "ee0ba3222acb4c84cbd8ddcd92ed4cfea7c90ec7"),
+        ] {
+            let (typ, data) = pack.read_object(offset).unwrap();
+            assert_eq!(typ, ObjectType::Blob);
+            assert_eq!(data, loose_content(&repo, sha1));
+            let info = pack.object_info(offset, &mut |_| unreachable!()).unwrap();
+            assert_eq!(info, (ObjectType::Blob, data.len()));
+        }
+    }
+
+    #[test]
+    fn resolve_ref_deltas() {
+        let test_dir = existing_test_repo("delta_pack");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let pack = Pack::open(test_dir.root().join(REF_DELTA_PACK), HashAlgorithm::Sha1).unwrap();
+        let index = PackIndex::open(
+            test_dir.root().join(REF_DELTA_PACK).with_extension("idx"),
+            HashAlgorithm::Sha1,
+        )
+        .unwrap();
+
+        let sha1 = "ee0ba3222acb4c84cbd8ddcd92ed4cfea7c90ec7";
+        let offset = index.find(sha1).unwrap();
+        assert!(matches!(
+            pack.entry(offset).unwrap().kind,
+            PackEntryKind::RefDelta { .. }
+        ));
+        assert!(matches!(
+            pack.read_object(offset),
+            Err(GitSyncError::ObjectNotFound { .. })
+        ));
+
+        fn resolve(pack: &Pack, index: &PackIndex, sha1: &str) -> Result<(ObjectType, Vec<u8>)> {
+            let offset = index.find(sha1).unwrap();
+            pack.read_object_with(offset, &mut |base| resolve(pack, index, base))
+        }
+        let (typ, data) = resolve(&pack, &index, sha1).unwrap();
+        assert_eq!(typ, ObjectType::Blob);
+        assert_eq!(data, loose_content(&repo, sha1));
+    }
+
+    #[test]
+    fn verify_pack() {
+        let test_dir = existing_test_repo("delta_pack");
+        let path = test_dir.root().join(DELTA_PACK);
+        let pack = Pack::open(&path, HashAlgorithm::Sha1).unwrap();
+        let index = PackIndex::open(path.with_extension("idx"), HashAlgorithm::Sha1).unwrap();
+
+        let (entries, errors) = pack.verify(&index);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(entries.len(), 9);
+        // as listed by `git verify-pack -v`
+        let last_blob = entries
+            .iter()
+            .find(|entry| entry.sha1 == "ee0ba3222acb4c84cbd8ddcd92ed4cfea7c90ec7")
+            .unwrap();
+        assert_eq!(last_blob.typ, ObjectType::Blob);
+        assert_eq!(last_blob.size, 16);
+        assert_eq!(last_blob.packed_size, 27);
+        assert_eq!(last_blob.offset, 1129);
+        assert_eq!(
+            last_blob.delta,
+            Some((2, "c583a4c3eb2eb9a262cce546d6f4037c186628a5".to_owned()))
+        );
+
+        let path = test_dir.root().join(REF_DELTA_PACK);
+        let pack = Pack::open(&path, HashAlgorithm::Sha1).unwrap();
+        let index = PackIndex::open(path.with_extension("idx"), HashAlgorithm::Sha1).unwrap();
+        let (entries, errors) = pack.verify(&index);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            entries.iter().filter(|entry| entry.delta.is_some()).count(),
+            2
+        );
+    }
+
+    #[test]
+    fn index_pack() {
+        let test_dir = existing_test_repo("delta_pack");
+        for path in [DELTA_PACK, REF_DELTA_PACK] {
+            let path = test_dir.root().join(path);
+            let pack = Pack::open(&path, HashAlgorithm::Sha1).unwrap();
+            let entries = pack
+                .index_entries(&mut |base| {
+                    Err(GitSyncError::ObjectNotFound {
+                        sha1: base.to_owned(),
+                    })
+                })
+                .unwrap();
+            assert_eq!(entries.len(), pack.object_count() as usize);
+
+            // the index git wrote must match byte for byte
+            let rebuilt = path.with_extension("rebuilt");
+            PackIndex::write(&rebuilt, &entries, &pack.checksum(), HashAlgorithm::Sha1).unwrap();
+            assert_eq!(
+                fs::read(&rebuilt).unwrap(),
+                fs::read(path.with_extension("idx")).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn write_pack_with_deltas() {
+        let test_dir = test_dir("write_pack_with_deltas");
+        // pseudo random, so that zlib alone can't shrink it
+        let mut state = 1u32;
+        let first: Vec<u8> = (0..100_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let mut second = first.clone();
+        second[500] ^= 1;
+        second.extend_from_slice(b"appended");
+        let mut third = second.clone();
+        third[90_000] ^= 1;
+        let objects = vec![
+            (ObjectType::Blob, first),
+            (ObjectType::Blob, b"small\n".to_vec()),
+            (ObjectType::Blob, second),
+            (ObjectType::Blob, third),
+        ];
+
+        let full = write_pack(&objects, HashAlgorithm::Sha1);
+        let options = DeltaOptions {
+            window: 10,
+            depth: 1,
+        };
+        let deltified = write_delta_pack(&objects, HashAlgorithm::Sha1, &options);
+        assert!(deltified.len() < full.len() / 2);
+
+        let path = test_dir.root().join("deltified.pack");
+        fs::write(&path, deltified).unwrap();
+        let pack = Pack::open(&path, HashAlgorithm::Sha1).unwrap();
+        let entries = pack
+            .index_entries(&mut |base| {
+                Err(GitSyncError::ObjectNotFound {
+                    sha1: base.to_owned(),
+                })
+            })
+            .unwrap();
+        let index_path = path.with_extension("idx");
+        PackIndex::write(&index_path, &entries, &pack.checksum(), HashAlgorithm::Sha1).unwrap();
+        let index = PackIndex::open(&index_path, HashAlgorithm::Sha1).unwrap();
+
+        let (verified, errors) = pack.verify(&index);
+        assert!(errors.is_empty(), "{errors:?}");
+        let deltas: Vec<usize> = verified
+            .iter()
+            .filter_map(|entry| entry.delta.as_ref().map(|(depth, _)| *depth))
+            .collect();
+        // both copies are stored as deltas, but not against each other
+        assert_eq!(deltas, [1, 1]);
+
+        for (typ, data) in &objects {
+            let sha1 = object_hash(HashAlgorithm::Sha1, *typ, data);
+            let offset = index.find(&sha1).unwrap();
+            assert_eq!(&pack.read_object(offset).unwrap(), &(*typ, data.clone()));
+        }
+    }
+
+    #[test]
+    fn write_and_read_pack() {
+        let test_dir = test_dir("write_and_read_pack");
+        let big = vec![b'x'; 5000];
+        let objects = vec![
+            (ObjectType::Blob, b"hello\n".to_vec()),
+            (ObjectType::Blob, big.clone()),
+            (ObjectType::Blob, Vec::new()),
+        ];
+        let path = test_dir.root().join("written.pack");
+        fs::write(&path, write_pack(&objects, HashAlgorithm::Sha1)).unwrap();
+
+        let pack = Pack::open(&path, HashAlgorithm::Sha1).unwrap();
+        assert_eq!(pack.object_count(), 3);
+        let entries: Vec<_> = pack.entries().map(|entry| entry.unwrap()).collect();
+        assert_eq!(entries[1].size, 5000);
+        for (entry, (typ, data)) in entries.iter().zip(&objects) {
+            assert_eq!(
+                &pack.read_object(entry.offset).unwrap(),
+                &(*typ, data.clone())
+            );
+        }
+    }
+
+    #[test]
+    fn reject_wrong_entry_size() {
+        let test_dir = test_dir("reject_wrong_entry_size");
+        let path = test_dir.root().join("huge.pack");
+        let pack = |size: usize| {
+            let mut pack = write_pack(&[], HashAlgorithm::Sha1);
+            pack.truncate(8);
+            pack.extend_from_slice(&1u32.to_be_bytes());
+            write_entry_header(&mut pack, ObjectType::Blob.to_pack_code(), size);
+            write_compressed(&mut pack, b"hello\n");
+            pack.extend_from_slice(&[0; 20]);
+            pack
+        };
+
+        // far too large to allocate
+        fs::write(&path, pack(1 << 50)).unwrap();
+        let pack_file = Pack::open(&path, HashAlgorithm::Sha1).unwrap();
+        assert!(matches!(
+            pack_file.read_object(12),
+            Err(GitSyncError::SizeMismatch { actual: 6, .. })
+        ));
+
+        fs::write(&path, pack(3)).unwrap();
+        let pack_file = Pack::open(&path, HashAlgorithm::Sha1).unwrap();
+        assert!(matches!(
+            pack_file.read_object(12),
+            Err(GitSyncError::SizeMismatch {
+                expected: 3,
+                actual: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn verify_truncated_pack() {
+        let test_dir = existing_test_repo("delta_pack");
+        let path = test_dir.root().join(DELTA_PACK);
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() - 30]).unwrap();
+
+        let pack = Pack::open(&path, HashAlgorithm::Sha1).unwrap();
+        let index = PackIndex::open(path.with_extension("idx"), HashAlgorithm::Sha1).unwrap();
+        let (entries, errors) = pack.verify(&index);
+        assert!(matches!(
+            errors[0],
+            PackVerifyError::ChecksumMismatch { .. }
+        ));
+        assert!(errors
+            .iter()
+            .any(|error| matches!(error, PackVerifyError::Corrupt { .. })));
+        assert!(entries.len() < 9);
+    }
+}
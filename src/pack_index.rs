@@ -0,0 +1,272 @@
+use std::{
+    cmp::Ordering,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use memmap2::Mmap;
+
+use crate::{from_hex, to_hex, GitSyncError, HashAlgorithm, Result};
+
+/// a memory mapped version 2 `.idx` file, which maps the objects of a
+/// [Pack](crate::Pack) to their offsets
+#[derive(Debug)]
+pub struct PackIndex {
+    path: PathBuf,
+    data: Mmap,
+    hash: HashAlgorithm,
+    object_count: usize,
+}
+
+/// an object of a pack as it is stored in the index, see [PackIndex::write]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackIndexEntry {
+    pub sha1: String,
+    /// offset of the entry in the pack
+    pub offset: u64,
+    /// crc32 of the entry's header and compressed data
+    pub crc32: u32,
+}
+
+const SIGNATURE: &[u8; 4] = b"\xfftOc";
+/// signature, version and the 256 entry fanout table
+const HEADER_LEN: usize = 8 + 256 * 4;
+/// offsets with the msb set index into the table of 64 bit offsets
+const LARGE_OFFSET: u32 = 0x8000_0000;
+
+impl PackIndex {
+    /// opens and validates the index at `path`, whose objects are named by
+    /// `hash`
+    pub fn open(path: impl Into<PathBuf>, hash: HashAlgorithm) -> Result<Self> {
+        let path = path.into();
+        let file = File::open(&path)?;
+        if file.metadata()?.len() < (HEADER_LEN + 2 * hash.raw_len()) as u64 {
+            return Err(invalid("file is too short"));
+        }
+        // SAFETY: like packs, indexes are never modified after they are written
+        let data = unsafe { Mmap::map(&file)? };
+
+        if &data[..4] != SIGNATURE {
+            return Err(invalid(
+                "missing index signature, only version 2 is supported",
+            ));
+        }
+        let version = u32::from_be_bytes(data[4..8].try_into().unwrap());
+        if version != 2 {
+            return Err(invalid(&format!("unsupported version {version}")));
+        }
+
+        let mut index = Self {
+            path,
+            data,
+            hash,
+            object_count: 0,
+        };
+        let fanout: Vec<u32> = (0..256).map(|i| index.fanout(i)).collect();
+        if fanout.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(invalid("fanout table is not sorted"));
+        }
+        index.object_count = fanout[255] as usize;
+
+        let min_len = HEADER_LEN + index.object_count * (hash.raw_len() + 8) + 2 * hash.raw_len();
+        if index.data.len() < min_len {
+            return Err(invalid("file is truncated"));
+        }
+        Ok(index)
+    }
+
+    /// writes a version 2 index of `entries` to `path` for the pack with the
+    /// checksum `pack_checksum`
+    pub fn write(
+        path: impl AsRef<Path>,
+        entries: &[PackIndexEntry],
+        pack_checksum: &str,
+        hash: HashAlgorithm,
+    ) -> Result<()> {
+        let mut entries: Vec<(Vec<u8>, &PackIndexEntry)> = entries
+            .iter()
+            .map(|entry| Ok((from_hex(&entry.sha1)?, entry)))
+            .collect::<Result<_>>()?;
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut data = Vec::new();
+        data.extend_from_slice(SIGNATURE);
+        data.extend_from_slice(&2u32.to_be_bytes());
+        for byte in 0..=u8::MAX {
+            let count = entries.iter().filter(|(raw, _)| raw[0] <= byte).count();
+            data.extend_from_slice(&(count as u32).to_be_bytes());
+        }
+        for (raw, _) in &entries {
+            data.extend_from_slice(raw);
+        }
+        for (_, entry) in &entries {
+            data.extend_from_slice(&entry.crc32.to_be_bytes());
+        }
+        let mut large_offsets = Vec::new();
+        for (_, entry) in &entries {
+            let offset = if entry.offset < LARGE_OFFSET as u64 {
+                entry.offset as u32
+            } else {
+                large_offsets.push(entry.offset);
+                LARGE_OFFSET | (large_offsets.len() - 1) as u32
+            };
+            data.extend_from_slice(&offset.to_be_bytes());
+        }
+        for offset in large_offsets {
+            data.extend_from_slice(&offset.to_be_bytes());
+        }
+        data.extend_from_slice(&from_hex(pack_checksum)?);
+
+        let mut hasher = hash.hasher();
+        hasher.write_all(&data)?;
+        data.extend_from_slice(&hasher.finalize());
+        fs::write(path, data)?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// the number of objects in the pack
+    pub fn object_count(&self) -> usize {
+        self.object_count
+    }
+
+    /// the number of objects whose first byte is at most `byte`
+    fn fanout(&self, byte: usize) -> u32 {
+        let start = 8 + byte * 4;
+        u32::from_be_bytes(self.data[start..start + 4].try_into().unwrap())
+    }
+
+    /// the raw sha1 of the `n`-th object in sorted order
+    fn raw_sha1(&self, n: usize) -> &[u8] {
+        let start = HEADER_LEN + n * self.hash.raw_len();
+        &self.data[start..start + self.hash.raw_len()]
+    }
+
+    /// the sha1 of the `n`-th object in sorted order
+    pub fn sha1(&self, n: usize) -> String {
+        to_hex(self.raw_sha1(n))
+    }
+
+    /// the pack offset of the `n`-th object in sorted order
+    pub fn offset(&self, n: usize) -> Result<u64> {
+        let offsets = HEADER_LEN + self.object_count * (self.hash.raw_len() + 4);
+        let start = offsets + n * 4;
+        let offset = u32::from_be_bytes(self.data[start..start + 4].try_into().unwrap());
+        if offset & LARGE_OFFSET == 0 {
+            return Ok(offset as u64);
+        }
+
+        let large_offsets = offsets + self.object_count * 4;
+        let start = large_offsets + (offset & !LARGE_OFFSET) as usize * 8;
+        // the large offsets are followed by the two checksums
+        if start + 8 > self.data.len() - 2 * self.hash.raw_len() {
+            return Err(invalid("large offset is out of bounds"));
+        }
+        Ok(u64::from_be_bytes(
+            self.data[start..start + 8].try_into().unwrap(),
+        ))
+    }
+
+    /// the checksum of the pack this index belongs to
+    pub fn pack_checksum(&self) -> String {
+        let end = self.data.len() - self.hash.raw_len();
+        to_hex(&self.data[end - self.hash.raw_len()..end])
+    }
+
+    /// the sha1s of all objects that start with the hex `prefix`, which is
+    /// at least 2 characters long
+    pub fn find_prefix(&self, prefix: &str) -> Vec<String> {
+        let Some(first) = prefix.get(..2).and_then(|first| from_hex(first).ok()) else {
+            return Vec::new();
+        };
+        let first = first[0] as usize;
+        let start = if first == 0 {
+            0
+        } else {
+            self.fanout(first - 1) as usize
+        };
+        (start..self.fanout(first) as usize)
+            .map(|n| self.sha1(n))
+            .filter(|sha1| sha1.starts_with(prefix))
+            .collect()
+    }
+
+    /// finds the offset of the object `sha1` in the pack
+    pub fn find(&self, sha1: &str) -> Option<u64> {
+        let raw = from_hex(sha1).ok()?;
+        if raw.len() != self.hash.raw_len() {
+            return None;
+        }
+
+        // the fanout table narrows the search down to objects with the same
+        // first byte
+        let first = raw[0] as usize;
+        let start = if first == 0 {
+            0
+        } else {
+            self.fanout(first - 1) as usize
+        };
+        let end = self.fanout(first) as usize;
+
+        let (mut low, mut high) = (start, end);
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.raw_sha1(mid).cmp(&raw) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return self.offset(mid).ok(),
+            }
+        }
+        None
+    }
+}
+
+fn invalid(reason: &str) -> GitSyncError {
+    GitSyncError::InvalidPack(reason.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use test_dir::DirBuilder;
+
+    use super::PackIndex;
+    use crate::test_utils::existing_test_repo;
+    use crate::HashAlgorithm;
+
+    const INDEX: &str = ".git/objects/pack/pack-3af1e45a1a333b30392b7a6a1906476cd1f15f6c.idx";
+
+    #[test]
+    fn find_offsets() {
+        let test_dir = existing_test_repo("packed_objects");
+        let index = PackIndex::open(test_dir.root().join(INDEX), HashAlgorithm::Sha1).unwrap();
+        assert_eq!(index.object_count(), 12);
+
+        // offsets as listed by `git verify-pack -v`
+        assert_eq!(
+            index.find("1e3e965f92c57b26cb86b4313edca25125678074"),
+            Some(12)
+        );
+        assert_eq!(
+            index.find("06fcdd77c9348567c50638b30d406500f521c304"),
+            Some(794)
+        );
+        assert_eq!(
+            index.find("08fe2720d8e3fe3a5f81fbb289bc4c7a522f13da"),
+            Some(849)
+        );
+
+        assert_eq!(index.find("06fcdd77c9348567c50638b30d406500f521c305"), None);
+        assert_eq!(index.find("ffffffffffffffffffffffffffffffffffffffff"), None);
+        assert_eq!(index.find("06fcdd77"), None);
+
+        assert_eq!(
+            index.find_prefix("06fcdd77"),
+            ["06fcdd77c9348567c50638b30d406500f521c304"]
+        );
+        assert!(index.find_prefix("06fcdd78").is_empty());
+    }
+}
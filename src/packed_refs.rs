@@ -0,0 +1,129 @@
+use std::{fmt, fs, io::ErrorKind, path::Path};
+
+use crate::{GitSyncError, Result};
+
+/// the refs stored in `.git/packed-refs`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackedRefs {
+    pub refs: Vec<PackedRef>,
+}
+
+/// a single ref in [PackedRefs]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedRef {
+    pub name: String,
+    pub sha1: String,
+    /// the object an annotated tag points to, if git stored it
+    pub peeled: Option<String>,
+}
+
+impl PackedRefs {
+    /// reads the packed-refs file at `path`. A missing file contains no refs
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => content.parse(),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// the ref called `name`
+    pub fn get(&self, name: &str) -> Option<&PackedRef> {
+        self.refs.iter().find(|packed| packed.name == name)
+    }
+}
+
+impl fmt::Display for PackedRefs {
+    /// formats the refs in the format of the packed-refs file. The refs are
+    /// written in their current order
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# pack-refs with: peeled fully-peeled sorted ")?;
+        for packed in &self.refs {
+            writeln!(f, "{} {}", packed.sha1, packed.name)?;
+            if let Some(peeled) = &packed.peeled {
+                writeln!(f, "^{peeled}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for PackedRefs {
+    type Err = GitSyncError;
+
+    /// parses lines of `<sha1> <name>`, each optionally followed by a
+    /// `^<sha1>` line with the peeled object. `#` lines like the
+    /// `# pack-refs with: ...` header are ignored.
+    fn from_str(content: &str) -> Result<Self> {
+        let invalid = |line: &str| GitSyncError::InvalidRef {
+            name: "packed-refs".to_owned(),
+            reason: format!("invalid line {line:?}"),
+        };
+
+        let mut refs: Vec<PackedRef> = Vec::new();
+        for line in content.lines() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(peeled) = line.strip_prefix('^') {
+                let last = refs.last_mut().ok_or_else(|| invalid(line))?;
+                if !is_hex(peeled) || last.peeled.is_some() {
+                    return Err(invalid(line));
+                }
+                last.peeled = Some(peeled.to_owned());
+                continue;
+            }
+
+            let (sha1, name) = line.split_once(' ').ok_or_else(|| invalid(line))?;
+            if !is_hex(sha1) || name.is_empty() {
+                return Err(invalid(line));
+            }
+            refs.push(PackedRef {
+                name: name.to_owned(),
+                sha1: sha1.to_owned(),
+                peeled: None,
+            });
+        }
+        Ok(Self { refs })
+    }
+}
+
+fn is_hex(sha1: &str) -> bool {
+    !sha1.is_empty() && sha1.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PackedRef, PackedRefs};
+
+    #[test]
+    fn parse_peeled_tags() {
+        let packed: PackedRefs = "# pack-refs with: peeled fully-peeled sorted \n\
+            1e3e965f92c57b26cb86b4313edca25125678074 refs/heads/master\n\
+            28d0e451b11758598f5e97c62bd8aa8c1061cba5 refs/tags/v1.0\n\
+            ^25a7b65d7c4c638b508f306909cb719db11cb7c2\n"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            packed.refs,
+            [
+                PackedRef {
+                    name: "refs/heads/master".to_owned(),
+                    sha1: "1e3e965f92c57b26cb86b4313edca25125678074".to_owned(),
+                    peeled: None,
+                },
+                PackedRef {
+                    name: "refs/tags/v1.0".to_owned(),
+                    sha1: "28d0e451b11758598f5e97c62bd8aa8c1061cba5".to_owned(),
+                    peeled: Some("25a7b65d7c4c638b508f306909cb719db11cb7c2".to_owned()),
+                },
+            ]
+        );
+
+        assert!("^25a7b65d7c4c638b508f306909cb719db11cb7c2\n"
+            .parse::<PackedRefs>()
+            .is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use std::{fmt, fs, io::ErrorKind, path::Path};
+
+use crate::{GitSyncError, Result, Signature};
+
+/// a single update of a ref, as recorded in `.git/logs/<refname>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    /// the previous value, all zeros if the ref was created
+    pub old: String,
+    pub new: String,
+    pub committer: Signature,
+    /// why the ref was updated, e.g. `commit: fix typo`. Never contains a
+    /// newline
+    pub message: String,
+}
+
+impl ReflogEntry {
+    /// creates an entry. Newlines in `message` are replaced with spaces, like
+    /// git does, so the entry fits on a single line
+    pub fn new(old: &str, new: &str, committer: Signature, message: &str) -> Self {
+        Self {
+            old: old.to_owned(),
+            new: new.to_owned(),
+            committer,
+            message: message.trim().replace('\n', " "),
+        }
+    }
+}
+
+/// reads the reflog at `path`, oldest entry first. A missing file contains no
+/// entries
+pub(crate) fn read_reflog(path: impl AsRef<Path>) -> Result<Vec<ReflogEntry>> {
+    let path = path.as_ref();
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.parse().map_err(|_| GitSyncError::InvalidRef {
+                name: path.display().to_string(),
+                reason: format!("invalid reflog line {line:?}"),
+            })
+        })
+        .collect()
+}
+
+impl fmt::Display for ReflogEntry {
+    /// formats the entry as a line of the reflog, without the trailing
+    /// newline
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}\t{}",
+            self.old, self.new, self.committer, self.message
+        )
+    }
+}
+
+impl std::str::FromStr for ReflogEntry {
+    type Err = GitSyncError;
+
+    /// parses a line of `<old> <new> <committer>\t<message>`. The message is
+    /// optional
+    fn from_str(line: &str) -> Result<Self> {
+        let invalid = || GitSyncError::InvalidSignature(line.to_owned());
+        let (entry, message) = line.split_once('\t').unwrap_or((line, ""));
+        let (old, rest) = entry.split_once(' ').ok_or_else(invalid)?;
+        let (new, committer) = rest.split_once(' ').ok_or_else(invalid)?;
+        Ok(Self {
+            old: old.to_owned(),
+            new: new.to_owned(),
+            committer: Signature::parse(committer)?,
+            message: message.to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ReflogEntry;
+    use crate::Signature;
+
+    #[test]
+    fn parse_and_format_entry() {
+        let line = "0000000000000000000000000000000000000000 \
+            1e3e965f92c57b26cb86b4313edca25125678074 \
+            Test User <test@example.com> 1700000000 -0830\tcommit (initial): first";
+        let entry: ReflogEntry = line.parse().unwrap();
+        assert_eq!(entry.old, "0".repeat(40));
+        assert_eq!(entry.new, "1e3e965f92c57b26cb86b4313edca25125678074");
+        assert_eq!(
+            entry.committer,
+            Signature::parse("Test User <test@example.com> 1700000000 -0830").unwrap()
+        );
+        assert_eq!(entry.message, "commit (initial): first");
+        assert_eq!(entry.to_string(), line);
+
+        let entry = ReflogEntry::new(
+            &entry.old,
+            &entry.new,
+            entry.committer.clone(),
+            "two\nlines\n",
+        );
+        assert_eq!(entry.message, "two lines");
+
+        assert!("not a reflog line".parse::<ReflogEntry>().is_err());
+    }
+}
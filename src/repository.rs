@@ -1,166 +1,597 @@
-use anyhow::{bail, Context, Result};
-use ini::Ini;
+use flate2::{bufread::ZlibDecoder, Compression};
+use memmap2::Mmap;
 use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashSet},
+    env,
+    ffi::{OsStr, OsString},
     fs::{self, File, OpenOptions},
-    io::Write,
-    path::{Path, PathBuf},
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
 };
+use thiserror::Error;
+
+use crate::blame;
+use crate::cache::ObjectCache;
+use crate::daemon;
+use crate::diff;
+use crate::gpg;
+use crate::hooks;
+use crate::identity;
+use crate::index::is_below;
+use crate::merge;
+use crate::object::SizedReader;
+use crate::pack::MAX_DELTA_DEPTH;
+use crate::reflog;
+use crate::time;
+use crate::transport;
+use crate::{
+    from_hex, to_hex, write_delta_pack, BlameLine, Change, CommitWalk, Config, DeltaOptions,
+    FetchedRef, GitSyncError, HashAlgorithm, Identity, IgnoreRules, Index, IndexEntry,
+    MergeOutcome, Object, ObjectType, Pack, PackIndex, PackedRefs, ReflogEntry, Result, Signature,
+    SignatureStatus, StatusReport, TreeChange, TreeEntry, WorktreeWalk,
+};
+
+/// a problem found by [Repository::fsck]
+#[derive(Debug, Error)]
+pub enum FsckError {
+    /// the object's content does not hash to its file name
+    #[error("hash mismatch for object {sha1}: content hashes to {actual}")]
+    HashMismatch { sha1: String, actual: String },
+
+    /// the object could not be decoded
+    #[error("corrupt object {sha1}: {error}")]
+    Corrupt { sha1: String, error: GitSyncError },
+}
+
+/// which of the two repositories in a worktree is used.
+///
+/// gitsync keeps its autocommits in a `.gitsync` dir next to `.git`. Both
+/// share the worktree, but have their own objects, refs and index.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RepoKind {
+    /// the normal repository in `.git`
+    #[default]
+    Git,
+    /// the autocommit repository in `.gitsync`
+    GitSync,
+}
+
+impl RepoKind {
+    /// the name of the git dir in the worktree
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            RepoKind::Git => ".git",
+            RepoKind::GitSync => ".gitsync",
+        }
+    }
+
+    /// whether `name` is the git dir of any kind, which is never part of the
+    /// worktree
+    pub(crate) fn is_dir_name(name: &std::ffi::OsStr) -> bool {
+        [RepoKind::Git, RepoKind::GitSync]
+            .iter()
+            .any(|kind| name == kind.dir_name())
+    }
+}
+
+/// options for [Repository::create_with]
+#[derive(Debug, Clone)]
+pub struct InitOptions {
+    /// the branch `HEAD` points to in the new repository
+    pub initial_branch: String,
+    /// creates the git files directly at the path, without a worktree
+    pub bare: bool,
+    /// whether `.git` or `.gitsync` is created. Ignored for bare
+    /// repositories
+    pub kind: RepoKind,
+}
+
+impl Default for InitOptions {
+    fn default() -> Self {
+        Self {
+            initial_branch: "main".to_owned(),
+            bare: false,
+            kind: RepoKind::Git,
+        }
+    }
+}
+
+/// what [Repository::reset] changes besides the branch `HEAD` points to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResetMode {
+    /// only moves the branch, the index and worktree are kept
+    Soft,
+    /// also replaces the index with the tree of the commit
+    #[default]
+    Mixed,
+    /// also overwrites the worktree with the tree of the commit and removes
+    /// the files that are no longer tracked
+    Hard,
+}
+
+/// options for [Repository::commit_with]
+#[derive(Debug, Clone, Default)]
+pub struct CommitOptions {
+    /// skips the `pre-commit` and `commit-msg` hooks, like `--no-verify`
+    pub no_verify: bool,
+}
+
+/// options for [Repository::describe]
+#[derive(Debug, Clone, Default)]
+pub struct DescribeOptions {
+    /// also considers lightweight tags, not only annotated ones
+    pub tags: bool,
+    /// falls back to the abbreviated sha1 if no tag is found
+    pub always: bool,
+}
+
+/// options for [Repository::discover]
+#[derive(Debug, Clone, Default)]
+pub struct DiscoverOptions {
+    /// whether a `.git` or a `.gitsync` dir is searched
+    pub kind: RepoKind,
+    /// the search doesn't go up into these directories, like
+    /// `GIT_CEILING_DIRECTORIES`. The start directory is always searched
+    pub ceiling_dirs: Vec<PathBuf>,
+}
+
+/// where [Repository::discover] found a repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discovered {
+    pub git_dir: PathBuf,
+    /// `None` for bare repositories
+    pub work_tree: Option<PathBuf>,
+    pub is_bare: bool,
+}
+
+/// the loaded packs of a repository with their indexes
+type Packs = Arc<Vec<(PackIndex, Pack)>>;
+
+/// the packs of [Repository::packs] and the modification times of the
+/// `pack` dirs they were read from, which tell whether they are outdated
+#[derive(Debug)]
+struct LoadedPacks {
+    packs: Packs,
+    dir_mtimes: Vec<Option<SystemTime>>,
+}
 
 #[derive(Debug)]
 pub struct Repository {
-    worktree_path: Box<Path>,
+    /// `None` for bare repositories
+    worktree_path: Option<Box<Path>>,
     git_dir_path: Box<Path>,
-    config: Ini,
+    /// where new objects are stored, see [Repository::objects_dir]
+    objects_dir: PathBuf,
+    /// more object directories to read from, see [read_alternates]
+    alternates: Vec<PathBuf>,
+    config: Config,
+    hash_algorithm: HashAlgorithm,
+    /// see [Repository::packs]
+    packs: Mutex<Option<LoadedPacks>>,
+    /// see [Repository::with_cache]
+    cache: Option<Mutex<ObjectCache>>,
 }
 
 impl Repository {
+    /// opens the repository at `path`.
+    ///
+    /// `path` is either a worktree containing a `.git` dir, a worktree
+    /// containing a `.git` file pointing to the git dir or the git dir of a
+    /// bare repository.
     pub fn new(path: impl Into<Box<Path>>) -> Result<Self> {
+        Self::new_of_kind(path, RepoKind::Git)
+    }
+
+    /// opens the repository of `kind` at `path`, like [Self::new] but looking
+    /// for the git dir of `kind` in a worktree
+    pub fn new_of_kind(path: impl Into<Box<Path>>, kind: RepoKind) -> Result<Self> {
         let path = path.into();
-        let mut git_dir_path = PathBuf::from(path.as_ref());
-        git_dir_path.push(".git");
+        let git_dir_path = path.join(kind.dir_name());
 
-        if !path.exists() {
-            bail!("Git worktree directory does not exist")
+        if git_dir_path.is_dir() {
+            Self::open(git_dir_path.into(), Some(path))
+        } else if git_dir_path.is_file() {
+            let git_dir_path = read_git_file(&git_dir_path)?;
+            Self::open(git_dir_path.into(), Some(path))
+        } else if is_git_dir(&path) {
+            Self::open(path, None)
+        } else {
+            Err(GitSyncError::NotARepository {
+                path: path.into_path_buf(),
+            })
         }
+    }
 
-        if !git_dir_path.exists() {
-            bail!("Git worktree directory does not exist")
-        }
+    /// opens the git dir `git_dir_path`, reading and validating its config.
+    ///
+    /// `worktree_path` is ignored if the repository is bare. See
+    /// [Self::open_with_env] for the environment variables that are read.
+    fn open(git_dir_path: Box<Path>, worktree_path: Option<Box<Path>>) -> Result<Self> {
+        Self::open_with_env(git_dir_path, worktree_path, |name| env::var_os(name))
+    }
 
-        let config_path = git_dir_path.join("config");
+    /// like [Self::open], but reading the environment variables from `var`.
+    ///
+    /// Objects are stored in `GIT_OBJECT_DIRECTORY` if it is set and also
+    /// read from the alternates in `GIT_ALTERNATE_OBJECT_DIRECTORIES`, see
+    /// [read_alternates].
+    fn open_with_env(
+        git_dir_path: Box<Path>,
+        worktree_path: Option<Box<Path>>,
+        var: impl Fn(&str) -> Option<OsString>,
+    ) -> Result<Self> {
+        let config = Config::parse(git_dir_path.join("config"))?;
+        let hash_algorithm = Self::check_format(&config)?;
+        let bare = config.get_bool("core", None, "bare")?.unwrap_or(false);
 
-        let config = Ini::load_from_file(config_path).context("failed to load config.")?;
-        if config
-            .section(Some("core"))
-            .context("No core section in config")?
-            .get("repositoryformatversion")
-            .context("no repositoryformatversion in core")?
-            .parse::<i32>()
-            .context("could not parse repository format")?
-            != 0
-        {
-            bail!("invalid repository format. Only 0 is supported");
-        }
+        let objects_dir = match var("GIT_OBJECT_DIRECTORY") {
+            Some(dir) => env::current_dir()?.join(dir),
+            None => git_dir_path.join("objects"),
+        };
+        let alternates = read_alternates(&objects_dir, var("GIT_ALTERNATE_OBJECT_DIRECTORIES"));
 
         Ok(Self {
-            worktree_path: path,
-            git_dir_path: git_dir_path.into_boxed_path(),
+            worktree_path: worktree_path.filter(|_| !bare),
+            git_dir_path,
+            objects_dir,
+            alternates,
             config,
+            hash_algorithm,
+            packs: Mutex::default(),
+            cache: None,
         })
     }
 
+    /// keeps up to `capacity_bytes` of decoded objects in memory.
+    ///
+    /// Once the cache is full the least recently used objects are dropped.
+    /// Objects are cached by their full hash.
+    pub fn with_cache(mut self, capacity_bytes: usize) -> Self {
+        self.cache = Some(Mutex::new(ObjectCache::new(capacity_bytes)));
+        self
+    }
+
+    /// the object `sha1` if it is cached
+    fn cached_object(&self, sha1: &str) -> Option<Object> {
+        let mut cache = self
+            .cache
+            .as_ref()?
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        cache.get(sha1).cloned()
+    }
+
     pub fn new_from_ref(path: impl AsRef<Path>) -> Result<Self> {
         let path = path.as_ref();
         return Self::new(path);
     }
 
     pub fn create_at(path: impl Into<Box<Path>>) -> Result<Self> {
+        Self::create_with(path, &InitOptions::default())
+    }
+
+    /// creates a new repository at `path`, configured by `options`
+    pub fn create_with(path: impl Into<Box<Path>>, options: &InitOptions) -> Result<Self> {
+        let head = format!("refs/heads/{}", options.initial_branch);
+        check_ref_name(&head)?;
+
         let worktree_path: Box<Path> = path.into();
-        let mut git_dir_path = worktree_path.clone().into_path_buf();
-        git_dir_path.push(".git");
-        let git_dir_path: Box<Path> = git_dir_path.into();
+        let git_dir_path: Box<Path> = if options.bare {
+            worktree_path.clone()
+        } else {
+            worktree_path.join(options.kind.dir_name()).into()
+        };
 
         // Verify that no repo exists at path
         if worktree_path.exists() {
             if !worktree_path.is_dir() {
-                bail!("{worktree_path:?} already exists and is not a directory");
+                return Err(GitSyncError::AlreadyExists {
+                    path: worktree_path.into_path_buf(),
+                });
             }
             if git_dir_path.exists() {
-                let mut dir_iter =
-                    fs::read_dir(&git_dir_path).context("could not open .git dir")?;
+                let mut dir_iter = fs::read_dir(&git_dir_path)?;
                 if dir_iter.next().is_some() {
-                    bail!("{git_dir_path:?} is not empty")
+                    return Err(GitSyncError::AlreadyExists {
+                        path: git_dir_path.into_path_buf(),
+                    });
                 }
             }
         } else {
-            fs::create_dir_all(&worktree_path).context("could not create workdir at {path:?}")?;
+            fs::create_dir_all(&worktree_path)?;
         }
 
         if !git_dir_path.exists() {
-            fs::create_dir(&git_dir_path).context("could not create dir at {path:?}")?;
+            fs::create_dir(&git_dir_path)?;
+        }
+
+        let mut config = Self::default_config();
+        if options.bare {
+            config.set("core", None, "bare", "true");
         }
 
         let repo = Self {
-            worktree_path,
+            worktree_path: (!options.bare).then_some(worktree_path),
+            objects_dir: git_dir_path.join("objects"),
+            alternates: Vec::new(),
             git_dir_path,
-            config: Self::default_config(),
+            config,
+            hash_algorithm: HashAlgorithm::Sha1,
+            packs: Mutex::default(),
+            cache: None,
         };
 
-        repo.dir("branches", true).context("create new repo")?;
-        repo.dir("objects", true).context("create new repo")?;
-        repo.dir("refs/heads", true).context("create new repo")?;
-        repo.dir("refs/tags", true).context("create new repo")?;
+        repo.dir("branches", true)?;
+        repo.dir("objects", true)?;
+        repo.dir("refs/heads", true)?;
+        repo.dir("refs/tags", true)?;
 
-        repo.config()
-            .write_to_file(repo.path("config"))
-            .context("failed to write config")?;
+        repo.config().write_to_file(repo.path("config"))?;
 
         let mut open_opts = File::options();
         open_opts.create_new(true).write(true);
 
         writeln!(
-            repo.file("description", &open_opts, true)
-                .context("create new repo")?,
+            repo.file("description", &open_opts, true)?,
             "Unnamed repository; edit this file 'description' to name the repository."
-        )
-        .context("create new repo: description")?;
+        )?;
 
-        writeln!(
-            repo.file("HEAD", &open_opts, true)
-                .context("create new repo")?,
-            "ref: refs/heads/main"
-        )
-        .context("create new repo: HEAD")?;
+        writeln!(repo.file("HEAD", &open_opts, true)?, "ref: {head}")?;
 
         Ok(repo)
     }
 
-    pub fn find() -> Option<Repository> {
-        Self::find_from(".")
+    /// finds the repository of `kind` for the current directory.
+    ///
+    /// For [RepoKind::Git], `GIT_DIR` and `GIT_WORK_TREE` take precedence
+    /// over searching, see [Self::from_env]. The search stops at the
+    /// directories in `GIT_CEILING_DIRECTORIES`.
+    pub fn find(kind: RepoKind) -> Option<Repository> {
+        let from_env = match kind {
+            RepoKind::Git => Self::from_env(),
+            RepoKind::GitSync => None,
+        };
+        if let Some(repo) = from_env {
+            // TODO better error handling
+            return repo.ok();
+        }
+        let options = DiscoverOptions {
+            kind,
+            ceiling_dirs: env::var_os("GIT_CEILING_DIRECTORIES")
+                .map(|dirs| env::split_paths(&dirs).collect())
+                .unwrap_or_default(),
+        };
+        Self::open_discovered(Self::discover(".", &options).ok()?).ok()
+    }
+
+    /// opens the repository specified by `GIT_DIR` and `GIT_WORK_TREE`.
+    ///
+    /// Returns `None` if `GIT_DIR` is not set. Without `GIT_WORK_TREE` the
+    /// current directory is used as worktree, like git does.
+    pub fn from_env() -> Option<Result<Repository>> {
+        let git_dir = env::var_os("GIT_DIR")?;
+        let open = || {
+            let current_dir = env::current_dir()?;
+            let worktree = match env::var_os("GIT_WORK_TREE") {
+                Some(worktree) => current_dir.join(worktree),
+                None => current_dir.clone(),
+            };
+            let git_dir = current_dir.join(git_dir);
+            if !is_git_dir(&git_dir) {
+                return Err(GitSyncError::NotARepository { path: git_dir });
+            }
+            Self::open(git_dir.into(), Some(worktree.into()))
+        };
+        Some(open())
+    }
+
+    /// finds the repository of `kind` containing `path`, see
+    /// [Repository::discover]
+    pub fn find_from(path: impl AsRef<Path>, kind: RepoKind) -> Option<Repository> {
+        let options = DiscoverOptions {
+            kind,
+            ..Default::default()
+        };
+        // TODO better error handling
+        Self::open_discovered(Self::discover(path, &options).ok()?).ok()
     }
 
-    /// finds the repository containing `path`.
+    /// finds the git dir and worktree of the repository containing `start`
+    /// without opening it.
     ///
-    /// # Panics
-    /// This function panics if the path does not exist.
-    pub fn find_from(path: impl AsRef<Path>) -> Option<Repository> {
+    /// `start` and its parents are searched for the git dir of
+    /// `options.kind`, a `.git` file pointing to one or a directory that is
+    /// itself a git dir, which is found as a bare repository. The search for
+    /// a `.gitsync` dir ignores `.git` dirs and the other way around.
+    pub fn discover(start: impl AsRef<Path>, options: &DiscoverOptions) -> Result<Discovered> {
         // TODO change to absolute when this is stabalized
         // https://github.com/rust-lang/rust/issues/92750
-        let path = fs::canonicalize(path).expect("could not get absolute path");
-        let mut current: Option<&Path> = Some(path.as_ref());
+        let start = fs::canonicalize(start)?;
+        // only ceilings above the start directory count
+        let ceiling = options
+            .ceiling_dirs
+            .iter()
+            .filter_map(|dir| fs::canonicalize(dir).ok())
+            .filter(|dir| start.starts_with(dir) && *dir != start)
+            .max_by_key(|dir| dir.components().count());
+
+        let mut current = Some(start.as_path());
         while let Some(dir) = current {
-            let git = dir.join(".git");
-            if git.exists() && git.is_dir() {
-                // TODO better error handling
-                return Some(Self::new(dir).ok()?);
+            if let Some(discovered) = discover_at(dir, options.kind)? {
+                return Ok(discovered);
             }
-            current = dir.parent()
+            current = dir
+                .parent()
+                .filter(|&parent| Some(parent) != ceiling.as_deref());
         }
-        None
+        Err(GitSyncError::NotARepository { path: start })
     }
 
-    fn default_config() -> Ini {
-        let mut config = Ini::new();
-        config
-            .with_section(Some("core"))
-            .set("repositoryformatversion", "0")
-            .set("filemode", "false")
-            .set("bare", "false");
+    fn open_discovered(discovered: Discovered) -> Result<Self> {
+        Self::open(
+            discovered.git_dir.into_boxed_path(),
+            discovered.work_tree.map(PathBuf::into_boxed_path),
+        )
+    }
+
+    /// verifies that the on-disk format declared in `config` is understood
+    /// and returns the hash algorithm used for objects.
+    ///
+    /// Version 0 ignores extensions. Version 1 is only accepted if every
+    /// extension is known.
+    fn check_format(config: &Config) -> Result<HashAlgorithm> {
+        /// extensions that don't change anything this implementation touches
+        const KNOWN_EXTENSIONS: &[&str] = &[
+            "noop",
+            "objectformat",
+            "preciousobjects",
+            "partialclone",
+            "worktreeconfig",
+        ];
+
+        let version = config
+            .get_int("core", None, "repositoryformatversion")?
+            .unwrap_or(0);
+        match version {
+            0 => Ok(HashAlgorithm::Sha1),
+            1 => {
+                for (name, _) in config.section("extensions", None) {
+                    if !KNOWN_EXTENSIONS.contains(&name) {
+                        return Err(GitSyncError::UnsupportedExtension {
+                            name: name.to_owned(),
+                        });
+                    }
+                }
+                HashAlgorithm::from_config(config)
+            }
+            version => Err(GitSyncError::UnsupportedRepositoryFormat { version }),
+        }
+    }
+
+    fn default_config() -> Config {
+        let mut config = Config::default();
+        config.set("core", None, "repositoryformatversion", "0");
+        config.set("core", None, "filemode", "false");
+        config.set("core", None, "bare", "false");
         config
     }
 
-    pub fn worktree_root(&self) -> &Path {
-        &self.worktree_path
+    /// the root of the worktree.
+    ///
+    /// Fails with [GitSyncError::NotAWorktree] for bare repositories.
+    pub fn worktree_root(&self) -> Result<&Path> {
+        self.worktree_path
+            .as_deref()
+            .ok_or_else(|| GitSyncError::NotAWorktree {
+                path: self.git_dir_path.to_path_buf(),
+            })
+    }
+
+    /// whether this repository has no worktree, see `core.bare`
+    pub fn is_bare(&self) -> bool {
+        self.config
+            .get_bool("core", None, "bare")
+            .ok()
+            .flatten()
+            .unwrap_or(false)
+    }
+
+    /// the zlib level for new objects, see `core.compression`.
+    ///
+    /// `-1` and invalid values use the zlib default.
+    pub fn compression_level(&self) -> Compression {
+        match self.config.get_int("core", None, "compression") {
+            Ok(Some(level @ 0..=9)) => Compression::new(level as u32),
+            _ => Compression::default(),
+        }
+    }
+
+    /// how hard [Repository::repack] looks for deltas, see `pack.window` and
+    /// `pack.depth`. Missing or negative values use the defaults.
+    pub fn delta_options(&self) -> DeltaOptions {
+        let default = DeltaOptions::default();
+        let get = |key| match self.config.get_int("pack", None, key) {
+            Ok(Some(value)) if value >= 0 => Some(value as usize),
+            _ => None,
+        };
+        DeltaOptions {
+            window: get("window").unwrap_or(default.window),
+            depth: get("depth").unwrap_or(default.depth),
+        }
     }
 
     pub fn gitdir_root(&self) -> &Path {
         &self.git_dir_path
     }
 
-    pub fn config(&self) -> &Ini {
+    pub fn config(&self) -> &Config {
         &self.config
     }
 
+    /// the directory new objects are written to, usually `objects` in the
+    /// git dir
+    pub fn objects_dir(&self) -> &Path {
+        &self.objects_dir
+    }
+
+    /// the object directories searched for objects: [Repository::objects_dir]
+    /// followed by the alternates
+    fn object_dirs(&self) -> impl Iterator<Item = &Path> {
+        std::iter::once(self.objects_dir.as_path())
+            .chain(self.alternates.iter().map(PathBuf::as_path))
+    }
+
+    /// the path of the loose object `sha1` in [Repository::objects_dir]
+    pub(crate) fn loose_object_path(&self, sha1: &str) -> PathBuf {
+        self.objects_dir.join(&sha1[..2]).join(&sha1[2..])
+    }
+
+    /// reads the index of this repository. A missing index is empty
+    pub fn read_index(&self) -> Result<Index> {
+        match Index::read_with(self.gitdir_root().join("index"), self.hash_algorithm) {
+            Err(GitSyncError::Io(err)) if err.kind() == ErrorKind::NotFound => Ok(Index::default()),
+            index => index,
+        }
+    }
+
+    /// replaces the index of this repository.
+    ///
+    /// The index is written to `index.lock` first, so readers never see a
+    /// partially written file. Fails with [GitSyncError::IndexLocked] if the
+    /// lock already exists, e.g. because git is writing the index.
+    pub fn write_index(&self, index: &Index) -> Result<()> {
+        let lock_path = self.gitdir_root().join("index.lock");
+        let mut lock = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                return Err(GitSyncError::IndexLocked { path: lock_path });
+            }
+            lock => lock?,
+        };
+
+        let result = (|| -> Result<()> {
+            lock.write_all(&index.serialize(self.hash_algorithm))?;
+            lock.sync_all()?;
+            fs::rename(&lock_path, self.gitdir_root().join("index"))?;
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&lock_path);
+        }
+        result
+    }
+
+    /// the hash algorithm used to name objects in this repository
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
     pub fn path(&self, path: impl AsRef<Path>) -> PathBuf {
         assert!(path.as_ref().is_relative());
         let mut res = self.git_dir_path.clone().into_path_buf();
@@ -172,17 +603,15 @@ impl Repository {
         let path = self.path(path);
         if path.exists() {
             if !path.is_dir() {
-                bail!("expected dir found file")
+                Err(GitSyncError::NotADirectory { path })
             } else {
                 Ok(path)
             }
+        } else if mkdir {
+            fs::create_dir_all(&path)?;
+            Ok(path)
         } else {
-            if mkdir {
-                fs::create_dir_all(&path).context("failed to create directory")?;
-                Ok(path)
-            } else {
-                bail!("directory not found")
-            }
+            Err(io::Error::from(ErrorKind::NotFound).into())
         }
     }
 
@@ -196,7 +625,7 @@ impl Repository {
 
         if create_parent {
             if let Some(parent) = path.parent() {
-                fs::create_dir_all(parent).context("failed to create parent dir")?;
+                fs::create_dir_all(parent)?;
             } else {
                 // parent dir is root. I don't think this can ever happen
                 // unless maybe if a repo is created at the root of a drive,
@@ -204,54 +633,5733 @@ impl Repository {
             }
         }
 
-        open_opts.open(path).context("failed to open {path:?}")
+        Ok(open_opts.open(path)?)
     }
 
-    pub fn worktree_path(&self, path: impl AsRef<Path>) -> PathBuf {
-        let mut res = self.worktree_path.clone().into_path_buf();
+    pub fn worktree_path(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
+        let mut res = self.worktree_root()?.to_path_buf();
         res.push(path);
-        res
+        Ok(res)
     }
 
-    pub fn find_object_sha1(&self, name: &str, _fmt: Option<()>, _follow: bool) -> String {
-        // TODO
-        name.to_owned()
+    /// maps `path`, relative to the current directory or absolute, to the
+    /// path relative to the worktree root used in the index, e.g.
+    /// `../sub/file` in `<root>/dir` to `sub/file`.
+    ///
+    /// The path doesn't need to exist. Symlinks are resolved in the
+    /// directories leading to it, but not in its final name, so a symlink is
+    /// mapped to itself. Fails with [GitSyncError::OutsideWorktree] if the
+    /// path is not inside the worktree.
+    pub fn to_workdir_relative(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
+        self.workdir_relative_from(&env::current_dir()?, path.as_ref())
     }
 
-    /// calculates path for the given object. Path is relative to .git dir
+    /// [Repository::to_workdir_relative] with `current_dir` instead of the
+    /// current directory of the process
+    fn workdir_relative_from(&self, current_dir: &Path, path: &Path) -> Result<PathBuf> {
+        let root = fs::canonicalize(self.worktree_root()?)?;
+        let absolute = resolve_parents(&current_dir.join(path));
+        match absolute.strip_prefix(&root) {
+            Ok(relative) => Ok(relative.to_path_buf()),
+            Err(_) => Err(GitSyncError::OutsideWorktree { path: absolute }),
+        }
+    }
+
+    /// resolves `name` to the full hash of an object.
     ///
-    /// use [file] to open the object file
-    pub fn sha1_to_object(sha1: &str) -> PathBuf {
-        assert_eq!(sha1.len(), 40);
-        let mut path = PathBuf::from("objects");
-        path.push(&sha1[0..2]);
-        path.push(&sha1[2..]);
-        path
+    /// `name` can either be a full hash, a ref like `HEAD` or `main` or an
+    /// abbreviated hash of at least 4 characters.
+    pub fn find_object_sha1(&self, name: &str, _fmt: Option<()>, _follow: bool) -> Result<String> {
+        // TODO fmt and follow
+        let hex_len = self.hash_algorithm.hex_len();
+        let is_hex = !name.is_empty() && name.chars().all(|c| c.is_ascii_hexdigit());
+        if is_hex && name.len() == hex_len {
+            return Ok(name.to_ascii_lowercase());
+        }
+
+        if let Some(sha1) = self.try_resolve_ref(name)? {
+            return Ok(sha1);
+        }
+
+        if is_hex && name.len() < hex_len {
+            return self.resolve_sha1_prefix(&name.to_ascii_lowercase());
+        }
+
+        Err(GitSyncError::InvalidObjectName {
+            name: name.to_owned(),
+        })
     }
-}
 
-#[cfg(test)]
-mod test {
-    use test_dir::DirBuilder;
+    /// resolves a revision to the full hash of an object, like
+    /// `git rev-parse`.
+    ///
+    /// `spec` is anything [Repository::find_object_sha1] accepts, followed by
+    /// any number of `^<n>` (the n-th parent, the first if `n` is missing and
+    /// the commit itself for `0`) and `~<n>` (the n-th first parent
+    /// ancestor) suffixes, e.g. `HEAD~2^2`.
+    ///
+    /// Instead of an object a ref can be followed by `@{<n>}` for the value
+    /// it had `n` updates ago or `@{<date>}` for its value at that time,
+    /// according to its [reflog](Repository::read_reflog), e.g. `HEAD@{1}` or
+    /// `main@{2.days.ago}`. Without a ref the current branch is used.
+    pub fn rev_parse(&self, spec: &str) -> Result<String> {
+        let unknown = || GitSyncError::UnknownRevision {
+            spec: spec.to_owned(),
+        };
+
+        let base_len = spec.find(['^', '~']).unwrap_or(spec.len());
+        let base = &spec[..base_len];
+        let reflog_selector = base
+            .strip_suffix('}')
+            .and_then(|base| base.split_once("@{"));
+        let mut sha1 = match reflog_selector {
+            Some((name, selector)) => self.reflog_value(name, selector)?,
+            None => self.find_object_sha1(base, None, true)?,
+        };
 
-    use crate::test_utils::{existing_test_repo, test_dir};
-    use crate::Repository;
+        let mut rest = &spec[base_len..];
+        while let Some(op) = rest.chars().next() {
+            if !matches!(op, '^' | '~') {
+                return Err(unknown());
+            }
+            let digits = rest[1..]
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(rest.len(), |len| len + 1);
+            let n = match &rest[1..digits] {
+                "" => 1,
+                n => n.parse::<usize>().map_err(|_| unknown())?,
+            };
+            rest = &rest[digits..];
 
-    #[test]
-    fn open_repository() {
-        let test_dir = existing_test_repo("valid_empty");
-        let repo = Repository::new(test_dir.root());
-        println!("{repo:?}")
+            let (commit, parents) = self.peel_to_commit(&sha1)?;
+            sha1 = match (op, n) {
+                (_, 0) => commit,
+                ('^', n) => parents.get(n - 1).cloned().ok_or_else(unknown)?,
+                (_, n) => {
+                    let mut parents = parents;
+                    for _ in 1..n {
+                        let first = parents.first().ok_or_else(unknown)?;
+                        parents = self.peel_to_commit(first)?.1;
+                    }
+                    parents.first().cloned().ok_or_else(unknown)?
+                }
+            };
+        }
+        Ok(sha1)
     }
 
-    #[test]
-    fn create_repository() {
-        let repo_path = test_dir("create_repo");
+    /// the value of the ref `name` `selector` updates ago or at the date
+    /// `selector`, see [Repository::rev_parse]
+    fn reflog_value(&self, name: &str, selector: &str) -> Result<String> {
+        let name = match name {
+            "" => self
+                .read_symbolic_ref("HEAD")?
+                .unwrap_or_else(|| "HEAD".to_owned()),
+            name => self.find_ref(name)?.map(|(name, _)| name).ok_or_else(|| {
+                GitSyncError::RefNotFound {
+                    name: name.to_owned(),
+                }
+            })?,
+        };
+        let entries = self.read_reflog(&name)?;
+        if entries.is_empty() {
+            return Err(GitSyncError::NoReflog { name });
+        }
 
-        let repo = Repository::create_at(repo_path.root()).expect("could not create repo");
+        if let Ok(index) = selector.parse::<usize>() {
+            let len = entries.len();
+            return match len.checked_sub(index + 1) {
+                Some(n) => Ok(entries[n].new.clone()),
+                None => Err(GitSyncError::ReflogIndexOutOfRange { name, index, len }),
+            };
+        }
 
-        drop(repo);
-        Repository::new(repo_path.root()).expect("could not open nearly created repo");
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as i64);
+        let date =
+            time::parse_date(selector, now).ok_or_else(|| GitSyncError::UnknownRevision {
+                spec: format!("{name}@{{{selector}}}"),
+            })?;
+        match entries
+            .iter()
+            .rev()
+            .find(|entry| entry.committer.time <= date)
+        {
+            Some(entry) => Ok(entry.new.clone()),
+            // before the first logged update the ref had its old value
+            None if entries[0].old != self.null_sha1() => Ok(entries[0].old.clone()),
+            None => Err(GitSyncError::ReflogTooShort {
+                name,
+                date: selector.to_owned(),
+            }),
+        }
+    }
+
+    /// follows tags from `sha1` until a commit is found and returns it along
+    /// with its parents
+    fn peel_to_commit(&self, sha1: &str) -> Result<(String, Vec<String>)> {
+        let mut sha1 = sha1.to_owned();
+        loop {
+            match self.load_object(&sha1)? {
+                Object::Commit { parents, .. } => return Ok((sha1, parents)),
+                Object::Tag { object, .. } => sha1 = object,
+                _ => {
+                    return Err(GitSyncError::UnexpectedObjectType {
+                        sha1,
+                        expected: ObjectType::Commit,
+                    })
+                }
+            }
+        }
+    }
+
+    /// resolves a ref to the sha1 it points to.
+    ///
+    /// `name` can be `HEAD`, a full ref name like `refs/heads/main` or a
+    /// short name like `main` which is looked up in the same order git uses.
+    /// Symbolic refs are followed until they point to a sha1.
+    pub fn resolve_ref(&self, name: &str) -> Result<String> {
+        self.try_resolve_ref(name)?
+            .ok_or_else(|| GitSyncError::RefNotFound {
+                name: name.to_owned(),
+            })
+    }
+
+    /// reads the target of the symbolic ref `name`, e.g. `refs/heads/main`
+    /// for `HEAD`.
+    ///
+    /// Returns `None` if `name` does not exist or is not a symbolic ref. The
+    /// target does not need to exist, e.g. in a repository without commits.
+    pub fn read_symbolic_ref(&self, name: &str) -> Result<Option<String>> {
+        check_ref_name(name)?;
+        let path = self.path(name);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)?;
+        Ok(content.trim_end().strip_prefix("ref: ").map(str::to_owned))
+    }
+
+    fn try_resolve_ref(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.find_ref(name)?.map(|(_, sha1)| sha1))
+    }
+
+    /// looks up the short ref name `name` like [Repository::resolve_ref] and
+    /// returns the full name of the ref along with the sha1 it points to
+    fn find_ref(&self, name: &str) -> Result<Option<(String, String)>> {
+        check_ref_name(name)?;
+
+        // only names like HEAD or FETCH_HEAD are looked up directly in the
+        // git dir, so that e.g. `config` does not resolve to the config file.
+        let is_special = name.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+        let direct = (is_special || name.starts_with("refs/")).then(|| name.to_owned());
+
+        let candidates = direct.into_iter().chain([
+            format!("refs/{name}"),
+            format!("refs/tags/{name}"),
+            format!("refs/heads/{name}"),
+            format!("refs/remotes/{name}"),
+            format!("refs/remotes/{name}/HEAD"),
+        ]);
+
+        for candidate in candidates {
+            if let Some(sha1) = self.read_ref(&candidate)? {
+                return Ok(Some((candidate, sha1)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// reads the refs stored in `packed-refs`
+    pub fn packed_refs(&self) -> Result<PackedRefs> {
+        PackedRefs::read(self.path("packed-refs"))
+    }
+
+    /// reads the ref `refname` from its loose file relative to the git dir or
+    /// from `packed-refs`, following symbolic refs. Returns `None` if the ref
+    /// does not exist.
+    fn read_ref(&self, refname: &str) -> Result<Option<String>> {
+        /// same limit git uses for nested symbolic refs
+        const MAX_SYMREF_DEPTH: usize = 5;
+
+        let mut refname = refname.to_owned();
+        for _ in 0..=MAX_SYMREF_DEPTH {
+            let path = self.path(&refname);
+            if !path.is_file() {
+                // loose refs take precedence over packed ones
+                let packed = self.packed_refs()?;
+                return Ok(packed.get(&refname).map(|packed| packed.sha1.clone()));
+            }
+
+            let content = fs::read_to_string(&path)?;
+            let content = content.trim_end();
+
+            if let Some(target) = content.strip_prefix("ref: ") {
+                refname = target.to_owned();
+            } else if content.len() == self.hash_algorithm.hex_len()
+                && content.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                return Ok(Some(content.to_owned()));
+            } else {
+                return Err(GitSyncError::InvalidRef {
+                    name: refname,
+                    reason: "does not contain a sha1 or symbolic ref".into(),
+                });
+            }
+        }
+
+        Err(GitSyncError::InvalidRef {
+            name: refname,
+            reason: "symbolic ref is nested too deeply".into(),
+        })
+    }
+
+    /// lists `HEAD` and all loose and packed refs below `refs/` as
+    /// `(name, sha1)` pairs.
+    ///
+    /// Symbolic refs are resolved to the sha1 they point to and skipped if
+    /// their target does not exist. Apart from `HEAD`, which comes first, refs
+    /// are sorted by name.
+    pub fn refs(&self) -> Result<Vec<(String, String)>> {
+        let mut names = Vec::new();
+        self.collect_ref_names("refs", &mut names)?;
+        names.extend(
+            self.packed_refs()?
+                .refs
+                .into_iter()
+                .map(|packed| packed.name)
+                .filter(|name| name.starts_with("refs/")),
+        );
+        names.sort();
+        names.dedup();
+
+        let mut refs = Vec::new();
+        for name in ["HEAD".to_owned()].into_iter().chain(names) {
+            if let Some(sha1) = self.read_ref(&name)? {
+                refs.push((name, sha1));
+            }
+        }
+        Ok(refs)
+    }
+
+    /// adds the names of all loose refs in the directory `dir` to `names`
+    fn collect_ref_names(&self, dir: &str, names: &mut Vec<String>) -> Result<()> {
+        let entries = match fs::read_dir(self.path(dir)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let Some(file_name) = entry.file_name().to_str().map(str::to_owned) else {
+                continue;
+            };
+            let name = format!("{dir}/{file_name}");
+            if entry.file_type()?.is_dir() {
+                self.collect_ref_names(&name, names)?;
+            } else if !file_name.ends_with(".lock") {
+                names.push(name);
+            }
+        }
+        Ok(())
+    }
+
+    /// points the ref `name` to `new_sha`, like `git update-ref`.
+    ///
+    /// If `name` is a symbolic ref like `HEAD`, the ref it points to is
+    /// updated. If `expected_old` is given, the ref is only updated if it
+    /// still points to `expected_old`. The all zero sha1 expects the ref to
+    /// not exist yet.
+    ///
+    /// The new value is written to `<name>.lock` and then renamed, so
+    /// readers never see a partial ref and concurrent updates fail. The
+    /// update is recorded in the reflog without a message, see
+    /// [Self::update_ref_with_message].
+    pub fn update_ref(&self, name: &str, new_sha: &str, expected_old: Option<&str>) -> Result<()> {
+        self.update_ref_with_message(name, new_sha, expected_old, "")
+    }
+
+    /// like [Self::update_ref], with `message` as the reason in the reflog.
+    ///
+    /// Branches, remote-tracking branches and `HEAD` are logged to
+    /// `logs/<name>` unless `core.logAllRefUpdates` is false, other refs only
+    /// if they already have a reflog. Updating the branch `HEAD` points to
+    /// is also logged for `HEAD`.
+    pub fn update_ref_with_message(
+        &self,
+        name: &str,
+        new_sha: &str,
+        expected_old: Option<&str>,
+        message: &str,
+    ) -> Result<()> {
+        let hex_len = self.hash_algorithm.hex_len();
+        if new_sha.len() != hex_len || !new_sha.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(GitSyncError::InvalidObjectName {
+                name: new_sha.to_owned(),
+            });
+        }
+
+        let name = self
+            .read_symbolic_ref(name)?
+            .unwrap_or_else(|| name.to_owned());
+        check_ref_name(&name)?;
+
+        let lock_name = format!("{name}.lock");
+        let lock = match self.file(
+            &lock_name,
+            OpenOptions::new().write(true).create_new(true),
+            true,
+        ) {
+            Err(GitSyncError::Io(err)) if err.kind() == ErrorKind::AlreadyExists => {
+                return Err(GitSyncError::RefLocked { name });
+            }
+            lock => lock?,
+        };
+
+        let old = match self.write_locked_ref(&name, lock, new_sha, expected_old) {
+            Ok(old) => old.unwrap_or_else(|| self.null_sha1()),
+            Err(err) => {
+                let _ = fs::remove_file(self.path(&lock_name));
+                return Err(err);
+            }
+        };
+
+        let entry = ReflogEntry::new(
+            &old,
+            &new_sha.to_ascii_lowercase(),
+            self.reflog_identity(),
+            message,
+        );
+        self.append_reflog(&name, &entry)?;
+        if name != "HEAD" && self.read_symbolic_ref("HEAD")?.as_deref() == Some(name.as_str()) {
+            self.append_reflog("HEAD", &entry)?;
+        }
+        Ok(())
+    }
+
+    /// writes `new_sha` to the locked ref `name` and returns its old value
+    fn write_locked_ref(
+        &self,
+        name: &str,
+        mut lock: File,
+        new_sha: &str,
+        expected_old: Option<&str>,
+    ) -> Result<Option<String>> {
+        let actual = self.read_ref(name)?;
+        if let Some(expected) = expected_old {
+            let matches = match &actual {
+                Some(actual) => actual.eq_ignore_ascii_case(expected),
+                None => expected.chars().all(|c| c == '0'),
+            };
+            if !matches {
+                return Err(GitSyncError::RefChanged {
+                    name: name.to_owned(),
+                    expected: expected.to_owned(),
+                    actual,
+                });
+            }
+        }
+
+        writeln!(lock, "{}", new_sha.to_ascii_lowercase())?;
+        lock.sync_all()?;
+        drop(lock);
+        fs::rename(self.path(format!("{name}.lock")), self.path(name))?;
+        Ok(actual)
+    }
+
+    /// appends `entry` to the reflog of `name`, if updates of `name` are
+    /// logged, see [Self::update_ref_with_message]
+    fn append_reflog(&self, name: &str, entry: &ReflogEntry) -> Result<()> {
+        let path = format!("logs/{name}");
+        let log_all = match self.config.get_str("core", None, "logAllRefUpdates") {
+            Some("always") => true,
+            _ => self
+                .config
+                .get_bool("core", None, "logAllRefUpdates")
+                .ok()
+                .flatten()
+                .unwrap_or(!self.is_bare()),
+        };
+        let logged = name == "HEAD"
+            || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+                .iter()
+                .any(|prefix| name.starts_with(prefix));
+        if !(log_all && logged) && !self.path(&path).is_file() {
+            return Ok(());
+        }
+
+        let mut log = self.file(&path, OpenOptions::new().append(true).create(true), true)?;
+        writeln!(log, "{entry}")?;
+        Ok(())
+    }
+
+    /// the reflog of the ref `name`, oldest entry first, like
+    /// `git reflog show`. Symbolic refs are not followed, so `HEAD` has its
+    /// own log. Refs without a reflog have no entries
+    pub fn read_reflog(&self, name: &str) -> Result<Vec<ReflogEntry>> {
+        check_ref_name(name)?;
+        reflog::read_reflog(self.path(format!("logs/{name}")))
+    }
+
+    /// the author of new commits, from `GIT_AUTHOR_NAME`,
+    /// `GIT_AUTHOR_EMAIL` and `GIT_AUTHOR_DATE`, falling back to `user.name`
+    /// and `user.email` and the current time.
+    ///
+    /// Fails with [GitSyncError::MissingIdentity] if the name or email is not
+    /// set anywhere.
+    pub fn author_identity(&self) -> Result<Identity> {
+        identity::resolve(&self.config, "AUTHOR", |key| env::var(key).ok())
+    }
+
+    /// the committer of new commits and tags, like
+    /// [Repository::author_identity] but from the `GIT_COMMITTER_*`
+    /// variables
+    pub fn committer_identity(&self) -> Result<Identity> {
+        identity::resolve(&self.config, "COMMITTER", |key| env::var(key).ok())
+    }
+
+    /// the committer recorded in reflog entries, see
+    /// [Repository::committer_identity].
+    ///
+    /// Unlike for commits a missing identity is not an error, so that
+    /// updating refs always works
+    fn reflog_identity(&self) -> Signature {
+        let identity = self.committer_identity().unwrap_or_else(|_| Identity {
+            name: "unknown".to_owned(),
+            email: "unknown".to_owned(),
+            date: None,
+        });
+        identity.signature()
+    }
+
+    /// the name of the branch `HEAD` points to, e.g. `main`. Returns `None`
+    /// if `HEAD` is detached.
+    pub fn current_branch(&self) -> Result<Option<String>> {
+        Ok(self
+            .read_symbolic_ref("HEAD")?
+            .and_then(|target| target.strip_prefix("refs/heads/").map(str::to_owned)))
+    }
+
+    /// lists the local branches as `(name, sha1)` pairs sorted by name, with
+    /// names like `main` instead of `refs/heads/main`
+    pub fn list_branches(&self) -> Result<Vec<(String, String)>> {
+        Ok(self
+            .refs()?
+            .into_iter()
+            .filter_map(|(name, sha1)| {
+                name.strip_prefix("refs/heads/")
+                    .map(|branch| (branch.to_owned(), sha1))
+            })
+            .collect())
+    }
+
+    /// creates the branch `name` at the commit `start` resolves to and
+    /// returns the sha1 of that commit. Fails if the branch already exists.
+    pub fn create_branch(&self, name: &str, start: &str) -> Result<String> {
+        let (sha1, _) = self.peel_to_commit(&self.rev_parse(start)?)?;
+        self.update_ref_with_message(
+            &format!("refs/heads/{name}"),
+            &sha1,
+            Some(&self.null_sha1()),
+            &format!("branch: Created from {start}"),
+        )?;
+        Ok(sha1)
+    }
+
+    /// deletes the branch `name` from the loose and packed refs.
+    ///
+    /// The branch `HEAD` points to can't be deleted.
+    pub fn delete_branch(&self, name: &str) -> Result<()> {
+        if self.current_branch()?.as_deref() == Some(name) {
+            return Err(GitSyncError::BranchCheckedOut {
+                name: name.to_owned(),
+            });
+        }
+        self.delete_ref(&format!("refs/heads/{name}"))
+    }
+
+    /// lists the tags as `(name, sha1)` pairs sorted by name, with names like
+    /// `v1.0` instead of `refs/tags/v1.0`. Annotated tags point to their tag
+    /// object.
+    pub fn list_tags(&self) -> Result<Vec<(String, String)>> {
+        Ok(self
+            .refs()?
+            .into_iter()
+            .filter_map(|(name, sha1)| {
+                name.strip_prefix("refs/tags/")
+                    .map(|tag| (tag.to_owned(), sha1))
+            })
+            .collect())
+    }
+
+    /// creates the tag `name` for the object `target` resolves to and returns
+    /// the sha1 the new ref points to. Fails if the tag already exists.
+    ///
+    /// Without `annotation` a lightweight tag is created, which points
+    /// directly to the object. Otherwise an annotated tag object with the
+    /// tagger and message of `annotation` is stored and the ref points to it.
+    pub fn create_tag(
+        &self,
+        name: &str,
+        target: &str,
+        annotation: Option<(&Signature, &str)>,
+    ) -> Result<String> {
+        let refname = format!("refs/tags/{name}");
+        check_ref_name(&refname)?;
+        let object = self.rev_parse(target)?;
+
+        let sha1 = match annotation {
+            Some((tagger, message)) => Object::Tag {
+                typ: self.object_type(&object)?,
+                object,
+                tag: name.to_owned(),
+                tagger: Some(tagger.clone()),
+                message: message.to_owned(),
+                gpgsig: None,
+            }
+            .save(self)?,
+            None => object,
+        };
+        self.update_ref(&refname, &sha1, Some(&self.null_sha1()))?;
+        Ok(sha1)
+    }
+
+    /// deletes the tag `name` from the loose and packed refs. The tag object
+    /// of an annotated tag is kept.
+    pub fn delete_tag(&self, name: &str) -> Result<()> {
+        self.delete_ref(&format!("refs/tags/{name}"))
+    }
+
+    /// deletes the ref `refname` and its reflog. Symbolic refs are not
+    /// followed.
+    fn delete_ref(&self, refname: &str) -> Result<()> {
+        check_ref_name(refname)?;
+
+        let mut packed = self.packed_refs()?;
+        let packed_len = packed.refs.len();
+        packed.refs.retain(|packed| packed.name != refname);
+        let loose = self.path(refname);
+        if !loose.is_file() && packed.refs.len() == packed_len {
+            return Err(GitSyncError::RefNotFound {
+                name: refname.to_owned(),
+            });
+        }
+
+        if packed.refs.len() != packed_len {
+            self.write_packed_refs(&packed)?;
+        }
+        if loose.is_file() {
+            fs::remove_file(loose)?;
+        }
+        let reflog = self.path(format!("logs/{refname}"));
+        if reflog.is_file() {
+            fs::remove_file(reflog)?;
+        }
+        Ok(())
+    }
+
+    /// replaces `packed-refs` through a lock file, like [Self::update_ref]
+    fn write_packed_refs(&self, packed: &PackedRefs) -> Result<()> {
+        let mut lock = match self.file(
+            "packed-refs.lock",
+            OpenOptions::new().write(true).create_new(true),
+            false,
+        ) {
+            Err(GitSyncError::Io(err)) if err.kind() == ErrorKind::AlreadyExists => {
+                return Err(GitSyncError::RefLocked {
+                    name: "packed-refs".to_owned(),
+                });
+            }
+            lock => lock?,
+        };
+
+        let result = (|| -> Result<()> {
+            write!(lock, "{packed}")?;
+            lock.sync_all()?;
+            fs::rename(self.path("packed-refs.lock"), self.path("packed-refs"))?;
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(self.path("packed-refs.lock"));
+        }
+        result
+    }
+
+    /// the all zero sha1, used for refs that don't exist
+    pub fn null_sha1(&self) -> String {
+        "0".repeat(self.hash_algorithm.hex_len())
+    }
+
+    /// finds the unique loose or packed object whose sha1 starts with `prefix`
+    fn resolve_sha1_prefix(&self, prefix: &str) -> Result<String> {
+        const MIN_PREFIX_LEN: usize = 4;
+        if prefix.len() < MIN_PREFIX_LEN {
+            // too short to be used as an abbreviated sha1
+            return Err(GitSyncError::InvalidObjectName {
+                name: prefix.to_owned(),
+            });
+        }
+
+        let (dir_name, _) = prefix.split_at(2);
+        let mut matches = Vec::new();
+        for objects_dir in self.object_dirs() {
+            let loose = loose_objects_in(objects_dir, dir_name, self.hash_algorithm)?;
+            matches.extend(loose.into_iter().filter(|sha1| sha1.starts_with(prefix)));
+        }
+        for (index, _) in self.packs()?.iter() {
+            matches.extend(index.find_prefix(prefix));
+        }
+        // objects can be both loose and packed
+        matches.sort();
+        matches.dedup();
+
+        match matches.len() {
+            0 => Err(GitSyncError::ObjectNotFound {
+                sha1: prefix.to_owned(),
+            }),
+            1 => Ok(matches.pop().unwrap()),
+            _ => Err(GitSyncError::AmbiguousObject {
+                prefix: prefix.to_owned(),
+            }),
+        }
+    }
+
+    /// iterates over the sha1 of every loose object in the repository.
+    ///
+    /// Packed objects are not included.
+    pub fn loose_objects(&self) -> Result<impl Iterator<Item = String>> {
+        let mut sha1s = Vec::new();
+        for entry in fs::read_dir(&self.objects_dir)? {
+            let entry = entry?;
+            let dir_name = entry.file_name();
+            let Some(dir_name) = dir_name.to_str() else {
+                continue;
+            };
+            // skips `pack` and `info`
+            if dir_name.len() == 2 && is_lower_hex(dir_name) {
+                sha1s.extend(loose_objects_in(
+                    &self.objects_dir,
+                    dir_name,
+                    self.hash_algorithm,
+                )?);
+            }
+        }
+        sha1s.sort();
+        Ok(sha1s.into_iter())
+    }
+
+    /// iterates over the sha1 of every loose object of type `typ`, see
+    /// [Repository::loose_objects].
+    ///
+    /// Only the headers are read. Objects whose header can't be read are
+    /// yielded as [FsckError::Corrupt], so callers can warn about them and
+    /// continue.
+    pub fn loose_objects_of_type(
+        &self,
+        typ: ObjectType,
+    ) -> Result<impl Iterator<Item = std::result::Result<String, FsckError>> + '_> {
+        Ok(self
+            .loose_objects()?
+            .filter_map(move |sha1| match self.object_info(&sha1) {
+                Ok((object_type, _)) => (object_type == typ).then_some(Ok(sha1)),
+                Err(error) => Some(Err(FsckError::Corrupt { sha1, error })),
+            }))
+    }
+
+    /// the sha1 of every object in the packs of the repository, sorted
+    pub fn packed_objects(&self) -> Result<Vec<String>> {
+        let mut sha1s = Vec::new();
+        for (index, _) in self.packs()?.iter() {
+            sha1s.extend((0..index.object_count()).map(|n| index.sha1(n)));
+        }
+        sha1s.sort();
+        sha1s.dedup();
+        Ok(sha1s)
+    }
+
+    /// every object reachable from `HEAD` and the refs, loose or packed.
+    ///
+    /// Commits lead to their trees and parents, trees to their entries and
+    /// tags to their targets. Submodule commits are not followed. This is
+    /// the mark phase of a garbage collection: everything else can be
+    /// removed.
+    pub fn reachable_objects(&self) -> Result<HashSet<String>> {
+        let starts = self.refs()?.into_iter().map(|(_, sha1)| sha1).collect();
+        let mut reachable = HashSet::new();
+        self.mark_reachable(starts, &mut reachable)?;
+        Ok(reachable)
+    }
+
+    /// adds the objects reachable from `starts` to `reachable`. Objects that
+    /// are already in `reachable` are not descended into again
+    pub(crate) fn mark_reachable(
+        &self,
+        starts: Vec<String>,
+        reachable: &mut HashSet<String>,
+    ) -> Result<()> {
+        let mut pending = starts;
+        while let Some(sha1) = pending.pop() {
+            if !reachable.insert(sha1.clone()) {
+                continue;
+            }
+            match self.load_object(&sha1)? {
+                Object::Commit { tree, parents, .. } => {
+                    pending.push(tree);
+                    pending.extend(parents);
+                }
+                Object::Tree { entries } => {
+                    for entry in entries {
+                        match entry.mode.as_str() {
+                            "40000" => pending.push(entry.sha1()),
+                            "160000" => {}
+                            // blobs have nothing to follow, so they are not
+                            // loaded
+                            _ => {
+                                reachable.insert(entry.sha1());
+                            }
+                        }
+                    }
+                }
+                Object::Tag { object, .. } => pending.push(object),
+                Object::Blob { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// deletes the loose objects that are not reachable from the refs or the
+    /// index and were last modified more than `older_than` ago, like
+    /// `git prune`. Returns the sha1s of the deleted objects.
+    ///
+    /// Recent objects are kept, as they might belong to a commit that is
+    /// still being created. Packed objects are never deleted. With `dry_run`
+    /// the objects are only listed.
+    pub fn prune(&self, older_than: Duration, dry_run: bool) -> Result<Vec<String>> {
+        let mut reachable = self.reachable_objects()?;
+        // staged files are not part of any commit yet
+        reachable.extend(self.read_index()?.entries.iter().map(|entry| entry.sha1()));
+
+        let now = SystemTime::now();
+        let mut pruned = Vec::new();
+        for sha1 in self.loose_objects()? {
+            if reachable.contains(&sha1) {
+                continue;
+            }
+            let path = self.loose_object_path(&sha1);
+            let modified = fs::metadata(&path)?.modified()?;
+            if now.duration_since(modified).unwrap_or_default() <= older_than {
+                continue;
+            }
+            if !dry_run {
+                self.remove_loose_object(&sha1)?;
+            }
+            pruned.push(sha1);
+        }
+        Ok(pruned)
+    }
+
+    /// moves all loose objects into a new pack, like `git repack`, and
+    /// returns the checksum the pack is named after, or `None` if there are
+    /// no loose objects.
+    ///
+    /// Similar objects are stored as deltas, see [Repository::delta_options].
+    /// With `remove_loose` the loose objects are deleted once the pack and
+    /// its index are written.
+    pub fn repack(&self, remove_loose: bool) -> Result<Option<String>> {
+        let sha1s: Vec<String> = self.loose_objects()?.collect();
+        if sha1s.is_empty() {
+            return Ok(None);
+        }
+
+        let mut objects = Vec::with_capacity(sha1s.len());
+        for sha1 in &sha1s {
+            let (typ, size, mut content) = self.object_header(sha1)?;
+            let mut data = Vec::with_capacity(size);
+            content
+                .read_to_end(&mut data)
+                .map_err(GitSyncError::from_io)?;
+            objects.push((typ, data));
+        }
+        let pack = write_delta_pack(&objects, self.hash_algorithm, &self.delta_options());
+        let checksum = self.store_pack(&pack)?;
+
+        if remove_loose {
+            for sha1 in &sha1s {
+                self.remove_loose_object(sha1)?;
+            }
+        }
+        Ok(Some(checksum))
+    }
+
+    /// deletes the file of the loose object `sha1` along with its fan-out
+    /// directory once that is empty
+    fn remove_loose_object(&self, sha1: &str) -> Result<()> {
+        let path = self.loose_object_path(sha1);
+        fs::remove_file(&path)?;
+        if let Some(dir) = path.parent() {
+            // fails if other objects are left
+            let _ = fs::remove_dir(dir);
+        }
+        Ok(())
+    }
+
+    /// counts the loose objects and their total size on disk in bytes
+    pub fn count_loose_objects(&self) -> Result<(usize, u64)> {
+        let mut count = 0;
+        let mut size = 0;
+        for sha1 in self.loose_objects()? {
+            count += 1;
+            size += fs::metadata(self.loose_object_path(&sha1))?.len();
+        }
+        Ok((count, size))
+    }
+
+    /// checks the integrity of every loose object.
+    ///
+    /// Each object is decoded and rehashed. Objects that can't be decoded or
+    /// don't hash to their file name are reported.
+    pub fn fsck(&self) -> Result<Vec<FsckError>> {
+        let mut errors = Vec::new();
+        for sha1 in self.loose_objects()? {
+            match self.load_object(&sha1) {
+                Ok(obj) => {
+                    let actual = obj.hash(self.hash_algorithm);
+                    if actual != sha1 {
+                        errors.push(FsckError::HashMismatch { sha1, actual });
+                    }
+                }
+                Err(error) => errors.push(FsckError::Corrupt { sha1, error }),
+            }
+        }
+        Ok(errors)
+    }
+
+    /// whether the object exists either loose or in a pack.
+    ///
+    /// `sha1` can also be an abbreviated hash, which must match exactly one
+    /// object.
+    pub fn has_object(&self, sha1: &str) -> bool {
+        let sha1 = sha1.to_ascii_lowercase();
+        if !is_lower_hex(&sha1) {
+            return false;
+        }
+        if sha1.len() != self.hash_algorithm.hex_len() {
+            return sha1.len() < self.hash_algorithm.hex_len()
+                && self.resolve_sha1_prefix(&sha1).is_ok();
+        }
+
+        if self.find_loose_object(&sha1).is_some() {
+            return true;
+        }
+        let found = self.find_in_packs(|packs| {
+            packs
+                .iter()
+                .any(|(index, _)| index.find(&sha1).is_some())
+                .then_some(())
+        });
+        matches!(found, Ok(Some(())))
+    }
+
+    /// whether the object `sha1`, a full hash, is a loose object or in one
+    /// of the packs. Unlike [Repository::has_object] the packs are never
+    /// loaded again, so an object in a pack written since they were loaded
+    /// is missed.
+    pub(crate) fn has_object_no_reload(&self, sha1: &str) -> bool {
+        self.find_loose_object(sha1).is_some()
+            || self
+                .packs()
+                .is_ok_and(|packs| packs.iter().any(|(index, _)| index.find(sha1).is_some()))
+    }
+
+    /// reads the type of the object without decoding its content
+    pub fn object_type(&self, sha1: &str) -> Result<ObjectType> {
+        let (typ, _, _) = self.object_header(sha1)?;
+        Ok(typ)
+    }
+
+    /// reads the size of the object's content as declared in its header.
+    ///
+    /// Small objects are fully decoded to validate the declared size.
+    pub fn object_size(&self, sha1: &str) -> Result<usize> {
+        /// objects up to this size are cheap enough to validate
+        const VALIDATE_LIMIT: usize = 1024 * 1024;
+
+        let (_, size, mut content) = self.object_header(sha1)?;
+
+        if size <= VALIDATE_LIMIT {
+            let real_size = io::copy(&mut content, &mut io::sink())?;
+            if real_size != size as u64 {
+                return Err(GitSyncError::SizeMismatch {
+                    expected: size,
+                    actual: real_size as usize,
+                });
+            }
+        }
+
+        Ok(size)
+    }
+
+    /// writes the object `sha1` to `out` in a readable form, like
+    /// `git cat-file -p`.
+    ///
+    /// Trees are listed like `git ls-tree`, blobs, commits and tags are
+    /// written as they are stored.
+    pub fn pretty_print(&self, sha1: &str, out: &mut impl Write) -> Result<()> {
+        if self.object_type(sha1)? == ObjectType::Tree {
+            for entry in self.read_tree(sha1)? {
+                writeln!(out, "{entry}")?;
+            }
+        } else {
+            let mut content = self.open_object_reader(sha1)?;
+            io::copy(&mut content, out).map_err(GitSyncError::from_io)?;
+        }
+        Ok(())
+    }
+
+    /// answers the object names read line by line from `input` like
+    /// `git cat-file --batch`.
+    ///
+    /// For every name, which can be any revision [Repository::rev_parse]
+    /// accepts, `<sha1> <type> <size>\n<content>\n` is written to `out`, or
+    /// `<name> missing\n` if it does not resolve to an object. `out` is
+    /// flushed after every answer, so the input can be written interactively.
+    pub fn cat_file_batch(&self, input: impl BufRead, out: &mut impl Write) -> Result<()> {
+        self.batch(input, out, true)
+    }
+
+    /// like [Repository::cat_file_batch], but only writes
+    /// `<sha1> <type> <size>\n` for every object, like
+    /// `git cat-file --batch-check`. The content is never read, see
+    /// [Repository::object_info]
+    pub fn cat_file_batch_check(&self, input: impl BufRead, out: &mut impl Write) -> Result<()> {
+        self.batch(input, out, false)
+    }
+
+    fn batch(&self, input: impl BufRead, out: &mut impl Write, with_content: bool) -> Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            let name = line.trim();
+            let Ok(sha1) = self.rev_parse(name) else {
+                writeln!(out, "{name} missing")?;
+                out.flush()?;
+                continue;
+            };
+            if with_content {
+                match self.object_header(&sha1) {
+                    Ok((typ, size, mut content)) => {
+                        writeln!(out, "{sha1} {typ} {size}")?;
+                        io::copy(&mut content, out).map_err(GitSyncError::from_io)?;
+                        writeln!(out)?;
+                    }
+                    Err(_) => writeln!(out, "{name} missing")?,
+                }
+            } else {
+                match self.object_info(&sha1) {
+                    Ok((typ, size)) => writeln!(out, "{sha1} {typ} {size}")?,
+                    Err(_) => writeln!(out, "{name} missing")?,
+                }
+            }
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// the type and size of the object `sha1` as declared in its header.
+    ///
+    /// Unlike [Repository::object_size] the content is never decompressed,
+    /// only the header of loose objects and the entry headers of packed
+    /// ones, so this is cheap even for large objects.
+    pub fn object_info(&self, sha1: &str) -> Result<(ObjectType, usize)> {
+        match self.open_object_file(sha1) {
+            Ok(file) => {
+                // the header is at most a few dozen bytes
+                let mut decoder =
+                    BufReader::with_capacity(64, ZlibDecoder::new(BufReader::new(file)));
+                Object::read_header(&mut decoder)
+            }
+            Err(GitSyncError::ObjectNotFound { .. }) => {
+                let found = self.find_in_packs(|packs| {
+                    packs.iter().find_map(|(index, pack)| {
+                        let offset = index.find(sha1)?;
+                        Some(pack.object_info(offset, &mut |base| self.object_type(base)))
+                    })
+                })?;
+                found.unwrap_or_else(|| {
+                    Err(GitSyncError::ObjectNotFound {
+                        sha1: sha1.to_owned(),
+                    })
+                })
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// opens a reader that streams the object's decompressed content.
+    ///
+    /// The header is skipped and the content is never buffered as a whole.
+    pub fn open_object_reader(&self, sha1: &str) -> Result<impl Read> {
+        let (_, _, content) = self.object_header(sha1)?;
+        Ok(content)
+    }
+
+    /// memory maps the compressed file of the loose object `sha1`.
+    ///
+    /// The map can be decoded with e.g. [Object::deserialize_zlib_with], which
+    /// avoids the read calls of a buffered file for large objects.
+    pub fn mmap_object(&self, sha1: &str) -> Result<Mmap> {
+        let file = self.open_object_file(sha1)?;
+        // mapping an empty file fails on some platforms
+        if file.metadata()?.len() == 0 {
+            return Err(GitSyncError::InvalidObjectHeader(format!(
+                "object file of {sha1} is empty"
+            )));
+        }
+        // SAFETY: object files are never modified after they are written,
+        // they are only replaced or removed
+        Ok(unsafe { Mmap::map(&file)? })
+    }
+
+    /// opens the object and reads its header.
+    ///
+    /// The returned reader is positioned at the start of the object's content.
+    /// Loose objects are streamed, packed objects are decoded into memory.
+    fn object_header(&self, sha1: &str) -> Result<(ObjectType, usize, Box<dyn BufRead>)> {
+        if let Some(object) = self.cached_object(sha1) {
+            let mut data = Vec::new();
+            object.serialize(&mut data)?;
+            return Ok((
+                object.object_type(),
+                data.len(),
+                Box::new(io::Cursor::new(data)),
+            ));
+        }
+
+        match self.open_object_file(sha1) {
+            Ok(file) => {
+                let mut decoder = BufReader::new(ZlibDecoder::new(BufReader::new(file)));
+                let (typ, size) = Object::read_header(&mut decoder)?;
+                Ok((typ, size, Box::new(SizedReader::new(decoder, size))))
+            }
+            Err(GitSyncError::ObjectNotFound { .. }) => {
+                let (typ, data) = self.read_packed_object(sha1)?;
+                Ok((typ, data.len(), Box::new(io::Cursor::new(data))))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// reads and decodes the loose or packed object `sha1`
+    pub fn load_object(&self, sha1: &str) -> Result<Object> {
+        if let Some(object) = self.cached_object(sha1) {
+            return Ok(object);
+        }
+
+        let (typ, size, mut content) = self.object_header(sha1)?;
+        let mut data = Vec::with_capacity(size);
+        content
+            .read_to_end(&mut data)
+            .map_err(GitSyncError::from_io)?;
+        let object = Object::deserialize_with(typ, data, self.hash_algorithm)?;
+
+        if let Some(cache) = &self.cache {
+            let mut cache = cache.lock().unwrap_or_else(|err| err.into_inner());
+            cache.insert(sha1, object.clone(), size);
+        }
+        Ok(object)
+    }
+
+    /// writes `object` to the repository unless it already exists and returns
+    /// its hash, see [Object::save]
+    pub fn write_object(&self, object: &Object) -> Result<String> {
+        object.save(self)
+    }
+
+    /// verifies the signature of the commit or tag `sha1` with gpg, like `git
+    /// verify-commit` and `git verify-tag`.
+    ///
+    /// The signed payload is the object without its `gpgsig`. Objects
+    /// without a signature are [SignatureStatus::NotSigned].
+    pub fn verify_signature(&self, sha1: &str) -> Result<SignatureStatus> {
+        let object = self.load_object(sha1)?;
+        let Some((payload, signature)) = object.signed_payload() else {
+            return Ok(SignatureStatus::NotSigned);
+        };
+        let program = self.config.get_str("gpg", None, "program").unwrap_or("gpg");
+        gpg::verify(program, &payload, signature, &self.path(""))
+    }
+
+    /// resolves the revision `rev` like [Repository::rev_parse] and reads the
+    /// object it names, returning its full hash along with the object
+    pub fn read_object(&self, rev: &str) -> Result<(String, Object)> {
+        let sha1 = self.rev_parse(rev)?;
+        let object = self.load_object(&sha1)?;
+        Ok((sha1, object))
+    }
+
+    /// the packs in `objects/pack` of every object directory along with
+    /// their indexes.
+    ///
+    /// The packs are loaded on first use. Packs without an index are ignored.
+    fn packs(&self) -> Result<Packs> {
+        let mut packs = self.packs.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some(loaded) = &*packs {
+            return Ok(Arc::clone(&loaded.packs));
+        }
+
+        // read before the dirs, so packs added while loading are found later
+        let dir_mtimes = self.pack_dir_mtimes();
+        let mut paths = Vec::new();
+        for objects_dir in self.object_dirs() {
+            let entries = match fs::read_dir(objects_dir.join("pack")) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+
+            let mut dir_paths = Vec::new();
+            for entry in entries {
+                let path = entry?.path();
+                if path.extension().is_some_and(|extension| extension == "idx")
+                    && path.with_extension("pack").is_file()
+                {
+                    dir_paths.push(path);
+                }
+            }
+            dir_paths.sort();
+            paths.extend(dir_paths);
+        }
+
+        let loaded = paths
+            .into_iter()
+            .map(|path| {
+                let index = PackIndex::open(&path, self.hash_algorithm)?;
+                let pack = Pack::open(path.with_extension("pack"), self.hash_algorithm)?;
+                Ok((index, pack))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let loaded = Arc::new(loaded);
+        *packs = Some(LoadedPacks {
+            packs: Arc::clone(&loaded),
+            dir_mtimes,
+        });
+        Ok(loaded)
+    }
+
+    /// the modification time of the `pack` dir of every object directory
+    fn pack_dir_mtimes(&self) -> Vec<Option<SystemTime>> {
+        self.object_dirs()
+            .map(|dir| fs::metadata(dir.join("pack")).and_then(|meta| meta.modified()))
+            .map(Result::ok)
+            .collect()
+    }
+
+    /// calls `find` with the [packs](Repository::packs) and, if it finds
+    /// nothing, once more with the packs loaded again. They are only loaded
+    /// again if a `pack` dir changed since, e.g. because another process
+    /// wrote a pack, so a miss usually costs one `stat` per object dir.
+    fn find_in_packs<T>(
+        &self,
+        mut find: impl FnMut(&[(PackIndex, Pack)]) -> Option<T>,
+    ) -> Result<Option<T>> {
+        if let Some(found) = find(&self.packs()?) {
+            return Ok(Some(found));
+        }
+        {
+            let mut packs = self.packs.lock().unwrap_or_else(|err| err.into_inner());
+            let unchanged = packs
+                .as_ref()
+                .is_some_and(|loaded| loaded.dir_mtimes == self.pack_dir_mtimes());
+            if unchanged {
+                return Ok(None);
+            }
+            *packs = None;
+        }
+        Ok(find(&self.packs()?))
+    }
+
+    /// writes the raw pack `data`, e.g. as received from a remote, to
+    /// `objects/pack` along with an index of its objects. Returns the
+    /// checksum the pack is named after.
+    ///
+    /// Ref deltas against objects outside of the pack are resolved from the
+    /// rest of the repository.
+    pub fn store_pack(&self, data: &[u8]) -> Result<String> {
+        let hash_len = self.hash_algorithm.raw_len();
+        if data.len() < hash_len {
+            return Err(GitSyncError::InvalidPack("pack is too short".to_owned()));
+        }
+        let (content, checksum) = data.split_at(data.len() - hash_len);
+        let mut hasher = self.hash_algorithm.hasher();
+        hasher.write_all(content)?;
+        if hasher.finalize() != checksum {
+            return Err(GitSyncError::InvalidPack(
+                "pack checksum does not match its content".to_owned(),
+            ));
+        }
+        let checksum = to_hex(checksum);
+
+        let dir = self.objects_dir.join("pack");
+        fs::create_dir_all(&dir)?;
+        let pack_path = dir.join(format!("pack-{checksum}.pack"));
+        let index_path = pack_path.with_extension("idx");
+        // the index is written last, packs without one are ignored
+        let temp_path = dir.join(format!("tmp_pack_{checksum}"));
+        fs::write(&temp_path, data)?;
+        let result = (|| -> Result<()> {
+            fs::rename(&temp_path, &pack_path)?;
+            let pack = Pack::open(&pack_path, self.hash_algorithm)?;
+            let entries =
+                pack.index_entries(&mut |base| self.read_delta_base(base, &mut Vec::new()))?;
+            let temp_index = dir.join(format!("tmp_idx_{checksum}"));
+            PackIndex::write(&temp_index, &entries, &checksum, self.hash_algorithm)?;
+            fs::rename(temp_index, &index_path)?;
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+            if !index_path.is_file() {
+                let _ = fs::remove_file(&pack_path);
+            }
+        }
+        result?;
+
+        *self.packs.lock().unwrap_or_else(|err| err.into_inner()) = None;
+        Ok(checksum)
+    }
+
+    /// finds the object `sha1` in one of the packs.
+    ///
+    /// If the object is not found, the packs are loaded again in case a new
+    /// pack was written since they were first loaded, see
+    /// [Repository::find_in_packs].
+    fn read_packed_object(&self, sha1: &str) -> Result<(ObjectType, Vec<u8>)> {
+        self.read_packed_object_from(sha1, &mut Vec::new())
+    }
+
+    /// reads the packed object `sha1`, whose ref delta bases are read from
+    /// anywhere in the repository.
+    ///
+    /// `pending` holds the objects whose bases are currently being resolved,
+    /// so that ref deltas which depend on each other fail instead of
+    /// recursing forever.
+    fn read_packed_object_from(
+        &self,
+        sha1: &str,
+        pending: &mut Vec<String>,
+    ) -> Result<(ObjectType, Vec<u8>)> {
+        let found = self.find_in_packs(|packs| {
+            let (offset, pack) = packs
+                .iter()
+                .find_map(|(index, pack)| Some((index.find(sha1)?, pack)))?;
+            Some(pack.read_object_with(offset, &mut |base| {
+                if pending.len() >= MAX_DELTA_DEPTH || pending.iter().any(|p| p == base) {
+                    return Err(GitSyncError::InvalidPack(format!(
+                        "delta chain of {sha1} is cyclic or too deep"
+                    )));
+                }
+                pending.push(sha1.to_owned());
+                let base = self.read_delta_base(base, pending);
+                pending.pop();
+                base
+            }))
+        })?;
+        found.unwrap_or_else(|| {
+            Err(GitSyncError::ObjectNotFound {
+                sha1: sha1.to_owned(),
+            })
+        })
+    }
+
+    /// reads the loose or packed object `sha1`, which is the base of a ref
+    /// delta
+    fn read_delta_base(
+        &self,
+        sha1: &str,
+        pending: &mut Vec<String>,
+    ) -> Result<(ObjectType, Vec<u8>)> {
+        match self.open_object_file(sha1) {
+            Ok(file) => {
+                let mut decoder = BufReader::new(ZlibDecoder::new(BufReader::new(file)));
+                let (typ, size) = Object::read_header(&mut decoder)?;
+                let mut data = Vec::with_capacity(size);
+                SizedReader::new(decoder, size)
+                    .read_to_end(&mut data)
+                    .map_err(GitSyncError::from_io)?;
+                Ok((typ, data))
+            }
+            Err(GitSyncError::ObjectNotFound { .. }) => self.read_packed_object_from(sha1, pending),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// reads the entries of the tree `sha1`.
+    ///
+    /// Commits and tags are dereferenced until they point to a tree.
+    pub fn read_tree(&self, sha1: &str) -> Result<Vec<TreeEntry>> {
+        let mut sha1 = sha1.to_owned();
+        loop {
+            match self.load_object(&sha1)? {
+                Object::Tree { entries } => return Ok(entries),
+                Object::Commit { tree, .. } => sha1 = tree,
+                Object::Tag { object, .. } => sha1 = object,
+                Object::Blob { .. } => {
+                    return Err(GitSyncError::UnexpectedObjectType {
+                        sha1,
+                        expected: ObjectType::Tree,
+                    })
+                }
+            }
+        }
+    }
+
+    /// reads all blobs and submodules in the tree `sha1` and its subtrees.
+    ///
+    /// The name of each entry is its path relative to the tree, e.g.
+    /// `dir/file.txt`. Subtrees themselves are not included.
+    pub fn read_tree_recursive(&self, sha1: &str) -> Result<Vec<TreeEntry>> {
+        let mut entries = Vec::new();
+        self.collect_tree(sha1, &[], &mut entries)?;
+        Ok(entries)
+    }
+
+    fn collect_tree(&self, sha1: &str, prefix: &[u8], entries: &mut Vec<TreeEntry>) -> Result<()> {
+        for mut entry in self.read_tree(sha1)? {
+            if !prefix.is_empty() {
+                entry.name = [prefix, b"/", &entry.name].concat();
+            }
+            if entry.is_tree() {
+                self.collect_tree(&entry.sha1(), &entry.name, entries)?;
+            } else {
+                entries.push(entry);
+            }
+        }
+        Ok(())
+    }
+
+    /// creates a commit of the tree `tree_sha` and returns its sha1, like
+    /// `git commit-tree`.
+    ///
+    /// The tree and all `parents` must exist and have the right type.
+    pub fn commit_tree(
+        &self,
+        tree_sha: &str,
+        parents: &[String],
+        author: &Signature,
+        committer: &Signature,
+        message: &str,
+    ) -> Result<String> {
+        self.expect_type(tree_sha, ObjectType::Tree)?;
+        for parent in parents {
+            self.expect_type(parent, ObjectType::Commit)?;
+        }
+
+        Object::Commit {
+            tree: tree_sha.to_owned(),
+            parents: parents.to_vec(),
+            author: author.clone(),
+            committer: committer.clone(),
+            encoding: None,
+            extra_headers: Vec::new(),
+            gpgsig: None,
+            message: message.as_bytes().to_vec(),
+        }
+        .save(self)
+    }
+
+    /// commits the index on top of HEAD and advances the branch HEAD points
+    /// to. Returns the sha1 of the new commit.
+    ///
+    /// On an unborn branch a root commit is created along with the branch.
+    /// If a merge with conflicts is in progress, the merged commit becomes
+    /// the second parent and the merge is finished.
+    ///
+    /// No hooks are run, see [Repository::commit_with] for that.
+    pub fn commit(
+        &self,
+        message: &str,
+        author: &Signature,
+        committer: &Signature,
+    ) -> Result<String> {
+        let tree = self.write_tree_from_index()?;
+        let mut parents = match self.resolve_ref("HEAD") {
+            Ok(head) => vec![head],
+            Err(GitSyncError::RefNotFound { .. }) => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        let merge_head = self.read_ref("MERGE_HEAD")?;
+        parents.extend(merge_head.clone());
+
+        let commit = self.commit_tree(&tree, &parents, author, committer, message)?;
+        // fails if HEAD moved while the commit was created
+        let old = parents.first().cloned().unwrap_or_else(|| self.null_sha1());
+        let kind = match parents.len() {
+            0 => "commit (initial)",
+            1 => "commit",
+            _ => "commit (merge)",
+        };
+        let subject = message.lines().next().unwrap_or_default();
+        self.update_ref_with_message("HEAD", &commit, Some(&old), &format!("{kind}: {subject}"))?;
+        if merge_head.is_some() {
+            fs::remove_file(self.path("MERGE_HEAD"))?;
+            let _ = fs::remove_file(self.path("MERGE_MSG"));
+        }
+        for head_file in ["CHERRY_PICK_HEAD", "REVERT_HEAD"] {
+            if self.path(head_file).is_file() {
+                fs::remove_file(self.path(head_file))?;
+                let _ = fs::remove_file(self.path("MERGE_MSG"));
+            }
+        }
+        Ok(commit)
+    }
+
+    /// like [Repository::commit], but runs the `pre-commit` and `commit-msg`
+    /// hooks first, like `git commit`.
+    ///
+    /// The message is written to `COMMIT_EDITMSG` and passed to `commit-msg`,
+    /// which may rewrite it. Fails with [GitSyncError::HookFailed] if a hook
+    /// exits with an error.
+    pub fn commit_with(
+        &self,
+        message: &str,
+        author: &Signature,
+        committer: &Signature,
+        options: &CommitOptions,
+    ) -> Result<String> {
+        if options.no_verify {
+            return self.commit(message, author, committer);
+        }
+        self.run_hook_checked("pre-commit", &[])?;
+
+        let message_file = self.path("COMMIT_EDITMSG");
+        fs::write(&message_file, message)?;
+        self.run_hook_checked("commit-msg", &[message_file.as_os_str()])?;
+        let message = fs::read_to_string(&message_file)?;
+        self.commit(&message, author, committer)
+    }
+
+    fn run_hook_checked(&self, name: &str, args: &[&OsStr]) -> Result<()> {
+        let status = hooks::run_hook(self, name, args, None)?;
+        if !status.success() {
+            return Err(GitSyncError::HookFailed {
+                name: name.to_owned(),
+                status,
+            });
+        }
+        Ok(())
+    }
+
+    fn expect_type(&self, sha1: &str, expected: ObjectType) -> Result<()> {
+        if self.object_type(sha1)? != expected {
+            return Err(GitSyncError::UnexpectedObjectType {
+                sha1: sha1.to_owned(),
+                expected,
+            });
+        }
+        Ok(())
+    }
+
+    /// a unified diff from the blob `a_sha1` to the blob `b_sha1`.
+    ///
+    /// Blobs containing a NUL byte are binary and only reported as
+    /// `Binary files differ`. Equal blobs have an empty diff.
+    pub fn diff_blobs(&self, a_sha1: &str, b_sha1: &str) -> Result<String> {
+        let a = self.read_blob(a_sha1)?;
+        let b = self.read_blob(b_sha1)?;
+        if a == b {
+            return Ok(String::new());
+        }
+        if a.contains(&0) || b.contains(&0) {
+            return Ok("Binary files differ\n".to_owned());
+        }
+        Ok(diff::unified_diff(
+            &a,
+            &b,
+            &format!("a/{a_sha1}"),
+            &format!("b/{b_sha1}"),
+        ))
+    }
+
+    /// the files that differ between the trees `a_sha1` and `b_sha1`, sorted
+    /// by path.
+    ///
+    /// Commits and tags are dereferenced to their trees. Subtrees are compared
+    /// recursively and identical subtrees are skipped. When a directory is
+    /// replaced by a file, or the other way around, the files below the
+    /// directory are reported separately from the file.
+    pub fn diff_trees(&self, a_sha1: &str, b_sha1: &str) -> Result<Vec<TreeChange>> {
+        let mut changes = Vec::new();
+        let a = self.read_tree(a_sha1)?;
+        let b = self.read_tree(b_sha1)?;
+        self.diff_tree_entries(&a, &b, &[], &mut changes)?;
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(changes)
+    }
+
+    fn diff_tree_entries(
+        &self,
+        a: &[TreeEntry],
+        b: &[TreeEntry],
+        prefix: &[u8],
+        changes: &mut Vec<TreeChange>,
+    ) -> Result<()> {
+        // both trees are sorted, so entries with the same name line up.
+        // A tree and a file with the same name are sorted apart, which
+        // treats them as different names
+        let (mut a, mut b) = (a.iter().peekable(), b.iter().peekable());
+        loop {
+            let (old, new) = match (a.peek(), b.peek()) {
+                (None, None) => return Ok(()),
+                (Some(_), None) => (a.next(), None),
+                (None, Some(_)) => (None, b.next()),
+                (Some(old), Some(new)) => match old.cmp_git(new) {
+                    Ordering::Less => (a.next(), None),
+                    Ordering::Greater => (None, b.next()),
+                    Ordering::Equal => (a.next(), b.next()),
+                },
+            };
+            let name = old.or(new).unwrap().name.as_slice();
+            let path = if prefix.is_empty() {
+                name.to_vec()
+            } else {
+                [prefix, b"/", name].concat()
+            };
+
+            match (old, new) {
+                (Some(old), Some(new)) if old.mode == new.mode && old.sha == new.sha => {}
+                (Some(old), Some(new)) if old.is_tree() => {
+                    let old_entries = self.read_tree(&old.sha1())?;
+                    let new_entries = self.read_tree(&new.sha1())?;
+                    self.diff_tree_entries(&old_entries, &new_entries, &path, changes)?;
+                }
+                (Some(old), Some(new)) => changes.push(TreeChange {
+                    path,
+                    change: Change::Modified,
+                    old: Some((old.mode.clone(), old.sha1())),
+                    new: Some((new.mode.clone(), new.sha1())),
+                }),
+                (Some(old), None) if old.is_tree() => {
+                    let old_entries = self.read_tree(&old.sha1())?;
+                    self.diff_tree_entries(&old_entries, &[], &path, changes)?;
+                }
+                (Some(old), None) => changes.push(TreeChange {
+                    path,
+                    change: Change::Deleted,
+                    old: Some((old.mode.clone(), old.sha1())),
+                    new: None,
+                }),
+                (None, Some(new)) if new.is_tree() => {
+                    let new_entries = self.read_tree(&new.sha1())?;
+                    self.diff_tree_entries(&[], &new_entries, &path, changes)?;
+                }
+                (None, Some(new)) => changes.push(TreeChange {
+                    path,
+                    change: Change::Added,
+                    old: None,
+                    new: Some((new.mode.clone(), new.sha1())),
+                }),
+                (None, None) => unreachable!(),
+            }
+        }
+    }
+
+    /// the content of the blob `sha1`
+    pub(crate) fn read_blob(&self, sha1: &str) -> Result<Vec<u8>> {
+        match self.load_object(sha1)? {
+            Object::Blob { data } => Ok(data),
+            _ => Err(GitSyncError::UnexpectedObjectType {
+                sha1: sha1.to_owned(),
+                expected: ObjectType::Blob,
+            }),
+        }
+    }
+
+    /// saves the staged files of the index as trees and returns the sha1 of
+    /// the root tree, like `git write-tree`.
+    ///
+    /// Fails if the index contains unmerged paths.
+    pub fn write_tree_from_index(&self) -> Result<String> {
+        let index = self.read_index()?;
+        if let Some(entry) = index.entries.iter().find(|entry| entry.stage() != 0) {
+            return Err(GitSyncError::UnmergedPath {
+                path: String::from_utf8_lossy(&entry.path).into_owned(),
+            });
+        }
+        self.write_index_tree(&index.entries, 0)
+    }
+
+    /// saves the tree of `entries`, which all share the first `prefix_len`
+    /// bytes of their path
+    fn write_index_tree(&self, entries: &[IndexEntry], prefix_len: usize) -> Result<String> {
+        let mut tree_entries = Vec::new();
+        let mut rest = entries;
+        while let Some(entry) = rest.first() {
+            let name = &entry.path[prefix_len..];
+            match name.iter().position(|&b| b == b'/') {
+                None => {
+                    tree_entries.push(TreeEntry {
+                        mode: entry.mode_str(),
+                        name: name.to_vec(),
+                        sha: entry.sha.clone(),
+                    });
+                    rest = &rest[1..];
+                }
+                Some(slash) => {
+                    // the index is sorted, so a directory's entries are adjacent
+                    let dir = &entry.path[..prefix_len + slash + 1];
+                    let len = rest
+                        .iter()
+                        .take_while(|entry| entry.path.starts_with(dir))
+                        .count();
+                    let sha1 = self.write_index_tree(&rest[..len], dir.len())?;
+                    tree_entries.push(TreeEntry {
+                        mode: "40000".to_owned(),
+                        name: name[..slash].to_vec(),
+                        sha: from_hex(&sha1)?,
+                    });
+                    rest = &rest[len..];
+                }
+            }
+        }
+        Object::Tree {
+            entries: tree_entries,
+        }
+        .save(self)
+    }
+
+    /// saves the content of the directory `path` as blobs and trees and
+    /// returns the sha1 of the root tree.
+    ///
+    /// `.git` entries are skipped and, like in git, empty directories are not
+    /// stored.
+    pub fn write_tree_from_dir(&self, path: impl AsRef<Path>) -> Result<String> {
+        let entries = self.dir_tree_entries(path.as_ref(), true)?;
+        Object::Tree { entries }.save(self)
+    }
+
+    /// like [Repository::write_tree_from_dir], but only calculates the sha1
+    /// of the root tree without saving any objects
+    pub fn hash_tree_from_dir(&self, path: impl AsRef<Path>) -> Result<String> {
+        let entries = self.dir_tree_entries(path.as_ref(), false)?;
+        Ok(Object::Tree { entries }.hash(self.hash_algorithm))
+    }
+
+    /// the tree entries of the directory `path`, whose blobs and subtrees are
+    /// saved if `write` is set
+    fn dir_tree_entries(&self, path: &Path, write: bool) -> Result<Vec<TreeEntry>> {
+        let store = |object: Object| {
+            if write {
+                object.save(self)
+            } else {
+                Ok(object.hash(self.hash_algorithm))
+            }
+        };
+        let mut entries = Vec::new();
+        for dir_entry in fs::read_dir(path)? {
+            let dir_entry = dir_entry?;
+            let name = dir_entry.file_name();
+            if RepoKind::is_dir_name(&name) {
+                continue;
+            }
+
+            let metadata = fs::symlink_metadata(dir_entry.path())?;
+            let (mode, sha1) = if metadata.is_dir() {
+                let sub_entries = self.dir_tree_entries(&dir_entry.path(), write)?;
+                if sub_entries.is_empty() {
+                    continue;
+                }
+                let tree = Object::Tree {
+                    entries: sub_entries,
+                };
+                ("40000".to_owned(), store(tree)?)
+            } else {
+                let sha1 = store(file_blob(&dir_entry.path(), &metadata)?)?;
+                (format!("{:o}", file_mode(&metadata)), sha1)
+            };
+
+            entries.push(TreeEntry {
+                mode,
+                name: os_str_bytes(&name),
+                sha: from_hex(&sha1)?,
+            });
+        }
+        entries.sort_by(|a, b| a.cmp_git(b));
+        Ok(entries)
+    }
+
+    /// saves the file or symlink at `path` as a blob and returns its sha1
+    fn save_file_blob(&self, path: &Path, metadata: &fs::Metadata) -> Result<String> {
+        file_blob(path, metadata)?.save(self)
+    }
+
+    /// stages the files at `paths`, which are relative to the worktree root.
+    ///
+    /// Directories are added recursively and tracked files that no longer
+    /// exist are removed from the index. Files whose stat data did not change
+    /// since they were staged are not read again.
+    pub fn add(&self, paths: &[impl AsRef<Path>]) -> Result<()> {
+        let root = self.worktree_root()?;
+        let mut index = self.read_index()?;
+
+        let mut changed = false;
+        for path in paths {
+            let path = path.as_ref();
+            let name = path_bytes(path);
+            let full_path = root.join(path);
+
+            changed |= match fs::symlink_metadata(&full_path) {
+                Ok(metadata) if metadata.is_dir() => {
+                    let mut changed = self.remove_deleted(&mut index, &name)?;
+                    let ignore = IgnoreRules::load(self)?;
+                    changed |= self.add_dir(&mut index, &ignore, &name, &full_path)?;
+                    changed
+                }
+                Ok(metadata) => self.add_file(&mut index, name, &full_path, &metadata)?,
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    if !index.remove(&name) {
+                        return Err(GitSyncError::PathspecNotFound {
+                            path: path.to_path_buf(),
+                        });
+                    }
+                    true
+                }
+                Err(err) => return Err(err.into()),
+            };
+        }
+
+        if changed {
+            self.write_index(&index)?;
+        }
+        Ok(())
+    }
+
+    /// replaces the index with the files of the tree `tree_ish`, like
+    /// `git read-tree`. Commits and tags are dereferenced to their tree.
+    ///
+    /// The entries have no stat data, so they never match the worktree until
+    /// the files are staged again. The worktree itself is not changed.
+    pub fn read_tree_into_index(&self, tree_ish: &str) -> Result<()> {
+        let mut entries = Vec::new();
+        for entry in self.read_tree_recursive(tree_ish)? {
+            let mode = entry_mode(&entry)?;
+            entries.push(IndexEntry::new(entry.name, entry.sha, mode));
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        let index = Index {
+            entries,
+            ..Index::default()
+        };
+        self.write_index(&index)
+    }
+
+    /// the entries of the index, like `git ls-files`. With `only_deleted`
+    /// only the entries whose file is missing in the worktree are returned
+    pub fn ls_files(&self, only_deleted: bool) -> Result<Vec<IndexEntry>> {
+        let mut entries = self.read_index()?.entries;
+        if only_deleted {
+            let root = self.worktree_root()?;
+            entries.retain(|entry| {
+                fs::symlink_metadata(root.join(bytes_path(&entry.path)))
+                    .map_or(true, |metadata| metadata.is_dir())
+            });
+        }
+        Ok(entries)
+    }
+
+    /// removes the index entries below `dir` whose file no longer exists
+    fn remove_deleted(&self, index: &mut Index, dir: &[u8]) -> Result<bool> {
+        let root = self.worktree_root()?;
+        let deleted: Vec<Vec<u8>> = index
+            .entries
+            .iter()
+            .filter(|entry| dir.is_empty() || is_below(&entry.path, dir))
+            .filter(|entry| {
+                fs::symlink_metadata(root.join(bytes_path(&entry.path)))
+                    .map_or(true, |metadata| metadata.is_dir())
+            })
+            .map(|entry| entry.path.clone())
+            .collect();
+
+        for path in &deleted {
+            index.remove(path);
+        }
+        Ok(!deleted.is_empty())
+    }
+
+    /// adds the files below the directory `path`. Ignored files are skipped
+    /// unless they are already tracked
+    fn add_dir(
+        &self,
+        index: &mut Index,
+        ignore: &IgnoreRules,
+        name: &[u8],
+        path: &Path,
+    ) -> Result<bool> {
+        let mut changed = false;
+        for dir_entry in fs::read_dir(path)? {
+            let dir_entry = dir_entry?;
+            if RepoKind::is_dir_name(&dir_entry.file_name()) {
+                continue;
+            }
+
+            let mut child = name.to_vec();
+            if !child.is_empty() {
+                child.push(b'/');
+            }
+            child.extend(os_str_bytes(&dir_entry.file_name()));
+
+            let metadata = fs::symlink_metadata(dir_entry.path())?;
+            let tracked = index.contains(&child) || index.contains_below(&child);
+            if !tracked && ignore.matches(&child, metadata.is_dir()) {
+                continue;
+            }
+            changed |= if metadata.is_dir() {
+                self.add_dir(index, ignore, &child, &dir_entry.path())?
+            } else {
+                self.add_file(index, child, &dir_entry.path(), &metadata)?
+            };
+        }
+        Ok(changed)
+    }
+
+    fn add_file(
+        &self,
+        index: &mut Index,
+        name: Vec<u8>,
+        path: &Path,
+        metadata: &fs::Metadata,
+    ) -> Result<bool> {
+        let mode = file_mode(metadata);
+        if let Some(entry) = index.entry(&name) {
+            if entry.mode == mode && entry.matches_metadata(metadata) {
+                return Ok(false);
+            }
+        }
+
+        let sha1 = self.save_file_blob(path, metadata)?;
+        let entry = IndexEntry::from_metadata(name, from_hex(&sha1)?, mode, metadata);
+        index.add(entry);
+        Ok(true)
+    }
+
+    /// writes the files of the tree `tree_sha` to the directory `dest`, like
+    /// `git checkout-index --all` after reading the tree into the index.
+    ///
+    /// Directories are created as needed, executables get their executable
+    /// bit and `120000` entries become symlinks. Files that already have the
+    /// right content and mode are left alone, so their mtime is kept. Files
+    /// in `dest` that are not in the tree are not removed.
+    pub fn checkout_tree(&self, tree_sha: &str, dest: impl AsRef<Path>) -> Result<()> {
+        let dest = dest.as_ref();
+        for entry in self.read_tree_recursive(tree_sha)? {
+            let path = dest.join(bytes_path(&entry.name));
+            if !is_checked_out(self, &path, &entry)? {
+                checkout_entry(self, &path, &entry)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// points the branch `HEAD` points to, or a detached `HEAD`, to the
+    /// commit `target` resolves to, like `git reset`. Returns the sha1 of the
+    /// commit.
+    ///
+    /// Depending on `mode` the index and worktree are reset to the commit as
+    /// well, which also ends a merge, cherry-pick or revert in progress.
+    /// Local changes are lost with [ResetMode::Hard].
+    pub fn reset(&self, target: &str, mode: ResetMode) -> Result<String> {
+        let (commit, _) = self.peel_to_commit(&self.rev_parse(target)?)?;
+        let old = self.try_resolve_ref("HEAD")?;
+
+        // everything tracked before, staged or not, is removed unless the
+        // commit has it
+        let mut tracked: HashSet<Vec<u8>> = HashSet::new();
+        if mode == ResetMode::Hard {
+            tracked.extend(
+                self.read_index()?
+                    .entries
+                    .into_iter()
+                    .map(|entry| entry.path),
+            );
+            if let Some(old) = &old {
+                tracked.extend(
+                    self.read_tree_recursive(old)?
+                        .into_iter()
+                        .map(|entry| entry.name),
+                );
+            }
+        }
+
+        let old = old.unwrap_or_else(|| self.null_sha1());
+        self.update_ref_with_message(
+            "HEAD",
+            &commit,
+            Some(&old),
+            &format!("reset: moving to {target}"),
+        )?;
+        if mode == ResetMode::Soft {
+            return Ok(commit);
+        }
+
+        self.read_tree_into_index(&commit)?;
+        for state in ["MERGE_HEAD", "CHERRY_PICK_HEAD", "REVERT_HEAD", "MERGE_MSG"] {
+            let _ = fs::remove_file(self.path(state));
+        }
+        if mode == ResetMode::Hard {
+            let root = self.worktree_root()?;
+            for entry in self.read_tree_recursive(&commit)? {
+                tracked.remove(&entry.name);
+            }
+            for path in tracked {
+                remove_worktree_file(root, &path)?;
+            }
+            self.checkout_tree(&commit, root)?;
+        }
+        Ok(commit)
+    }
+
+    /// checks out `target`, like `git switch`.
+    ///
+    /// If `target` is a branch, `HEAD` points to it afterwards. Any other
+    /// revision is checked out as a detached `HEAD`. The worktree and index
+    /// are updated to the tree of the commit: files that differ between the
+    /// trees are written and files that are no longer tracked are removed.
+    ///
+    /// Unless `force` is set, switching fails if it would overwrite staged,
+    /// unstaged or untracked changes.
+    pub fn switch(&self, target: &str, force: bool) -> Result<()> {
+        let branch = format!("refs/heads/{target}");
+        let is_branch = self.read_ref(&branch)?.is_some();
+        let spec = if is_branch { &branch } else { target };
+        let (commit, _) = self.peel_to_commit(&self.rev_parse(spec)?)?;
+        let old = self.try_resolve_ref("HEAD")?;
+        let old_entries = match &old {
+            Some(old) => self.read_tree_recursive(old)?,
+            None => Vec::new(),
+        };
+        let new_entries = self.read_tree_recursive(&commit)?;
+        self.checkout_entries(&old_entries, &new_entries, force)?;
+
+        let from = match (self.current_branch()?, &old) {
+            (Some(branch), _) => branch,
+            (None, Some(old)) => old.clone(),
+            (None, None) => "HEAD".to_owned(),
+        };
+        if is_branch {
+            self.write_symbolic_ref("HEAD", &branch)?;
+        } else {
+            self.write_raw_ref("HEAD", &commit)?;
+        }
+        let entry = ReflogEntry::new(
+            &old.unwrap_or_else(|| self.null_sha1()),
+            &commit,
+            self.reflog_identity(),
+            &format!("checkout: moving from {from} to {target}"),
+        );
+        self.append_reflog("HEAD", &entry)
+    }
+
+    /// updates the worktree and index from the files `old_entries` of the
+    /// current commit to `new_entries`, as returned by
+    /// [Repository::read_tree_recursive]. Unless `force` is set, fails
+    /// without changes if local changes would be overwritten
+    fn checkout_entries(
+        &self,
+        old_entries: &[TreeEntry],
+        new_entries: &[TreeEntry],
+        force: bool,
+    ) -> Result<()> {
+        let old: BTreeMap<&[u8], &TreeEntry> = old_entries
+            .iter()
+            .map(|entry| (entry.name.as_slice(), entry))
+            .collect();
+        let new: BTreeMap<&[u8], &TreeEntry> = new_entries
+            .iter()
+            .map(|entry| (entry.name.as_slice(), entry))
+            .collect();
+
+        let differs = |path: &[u8]| old.get(path) != new.get(path);
+        if !force {
+            let status = self.status()?;
+            let dirty = status
+                .staged
+                .iter()
+                .chain(&status.unstaged)
+                .map(|(path, _)| path)
+                .filter(|path| differs(path))
+                .chain(status.untracked.iter().filter(|path| {
+                    // untracked directories are reported with a trailing `/`
+                    let dir = path.strip_suffix(b"/").unwrap_or(path);
+                    new.keys().any(|name| *name == dir || is_below(name, dir))
+                }))
+                .next();
+            if let Some(path) = dirty {
+                return Err(GitSyncError::WouldOverwrite {
+                    path: String::from_utf8_lossy(path).into_owned(),
+                });
+            }
+        }
+
+        let root = self.worktree_root()?;
+        // paths that are the same in both trees keep their index entries, so
+        // their local changes survive
+        let mut index = if force {
+            Index::default()
+        } else {
+            self.read_index()?
+        };
+        for path in old.keys().filter(|path| !new.contains_key(*path)) {
+            remove_worktree_file(root, path)?;
+            index.remove(path);
+        }
+        for (path, entry) in &new {
+            if !force && !differs(path) {
+                continue;
+            }
+            let full_path = root.join(bytes_path(path));
+            checkout_entry(self, &full_path, entry)?;
+            let metadata = fs::symlink_metadata(&full_path)?;
+            index.add(IndexEntry::from_metadata(
+                path.to_vec(),
+                entry.sha.clone(),
+                entry_mode(entry)?,
+                &metadata,
+            ));
+        }
+        self.write_index(&index)
+    }
+
+    /// merges the commit `target` into `HEAD`, like `git merge`.
+    ///
+    /// If `HEAD` is an ancestor of the commit, the branch, index and worktree
+    /// are fast-forwarded to it. Otherwise the changes of both sides since
+    /// their [merge base](Repository::merge_base) are combined file by file,
+    /// and line by line for files changed on both sides, and committed with
+    /// `HEAD` and the commit as parents.
+    ///
+    /// Conflicts are not committed, but left in the index and worktree,
+    /// see [MergeOutcome::Conflicts]. Fails without changes if local changes
+    /// to the merged files would be overwritten.
+    pub fn merge(
+        &self,
+        target: &str,
+        author: &Signature,
+        committer: &Signature,
+    ) -> Result<MergeOutcome> {
+        self.check_no_merge_in_progress()?;
+        let head = self.resolve_ref("HEAD")?;
+        let (theirs, _) = self.peel_to_commit(&self.rev_parse(target)?)?;
+        let base = self
+            .merge_base(&head, &theirs)?
+            .ok_or(GitSyncError::UnrelatedHistories)?;
+        if base == theirs {
+            return Ok(MergeOutcome::UpToDate);
+        }
+
+        let our_entries = self.read_tree_recursive(&head)?;
+        let their_entries = self.read_tree_recursive(&theirs)?;
+        if base == head {
+            self.checkout_entries(&our_entries, &their_entries, false)?;
+            self.update_ref_with_message(
+                "HEAD",
+                &theirs,
+                Some(&head),
+                &format!("merge {target}: Fast-forward"),
+            )?;
+            return Ok(MergeOutcome::FastForward { commit: theirs });
+        }
+
+        let base_entries = self.read_tree_recursive(&base)?;
+        let merge =
+            self.merge_entries(&base_entries, &our_entries, &their_entries, "HEAD", target)?;
+        self.checkout_merge(&our_entries, &merge)?;
+
+        let kind = if self.read_ref(&format!("refs/heads/{target}"))?.is_some() {
+            "branch"
+        } else if self.read_ref(&format!("refs/remotes/{target}"))?.is_some() {
+            "remote-tracking branch"
+        } else {
+            "commit"
+        };
+        let message = format!("Merge {kind} '{target}'\n");
+        if merge.conflicts.is_empty() {
+            let tree = self.write_tree_from_index()?;
+            let parents = [head.clone(), theirs];
+            let commit = self.commit_tree(&tree, &parents, author, committer, &message)?;
+            self.update_ref_with_message(
+                "HEAD",
+                &commit,
+                Some(&head),
+                &format!("merge {target}: Merge made by three-way merge"),
+            )?;
+            return Ok(MergeOutcome::Merged { commit });
+        }
+
+        self.write_conflicts(&merge)?;
+        fs::write(self.path("MERGE_HEAD"), format!("{theirs}\n"))?;
+        fs::write(self.path("MERGE_MSG"), message)?;
+        Ok(MergeOutcome::Conflicts {
+            paths: merge.conflicts.into_iter().map(|(path, _)| path).collect(),
+        })
+    }
+
+    /// applies the changes the commit `commit` made to its first parent to
+    /// `HEAD` and commits them with the author and message of `commit`, like
+    /// `git cherry-pick`.
+    ///
+    /// The changes are merged like in [Repository::merge], with the parent
+    /// as the merge base. Conflicts are left in the index and worktree with
+    /// `CHERRY_PICK_HEAD` pointing to `commit`, committing after they are
+    /// added finishes the cherry-pick. [MergeOutcome::UpToDate] means that
+    /// `HEAD` already contains the changes.
+    pub fn cherry_pick(&self, commit: &str, committer: &Signature) -> Result<MergeOutcome> {
+        self.check_no_merge_in_progress()?;
+        let head = self.resolve_ref("HEAD")?;
+        let (picked, parents) = self.peel_to_commit(&self.rev_parse(commit)?)?;
+        let Object::Commit {
+            author, message, ..
+        } = self.load_object(&picked)?
+        else {
+            unreachable!("peeled to a commit");
+        };
+
+        let message = String::from_utf8_lossy(&message);
+        let subject = message.lines().next().unwrap_or_default();
+        let label = format!("{} ({subject})", &picked[..7]);
+        let parent = parents.first().map(String::as_str);
+        match self.apply_changes(&head, parent, Some(picked.as_str()), &label)? {
+            Applied::Unchanged => Ok(MergeOutcome::UpToDate),
+            Applied::Tree(tree) => {
+                let commit =
+                    self.commit_tree(&tree, &[head.clone()], &author, committer, &message)?;
+                self.update_ref_with_message(
+                    "HEAD",
+                    &commit,
+                    Some(&head),
+                    &format!("cherry-pick: {subject}"),
+                )?;
+                Ok(MergeOutcome::Merged { commit })
+            }
+            Applied::Conflicts(merge) => {
+                self.stop_at_conflicts(merge, "CHERRY_PICK_HEAD", &picked, &message)
+            }
+        }
+    }
+
+    /// applies the inverse of the changes the commit `commit` made to its
+    /// parent to `HEAD` and commits them with a `Revert "<subject>"` message,
+    /// like `git revert`.
+    ///
+    /// Merge commits can only be reverted relative to the parent `mainline`,
+    /// counting from 1, which must not be given for other commits.
+    /// Conflicts are handled like in [Repository::cherry_pick], with
+    /// `REVERT_HEAD` pointing to `commit`.
+    pub fn revert(
+        &self,
+        commit: &str,
+        mainline: Option<usize>,
+        author: &Signature,
+        committer: &Signature,
+    ) -> Result<MergeOutcome> {
+        self.check_no_merge_in_progress()?;
+        let head = self.resolve_ref("HEAD")?;
+        let (reverted, parents) = self.peel_to_commit(&self.rev_parse(commit)?)?;
+        let invalid_mainline = |reason: &str| GitSyncError::InvalidMainline {
+            commit: reverted.clone(),
+            reason: reason.to_owned(),
+        };
+        let parent = match (mainline, parents.len()) {
+            (None, 0 | 1) => parents.first(),
+            (None, _) => return Err(invalid_mainline("it is a merge but no mainline was given")),
+            (Some(_), 0 | 1) => return Err(invalid_mainline("it is not a merge")),
+            (Some(mainline), _) => Some(
+                mainline
+                    .checked_sub(1)
+                    .and_then(|index| parents.get(index))
+                    .ok_or_else(|| invalid_mainline(&format!("it has no parent {mainline}")))?,
+            ),
+        };
+        let Object::Commit {
+            message: reverted_message,
+            ..
+        } = self.load_object(&reverted)?
+        else {
+            unreachable!("peeled to a commit");
+        };
+
+        let reverted_message = String::from_utf8_lossy(&reverted_message);
+        let subject = reverted_message.lines().next().unwrap_or_default();
+        let message = format!("Revert \"{subject}\"\n\nThis reverts commit {reverted}.\n");
+        let label = format!("parent of {} ({subject})", &reverted[..7]);
+        // the changes from the commit back to its parent
+        let parent = parent.map(String::as_str);
+        match self.apply_changes(&head, Some(reverted.as_str()), parent, &label)? {
+            Applied::Unchanged => Ok(MergeOutcome::UpToDate),
+            Applied::Tree(tree) => {
+                let commit =
+                    self.commit_tree(&tree, &[head.clone()], author, committer, &message)?;
+                self.update_ref_with_message(
+                    "HEAD",
+                    &commit,
+                    Some(&head),
+                    &format!("revert: {}", message.lines().next().unwrap_or_default()),
+                )?;
+                Ok(MergeOutcome::Merged { commit })
+            }
+            Applied::Conflicts(merge) => {
+                self.stop_at_conflicts(merge, "REVERT_HEAD", &reverted, &message)
+            }
+        }
+    }
+
+    /// merges the changes from the tree of the commit `base` to the tree of
+    /// the commit `theirs` into `HEAD` and checks the result out. A missing
+    /// commit stands for the empty tree, e.g. the parent of a root commit
+    fn apply_changes(
+        &self,
+        head: &str,
+        base: Option<&str>,
+        theirs: Option<&str>,
+        their_label: &str,
+    ) -> Result<Applied> {
+        let entries = |commit: Option<&str>| match commit {
+            Some(commit) => self.read_tree_recursive(commit),
+            None => Ok(Vec::new()),
+        };
+        let base_entries = entries(base)?;
+        let our_entries = self.read_tree_recursive(head)?;
+        let their_entries = entries(theirs)?;
+        let merge = self.merge_entries(
+            &base_entries,
+            &our_entries,
+            &their_entries,
+            "HEAD",
+            their_label,
+        )?;
+        self.checkout_merge(&our_entries, &merge)?;
+        if !merge.conflicts.is_empty() {
+            return Ok(Applied::Conflicts(merge));
+        }
+
+        let tree = self.write_tree_from_index()?;
+        let Object::Commit {
+            tree: head_tree, ..
+        } = self.load_object(head)?
+        else {
+            unreachable!("HEAD resolved to a commit");
+        };
+        if tree == head_tree {
+            return Ok(Applied::Unchanged);
+        }
+        Ok(Applied::Tree(tree))
+    }
+
+    /// leaves the conflicts of a cherry-pick or revert of `commit` for the
+    /// user to resolve, with `head_file` pointing to `commit`
+    fn stop_at_conflicts(
+        &self,
+        merge: TreeMerge,
+        head_file: &str,
+        commit: &str,
+        message: &str,
+    ) -> Result<MergeOutcome> {
+        self.write_conflicts(&merge)?;
+        fs::write(self.path(head_file), format!("{commit}\n"))?;
+        fs::write(self.path("MERGE_MSG"), message)?;
+        Ok(MergeOutcome::Conflicts {
+            paths: merge.conflicts.into_iter().map(|(path, _)| path).collect(),
+        })
+    }
+
+    /// fails if a merge, cherry-pick or revert with conflicts is in progress
+    fn check_no_merge_in_progress(&self) -> Result<()> {
+        if self.path("MERGE_HEAD").is_file() {
+            return Err(GitSyncError::MergeInProgress);
+        }
+        if self.path("CHERRY_PICK_HEAD").is_file() {
+            return Err(GitSyncError::CherryPickInProgress);
+        }
+        if self.path("REVERT_HEAD").is_file() {
+            return Err(GitSyncError::RevertInProgress);
+        }
+        Ok(())
+    }
+
+    /// merges the changes from `base_entries` to `our_entries` and to
+    /// `their_entries` file by file, and line by line for files changed on
+    /// both sides. Merged blobs are stored, nothing else is changed.
+    fn merge_entries(
+        &self,
+        base_entries: &[TreeEntry],
+        our_entries: &[TreeEntry],
+        their_entries: &[TreeEntry],
+        our_label: &str,
+        their_label: &str,
+    ) -> Result<TreeMerge> {
+        let by_path = |entries: &[TreeEntry]| -> BTreeMap<Vec<u8>, TreeEntry> {
+            entries
+                .iter()
+                .map(|entry| (entry.name.clone(), entry.clone()))
+                .collect()
+        };
+        let (base_files, our_files, their_files) = (
+            by_path(base_entries),
+            by_path(our_entries),
+            by_path(their_entries),
+        );
+        let mut paths: Vec<&Vec<u8>> = base_files
+            .keys()
+            .chain(our_files.keys())
+            .chain(their_files.keys())
+            .collect();
+        paths.sort();
+        paths.dedup();
+
+        let mut merged = Vec::new();
+        // the conflicted paths with the content written to the worktree
+        let mut conflicts: Vec<(Vec<u8>, Option<Vec<u8>>)> = Vec::new();
+        for path in paths {
+            let (b, o, t) = (
+                base_files.get(path),
+                our_files.get(path),
+                their_files.get(path),
+            );
+            if o == t || b == t {
+                merged.extend(o.cloned());
+                continue;
+            }
+            if b == o {
+                merged.extend(t.cloned());
+                continue;
+            }
+
+            // changed on both sides. Only files that still exist on both
+            // sides and are not binary are merged line by line, otherwise
+            // our version is kept
+            let (Some(o), Some(t)) = (o, t) else {
+                merged.extend(o.or(t).cloned());
+                conflicts.push((path.clone(), None));
+                continue;
+            };
+            let base_data = match b {
+                Some(b) if is_regular_file(b) => self.read_blob(&b.sha1())?,
+                _ => Vec::new(),
+            };
+            let (our_data, their_data) = (self.read_blob(&o.sha1())?, self.read_blob(&t.sha1())?);
+            let binary = [&base_data, &our_data, &their_data]
+                .iter()
+                .any(|data| data.contains(&0));
+            if !is_regular_file(o) || !is_regular_file(t) || binary {
+                merged.push(o.clone());
+                conflicts.push((path.clone(), None));
+                continue;
+            }
+
+            let (data, conflict) =
+                merge::merge_text(&base_data, &our_data, &their_data, our_label, their_label);
+            if conflict {
+                merged.push(o.clone());
+                conflicts.push((path.clone(), Some(data)));
+                continue;
+            }
+            let sha1 = Object::Blob { data }.save(self)?;
+            // keep a mode change of either side
+            let mode = match b {
+                Some(b) if b.mode == o.mode => t.mode.clone(),
+                _ => o.mode.clone(),
+            };
+            merged.push(TreeEntry {
+                mode,
+                name: path.clone(),
+                sha: from_hex(&sha1)?,
+            });
+        }
+
+        Ok(TreeMerge {
+            base_files,
+            our_files,
+            their_files,
+            merged,
+            conflicts,
+        })
+    }
+
+    /// checks out the merged entries over `our_entries`. Fails without
+    /// changes if local changes would be overwritten
+    fn checkout_merge(&self, our_entries: &[TreeEntry], merge: &TreeMerge) -> Result<()> {
+        if !merge.conflicts.is_empty() {
+            // conflicted files are overwritten without checking them below
+            let status = self.status()?;
+            let dirty = status
+                .staged
+                .iter()
+                .chain(&status.unstaged)
+                .find(|(path, _)| merge.conflicts.iter().any(|(conflict, _)| conflict == path));
+            if let Some((path, _)) = dirty {
+                return Err(GitSyncError::WouldOverwrite {
+                    path: String::from_utf8_lossy(path).into_owned(),
+                });
+            }
+        }
+        self.checkout_entries(our_entries, &merge.merged, false)
+    }
+
+    /// replaces the conflicted paths in the index with their versions at
+    /// stages 1 to 3 and writes text conflicts to the worktree
+    fn write_conflicts(&self, merge: &TreeMerge) -> Result<()> {
+        let root = self.worktree_root()?;
+        let mut index = self.read_index()?;
+        for (path, data) in &merge.conflicts {
+            index.remove(path);
+            let sides = [
+                (1, &merge.base_files),
+                (2, &merge.our_files),
+                (3, &merge.their_files),
+            ];
+            for (stage, files) in sides {
+                if let Some(entry) = files.get(path) {
+                    let mut entry =
+                        IndexEntry::new(path.clone(), entry.sha.clone(), entry_mode(entry)?);
+                    entry.set_stage(stage);
+                    index.entries.push(entry);
+                }
+            }
+            if let Some(data) = data {
+                fs::write(root.join(bytes_path(path)), data)?;
+            }
+        }
+        index
+            .entries
+            .sort_by(|a, b| (&a.path, a.stage()).cmp(&(&b.path, b.stage())));
+        self.write_index(&index)
+    }
+
+    /// points the symbolic ref `name` to the ref `target`, e.g. `HEAD` to
+    /// `refs/heads/main`, like `git symbolic-ref`. The target does not need
+    /// to exist, but `HEAD` can only point to refs below `refs/`
+    pub fn write_symbolic_ref(&self, name: &str, target: &str) -> Result<()> {
+        check_ref_name(target)?;
+        if name == "HEAD" && !target.starts_with("refs/") {
+            return Err(GitSyncError::InvalidRef {
+                name: target.to_owned(),
+                reason: "HEAD can only point to refs below refs/".to_owned(),
+            });
+        }
+        self.write_raw_ref(name, &format!("ref: {target}"))
+    }
+
+    /// replaces the content of the ref `name` with `content`, either a sha1
+    /// or a symbolic ref like `ref: refs/heads/main`. Symbolic refs are not
+    /// followed
+    fn write_raw_ref(&self, name: &str, content: &str) -> Result<()> {
+        check_ref_name(name)?;
+        let lock_name = format!("{name}.lock");
+        let mut lock = match self.file(
+            &lock_name,
+            OpenOptions::new().write(true).create_new(true),
+            true,
+        ) {
+            Err(GitSyncError::Io(err)) if err.kind() == ErrorKind::AlreadyExists => {
+                return Err(GitSyncError::RefLocked {
+                    name: name.to_owned(),
+                });
+            }
+            lock => lock?,
+        };
+
+        let result = (|| -> Result<()> {
+            writeln!(lock, "{content}")?;
+            lock.sync_all()?;
+            fs::rename(self.path(&lock_name), self.path(name))?;
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(self.path(&lock_name));
+        }
+        result
+    }
+
+    /// compares HEAD, the index and the worktree, like `git status`
+    pub fn status(&self) -> Result<StatusReport> {
+        StatusReport::new(self)
+    }
+
+    /// clones the repository at `url` into a new repository at `path`.
+    ///
+    /// The branches of the remote are stored as `refs/remotes/origin/*`, its
+    /// tags are copied and the branch the remote `HEAD` points to is created
+    /// and checked out. The remote is saved as `origin` in the config.
+    pub fn clone_remote(url: &str, path: impl AsRef<Path>) -> Result<Self> {
+        transport::clone_remote(url, path.as_ref())
+    }
+
+    /// downloads the branches of the configured `remote` that changed and
+    /// points `refs/remotes/<remote>/*` to them, like `git fetch`. Local
+    /// branches and the worktree are not touched.
+    ///
+    /// The local refs are sent as haves, so that only new objects are
+    /// transferred. Returns the remote-tracking refs that changed.
+    pub fn fetch(&self, remote: &str) -> Result<Vec<FetchedRef>> {
+        transport::fetch_remote(self, remote)
+    }
+
+    /// pushes the local branch `branch` to the branch of the same name on
+    /// `remote`, which is either the name of a configured remote like
+    /// `origin` or a url. Returns the sha1 the remote branch pointed to
+    /// before, `None` if it was created.
+    ///
+    /// Only objects the remote is missing are sent. Unless `force` is set,
+    /// the push fails with [GitSyncError::NonFastForward] if the remote
+    /// branch is not an ancestor of the local one. For configured remotes,
+    /// the remote-tracking branch is updated as well.
+    pub fn push(&self, remote: &str, branch: &str, force: bool) -> Result<Option<String>> {
+        transport::push_branch(self, remote, branch, force)
+    }
+
+    /// collapses the autocommits on the current branch that are older than
+    /// `older_than` into one snapshot per hour.
+    ///
+    /// Each snapshot keeps the tree, author and committer of the newest
+    /// commit it replaces. Newer commits are recreated on top of the
+    /// snapshots and history before the newest merge is not touched.
+    /// Squashing again does not change already squashed commits. Returns the
+    /// new sha1 of `HEAD`.
+    pub fn squash_autocommits(&self, older_than: Duration) -> Result<String> {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as i64);
+        daemon::squash_autocommits(self, now - older_than.as_secs() as i64)
+    }
+
+    /// walks the history starting at the commit `start_sha`, see [CommitWalk]
+    pub fn walk_commits(&self, start_sha: &str) -> CommitWalk<'_> {
+        CommitWalk::new(self, start_sha)
+    }
+
+    /// the commits reachable from any of the commits `includes`, but not
+    /// from any of the commits `excludes`, newest first by committer time,
+    /// like `git rev-list <includes> ^<excludes>`
+    pub fn rev_list(&self, includes: &[String], excludes: &[String]) -> Result<Vec<String>> {
+        let mut excluded = HashSet::new();
+        if !excludes.is_empty() {
+            for commit in CommitWalk::from_starts(self, excludes.to_vec()) {
+                excluded.insert(commit?.0);
+            }
+        }
+
+        let mut commits = Vec::new();
+        for commit in CommitWalk::from_starts(self, includes.to_vec()) {
+            let (sha1, _) = commit?;
+            if !excluded.contains(&sha1) {
+                commits.push(sha1);
+            }
+        }
+        Ok(commits)
+    }
+
+    /// the best common ancestor of the commits `a` and `b`, like
+    /// `git merge-base`, or `None` if their histories are unrelated.
+    ///
+    /// If there are multiple best common ancestors, e.g. after criss-cross
+    /// merges, the newest one is returned, see [Repository::merge_bases].
+    pub fn merge_base(&self, a: &str, b: &str) -> Result<Option<String>> {
+        Ok(self.merge_bases(a, b)?.into_iter().next())
+    }
+
+    /// all best common ancestors of the commits `a` and `b`, newest first by
+    /// committer time, like `git merge-base --all`.
+    ///
+    /// A common ancestor is best if it is not an ancestor of another common
+    /// ancestor.
+    pub fn merge_bases(&self, a: &str, b: &str) -> Result<Vec<String>> {
+        let mut ancestors = HashSet::new();
+        for commit in self.walk_commits(a) {
+            ancestors.insert(commit?.0);
+        }
+
+        let mut common = Vec::new();
+        // a common ancestor of another common ancestor is the parent of a
+        // common commit, because all commits on the path between them are
+        // common as well
+        let mut redundant = HashSet::new();
+        for commit in self.walk_commits(b) {
+            let (sha1, commit) = commit?;
+            if !ancestors.contains(&sha1) {
+                continue;
+            }
+            if let Object::Commit { parents, .. } = commit {
+                redundant.extend(parents);
+            }
+            common.push(sha1);
+        }
+        common.retain(|sha1| !redundant.contains(sha1));
+        Ok(common)
+    }
+
+    /// attributes every line of the file at `path`, relative to the worktree
+    /// root, in the commit `commit` to the commit that last changed it, like
+    /// a simple `git blame`.
+    ///
+    /// The history is followed back through the first parent, or a parent
+    /// with the same file. Renames are not detected.
+    pub fn blame(&self, commit: &str, path: impl AsRef<Path>) -> Result<Vec<BlameLine>> {
+        blame::blame(self, commit, path.as_ref())
+    }
+
+    /// names the commit `commit` after the nearest tag it is reachable from,
+    /// like `git describe`.
+    ///
+    /// The name is `<tag>-<n>-g<abbreviated sha1>`, where `n` is the number
+    /// of commits since the tag, or just `<tag>` if `commit` is tagged. Like
+    /// git, the tag with the fewest commits since it out of the first 10
+    /// tagged commits found in the history wins. Annotated tags are preferred
+    /// over lightweight ones on the same commit.
+    pub fn describe(&self, commit: &str, options: &DescribeOptions) -> Result<String> {
+        const MAX_CANDIDATES: usize = 10;
+
+        // the tag of each tagged commit, annotated tags first
+        let mut tagged: BTreeMap<String, (bool, String)> = BTreeMap::new();
+        for (name, sha1) in self.list_tags()? {
+            let annotated = self.object_type(&sha1)? == ObjectType::Tag;
+            if !annotated && !options.tags {
+                continue;
+            }
+            // tags of trees and blobs can't name a commit
+            let Ok((target, _)) = self.peel_to_commit(&sha1) else {
+                continue;
+            };
+            match tagged.get(&target) {
+                Some((other_annotated, _)) if *other_annotated || !annotated => {}
+                _ => {
+                    tagged.insert(target, (annotated, name));
+                }
+            }
+        }
+
+        let mut candidates = Vec::new();
+        for walked in self.walk_commits(commit) {
+            let (sha1, _) = walked?;
+            if let Some((_, name)) = tagged.get(&sha1) {
+                if sha1 == commit {
+                    return Ok(name.clone());
+                }
+                candidates.push((sha1, name));
+                if candidates.len() == MAX_CANDIDATES {
+                    break;
+                }
+            }
+        }
+
+        let mut best: Option<(usize, &String)> = None;
+        for (tagged_commit, name) in candidates {
+            let depth = self.rev_list(&[commit.to_owned()], &[tagged_commit])?.len();
+            if best.is_none_or(|(best_depth, _)| depth < best_depth) {
+                best = Some((depth, name));
+            }
+        }
+
+        let abbrev = self.abbreviate(commit)?;
+        match best {
+            Some((depth, name)) => Ok(format!("{name}-{depth}-g{abbrev}")),
+            None if options.always => Ok(abbrev),
+            None => Err(GitSyncError::NoTagFound {
+                commit: commit.to_owned(),
+            }),
+        }
+    }
+
+    /// the shortest prefix of `sha1` with at least 7 characters that no
+    /// other object starts with
+    fn abbreviate(&self, sha1: &str) -> Result<String> {
+        const MIN_LEN: usize = 7;
+        for len in MIN_LEN..sha1.len() {
+            match self.resolve_sha1_prefix(&sha1[..len]) {
+                Err(GitSyncError::AmbiguousObject { .. }) => continue,
+                result => return result.map(|_| sha1[..len].to_owned()),
+            }
+        }
+        Ok(sha1.to_owned())
+    }
+
+    /// walks the files in the worktree that are not ignored, see
+    /// [WorktreeWalk]
+    pub fn walk_worktree(&self) -> WorktreeWalk {
+        WorktreeWalk::new(self)
+    }
+
+    /// opens the compressed file of the loose object `sha1`
+    fn open_object_file(&self, sha1: &str) -> Result<File> {
+        for objects_dir in self.object_dirs() {
+            match File::open(objects_dir.join(&sha1[..2]).join(&sha1[2..])) {
+                Ok(file) => return Ok(file),
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Err(GitSyncError::ObjectNotFound {
+            sha1: sha1.to_owned(),
+        })
+    }
+
+    /// the path of the loose object `sha1` in the first object directory
+    /// that contains it
+    fn find_loose_object(&self, sha1: &str) -> Option<PathBuf> {
+        self.object_dirs()
+            .map(|objects_dir| objects_dir.join(&sha1[..2]).join(&sha1[2..]))
+            .find(|path| path.is_file())
+    }
+
+    /// calculates path for the given object. Path is relative to .git dir
+    ///
+    /// `hash` can be either a sha1 or a sha256.
+    /// use [file] to open the object file
+    pub fn hash_to_object(hash: &str) -> PathBuf {
+        assert!(hash.len() == 40 || hash.len() == 64);
+        let mut path = PathBuf::from("objects");
+        path.push(&hash[0..2]);
+        path.push(&hash[2..]);
+        path
+    }
+}
+
+/// reads the git dir from a `.git` file containing `gitdir: <path>`, as used
+/// by linked worktrees and submodules.
+///
+/// Relative paths are relative to the directory containing the file.
+fn read_git_file(path: &Path) -> Result<PathBuf> {
+    let content = fs::read_to_string(path)?;
+    let Some(git_dir) = content.trim_end().strip_prefix("gitdir: ") else {
+        return Err(GitSyncError::NotARepository {
+            path: path.to_path_buf(),
+        });
+    };
+
+    let base = path.parent().unwrap_or(Path::new("."));
+    let git_dir = fs::canonicalize(base.join(git_dir))?;
+    if !is_git_dir(&git_dir) {
+        return Err(GitSyncError::NotARepository { path: git_dir });
+    }
+    Ok(git_dir)
+}
+
+/// the longest chain of alternates that is followed, like git
+const MAX_ALTERNATE_DEPTH: usize = 5;
+
+/// the alternate object directories of `objects_dir`.
+///
+/// These are the directories in `from_env`, the value of
+/// `GIT_ALTERNATE_OBJECT_DIRECTORIES`, followed by the ones listed in
+/// `objects/info/alternates`, one per line, relative to `objects_dir`. The
+/// alternates of alternates are included too. Missing directories and
+/// duplicates are skipped.
+fn read_alternates(objects_dir: &Path, from_env: Option<OsString>) -> Vec<PathBuf> {
+    let main = fs::canonicalize(objects_dir).unwrap_or_else(|_| objects_dir.to_path_buf());
+    let mut alternates = Vec::new();
+    let mut pending: Vec<(PathBuf, usize)> = Vec::new();
+    if let Some(from_env) = from_env {
+        pending.extend(
+            env::split_paths(&from_env)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .map(|dir| (dir, 1)),
+        );
+    }
+    pending.extend(alternates_file(objects_dir).into_iter().map(|dir| (dir, 1)));
+    // depth first, so the alternates of an alternate come right after it
+    pending.reverse();
+
+    while let Some((dir, depth)) = pending.pop() {
+        let Ok(dir) = fs::canonicalize(&dir) else {
+            continue;
+        };
+        if dir == main || alternates.contains(&dir) {
+            continue;
+        }
+        if depth < MAX_ALTERNATE_DEPTH {
+            let nested = alternates_file(&dir);
+            pending.extend(nested.into_iter().rev().map(|nested| (nested, depth + 1)));
+        }
+        alternates.push(dir);
+    }
+    alternates
+}
+
+/// the directories listed in `info/alternates` of `objects_dir`, ignoring
+/// comments and empty lines
+fn alternates_file(objects_dir: &Path) -> Vec<PathBuf> {
+    let Ok(content) = fs::read_to_string(objects_dir.join("info/alternates")) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| objects_dir.join(line))
+        .collect()
+}
+
+/// lists the loose objects in the fan-out directory `<objects_dir>/<dir_name>`
+fn loose_objects_in(
+    objects_dir: &Path,
+    dir_name: &str,
+    hash: HashAlgorithm,
+) -> Result<Vec<String>> {
+    let dir = objects_dir.join(dir_name);
+
+    let mut sha1s = Vec::new();
+    if dir.is_dir() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                continue;
+            };
+            if file_name.len() == hash.hex_len() - 2 && is_lower_hex(file_name) {
+                sha1s.push(format!("{dir_name}{file_name}"));
+            }
+        }
+    }
+    Ok(sha1s)
+}
+
+/// removes `.` and `..` from `path` and resolves the symlinks of every
+/// existing component but the last
+fn resolve_parents(path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+    let mut components = path.components().peekable();
+    while let Some(component) = components.next() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => {
+                resolved.push(component);
+                if components.peek().is_some() {
+                    if let Ok(canonical) = fs::canonicalize(&resolved) {
+                        resolved = canonical;
+                    }
+                }
+            }
+        }
+    }
+    resolved
+}
+
+/// the repository of `kind` at `dir`, without searching its parents
+fn discover_at(dir: &Path, kind: RepoKind) -> Result<Option<Discovered>> {
+    let dot_git = dir.join(kind.dir_name());
+    let (git_dir, work_tree) = if dot_git.is_dir() {
+        (dot_git, Some(dir))
+    } else if dot_git.is_file() {
+        (read_git_file(&dot_git)?, Some(dir))
+    } else if is_git_dir(dir) {
+        (dir.to_path_buf(), None)
+    } else {
+        return Ok(None);
+    };
+
+    let config = Config::parse(git_dir.join("config"))?;
+    let is_bare = work_tree.is_none() || config.get_bool("core", None, "bare")?.unwrap_or(false);
+    Ok(Some(Discovered {
+        git_dir,
+        work_tree: work_tree.filter(|_| !is_bare).map(Path::to_path_buf),
+        is_bare,
+    }))
+}
+
+/// checks for the files git requires in a git dir
+fn is_git_dir(path: &Path) -> bool {
+    path.join("HEAD").is_file() && path.join("objects").is_dir() && path.join("refs").is_dir()
+}
+
+/// writes the blob or symlink of the tree `entry` to `path`, replacing
+/// what was there. Submodules are checked out as empty directories
+fn checkout_entry(repo: &Repository, path: &Path, entry: &TreeEntry) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+
+    match entry.mode.as_str() {
+        "160000" => fs::create_dir(path)?,
+        "120000" => {
+            let target = repo.read_blob(&entry.sha1())?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(bytes_path(&target), path)?;
+            // without symlinks the target is written to a plain file, like
+            // git does with `core.symlinks = false`
+            #[cfg(not(unix))]
+            fs::write(path, target)?;
+        }
+        mode => {
+            fs::write(path, repo.read_blob(&entry.sha1())?)?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let permissions = if mode == "100755" { 0o755 } else { 0o644 };
+                fs::set_permissions(path, fs::Permissions::from_mode(permissions))?;
+            }
+            #[cfg(not(unix))]
+            let _ = mode;
+        }
+    }
+    Ok(())
+}
+
+/// whether the file at `path` already has the content and mode of the tree
+/// `entry`
+fn is_checked_out(repo: &Repository, path: &Path, entry: &TreeEntry) -> Result<bool> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+    if metadata.is_dir() {
+        // only a checked out submodule is a directory
+        return Ok(entry.mode == "160000");
+    }
+    if file_mode(&metadata) != entry_mode(entry)? {
+        return Ok(false);
+    }
+    let Object::Blob { data } = file_blob(path, &metadata)? else {
+        unreachable!("file_blob always returns a blob");
+    };
+    Ok(data == repo.read_blob(&entry.sha1())?)
+}
+
+/// the mode of a tree entry as a number
+fn entry_mode(entry: &TreeEntry) -> Result<u32> {
+    u32::from_str_radix(&entry.mode, 8).map_err(|_| GitSyncError::InvalidObject {
+        typ: ObjectType::Tree,
+        reason: format!("invalid mode {:?}", entry.mode),
+    })
+}
+
+/// the result of [Repository::apply_changes]
+enum Applied {
+    /// `HEAD` already contains the changes
+    Unchanged,
+    /// the sha1 of the tree with the changes applied
+    Tree(String),
+    Conflicts(TreeMerge),
+}
+
+/// the result of [Repository::merge_entries]
+struct TreeMerge {
+    base_files: BTreeMap<Vec<u8>, TreeEntry>,
+    our_files: BTreeMap<Vec<u8>, TreeEntry>,
+    their_files: BTreeMap<Vec<u8>, TreeEntry>,
+    /// the merged entries, with our version of conflicted paths
+    merged: Vec<TreeEntry>,
+    /// the conflicted paths with the content written to the worktree
+    conflicts: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+/// whether a tree entry is a regular file, which can be merged line by line
+fn is_regular_file(entry: &TreeEntry) -> bool {
+    entry.mode == "100644" || entry.mode == "100755"
+}
+
+/// removes the file `name` from the worktree at `root`, along with the
+/// directories that become empty
+fn remove_worktree_file(root: &Path, name: &[u8]) -> Result<()> {
+    let path = root.join(bytes_path(name));
+    match fs::symlink_metadata(&path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&path)?,
+        Ok(_) => fs::remove_file(&path)?,
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    let mut dir = path.parent();
+    while let Some(parent) = dir.filter(|&parent| parent != root) {
+        if fs::remove_dir(parent).is_err() {
+            // not empty
+            break;
+        }
+        dir = parent.parent();
+    }
+    Ok(())
+}
+
+/// the blob of the file or symlink at `path`
+pub(crate) fn file_blob(path: &Path, metadata: &fs::Metadata) -> Result<Object> {
+    let data = if metadata.is_symlink() {
+        os_str_bytes(fs::read_link(path)?.as_os_str())
+    } else {
+        fs::read(path)?
+    };
+    Ok(Object::Blob { data })
+}
+
+/// the raw bytes of a file name. Outside of unix, names are stored as utf-8
+pub(crate) fn os_str_bytes(name: &std::ffi::OsStr) -> Vec<u8> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        name.as_bytes().to_vec()
+    }
+    #[cfg(not(unix))]
+    {
+        name.to_string_lossy().into_owned().into_bytes()
+    }
+}
+
+/// the inverse of [os_str_bytes]
+pub(crate) fn bytes_path(name: &[u8]) -> PathBuf {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(name))
+    }
+    #[cfg(not(unix))]
+    {
+        PathBuf::from(String::from_utf8_lossy(name).into_owned())
+    }
+}
+
+/// the `/` separated name of a relative path, as stored in trees and the
+/// index. `.` components are skipped
+pub(crate) fn path_bytes(path: &Path) -> Vec<u8> {
+    let parts: Vec<Vec<u8>> = path
+        .components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .map(|component| os_str_bytes(component.as_os_str()))
+        .collect();
+    parts.join(&b'/')
+}
+
+/// the mode git stores for a file: a symlink, an executable or a regular file
+pub(crate) fn file_mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.is_symlink() {
+        return 0o120000;
+    }
+
+    #[cfg(unix)]
+    let executable = {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o111 != 0
+    };
+    #[cfg(not(unix))]
+    let executable = false;
+
+    if executable {
+        0o100755
+    } else {
+        0o100644
+    }
+}
+
+/// rejects ref names that could escape the refs directory
+fn check_ref_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..")
+    {
+        return Err(GitSyncError::InvalidRef {
+            name: name.to_owned(),
+            reason: "not a valid ref name".into(),
+        });
+    }
+    Ok(())
+}
+
+fn is_lower_hex(s: &str) -> bool {
+    s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashSet,
+        fs,
+        io::{Read, Write},
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime},
+    };
+
+    use test_dir::DirBuilder;
+
+    use super::{
+        DescribeOptions, DiscoverOptions, Discovered, FsckError, InitOptions, RepoKind, ResetMode,
+    };
+    use crate::test_utils::{existing_test_repo, set_env, test_dir};
+    use crate::{
+        from_hex, quote_path, Change, Config, GitSyncError, HashAlgorithm, Index, MergeOutcome,
+        Object, ObjectType, Repository, Signature, SignatureStatus, TreeChange, TreeEntry,
+    };
+
+    #[test]
+    fn open_repository() {
+        let test_dir = existing_test_repo("valid_empty");
+        let repo = Repository::new(test_dir.root());
+        println!("{repo:?}")
+    }
+
+    #[test]
+    fn open_missing_repository() {
+        let test_dir = test_dir("open_missing_repo");
+        let err = Repository::new(test_dir.root()).unwrap_err();
+        assert!(matches!(err, GitSyncError::NotARepository { .. }));
+    }
+
+    #[test]
+    fn open_unsupported_format_version() {
+        let test_dir = existing_test_repo("unsupported_format");
+        let err = Repository::new(test_dir.root()).unwrap_err();
+        assert!(matches!(
+            err,
+            GitSyncError::UnsupportedRepositoryFormat { version: 99 }
+        ));
+    }
+
+    #[test]
+    fn configured_compression() {
+        let blob = Object::Blob {
+            data: "compress me ".repeat(100).into_bytes(),
+        };
+        let mut sizes = Vec::new();
+        for level in ["0", "9"] {
+            let repo_path = test_dir(&format!("configured_compression_{level}"));
+            let repo = Repository::create_at(repo_path.root()).unwrap();
+            let mut config = repo.config().clone();
+            config.set("core", None, "compression", level);
+            config.write_to_file(repo.path("config")).unwrap();
+
+            let repo = Repository::new(repo_path.root()).unwrap();
+            assert_eq!(
+                repo.compression_level().level(),
+                level.parse::<u32>().unwrap()
+            );
+            let sha1 = blob.save(&repo).unwrap();
+            let path = repo.path(Repository::hash_to_object(&sha1));
+            sizes.push(fs::metadata(path).unwrap().len());
+            assert_eq!(
+                repo.load_object(&sha1).unwrap().hash(repo.hash_algorithm()),
+                sha1
+            );
+        }
+        assert!(sizes[0] > sizes[1], "{sizes:?}");
+    }
+
+    #[test]
+    fn open_format_version_1() {
+        let repo_path = test_dir("open_format_version_1");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+
+        let mut config = repo.config().clone();
+        config.set("core", None, "repositoryformatversion", "1");
+        config.set("extensions", None, "noop", "true");
+        config.set("extensions", None, "objectFormat", "sha1");
+        config.write_to_file(repo.path("config")).unwrap();
+        Repository::new(repo_path.root()).unwrap();
+
+        config.set("extensions", None, "someFutureThing", "true");
+        config.write_to_file(repo.path("config")).unwrap();
+        let err = Repository::new(repo_path.root()).unwrap_err();
+        assert!(matches!(err, GitSyncError::UnsupportedExtension { .. }));
+
+        // extensions are ignored by version 0
+        config.set("core", None, "repositoryformatversion", "0");
+        config.write_to_file(repo.path("config")).unwrap();
+        Repository::new(repo_path.root()).unwrap();
+    }
+
+    #[test]
+    fn sha256_repository() {
+        let repo_path = test_dir("sha256_repository");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+
+        let mut config = repo.config().clone();
+        config.set("core", None, "repositoryformatversion", "1");
+        config.set("extensions", None, "objectFormat", "sha256");
+        config.write_to_file(repo.path("config")).unwrap();
+        let repo = Repository::new(repo_path.root()).unwrap();
+        assert_eq!(repo.hash_algorithm(), HashAlgorithm::Sha256);
+
+        let data = b"this is a simple test blob\n".to_vec();
+        let hash = Object::Blob { data }.save(&repo).unwrap();
+        assert_eq!(
+            hash,
+            "9a6d0189d45d52076b31cf0f0188d2672c1c3ac3cfa55bc378ee0bdd22492d8a"
+        );
+        assert!(repo
+            .path("objects/9a/6d0189d45d52076b31cf0f0188d2672c1c3ac3cfa55bc378ee0bdd22492d8a")
+            .is_file());
+
+        assert_eq!(
+            repo.loose_objects().unwrap().collect::<Vec<_>>(),
+            vec![hash.clone()]
+        );
+        assert_eq!(repo.find_object_sha1("9a6d01", None, true).unwrap(), hash);
+        assert_eq!(repo.object_type(&hash).unwrap(), ObjectType::Blob);
+        assert!(repo.fsck().unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_object_is_typed_error() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let err = repo
+            .object_type("0000000000000000000000000000000000000000")
+            .unwrap_err();
+        assert!(matches!(err, GitSyncError::ObjectNotFound { .. }));
+    }
+
+    #[test]
+    fn stream_large_blob() {
+        let repo_path = test_dir("stream_large_blob");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+
+        let data: Vec<u8> = (0..4 * 1024 * 1024u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect();
+        let sha1 = Object::Blob { data: data.clone() }.save(&repo).unwrap();
+
+        let mut streamed = Vec::new();
+        repo.open_object_reader(&sha1)
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .unwrap();
+        assert!(streamed == data);
+    }
+
+    #[test]
+    fn list_loose_objects() {
+        let repo_path = test_dir("list_loose_objects");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+
+        let mut expected: Vec<String> = ["first", "second", "third"]
+            .into_iter()
+            .map(|content| {
+                let data = content.as_bytes().to_vec();
+                Object::Blob { data }.save(&repo).unwrap()
+            })
+            .collect();
+        expected.sort();
+
+        // neither of these are loose objects
+        fs::create_dir_all(repo.path("objects/pack")).unwrap();
+        fs::create_dir_all(repo.path("objects/info")).unwrap();
+        fs::write(
+            repo.path(format!("objects/{}/short", &expected[0][..2])),
+            "",
+        )
+        .unwrap();
+
+        let sha1s: Vec<String> = repo.loose_objects().unwrap().collect();
+        assert_eq!(sha1s, expected);
+    }
+
+    #[test]
+    fn filter_loose_objects_by_type() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        // not an object, so it is reported instead
+        fs::create_dir_all(repo.path("objects/ff")).unwrap();
+        fs::write(
+            repo.path(format!("objects/ff/{}", "f".repeat(38))),
+            "garbage",
+        )
+        .unwrap();
+
+        let (commits, corrupt): (Vec<_>, Vec<_>) = repo
+            .loose_objects_of_type(ObjectType::Commit)
+            .unwrap()
+            .partition(|sha1| sha1.is_ok());
+        let commits: Vec<String> = commits.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            commits,
+            [
+                "09ed861f6cd57e64a23811b30686bb4919a41674",
+                "1e3e965f92c57b26cb86b4313edca25125678074",
+                "25a7b65d7c4c638b508f306909cb719db11cb7c2",
+            ]
+        );
+
+        assert!(matches!(
+            &corrupt[..],
+            [Err(FsckError::Corrupt { sha1, .. })] if *sha1 == "f".repeat(40)
+        ));
+
+        let blobs: Vec<String> = repo
+            .loose_objects_of_type(ObjectType::Blob)
+            .unwrap()
+            .filter_map(|sha1| sha1.ok())
+            .collect();
+        assert_eq!(
+            blobs,
+            [
+                "06fcdd77c9348567c50638b30d406500f521c304",
+                "08fe2720d8e3fe3a5f81fbb289bc4c7a522f13da",
+                "61780798228d17af2d34fce4cfbdf35556832472",
+                "79c53955ef856f16f2107446bc721c8879a1bd2e",
+            ]
+        );
+    }
+
+    #[test]
+    fn all_objects_are_reachable() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let loose: HashSet<String> = repo.loose_objects().unwrap().collect();
+        assert_eq!(loose.len(), 12);
+        assert_eq!(repo.reachable_objects().unwrap(), loose);
+
+        let orphan = Object::Blob {
+            data: b"orphan\n".to_vec(),
+        }
+        .save(&repo)
+        .unwrap();
+        assert!(repo.has_object(&orphan));
+        assert!(!repo.reachable_objects().unwrap().contains(&orphan));
+
+        let test_dir = existing_test_repo("packed_objects");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let packed: HashSet<String> = repo.packed_objects().unwrap().into_iter().collect();
+        assert_eq!(packed.len(), 12);
+        assert_eq!(repo.reachable_objects().unwrap(), packed);
+    }
+
+    #[test]
+    fn prune_unreachable_objects() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let two_weeks = Duration::from_secs(14 * 24 * 60 * 60);
+        let blob = |data: &str| {
+            Object::Blob {
+                data: data.as_bytes().to_vec(),
+            }
+            .save(&repo)
+            .unwrap()
+        };
+        let age = |sha1: &str, age: Duration| {
+            let file = fs::File::options()
+                .write(true)
+                .open(repo.path(Repository::hash_to_object(sha1)))
+                .unwrap();
+            file.set_modified(SystemTime::now() - age).unwrap();
+        };
+
+        let old = blob("old\n");
+        age(&old, 3 * two_weeks);
+        let recent = blob("recent\n");
+        age(&recent, two_weeks / 2);
+        // reachable objects are kept no matter how old they are
+        let reachable = "06fcdd77c9348567c50638b30d406500f521c304";
+        age(reachable, 3 * two_weeks);
+
+        assert_eq!(repo.prune(two_weeks, true).unwrap(), [old.clone()]);
+        assert!(repo.has_object(&old));
+
+        assert_eq!(repo.prune(two_weeks, false).unwrap(), [old.clone()]);
+        assert!(!repo.has_object(&old));
+        assert!(!repo.path(&Repository::hash_to_object(&old)).exists());
+        assert!(repo.has_object(&recent));
+        assert!(repo.has_object(reachable));
+        assert_eq!(repo.loose_objects().unwrap().count(), 13);
+
+        // everything unreachable
+        assert_eq!(repo.prune(Duration::ZERO, false).unwrap(), [recent]);
+        assert_eq!(repo.reachable_objects().unwrap().len(), 12);
+    }
+
+    #[test]
+    fn repack_loose_objects() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let loose: Vec<String> = repo.loose_objects().unwrap().collect();
+        let read = |repo: &Repository, sha1: &str| {
+            let mut content = Vec::new();
+            repo.open_object_reader(sha1)
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            content
+        };
+        let contents: Vec<Vec<u8>> = loose.iter().map(|sha1| read(&repo, sha1)).collect();
+
+        let checksum = repo.repack(false).unwrap().unwrap();
+        assert!(repo
+            .path(format!("objects/pack/pack-{checksum}.idx"))
+            .is_file());
+        assert_eq!(repo.packed_objects().unwrap(), loose);
+        // the loose objects are kept without `remove_loose`
+        assert_eq!(repo.loose_objects().unwrap().count(), loose.len());
+
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        repo.repack(true).unwrap().unwrap();
+        assert_eq!(repo.loose_objects().unwrap().count(), 0);
+        assert_eq!(repo.packed_objects().unwrap(), loose);
+        for (sha1, content) in loose.iter().zip(&contents) {
+            assert_eq!(&read(&repo, sha1), content);
+        }
+        assert_eq!(repo.repack(true).unwrap(), None);
+    }
+
+    #[test]
+    fn count_loose_objects() {
+        let repo_path = test_dir("count_loose_objects");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        assert_eq!(repo.count_loose_objects().unwrap(), (0, 0));
+
+        let mut expected_size = 0;
+        for content in ["first", "second", "third"] {
+            let data = content.as_bytes().to_vec();
+            let sha1 = Object::Blob { data }.save(&repo).unwrap();
+            expected_size += fs::metadata(repo.path(Repository::hash_to_object(&sha1)))
+                .unwrap()
+                .len();
+        }
+
+        assert_eq!(repo.count_loose_objects().unwrap(), (3, expected_size));
+    }
+
+    #[test]
+    fn fsck_finds_corrupt_objects() {
+        let repo_path = test_dir("fsck_corrupt_objects");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+
+        let data = b"some content that will get corrupted\n".to_vec();
+        let sha1 = Object::Blob { data }.save(&repo).unwrap();
+        Object::Blob {
+            data: b"untouched\n".to_vec(),
+        }
+        .save(&repo)
+        .unwrap();
+        assert!(repo.fsck().unwrap().is_empty());
+
+        let path = repo.path(Repository::hash_to_object(&sha1));
+        let mut content = fs::read(&path).unwrap();
+        let middle = content.len() / 2;
+        content[middle] ^= 0x20;
+        fs::write(&path, content).unwrap();
+
+        let errors = repo.fsck().unwrap();
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            FsckError::HashMismatch { sha1: found, .. }
+            | FsckError::Corrupt { sha1: found, .. } => {
+                assert_eq!(found, &sha1)
+            }
+        }
+    }
+
+    #[test]
+    fn fsck_finds_hash_mismatch() {
+        let repo_path = test_dir("fsck_hash_mismatch");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+
+        let data = b"misplaced\n".to_vec();
+        let actual = Object::Blob { data }.save(&repo).unwrap();
+        let sha1 = "0123456789abcdef0123456789abcdef01234567";
+        let target = repo.path(Repository::hash_to_object(sha1));
+        fs::create_dir_all(target.parent().unwrap()).unwrap();
+        fs::rename(repo.path(Repository::hash_to_object(&actual)), target).unwrap();
+
+        let errors = repo.fsck().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            FsckError::HashMismatch { sha1: s, actual: a } if s == sha1 && a == &actual
+        ));
+    }
+
+    #[test]
+    fn create_repository() {
+        let repo_path = test_dir("create_repo");
+
+        let repo = Repository::create_at(repo_path.root()).expect("could not create repo");
+
+        drop(repo);
+        Repository::new(repo_path.root()).expect("could not open nearly created repo");
+    }
+
+    #[test]
+    fn created_repository_layout() {
+        let repo_path = test_dir("created_repo_layout");
+        Repository::create_at(repo_path.root()).unwrap();
+
+        let git_dir = repo_path.root().join(".git");
+        for dir in ["objects", "refs/heads", "refs/tags"] {
+            let dir = git_dir.join(dir);
+            assert!(dir.is_dir(), "{dir:?}");
+            assert_eq!(fs::read_dir(dir).unwrap().count(), 0);
+        }
+        assert_eq!(
+            fs::read_to_string(git_dir.join("HEAD")).unwrap(),
+            "ref: refs/heads/main\n"
+        );
+        assert!(git_dir.join("description").is_file());
+
+        let config = Config::parse(git_dir.join("config")).unwrap();
+        assert_eq!(
+            config
+                .get_int("core", None, "repositoryformatversion")
+                .unwrap(),
+            Some(0)
+        );
+        assert_eq!(config.get_bool("core", None, "bare").unwrap(), Some(false));
+
+        let repo = Repository::find_from(repo_path.root(), RepoKind::Git).unwrap();
+        assert_eq!(
+            repo.read_symbolic_ref("HEAD").unwrap().as_deref(),
+            Some("refs/heads/main")
+        );
+        // there are no commits yet
+        assert!(repo.resolve_ref("HEAD").is_err());
+    }
+
+    #[test]
+    fn create_bare_repository() {
+        let repo_path = test_dir("create_bare_repo");
+        let options = InitOptions {
+            bare: true,
+            ..Default::default()
+        };
+        let repo = Repository::create_with(repo_path.root(), &options).unwrap();
+        assert!(repo.is_bare());
+        assert!(matches!(
+            repo.worktree_root(),
+            Err(GitSyncError::NotAWorktree { .. })
+        ));
+
+        let root = repo_path.root();
+        assert!(!root.join(".git").exists());
+        assert!(root.join("HEAD").is_file());
+        assert!(root.join("objects").is_dir());
+        let config = Config::parse(root.join("config")).unwrap();
+        assert_eq!(config.get_bool("core", None, "bare").unwrap(), Some(true));
+
+        let data = b"stored in a bare repo\n".to_vec();
+        let sha1 = Object::Blob { data }.save(&repo).unwrap();
+
+        for repo in [
+            Repository::new(root).unwrap(),
+            Repository::find_from(root.join("objects"), RepoKind::Git).unwrap(),
+        ] {
+            assert!(repo.is_bare());
+            assert!(repo.worktree_root().is_err());
+            assert_eq!(repo.object_type(&sha1).unwrap(), ObjectType::Blob);
+        }
+    }
+
+    #[test]
+    fn create_with_initial_branch() {
+        let repo_path = test_dir("create_with_initial_branch");
+        let options = InitOptions {
+            initial_branch: "trunk".to_owned(),
+            ..Default::default()
+        };
+        let repo = Repository::create_with(repo_path.root(), &options).unwrap();
+        assert_eq!(
+            repo.read_symbolic_ref("HEAD").unwrap().as_deref(),
+            Some("refs/heads/trunk")
+        );
+
+        let invalid = InitOptions {
+            initial_branch: "../escape".to_owned(),
+            ..Default::default()
+        };
+        let invalid_path = test_dir("create_with_invalid_branch");
+        assert!(Repository::create_with(invalid_path.root(), &invalid).is_err());
+    }
+
+    #[test]
+    fn object_type_without_decoding() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        for (sha1, typ) in [
+            ("06fcdd77c9348567c50638b30d406500f521c304", ObjectType::Blob),
+            ("3d8a457a40a1b21da38ebcb0ab009e4e997a5bb9", ObjectType::Tree),
+            (
+                "1e3e965f92c57b26cb86b4313edca25125678074",
+                ObjectType::Commit,
+            ),
+            ("28d0e451b11758598f5e97c62bd8aa8c1061cba5", ObjectType::Tag),
+        ] {
+            assert_eq!(repo.object_type(sha1).unwrap(), typ);
+        }
+    }
+
+    #[test]
+    fn object_size_without_printing() {
+        let test_dir = existing_test_repo("simple_test_blob");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let size = repo
+            .object_size("2bb09523ce4baf1940ee8fef49f6cade5afe3d03")
+            .unwrap();
+        assert_eq!(size, b"this is a simple test blob\n".len());
+    }
+
+    #[test]
+    fn cat_file_batch() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let blob = "61780798228d17af2d34fce4cfbdf35556832472";
+        let input = format!("{blob}\nHEAD\nbogus\n");
+
+        let mut out = Vec::new();
+        repo.cat_file_batch(input.as_bytes(), &mut out).unwrap();
+        let mut expected = format!("{blob} blob 2\n").into_bytes();
+        expected.extend(repo.read_blob(blob).unwrap());
+        expected.extend_from_slice(b"\n");
+        let commit = repo.rev_parse("HEAD").unwrap();
+        let mut content = Vec::new();
+        repo.open_object_reader(&commit)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        expected.extend(format!("{commit} commit {}\n", content.len()).into_bytes());
+        expected.extend(content);
+        expected.extend_from_slice(b"\nbogus missing\n");
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(expected).unwrap()
+        );
+    }
+
+    #[test]
+    fn cat_file_batch_check() {
+        let packed = existing_test_repo("packed_objects");
+        let repo = Repository::new(packed.root()).unwrap();
+        let objects = repo.packed_objects().unwrap();
+        let input = format!("{}\nbogus\n", objects.join("\n"));
+
+        let mut out = Vec::new();
+        repo.cat_file_batch_check(input.as_bytes(), &mut out)
+            .unwrap();
+        let mut expected = String::new();
+        let mut types = HashSet::new();
+        for sha1 in &objects {
+            let typ = repo.object_type(sha1).unwrap();
+            let mut content = Vec::new();
+            repo.open_object_reader(sha1)
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            expected.push_str(&format!("{sha1} {typ} {}\n", content.len()));
+            types.insert(typ);
+        }
+        expected.push_str("bogus missing\n");
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        assert_eq!(types.len(), 4);
+
+        // only the header of a loose object is read, so a truncated body
+        // goes unnoticed
+        let repo_path = test_dir("cat_file_batch_check");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let sha1 = "0123456789abcdef0123456789abcdef01234567";
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"blob 1000000\0truncated").unwrap();
+        let path = repo.path(Repository::hash_to_object(sha1));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, encoder.finish().unwrap()).unwrap();
+        assert_eq!(
+            repo.object_info(sha1).unwrap(),
+            (ObjectType::Blob, 1_000_000)
+        );
+        assert!(repo.object_size(sha1).is_err());
+    }
+
+    #[test]
+    fn pretty_print_objects() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let pretty = |sha1: &str| {
+            let mut out = Vec::new();
+            repo.pretty_print(sha1, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!(
+            pretty("3d8a457a40a1b21da38ebcb0ab009e4e997a5bb9"),
+            "100644 blob 06fcdd77c9348567c50638b30d406500f521c304\ta.txt\n\
+            100644 blob 61780798228d17af2d34fce4cfbdf35556832472\tb.txt\n\
+            040000 tree 1fb43f1b1d0e9e6f60f6b41185b21cd30319d0db\tdir\n"
+        );
+        assert!(pretty("06fcdd77c9348567c50638b30d406500f521c304")
+            .starts_with("first line\nsecond line\n"));
+        assert_eq!(
+            pretty("1e3e965f92c57b26cb86b4313edca25125678074"),
+            "tree 3d8a457a40a1b21da38ebcb0ab009e4e997a5bb9\n\
+            parent 09ed861f6cd57e64a23811b30686bb4919a41674\n\
+            author Test User <test@example.com> 1700000200 -0830\n\
+            committer Test User <test@example.com> 1700000200 -0830\n\
+            \n\
+            third commit\n"
+        );
+        assert_eq!(
+            pretty("28d0e451b11758598f5e97c62bd8aa8c1061cba5"),
+            "object 25a7b65d7c4c638b508f306909cb719db11cb7c2\n\
+            type commit\n\
+            tag v1.0\n\
+            tagger Test User <test@example.com> 1700000050 +0100\n\
+            \n\
+            version 1.0\n"
+        );
+
+        let tree = Object::Tree {
+            entries: vec![TreeEntry {
+                mode: "100644".to_owned(),
+                name: b"caf\xe9.txt".to_vec(),
+                sha: from_hex("06fcdd77c9348567c50638b30d406500f521c304").unwrap(),
+            }],
+        }
+        .save(&repo)
+        .unwrap();
+        assert_eq!(
+            pretty(&tree),
+            "100644 blob 06fcdd77c9348567c50638b30d406500f521c304\t\"caf\\351.txt\"\n"
+        );
+    }
+
+    #[test]
+    fn find_object_by_unique_prefix() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let sha1 = repo.find_object_sha1("06fcdd7", None, true).unwrap();
+        assert_eq!(sha1, "06fcdd77c9348567c50638b30d406500f521c304");
+
+        let sha1 = repo.find_object_sha1("06FCDD77C934", None, true).unwrap();
+        assert_eq!(sha1, "06fcdd77c9348567c50638b30d406500f521c304");
+    }
+
+    #[test]
+    fn find_object_by_ambiguous_prefix() {
+        let test_dir = existing_test_repo("ambiguous_prefix");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let err = repo.find_object_sha1("eb399", None, true).unwrap_err();
+        assert!(matches!(err, GitSyncError::AmbiguousObject { .. }));
+
+        let sha1 = repo.find_object_sha1("eb3993", None, true).unwrap();
+        assert_eq!(sha1, "eb3993ea98276254dd7387c10caf3cdfc210d22b");
+    }
+
+    #[test]
+    fn find_object_by_unknown_prefix() {
+        let test_dir = existing_test_repo("ambiguous_prefix");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let err = repo.find_object_sha1("abcdef0", None, true).unwrap_err();
+        assert!(matches!(err, GitSyncError::ObjectNotFound { .. }));
+
+        assert!(repo.find_object_sha1("eb3", None, true).is_err());
+    }
+
+    #[test]
+    fn resolve_symbolic_head() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let head = repo.resolve_ref("HEAD").unwrap();
+        assert_eq!(head, "1e3e965f92c57b26cb86b4313edca25125678074");
+        assert_eq!(repo.resolve_ref("master").unwrap(), head);
+        assert_eq!(repo.resolve_ref("refs/heads/master").unwrap(), head);
+        assert_eq!(repo.find_object_sha1("HEAD", None, true).unwrap(), head);
+    }
+
+    #[test]
+    fn resolve_detached_head() {
+        const DETACHED: &str = "09ed861f6cd57e64a23811b30686bb4919a41674";
+
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        fs::write(repo.path("HEAD"), format!("{DETACHED}\n")).unwrap();
+
+        assert_eq!(repo.resolve_ref("HEAD").unwrap(), DETACHED);
+    }
+
+    #[test]
+    fn resolve_tags() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let light = repo.resolve_ref("light").unwrap();
+        assert_eq!(light, "09ed861f6cd57e64a23811b30686bb4919a41674");
+        assert_eq!(repo.resolve_ref("refs/tags/light").unwrap(), light);
+
+        // annotated tags resolve to the tag object itself
+        let annotated = repo.resolve_ref("v1.0").unwrap();
+        assert_eq!(annotated, "28d0e451b11758598f5e97c62bd8aa8c1061cba5");
+
+        assert!(repo.resolve_ref("does-not-exist").is_err());
+        assert!(repo.resolve_ref("../config").is_err());
+    }
+
+    #[test]
+    fn open_through_git_file() {
+        let test_dir = existing_test_repo("gitfile_redirect");
+        let worktree = test_dir.root().join("worktree");
+        assert!(worktree.join(".git").is_file());
+
+        for repo in [
+            Repository::new(worktree.as_path()).unwrap(),
+            Repository::find_from(&worktree, RepoKind::Git).unwrap(),
+        ] {
+            assert_eq!(
+                repo.gitdir_root(),
+                fs::canonicalize(test_dir.root().join("storage/repo.git")).unwrap()
+            );
+            assert!(repo.worktree_root().unwrap().ends_with("worktree"));
+            assert_eq!(
+                repo.object_type("2bb09523ce4baf1940ee8fef49f6cade5afe3d03")
+                    .unwrap(),
+                ObjectType::Blob
+            );
+        }
+    }
+
+    #[test]
+    fn discover_repository() {
+        let repo_path = test_dir("discover_repository");
+        Repository::create_at(repo_path.root()).unwrap();
+        let root = fs::canonicalize(repo_path.root()).unwrap();
+        let nested = root.join("a/b/c");
+        fs::create_dir_all(&nested).unwrap();
+
+        let expected = Discovered {
+            git_dir: root.join(".git"),
+            work_tree: Some(root.clone()),
+            is_bare: false,
+        };
+        let options = DiscoverOptions::default();
+        assert_eq!(Repository::discover(&nested, &options).unwrap(), expected);
+        assert_eq!(Repository::discover(&root, &options).unwrap(), expected);
+        let bare = Repository::discover(root.join(".git/objects"), &options).unwrap();
+        assert_eq!(bare.git_dir, root.join(".git"));
+        assert_eq!(bare.work_tree, None);
+        assert!(bare.is_bare);
+
+        // a .gitsync repository is searched separately
+        let options = DiscoverOptions {
+            kind: RepoKind::GitSync,
+            ceiling_dirs: vec![root.parent().unwrap().to_path_buf()],
+        };
+        assert!(Repository::discover(&nested, &options).is_err());
+
+        for ceiling in [root.join("a"), root.clone()] {
+            let options = DiscoverOptions {
+                ceiling_dirs: vec![PathBuf::from("/does/not/exist"), ceiling],
+                ..Default::default()
+            };
+            assert!(matches!(
+                Repository::discover(&nested, &options),
+                Err(GitSyncError::NotARepository { .. })
+            ));
+            // the start directory is searched even if it is a ceiling
+            assert_eq!(Repository::discover(&root, &options).unwrap(), expected);
+        }
+        let options = DiscoverOptions {
+            ceiling_dirs: vec![root.parent().unwrap().to_path_buf()],
+            ..Default::default()
+        };
+        assert_eq!(Repository::discover(&nested, &options).unwrap(), expected);
+
+        let repo = Repository::find_from(&nested, RepoKind::Git).unwrap();
+        assert_eq!(repo.gitdir_root(), root.join(".git"));
+    }
+
+    #[test]
+    fn map_paths_to_the_worktree() {
+        let repo_path = test_dir("map_paths_to_the_worktree");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = fs::canonicalize(repo_path.root()).unwrap();
+        fs::create_dir_all(root.join("nested/deeper")).unwrap();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::write(root.join("sub/file"), "content\n").unwrap();
+        let nested = root.join("nested");
+        let relative = |path: &str| repo.workdir_relative_from(&nested, Path::new(path));
+
+        assert_eq!(relative("../sub/file").unwrap(), Path::new("sub/file"));
+        assert_eq!(
+            relative("./deeper/../file").unwrap(),
+            Path::new("nested/file")
+        );
+        // missing files can be mapped too, e.g. to stage their removal
+        assert_eq!(
+            relative("../sub/missing").unwrap(),
+            Path::new("sub/missing")
+        );
+        assert_eq!(
+            relative(root.join("sub/file").to_str().unwrap()).unwrap(),
+            Path::new("sub/file")
+        );
+        assert_eq!(relative("..").unwrap(), Path::new(""));
+
+        assert!(matches!(
+            relative("../../outside"),
+            Err(GitSyncError::OutsideWorktree { .. })
+        ));
+
+        #[cfg(unix)]
+        {
+            // a symlink is mapped to itself, not to its target
+            std::os::unix::fs::symlink(root.parent().unwrap(), root.join("link")).unwrap();
+            assert_eq!(relative("../link").unwrap(), Path::new("link"));
+            assert!(relative("../link/outside").is_err());
+        }
+    }
+
+    #[test]
+    fn read_tree_of_commit() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let entries = repo
+            .read_tree("1e3e965f92c57b26cb86b4313edca25125678074")
+            .unwrap();
+        let names: Vec<&[u8]> = entries.iter().map(|entry| entry.name.as_slice()).collect();
+        assert_eq!(names, [&b"a.txt"[..], b"b.txt", b"dir"]);
+        assert_eq!(entries[2].object_type(), ObjectType::Tree);
+
+        // the annotated tag points to the root commit
+        let tagged = repo
+            .read_tree("28d0e451b11758598f5e97c62bd8aa8c1061cba5")
+            .unwrap();
+        assert_eq!(tagged.len(), 1);
+
+        assert!(matches!(
+            repo.read_tree("06fcdd77c9348567c50638b30d406500f521c304"),
+            Err(GitSyncError::UnexpectedObjectType { .. })
+        ));
+    }
+
+    #[test]
+    fn read_tree_recursively() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let lines: Vec<String> = repo
+            .read_tree_recursive("3d8a457a40a1b21da38ebcb0ab009e4e997a5bb9")
+            .unwrap()
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{:0>6} {} {}\t{}",
+                    entry.mode,
+                    entry.object_type(),
+                    entry.sha1(),
+                    quote_path(&entry.name)
+                )
+            })
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "100644 blob 06fcdd77c9348567c50638b30d406500f521c304\ta.txt",
+                "100644 blob 61780798228d17af2d34fce4cfbdf35556832472\tb.txt",
+                "100644 blob 79c53955ef856f16f2107446bc721c8879a1bd2e\tdir/c.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn commit_tree_matches_git() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let signature = Signature::parse("Test User <test@example.com> 1700000000 +0100").unwrap();
+        let commit = repo
+            .commit_tree(
+                "3d8a457a40a1b21da38ebcb0ab009e4e997a5bb9",
+                &["1e3e965f92c57b26cb86b4313edca25125678074".to_owned()],
+                &signature,
+                &signature,
+                "add commit-tree\n",
+            )
+            .unwrap();
+        assert_eq!(commit, "a4f4cd43aea6ae9774fbb60a4a4b88769f56c23e");
+
+        // the parent must be a commit
+        assert!(matches!(
+            repo.commit_tree(
+                "3d8a457a40a1b21da38ebcb0ab009e4e997a5bb9",
+                &["3d8a457a40a1b21da38ebcb0ab009e4e997a5bb9".to_owned()],
+                &signature,
+                &signature,
+                "tree as parent\n",
+            ),
+            Err(GitSyncError::UnexpectedObjectType {
+                expected: ObjectType::Commit,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn write_tree_matches_git() {
+        let repo_path = test_dir("write_tree_matches_git");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+
+        fs::write(root.join("foo.txt"), "hello\n").unwrap();
+        fs::create_dir(root.join("foo")).unwrap();
+        fs::write(root.join("foo/bar.txt"), "nested\n").unwrap();
+        fs::create_dir(root.join("empty")).unwrap();
+        fs::write(root.join("run.sh"), "#!/bin/sh\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{symlink, PermissionsExt};
+            fs::set_permissions(root.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+            symlink("foo.txt", root.join("link")).unwrap();
+        }
+
+        let tree = repo.write_tree_from_dir(root).unwrap();
+
+        // `git add -A && git write-tree` on the same directory
+        #[cfg(unix)]
+        assert_eq!(tree, "08ee4ec9c6b758d381cd9e3416bec50a39dff740");
+
+        let entries = repo.read_tree_recursive(&tree).unwrap();
+        assert!(entries.iter().any(|entry| entry.name == b"foo/bar.txt"
+            && entry.sha1() == "79c53955ef856f16f2107446bc721c8879a1bd2e"));
+    }
+
+    #[test]
+    fn checkout_tree() {
+        let repo_path = test_dir("checkout_tree");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+        fs::write(root.join("foo.txt"), "hello\n").unwrap();
+        fs::create_dir_all(root.join("dir/nested")).unwrap();
+        fs::write(root.join("dir/nested/bar.txt"), "nested\n").unwrap();
+        fs::write(root.join("run.sh"), "#!/bin/sh\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{symlink, PermissionsExt};
+            fs::set_permissions(root.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+            symlink("foo.txt", root.join("link")).unwrap();
+        }
+        let tree = repo.write_tree_from_dir(root).unwrap();
+
+        let dest_dir = test_dir("checkout_tree_dest");
+        let dest = dest_dir.root();
+        repo.checkout_tree(&tree, dest).unwrap();
+        assert_eq!(fs::read_to_string(dest.join("foo.txt")).unwrap(), "hello\n");
+        assert_eq!(
+            fs::read_to_string(dest.join("dir/nested/bar.txt")).unwrap(),
+            "nested\n"
+        );
+        assert_eq!(repo.write_tree_from_dir(dest).unwrap(), tree);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{MetadataExt, PermissionsExt};
+            let mode = |name: &str| fs::symlink_metadata(dest.join(name)).unwrap().mode();
+            assert_eq!(mode("run.sh") & 0o777, 0o755);
+            assert_eq!(mode("foo.txt") & 0o111, 0);
+            assert_eq!(
+                fs::read_link(dest.join("link")).unwrap(),
+                std::path::Path::new("foo.txt")
+            );
+
+            // unchanged files are kept, changed ones are replaced
+            let inode = |name: &str| fs::symlink_metadata(dest.join(name)).unwrap().ino();
+            let unchanged = inode("foo.txt");
+            fs::write(dest.join("dir/nested/bar.txt"), "changed\n").unwrap();
+            fs::set_permissions(dest.join("run.sh"), fs::Permissions::from_mode(0o644)).unwrap();
+            repo.checkout_tree(&tree, dest).unwrap();
+            assert_eq!(inode("foo.txt"), unchanged);
+            assert_eq!(
+                fs::read_to_string(dest.join("dir/nested/bar.txt")).unwrap(),
+                "nested\n"
+            );
+            assert_eq!(mode("run.sh") & 0o777, 0o755);
+        }
+    }
+
+    #[test]
+    fn diff_two_blobs() {
+        let repo_path = test_dir("diff_two_blobs");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let save = |data: &[u8]| {
+            Object::Blob {
+                data: data.to_vec(),
+            }
+            .save(&repo)
+            .unwrap()
+        };
+        let a = save(b"first\nsecond\nthird\n");
+        let b = save(b"first\nchanged\nthird\nadded\n");
+
+        assert_eq!(
+            repo.diff_blobs(&a, &b).unwrap(),
+            format!(
+                "--- a/{a}\n+++ b/{b}\n@@ -1,3 +1,4 @@\n first\n-second\n+changed\n third\n+added\n"
+            )
+        );
+        assert_eq!(repo.diff_blobs(&a, &a).unwrap(), "");
+
+        let binary = save(b"first\n\0second\n");
+        assert_eq!(
+            repo.diff_blobs(&a, &binary).unwrap(),
+            "Binary files differ\n"
+        );
+    }
+
+    #[test]
+    fn diff_fixture_commits() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let first = repo.rev_parse("HEAD~2").unwrap();
+        let third = repo.rev_parse("HEAD").unwrap();
+        let changes = repo.diff_trees(&first, &third).unwrap();
+        let blob = |sha1: &str| Some(("100644".to_owned(), sha1.to_owned()));
+        assert_eq!(
+            changes,
+            [
+                TreeChange {
+                    path: b"a.txt".to_vec(),
+                    change: Change::Modified,
+                    old: blob("08fe2720d8e3fe3a5f81fbb289bc4c7a522f13da"),
+                    new: blob("06fcdd77c9348567c50638b30d406500f521c304"),
+                },
+                TreeChange {
+                    path: b"b.txt".to_vec(),
+                    change: Change::Added,
+                    old: None,
+                    new: blob("61780798228d17af2d34fce4cfbdf35556832472"),
+                },
+                TreeChange {
+                    path: b"dir/c.txt".to_vec(),
+                    change: Change::Added,
+                    old: None,
+                    new: blob("79c53955ef856f16f2107446bc721c8879a1bd2e"),
+                },
+            ]
+        );
+        assert!(repo.diff_trees(&third, &third).unwrap().is_empty());
+    }
+
+    #[test]
+    fn diff_dir_replaced_by_file() {
+        let repo_path = test_dir("diff_dir_replaced_by_file");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+
+        fs::create_dir(root.join("x")).unwrap();
+        fs::write(root.join("x/a.txt"), "a\n").unwrap();
+        fs::write(root.join("y.txt"), "y\n").unwrap();
+        let before = repo.write_tree_from_dir(root).unwrap();
+
+        fs::remove_dir_all(root.join("x")).unwrap();
+        fs::write(root.join("x"), "x\n").unwrap();
+        let after = repo.write_tree_from_dir(root).unwrap();
+
+        let changes: Vec<_> = repo
+            .diff_trees(&before, &after)
+            .unwrap()
+            .into_iter()
+            .map(|change| (change.path, change.change))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (b"x".to_vec(), Change::Added),
+                (b"x/a.txt".to_vec(), Change::Deleted),
+            ]
+        );
+    }
+
+    #[test]
+    fn hash_tree_matches_index_tree() {
+        let repo_path = test_dir("hash_tree_matches_index_tree");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+
+        fs::write(root.join("foo.txt"), "hello\n").unwrap();
+        fs::create_dir(root.join("foo")).unwrap();
+        fs::write(root.join("foo/bar.txt"), "nested\n").unwrap();
+        fs::write(root.join("run.sh"), "#!/bin/sh\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{symlink, PermissionsExt};
+            fs::set_permissions(root.join("run.sh"), fs::Permissions::from_mode(0o755)).unwrap();
+            symlink("foo.txt", root.join("link")).unwrap();
+        }
+
+        let tree = repo.hash_tree_from_dir(root).unwrap();
+        assert!(!repo.has_object(&tree));
+        assert_eq!(repo.loose_objects().unwrap().count(), 0);
+
+        repo.add(&["."]).unwrap();
+        assert_eq!(repo.write_tree_from_index().unwrap(), tree);
+        assert_eq!(repo.write_tree_from_dir(root).unwrap(), tree);
+    }
+
+    #[test]
+    fn add_stages_files() {
+        let repo_path = test_dir("add_stages_files");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+
+        fs::write(root.join("foo.txt"), "hello\n").unwrap();
+        fs::create_dir(root.join("foo")).unwrap();
+        fs::write(root.join("foo/bar.txt"), "nested\n").unwrap();
+
+        fs::create_dir_all(repo.path("info")).unwrap();
+        fs::write(repo.path("info/exclude"), "*.log\n").unwrap();
+        fs::write(root.join("foo/debug.log"), "").unwrap();
+
+        repo.add(&["foo.txt", "foo"]).unwrap();
+        let index = repo.read_index().unwrap();
+        let entries: Vec<(&[u8], String)> = index
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_slice(), entry.sha1()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (
+                    &b"foo.txt"[..],
+                    "ce013625030ba8dba906f756967f9e9ca394464a".to_owned()
+                ),
+                (
+                    b"foo/bar.txt",
+                    "79c53955ef856f16f2107446bc721c8879a1bd2e".to_owned()
+                ),
+            ]
+        );
+        assert_eq!(index.entry(b"foo.txt").unwrap().size, 6);
+
+        // adding unchanged files does not touch the index
+        let index_path = root.join(".git/index");
+        let before = fs::read(&index_path).unwrap();
+        repo.add(&["."]).unwrap();
+        assert_eq!(fs::read(&index_path).unwrap(), before);
+
+        fs::remove_file(root.join("foo/bar.txt")).unwrap();
+        repo.add(&["."]).unwrap();
+        let index = repo.read_index().unwrap();
+        assert_eq!(index.entries.len(), 1);
+        assert_eq!(index.entries[0].path, b"foo.txt");
+
+        assert!(matches!(
+            repo.add(&["missing.txt"]),
+            Err(GitSyncError::PathspecNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn write_index_respects_lock() {
+        let repo_path = test_dir("write_index_respects_lock");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        fs::write(repo_path.root().join("a.txt"), "a\n").unwrap();
+        repo.add(&["a.txt"]).unwrap();
+        let index = repo.read_index().unwrap();
+
+        let lock = repo.path("index.lock");
+        fs::write(&lock, "held by git").unwrap();
+        assert!(matches!(
+            repo.write_index(&Index::default()),
+            Err(GitSyncError::IndexLocked { .. })
+        ));
+        assert_eq!(fs::read_to_string(&lock).unwrap(), "held by git");
+        assert_eq!(repo.read_index().unwrap().entries, index.entries);
+
+        fs::remove_file(&lock).unwrap();
+        repo.write_index(&Index::default()).unwrap();
+        assert!(!lock.exists());
+        assert!(repo.read_index().unwrap().entries.is_empty());
+    }
+
+    #[test]
+    fn commit_index() {
+        let repo_path = test_dir("commit_index");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+
+        fs::write(root.join("foo.txt"), "hello\n").unwrap();
+        fs::create_dir(root.join("foo")).unwrap();
+        fs::write(root.join("foo/bar.txt"), "nested\n").unwrap();
+        repo.add(&["."]).unwrap();
+
+        // not staged, so not part of the commit
+        fs::write(root.join("other.txt"), "other\n").unwrap();
+
+        let signature = Signature::parse("Test User <test@example.com> 1700000000 +0100").unwrap();
+        let first = repo.commit("first\n", &signature, &signature).unwrap();
+        assert_eq!(repo.resolve_ref("refs/heads/main").unwrap(), first);
+
+        let commits: Vec<(String, Object)> = repo
+            .walk_commits(&repo.resolve_ref("HEAD").unwrap())
+            .map(|commit| commit.unwrap())
+            .collect();
+        assert_eq!(commits.len(), 1);
+        let Object::Commit { tree, parents, .. } = &commits[0].1 else {
+            panic!("expected a commit");
+        };
+        assert!(parents.is_empty());
+        // only the staged files are committed
+        let entries = repo.read_tree_recursive(tree).unwrap();
+        let names: Vec<&[u8]> = entries.iter().map(|entry| entry.name.as_slice()).collect();
+        assert_eq!(names, [&b"foo.txt"[..], b"foo/bar.txt"]);
+
+        repo.add(&["other.txt"]).unwrap();
+        let second = repo.commit("second\n", &signature, &signature).unwrap();
+        let Object::Commit { parents, .. } = repo.load_object(&second).unwrap() else {
+            panic!("expected a commit");
+        };
+        assert_eq!(parents, [first]);
+    }
+
+    #[test]
+    fn git_and_gitsync_repos_are_separate() {
+        let repo_path = test_dir("git_and_gitsync_repos_are_separate");
+        let root = repo_path.root();
+        let git = Repository::create_at(root).unwrap();
+        let options = InitOptions {
+            kind: RepoKind::GitSync,
+            ..InitOptions::default()
+        };
+        let gitsync = Repository::create_with(root, &options).unwrap();
+        assert!(root.join(".gitsync/HEAD").is_file());
+
+        let found = Repository::find_from(root, RepoKind::GitSync).unwrap();
+        assert_eq!(
+            fs::canonicalize(found.gitdir_root()).unwrap(),
+            fs::canonicalize(root.join(".gitsync")).unwrap()
+        );
+        let found = Repository::find_from(root, RepoKind::Git).unwrap();
+        assert_eq!(
+            fs::canonicalize(found.gitdir_root()).unwrap(),
+            fs::canonicalize(root.join(".git")).unwrap()
+        );
+
+        fs::write(root.join("a.txt"), "shared\n").unwrap();
+        let signature = Signature::parse("Test User <test@example.com> 1700000000 +0100").unwrap();
+        git.add(&["."]).unwrap();
+        let commit = git.commit("first\n", &signature, &signature).unwrap();
+
+        // neither repository tracks the other's git dir
+        assert!(git.status().unwrap().is_clean());
+        let status = gitsync.status().unwrap();
+        assert_eq!(status.untracked, [b"a.txt".to_vec()]);
+        assert!(gitsync.resolve_ref("HEAD").is_err());
+        assert!(!gitsync.has_object(&commit));
+
+        gitsync.add(&["."]).unwrap();
+        let sync_commit = gitsync.commit("sync\n", &signature, &signature).unwrap();
+        assert_eq!(git.resolve_ref("HEAD").unwrap(), commit);
+        assert!(!git.has_object(&sync_commit));
+        let Object::Commit { tree, .. } = git.load_object(&commit).unwrap() else {
+            panic!("expected a commit");
+        };
+        // both commits store the same tree
+        assert!(gitsync.has_object(&tree));
+    }
+
+    #[test]
+    fn switch_between_branches() {
+        let repo_path = test_dir("switch_between_branches");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+        let signature = Signature::parse("Test User <test@example.com> 1700000000 +0100").unwrap();
+
+        fs::write(root.join("a.txt"), "main\n").unwrap();
+        fs::write(root.join("same.txt"), "same\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let first = repo.commit("first\n", &signature, &signature).unwrap();
+
+        repo.create_branch("other", "HEAD").unwrap();
+        repo.switch("other", false).unwrap();
+        assert_eq!(repo.current_branch().unwrap().as_deref(), Some("other"));
+        fs::write(root.join("a.txt"), "other\n").unwrap();
+        fs::create_dir(root.join("dir")).unwrap();
+        fs::write(root.join("dir/b.txt"), "new\n").unwrap();
+        repo.add(&["."]).unwrap();
+        repo.commit("second\n", &signature, &signature).unwrap();
+
+        repo.switch("main", false).unwrap();
+        assert_eq!(repo.current_branch().unwrap().as_deref(), Some("main"));
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "main\n");
+        assert!(!root.join("dir").exists());
+        assert!(repo.status().unwrap().is_clean());
+
+        // the change to a.txt would be lost
+        fs::write(root.join("a.txt"), "changed\n").unwrap();
+        assert!(matches!(
+            repo.switch("other", false),
+            Err(GitSyncError::WouldOverwrite { .. })
+        ));
+        assert_eq!(repo.current_branch().unwrap().as_deref(), Some("main"));
+        repo.switch("other", true).unwrap();
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "other\n");
+        assert_eq!(fs::read_to_string(root.join("dir/b.txt")).unwrap(), "new\n");
+        assert!(repo.status().unwrap().is_clean());
+
+        repo.switch(&first, false).unwrap();
+        assert_eq!(repo.read_symbolic_ref("HEAD").unwrap(), None);
+        assert_eq!(repo.resolve_ref("HEAD").unwrap(), first);
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "main\n");
+    }
+
+    #[test]
+    fn fast_forward_merge() {
+        let repo_path = test_dir("fast_forward_merge");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+        let signature = Signature::parse("Test User <test@example.com> 1700000000 +0100").unwrap();
+
+        fs::write(root.join("a.txt"), "first\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let first = repo.commit("first\n", &signature, &signature).unwrap();
+        repo.create_branch("feature", "HEAD").unwrap();
+        repo.switch("feature", false).unwrap();
+        fs::write(root.join("a.txt"), "second\n").unwrap();
+        fs::write(root.join("b.txt"), "new\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let second = repo.commit("second\n", &signature, &signature).unwrap();
+
+        repo.switch("main", false).unwrap();
+        assert_eq!(
+            repo.merge("feature", &signature, &signature).unwrap(),
+            MergeOutcome::FastForward {
+                commit: second.clone()
+            }
+        );
+        assert_eq!(repo.current_branch().unwrap().as_deref(), Some("main"));
+        assert_eq!(repo.resolve_ref("refs/heads/main").unwrap(), second);
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "second\n");
+        assert_eq!(fs::read_to_string(root.join("b.txt")).unwrap(), "new\n");
+        assert!(repo.status().unwrap().is_clean());
+
+        assert_eq!(
+            repo.merge(&first, &signature, &signature).unwrap(),
+            MergeOutcome::UpToDate
+        );
+    }
+
+    #[test]
+    fn keep_local_changes_to_unchanged_files() {
+        let repo_path = test_dir("keep_local_changes_to_unchanged_files");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+        let signature = Signature::parse("Test User <test@example.com> 1700000000 +0100").unwrap();
+
+        fs::write(root.join("a.txt"), "first\n").unwrap();
+        fs::write(root.join("staged.txt"), "staged\n").unwrap();
+        fs::write(root.join("unstaged.txt"), "unstaged\n").unwrap();
+        repo.add(&["."]).unwrap();
+        repo.commit("first\n", &signature, &signature).unwrap();
+        repo.create_branch("feature", "HEAD").unwrap();
+        repo.switch("feature", false).unwrap();
+        fs::write(root.join("a.txt"), "second\n").unwrap();
+        repo.add(&["a.txt"]).unwrap();
+        repo.commit("second\n", &signature, &signature).unwrap();
+
+        fs::write(root.join("staged.txt"), "staged change\n").unwrap();
+        repo.add(&["staged.txt"]).unwrap();
+        fs::write(root.join("unstaged.txt"), "unstaged change\n").unwrap();
+        let changes = |repo: &Repository| {
+            let status = repo.status().unwrap();
+            (status.staged, status.unstaged)
+        };
+        let expected = (
+            vec![(b"staged.txt".to_vec(), Change::Modified)],
+            vec![(b"unstaged.txt".to_vec(), Change::Modified)],
+        );
+
+        repo.switch("main", false).unwrap();
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "first\n");
+        assert_eq!(changes(&repo), expected);
+
+        assert!(matches!(
+            repo.merge("feature", &signature, &signature).unwrap(),
+            MergeOutcome::FastForward { .. }
+        ));
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "second\n");
+        assert_eq!(changes(&repo), expected);
+        assert_eq!(
+            fs::read_to_string(root.join("unstaged.txt")).unwrap(),
+            "unstaged change\n"
+        );
+    }
+
+    #[test]
+    fn three_way_merge() {
+        let repo_path = test_dir("three_way_merge");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+        let signature = Signature::parse("Test User <test@example.com> 1700000000 +0100").unwrap();
+
+        fs::write(root.join("a.txt"), "1\n2\n3\n4\n5\n").unwrap();
+        fs::write(root.join("b.txt"), "b\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let base = repo.commit("base\n", &signature, &signature).unwrap();
+
+        repo.create_branch("feature", "HEAD").unwrap();
+        repo.switch("feature", false).unwrap();
+        fs::write(root.join("a.txt"), "1\n2\n3\n4\nfive\n").unwrap();
+        fs::write(root.join("c.txt"), "c\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let theirs = repo.commit("feature\n", &signature, &signature).unwrap();
+
+        repo.switch("main", false).unwrap();
+        fs::write(root.join("a.txt"), "one\n2\n3\n4\n5\n").unwrap();
+        fs::remove_file(root.join("b.txt")).unwrap();
+        repo.add(&["."]).unwrap();
+        let ours = repo.commit("main\n", &signature, &signature).unwrap();
+        assert_eq!(repo.merge_base(&ours, &theirs).unwrap(), Some(base));
+
+        let MergeOutcome::Merged { commit } =
+            repo.merge("feature", &signature, &signature).unwrap()
+        else {
+            panic!("expected a merge commit");
+        };
+        let Object::Commit {
+            parents, message, ..
+        } = repo.load_object(&commit).unwrap()
+        else {
+            panic!("expected a commit");
+        };
+        assert_eq!(parents, [ours, theirs]);
+        assert_eq!(message, b"Merge branch 'feature'\n");
+        assert_eq!(repo.resolve_ref("refs/heads/main").unwrap(), commit);
+
+        assert_eq!(
+            fs::read_to_string(root.join("a.txt")).unwrap(),
+            "one\n2\n3\n4\nfive\n"
+        );
+        assert!(!root.join("b.txt").exists());
+        assert_eq!(fs::read_to_string(root.join("c.txt")).unwrap(), "c\n");
+        assert!(repo.status().unwrap().is_clean());
+    }
+
+    #[test]
+    fn reset_modes() {
+        let repo_path = test_dir("reset_modes");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+        let signature = Signature::parse("Test User <test@example.com> 1700000000 +0100").unwrap();
+
+        fs::write(root.join("a.txt"), "1\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let first = repo.commit("first\n", &signature, &signature).unwrap();
+        fs::write(root.join("a.txt"), "2\n").unwrap();
+        fs::write(root.join("b.txt"), "b\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let second = repo.commit("second\n", &signature, &signature).unwrap();
+        let index_paths = |repo: &Repository| -> Vec<Vec<u8>> {
+            repo.ls_files(false)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.path)
+                .collect()
+        };
+
+        // only the branch moves, the changes of the second commit are staged
+        assert_eq!(repo.reset(&first, ResetMode::Soft).unwrap(), first);
+        assert_eq!(repo.resolve_ref("refs/heads/main").unwrap(), first);
+        assert_eq!(repo.current_branch().unwrap().as_deref(), Some("main"));
+        let status = repo.status().unwrap();
+        assert_eq!(
+            status.staged,
+            [
+                (b"a.txt".to_vec(), Change::Modified),
+                (b"b.txt".to_vec(), Change::Added)
+            ]
+        );
+        assert!(status.unstaged.is_empty());
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "2\n");
+
+        // the index matches the commit, the worktree is kept
+        repo.reset(&second, ResetMode::Soft).unwrap();
+        repo.reset("HEAD~1", ResetMode::Mixed).unwrap();
+        assert_eq!(repo.resolve_ref("HEAD").unwrap(), first);
+        assert_eq!(index_paths(&repo), [b"a.txt".to_vec()]);
+        let status = repo.status().unwrap();
+        assert!(status.staged.is_empty());
+        assert_eq!(status.unstaged, [(b"a.txt".to_vec(), Change::Modified)]);
+        assert_eq!(status.untracked, [b"b.txt".to_vec()]);
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "2\n");
+
+        // everything matches the commit, even staged new files are removed
+        repo.reset(&second, ResetMode::Hard).unwrap();
+        assert_eq!(fs::read_to_string(root.join("b.txt")).unwrap(), "b\n");
+        assert!(repo.status().unwrap().is_clean());
+        fs::write(root.join("a.txt"), "local\n").unwrap();
+        fs::create_dir(root.join("dir")).unwrap();
+        fs::write(root.join("dir/c.txt"), "c\n").unwrap();
+        repo.add(&["dir"]).unwrap();
+        fs::write(root.join("untracked.txt"), "kept\n").unwrap();
+        fs::write(repo.path("MERGE_HEAD"), format!("{second}\n")).unwrap();
+
+        repo.reset(&first, ResetMode::Hard).unwrap();
+        assert_eq!(repo.resolve_ref("refs/heads/main").unwrap(), first);
+        assert_eq!(index_paths(&repo), [b"a.txt".to_vec()]);
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "1\n");
+        assert!(!root.join("b.txt").exists());
+        assert!(!root.join("dir").exists());
+        assert!(!repo.path("MERGE_HEAD").exists());
+        let status = repo.status().unwrap();
+        assert!(status.staged.is_empty());
+        assert!(status.unstaged.is_empty());
+        assert_eq!(status.untracked, [b"untracked.txt".to_vec()]);
+    }
+
+    #[test]
+    fn cherry_pick_commit() {
+        let repo_path = test_dir("cherry_pick_commit");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+        let signature = Signature::parse("Test User <test@example.com> 1700000000 +0100").unwrap();
+        let author = Signature::parse("Other <other@example.com> 1700000100 +0000").unwrap();
+
+        fs::write(root.join("a.txt"), "1\n2\n3\n").unwrap();
+        repo.add(&["."]).unwrap();
+        repo.commit("base\n", &signature, &signature).unwrap();
+
+        repo.create_branch("feature", "HEAD").unwrap();
+        repo.switch("feature", false).unwrap();
+        fs::write(root.join("b.txt"), "not picked\n").unwrap();
+        repo.add(&["."]).unwrap();
+        repo.commit("unrelated\n", &signature, &signature).unwrap();
+        fs::write(root.join("a.txt"), "1\n2\nthree\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let fix = repo.commit("fix three\n", &author, &author).unwrap();
+        fs::write(root.join("a.txt"), "one\n2\nthree\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let conflicting = repo.commit("fix one\n", &signature, &signature).unwrap();
+
+        repo.switch("main", false).unwrap();
+        fs::write(root.join("a.txt"), "ONE\n2\n3\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let head = repo.commit("main\n", &signature, &signature).unwrap();
+
+        let MergeOutcome::Merged { commit } = repo.cherry_pick(&fix, &signature).unwrap() else {
+            panic!("expected a new commit");
+        };
+        let Object::Commit {
+            parents,
+            author: picked_author,
+            message,
+            ..
+        } = repo.load_object(&commit).unwrap()
+        else {
+            panic!("expected a commit");
+        };
+        assert_eq!(parents, [head]);
+        assert_eq!(picked_author, author);
+        assert_eq!(message, b"fix three\n");
+        assert_eq!(repo.resolve_ref("refs/heads/main").unwrap(), commit);
+        assert_eq!(
+            fs::read_to_string(root.join("a.txt")).unwrap(),
+            "ONE\n2\nthree\n"
+        );
+        assert!(!root.join("b.txt").exists());
+        assert!(repo.status().unwrap().is_clean());
+
+        // the changes are already there
+        assert_eq!(
+            repo.cherry_pick(&fix, &signature).unwrap(),
+            MergeOutcome::UpToDate
+        );
+
+        let outcome = repo.cherry_pick(&conflicting, &signature).unwrap();
+        assert_eq!(
+            outcome,
+            MergeOutcome::Conflicts {
+                paths: vec![b"a.txt".to_vec()]
+            }
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("a.txt")).unwrap(),
+            format!(
+                "<<<<<<< HEAD\nONE\n=======\none\n>>>>>>> {} (fix one)\n2\nthree\n",
+                &conflicting[..7]
+            )
+        );
+        assert!(repo.path("CHERRY_PICK_HEAD").is_file());
+        assert!(matches!(
+            repo.cherry_pick(&fix, &signature),
+            Err(GitSyncError::CherryPickInProgress)
+        ));
+
+        fs::write(root.join("a.txt"), "one\n2\nthree\n").unwrap();
+        repo.add(&["a.txt"]).unwrap();
+        let resolved = repo.commit("fix one\n", &signature, &signature).unwrap();
+        let Object::Commit { parents, .. } = repo.load_object(&resolved).unwrap() else {
+            panic!("expected a commit");
+        };
+        assert_eq!(parents, [commit]);
+        assert!(!repo.path("CHERRY_PICK_HEAD").exists());
+    }
+
+    #[test]
+    fn revert_commit() {
+        let repo_path = test_dir("revert_commit");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+        let signature = Signature::parse("Test User <test@example.com> 1700000000 +0100").unwrap();
+
+        fs::write(root.join("a.txt"), "a\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let base = repo.commit("base\n", &signature, &signature).unwrap();
+        fs::write(root.join("b.txt"), "b\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let added = repo
+            .commit("add b\n\nwith a body\n", &signature, &signature)
+            .unwrap();
+        fs::write(root.join("a.txt"), "changed\n").unwrap();
+        repo.add(&["."]).unwrap();
+        let head = repo.commit("change a\n", &signature, &signature).unwrap();
+
+        let MergeOutcome::Merged { commit } =
+            repo.revert(&added, None, &signature, &signature).unwrap()
+        else {
+            panic!("expected a new commit");
+        };
+        let Object::Commit {
+            tree,
+            parents,
+            message,
+            ..
+        } = repo.load_object(&commit).unwrap()
+        else {
+            panic!("expected a commit");
+        };
+        assert_eq!(parents, [head.clone()]);
+        assert_eq!(
+            message,
+            format!("Revert \"add b\"\n\nThis reverts commit {added}.\n").into_bytes()
+        );
+        let names: Vec<Vec<u8>> = repo
+            .read_tree_recursive(&tree)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, [b"a.txt".to_vec()]);
+        assert!(!root.join("b.txt").exists());
+        assert_eq!(fs::read_to_string(root.join("a.txt")).unwrap(), "changed\n");
+        assert!(repo.status().unwrap().is_clean());
+
+        // reverting it again changes nothing
+        assert_eq!(
+            repo.revert(&added, None, &signature, &signature).unwrap(),
+            MergeOutcome::UpToDate
+        );
+
+        // merges need a mainline, other commits must not have one
+        let Object::Commit { tree, .. } = repo.load_object(&head).unwrap() else {
+            panic!("expected a commit");
+        };
+        let merge = repo
+            .commit_tree(&tree, &[head, base], &signature, &signature, "merge\n")
+            .unwrap();
+        for (commit, mainline) in [(&merge, None), (&merge, Some(3)), (&added, Some(1))] {
+            assert!(matches!(
+                repo.revert(commit, mainline, &signature, &signature),
+                Err(GitSyncError::InvalidMainline { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn rev_list_of_diverging_branches() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074".to_owned();
+        let parent = "09ed861f6cd57e64a23811b30686bb4919a41674".to_owned();
+        let root_commit = "25a7b65d7c4c638b508f306909cb719db11cb7c2".to_owned();
+        let Object::Commit { tree, .. } = repo.load_object(&head).unwrap() else {
+            panic!("expected a commit");
+        };
+        let signature = Signature::parse("Test User <test@example.com> 1800000000 +0000").unwrap();
+        // a branch of two commits forked from the parent of HEAD
+        let side = repo
+            .commit_tree(&tree, &[parent.clone()], &signature, &signature, "side\n")
+            .unwrap();
+        let signature = Signature::parse("Test User <test@example.com> 1800000100 +0000").unwrap();
+        let side_tip = repo
+            .commit_tree(&tree, &[side.clone()], &signature, &signature, "tip\n")
+            .unwrap();
+
+        assert_eq!(
+            repo.rev_list(&[head.clone()], &[]).unwrap(),
+            [head.clone(), parent.clone(), root_commit.clone()]
+        );
+        assert_eq!(
+            repo.rev_list(&[side_tip.clone()], &[head.clone()]).unwrap(),
+            [side_tip.clone(), side.clone()]
+        );
+        assert_eq!(
+            repo.rev_list(&[head.clone()], &[side_tip.clone()]).unwrap(),
+            [head.clone()]
+        );
+        assert_eq!(
+            repo.rev_list(&[head.clone(), side_tip.clone()], &[root_commit.clone()])
+                .unwrap(),
+            [side_tip.clone(), side, head.clone(), parent]
+        );
+        assert!(repo.rev_list(&[head.clone()], &[head]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn describe_commits() {
+        let fixture = existing_test_repo("linear_history");
+        let repo = Repository::new(fixture.root()).unwrap();
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074";
+        let tags = DescribeOptions {
+            tags: true,
+            ..DescribeOptions::default()
+        };
+
+        // v1.0 is annotated and tags the root commit
+        assert_eq!(
+            repo.describe(head, &DescribeOptions::default()).unwrap(),
+            "v1.0-2-g1e3e965"
+        );
+        assert_eq!(
+            repo.describe(
+                "25a7b65d7c4c638b508f306909cb719db11cb7c2",
+                &DescribeOptions::default()
+            )
+            .unwrap(),
+            "v1.0"
+        );
+        // the lightweight tag is one commit behind HEAD
+        assert_eq!(repo.describe(head, &tags).unwrap(), "light-1-g1e3e965");
+
+        let repo_path = test_dir("describe_commits");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let signature = Signature::parse("Test User <test@example.com> 1700000000 +0100").unwrap();
+        let commit = repo.commit("first\n", &signature, &signature).unwrap();
+        assert!(matches!(
+            repo.describe(&commit, &tags),
+            Err(GitSyncError::NoTagFound { .. })
+        ));
+        let always = DescribeOptions {
+            always: true,
+            ..DescribeOptions::default()
+        };
+        assert_eq!(repo.describe(&commit, &always).unwrap(), &commit[..7]);
+    }
+
+    #[test]
+    fn merge_base_of_branches() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074";
+        let parent = "09ed861f6cd57e64a23811b30686bb4919a41674";
+        let root_commit = "25a7b65d7c4c638b508f306909cb719db11cb7c2";
+        let Object::Commit { tree, .. } = repo.load_object(head).unwrap() else {
+            panic!("expected a commit");
+        };
+        let signature = Signature::parse("Test User <test@example.com> 1800000000 +0000").unwrap();
+        let commit = |parents: &[&str], message: &str| {
+            let parents: Vec<String> = parents.iter().map(|parent| parent.to_string()).collect();
+            repo.commit_tree(&tree, &parents, &signature, &signature, message)
+                .unwrap()
+        };
+
+        // a branch forked from the parent of HEAD
+        let side = commit(&[parent], "side\n");
+        assert_eq!(
+            repo.merge_base(head, &side).unwrap().as_deref(),
+            Some(parent)
+        );
+        assert_eq!(
+            repo.merge_base(&side, head).unwrap().as_deref(),
+            Some(parent)
+        );
+        assert_eq!(
+            repo.merge_base(head, root_commit).unwrap().as_deref(),
+            Some(root_commit)
+        );
+        assert_eq!(repo.merge_base(head, head).unwrap().as_deref(), Some(head));
+
+        // criss-cross merges have two best common ancestors
+        let x = commit(&[head, side.as_str()], "x\n");
+        let y = commit(&[side.as_str(), head], "y\n");
+        let mut bases = repo.merge_bases(&x, &y).unwrap();
+        bases.sort();
+        let mut expected = [head.to_owned(), side.clone()];
+        expected.sort();
+        assert_eq!(bases, expected);
+        assert!(expected.contains(&repo.merge_base(&x, &y).unwrap().unwrap()));
+
+        let unrelated = commit(&[], "unrelated\n");
+        assert_eq!(repo.merge_base(head, &unrelated).unwrap(), None);
+    }
+
+    #[test]
+    fn update_ref_compare_and_swap() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let root_commit = "25a7b65d7c4c638b508f306909cb719db11cb7c2";
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074";
+
+        repo.update_ref("refs/heads/master", root_commit, Some(head))
+            .unwrap();
+        assert_eq!(repo.resolve_ref("master").unwrap(), root_commit);
+
+        // `head` is stale now
+        assert!(matches!(
+            repo.update_ref("refs/heads/master", head, Some(head)),
+            Err(GitSyncError::RefChanged { .. })
+        ));
+        assert_eq!(repo.resolve_ref("master").unwrap(), root_commit);
+        assert!(!test_dir.root().join(".git/refs/heads/master.lock").exists());
+
+        // symbolic refs update their target
+        repo.update_ref("HEAD", head, None).unwrap();
+        assert_eq!(repo.resolve_ref("refs/heads/master").unwrap(), head);
+
+        // the null sha1 only matches missing refs
+        let null = repo.null_sha1();
+        repo.update_ref("refs/heads/feature/x", head, Some(&null))
+            .unwrap();
+        assert_eq!(repo.resolve_ref("feature/x").unwrap(), head);
+        assert!(matches!(
+            repo.update_ref("refs/heads/feature/x", head, Some(&null)),
+            Err(GitSyncError::RefChanged { .. })
+        ));
+    }
+
+    #[test]
+    fn reflog_records_updates() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let root_commit = "25a7b65d7c4c638b508f306909cb719db11cb7c2";
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074";
+        let existing = repo.read_reflog("refs/heads/master").unwrap();
+        assert_eq!(existing.len(), 3);
+        assert_eq!(existing[2].message, "commit: third commit");
+
+        repo.update_ref_with_message("refs/heads/master", root_commit, None, "reset: moving")
+            .unwrap();
+        repo.update_ref("HEAD", head, None).unwrap();
+        let log = repo.read_reflog("refs/heads/master").unwrap();
+        assert_eq!(log.len(), 5);
+        assert_eq!(
+            (log[3].old.as_str(), log[3].new.as_str()),
+            (head, root_commit)
+        );
+        assert_eq!(log[3].message, "reset: moving");
+        assert_eq!(
+            (log[4].old.as_str(), log[4].new.as_str()),
+            (root_commit, head)
+        );
+        assert_eq!(log[4].message, "");
+        // HEAD points to master, so it logs the same updates
+        assert_eq!(repo.read_reflog("HEAD").unwrap()[3..], log[3..]);
+
+        repo.update_ref("refs/heads/new", head, None).unwrap();
+        let log = repo.read_reflog("refs/heads/new").unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].old, repo.null_sha1());
+        // tags are not logged
+        repo.update_ref("refs/tags/new", head, None).unwrap();
+        assert!(repo.read_reflog("refs/tags/new").unwrap().is_empty());
+        assert_eq!(repo.read_reflog("HEAD").unwrap().len(), 5);
+    }
+
+    #[test]
+    fn list_refs() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074".to_owned();
+
+        assert_eq!(
+            repo.refs().unwrap(),
+            [
+                ("HEAD".to_owned(), head.clone()),
+                ("refs/heads/master".to_owned(), head.clone()),
+                (
+                    "refs/tags/light".to_owned(),
+                    "09ed861f6cd57e64a23811b30686bb4919a41674".to_owned()
+                ),
+                (
+                    "refs/tags/v1.0".to_owned(),
+                    "28d0e451b11758598f5e97c62bd8aa8c1061cba5".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_packed_refs() {
+        let test_dir = existing_test_repo("packed_refs");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074";
+        let root_commit = "25a7b65d7c4c638b508f306909cb719db11cb7c2";
+
+        // only packed
+        assert_eq!(repo.resolve_ref("HEAD").unwrap(), head);
+        assert_eq!(repo.resolve_ref("master").unwrap(), head);
+        assert_eq!(
+            repo.resolve_ref("v1.0").unwrap(),
+            "28d0e451b11758598f5e97c62bd8aa8c1061cba5"
+        );
+        // packed, but the loose ref is newer
+        assert_eq!(repo.resolve_ref("feature").unwrap(), root_commit);
+
+        let packed = repo.packed_refs().unwrap();
+        assert_eq!(
+            packed.get("refs/tags/v1.0").unwrap().peeled.as_deref(),
+            Some(root_commit)
+        );
+
+        let names: Vec<String> = repo
+            .refs()
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "HEAD",
+                "refs/heads/feature",
+                "refs/heads/loose-only",
+                "refs/heads/master",
+                "refs/tags/light",
+                "refs/tags/v1.0",
+            ]
+        );
+    }
+
+    #[test]
+    fn create_list_and_delete_branches() {
+        let test_dir = existing_test_repo("packed_refs");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let parent = "09ed861f6cd57e64a23811b30686bb4919a41674";
+
+        assert_eq!(repo.create_branch("topic", "HEAD^").unwrap(), parent);
+        assert!(repo.create_branch("topic", "HEAD").is_err());
+        assert_eq!(repo.current_branch().unwrap().as_deref(), Some("master"));
+
+        let branches: Vec<_> = repo
+            .list_branches()
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(branches, ["feature", "loose-only", "master", "topic"]);
+
+        // feature is both packed and loose
+        repo.delete_branch("feature").unwrap();
+        repo.delete_branch("topic").unwrap();
+        assert!(repo.delete_branch("topic").is_err());
+        assert!(repo.delete_branch("master").is_err());
+        assert!(repo.resolve_ref("refs/heads/master").is_ok());
+        assert!(repo.resolve_ref("refs/heads/feature").is_err());
+        assert!(repo.packed_refs().unwrap().get("refs/tags/v1.0").is_some());
+
+        let branches: Vec<_> = repo
+            .list_branches()
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(branches, ["loose-only", "master"]);
+    }
+
+    #[test]
+    fn create_and_delete_tags() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074";
+        let parent = "09ed861f6cd57e64a23811b30686bb4919a41674";
+        let tagger = Signature {
+            name: "Tagger".to_owned(),
+            email: "tagger@example.com".to_owned(),
+            time: 1700000000,
+            tz_offset: 60,
+            raw_time: None,
+        };
+
+        assert_eq!(repo.create_tag("v0.9", "HEAD^", None).unwrap(), parent);
+        assert_eq!(repo.resolve_ref("v0.9").unwrap(), parent);
+
+        let tag = repo
+            .create_tag("v2.0", "HEAD", Some((&tagger, "release 2.0\n")))
+            .unwrap();
+        assert_eq!(repo.resolve_ref("refs/tags/v2.0").unwrap(), tag);
+        assert!(repo.has_object(&tag));
+        match repo.load_object(&tag).unwrap() {
+            Object::Tag {
+                object,
+                typ,
+                tag,
+                tagger: Some(stored),
+                message,
+                ..
+            } => {
+                assert_eq!(object, head);
+                assert_eq!(typ, ObjectType::Commit);
+                assert_eq!(tag, "v2.0");
+                assert_eq!(stored, tagger);
+                assert_eq!(message, "release 2.0\n");
+            }
+            object => panic!("expected a tag, got {object:?}"),
+        }
+        assert_eq!(repo.rev_parse("v2.0^0").unwrap(), head);
+        assert!(repo.create_tag("v2.0", "HEAD", None).is_err());
+
+        let tags: Vec<_> = repo
+            .list_tags()
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(tags, ["light", "v0.9", "v1.0", "v2.0"]);
+
+        repo.delete_tag("v2.0").unwrap();
+        assert!(repo.resolve_ref("refs/tags/v2.0").is_err());
+        assert!(repo.has_object(&tag));
+        assert!(repo.delete_tag("v2.0").is_err());
+    }
+
+    #[test]
+    fn rev_parse_ancestors() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074";
+        let parent = "09ed861f6cd57e64a23811b30686bb4919a41674";
+        let root_commit = "25a7b65d7c4c638b508f306909cb719db11cb7c2";
+
+        assert_eq!(repo.rev_parse("HEAD").unwrap(), head);
+        assert_eq!(repo.rev_parse("HEAD^").unwrap(), parent);
+        assert_eq!(repo.rev_parse("master^1").unwrap(), parent);
+        assert_eq!(repo.rev_parse("HEAD~2").unwrap(), root_commit);
+        assert_eq!(repo.rev_parse("HEAD~").unwrap(), parent);
+        assert_eq!(repo.rev_parse("1e3e965^^").unwrap(), root_commit);
+        assert_eq!(repo.rev_parse("HEAD~1^").unwrap(), root_commit);
+        assert_eq!(repo.rev_parse("HEAD~0").unwrap(), head);
+        // the annotated tag is peeled to its commit
+        assert_eq!(repo.rev_parse("v1.0^0").unwrap(), root_commit);
+
+        for spec in ["HEAD~3", "HEAD^2", "v1.0^", "HEAD^x"] {
+            assert!(
+                matches!(
+                    repo.rev_parse(spec),
+                    Err(GitSyncError::UnknownRevision { .. })
+                ),
+                "{spec}"
+            );
+        }
+    }
+
+    #[test]
+    fn ls_files() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let entries = repo.ls_files(false).unwrap();
+        let paths: Vec<&[u8]> = entries.iter().map(|entry| entry.path.as_slice()).collect();
+        assert_eq!(paths, [&b"a.txt"[..], b"b.txt", b"dir/c.txt"]);
+        assert_eq!(
+            entries[2].to_string(),
+            "100644 79c53955ef856f16f2107446bc721c8879a1bd2e 0\tdir/c.txt"
+        );
+        assert!(repo.ls_files(true).unwrap().is_empty());
+
+        fs::remove_file(test_dir.root().join("b.txt")).unwrap();
+        let deleted = repo.ls_files(true).unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(
+            deleted[0].to_string(),
+            "100644 61780798228d17af2d34fce4cfbdf35556832472 0\tb.txt"
+        );
+    }
+
+    #[test]
+    fn read_tree_into_index() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let index_entries = |repo: &Repository| -> Vec<(Vec<u8>, String, String)> {
+            repo.ls_files(false)
+                .unwrap()
+                .into_iter()
+                .map(|entry| (entry.path.clone(), entry.mode_str(), entry.sha1()))
+                .collect()
+        };
+        let tree_entries = |repo: &Repository, tree: &str| -> Vec<(Vec<u8>, String, String)> {
+            repo.read_tree_recursive(tree)
+                .unwrap()
+                .into_iter()
+                .map(|entry| (entry.name.clone(), entry.mode.clone(), entry.sha1()))
+                .collect()
+        };
+
+        let root_commit = "25a7b65d7c4c638b508f306909cb719db11cb7c2";
+        repo.read_tree_into_index(root_commit).unwrap();
+        assert_eq!(index_entries(&repo), tree_entries(&repo, root_commit));
+        let index = repo.read_index().unwrap();
+        assert!(index
+            .entries
+            .iter()
+            .all(|entry| entry.mtime.secs == 0 && entry.size == 0));
+
+        let head = repo.rev_parse("HEAD").unwrap();
+        repo.read_tree_into_index(&head).unwrap();
+        assert_eq!(index_entries(&repo), tree_entries(&repo, &head));
+        assert_eq!(index_entries(&repo).len(), 3);
+        let Object::Commit { tree, .. } = repo.load_object(&head).unwrap() else {
+            panic!("HEAD is not a commit");
+        };
+        assert_eq!(repo.write_tree_from_index().unwrap(), tree);
+    }
+
+    #[test]
+    fn rev_parse_reflog() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074";
+        let parent = "09ed861f6cd57e64a23811b30686bb4919a41674";
+        let root_commit = "25a7b65d7c4c638b508f306909cb719db11cb7c2";
+
+        assert_eq!(repo.rev_parse("HEAD@{0}").unwrap(), head);
+        assert_eq!(repo.rev_parse("HEAD@{1}").unwrap(), parent);
+        assert_eq!(repo.rev_parse("master@{2}").unwrap(), root_commit);
+        assert_eq!(repo.rev_parse("@{1}").unwrap(), parent);
+        assert_eq!(repo.rev_parse("HEAD@{1}~1").unwrap(), root_commit);
+        assert!(matches!(
+            repo.rev_parse("HEAD@{3}"),
+            Err(GitSyncError::ReflogIndexOutOfRange {
+                index: 3,
+                len: 3,
+                ..
+            })
+        ));
+
+        // the fixture's commits are 100 seconds apart, the first at
+        // 2023-11-14 22:13:20 UTC
+        assert_eq!(repo.rev_parse("master@{@1700000150}").unwrap(), parent);
+        assert_eq!(
+            repo.rev_parse("HEAD@{2023-11-14 22:14:00}").unwrap(),
+            root_commit
+        );
+        assert_eq!(repo.rev_parse("HEAD@{now}").unwrap(), head);
+        assert!(matches!(
+            repo.rev_parse("HEAD@{2020-01-01}"),
+            Err(GitSyncError::ReflogTooShort { .. })
+        ));
+
+        assert!(matches!(
+            repo.rev_parse("light@{1}"),
+            Err(GitSyncError::NoReflog { .. })
+        ));
+        assert!(matches!(
+            repo.rev_parse("missing@{1}"),
+            Err(GitSyncError::RefNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn symbolic_ref() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074";
+        assert_eq!(
+            repo.read_symbolic_ref("HEAD").unwrap().as_deref(),
+            Some("refs/heads/master")
+        );
+
+        // the target doesn't need to exist
+        repo.write_symbolic_ref("HEAD", "refs/heads/other").unwrap();
+        assert_eq!(
+            repo.read_symbolic_ref("HEAD").unwrap().as_deref(),
+            Some("refs/heads/other")
+        );
+        assert_eq!(
+            fs::read_to_string(test_dir.root().join(".git/HEAD")).unwrap(),
+            "ref: refs/heads/other\n"
+        );
+        assert!(matches!(
+            repo.write_symbolic_ref("HEAD", "master"),
+            Err(GitSyncError::InvalidRef { .. })
+        ));
+
+        repo.write_symbolic_ref("HEAD", "refs/heads/master")
+            .unwrap();
+        repo.switch(head, false).unwrap();
+        assert_eq!(repo.read_symbolic_ref("HEAD").unwrap(), None);
+        repo.switch("master", false).unwrap();
+        assert_eq!(repo.current_branch().unwrap().as_deref(), Some("master"));
+    }
+
+    #[test]
+    fn read_objects_from_alternates() {
+        let shared_dir = test_dir("alternates_shared");
+        let shared = Repository::create_at(shared_dir.root()).unwrap();
+        let blob = |data: &[u8]| Object::Blob {
+            data: data.to_vec(),
+        };
+        let packed = blob(b"packed in the alternate\n").save(&shared).unwrap();
+        shared.repack(true).unwrap();
+        let loose = blob(b"loose in the alternate\n").save(&shared).unwrap();
+        let shared_objects = fs::canonicalize(shared.objects_dir()).unwrap();
+
+        let repo_dir = test_dir("alternates_repo");
+        let repo = Repository::create_at(repo_dir.root()).unwrap();
+        assert!(!repo.has_object(&loose));
+        fs::create_dir_all(repo.objects_dir().join("info")).unwrap();
+        fs::write(
+            repo.objects_dir().join("info/alternates"),
+            format!("# shared objects\n{}\n", shared_objects.display()),
+        )
+        .unwrap();
+
+        // alternates are read when the repository is opened
+        let repo = Repository::new(repo_dir.root()).unwrap();
+        for sha1 in [&packed, &loose] {
+            assert!(repo.has_object(sha1));
+            assert_eq!(repo.load_object(sha1).unwrap().sha1(), *sha1);
+        }
+        let mut content = Vec::new();
+        repo.open_object_reader(&loose)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, b"loose in the alternate\n");
+        assert_eq!(repo.rev_parse(&loose[..8]).unwrap(), loose);
+
+        // new objects are only written to the repository itself
+        assert_eq!(repo.loose_objects().unwrap().count(), 0);
+        let own = blob(b"own\n").save(&repo).unwrap();
+        assert!(repo.loose_object_path(&own).is_file());
+        assert!(!shared.has_object(&own));
+
+        let env_dir = test_dir("alternates_env");
+        let objects = fs::canonicalize(env_dir.root()).unwrap().join("objects");
+        let var = |name: &str| match name {
+            "GIT_OBJECT_DIRECTORY" => Some(objects.clone().into_os_string()),
+            "GIT_ALTERNATE_OBJECT_DIRECTORIES" => Some(shared_objects.clone().into_os_string()),
+            _ => None,
+        };
+        let repo = Repository::open_with_env(
+            repo_dir.root().join(".git").into(),
+            Some(repo_dir.root().into()),
+            var,
+        )
+        .unwrap();
+        assert_eq!(repo.objects_dir(), objects);
+        assert!(repo.has_object(&loose));
+        assert!(!repo.has_object(&own));
+        let moved = blob(b"moved\n").save(&repo).unwrap();
+        assert!(objects.join(&moved[..2]).join(&moved[2..]).is_file());
+    }
+
+    #[test]
+    fn load_packs_again_only_when_changed() {
+        let repo_path = test_dir("load_packs_again_only_when_changed");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let blob = |data: &[u8]| Object::Blob {
+            data: data.to_vec(),
+        };
+        let first = blob(b"first\n").save(&repo).unwrap();
+        repo.repack(true).unwrap();
+        assert!(repo.has_object(&first));
+
+        let loaded = repo.packs().unwrap();
+        let missing = blob(b"missing\n");
+        assert!(!repo.has_object(&missing.sha1()));
+        assert!(std::sync::Arc::ptr_eq(&loaded, &repo.packs().unwrap()));
+
+        // a pack written by another process is found after a miss
+        let other = Repository::new(repo_path.root()).unwrap();
+        missing.save(&other).unwrap();
+        other.repack(true).unwrap();
+        assert!(repo.has_object(&missing.sha1()));
+        assert_eq!(
+            repo.load_object(&missing.sha1()).unwrap().sha1(),
+            missing.sha1()
+        );
+    }
+
+    #[test]
+    fn write_object_once() {
+        let repo_path = test_dir("write_object_once");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let blob = Object::Blob {
+            data: b"hello\n".to_vec(),
+        };
+        let sha1 = repo.write_object(&blob).unwrap();
+        let path = repo.path(Repository::hash_to_object(&sha1));
+        let written = fs::read(&path).unwrap();
+        let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+
+        assert_eq!(repo.write_object(&blob).unwrap(), sha1);
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), mtime);
+        assert_eq!(fs::read(&path).unwrap(), written);
+
+        // existing files are trusted without reading them
+        fs::write(&path, b"garbage").unwrap();
+        blob.save(&repo).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"garbage");
+
+        // but an empty file is left by an interrupted write
+        fs::write(&path, b"").unwrap();
+        blob.save(&repo).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), written);
+    }
+
+    #[test]
+    fn check_object_without_writing() {
+        // what `hash-object --check` does
+        let repo_path = test_dir("check_object_without_writing");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let blob = Object::Blob {
+            data: b"snapshot\n".to_vec(),
+        };
+        let sha1 = blob.hash(repo.hash_algorithm());
+        assert!(!repo.has_object(&sha1));
+        assert_eq!(repo.count_loose_objects().unwrap().0, 0);
+
+        assert_eq!(repo.write_object(&blob).unwrap(), sha1);
+        let loose = repo.count_loose_objects().unwrap();
+        assert_eq!(loose.0, 1);
+
+        assert_eq!(blob.hash(repo.hash_algorithm()), sha1);
+        assert!(repo.has_object(&sha1));
+        assert_eq!(repo.count_loose_objects().unwrap(), loose);
+        let path = repo.path(Repository::hash_to_object(&sha1));
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn verify_unsigned_objects() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        for rev in ["HEAD", "v1.0"] {
+            let sha1 = repo.rev_parse(rev).unwrap();
+            assert_eq!(
+                repo.verify_signature(&sha1).unwrap(),
+                SignatureStatus::NotSigned
+            );
+        }
+    }
+
+    #[test]
+    fn read_object_by_revision() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074";
+        let parent = "09ed861f6cd57e64a23811b30686bb4919a41674";
+
+        let (sha1, object) = repo.read_object("master").unwrap();
+        assert_eq!(sha1, head);
+        let Object::Commit {
+            parents, message, ..
+        } = object
+        else {
+            panic!("master is not a commit: {object:?}");
+        };
+        assert_eq!(parents, [parent]);
+        assert_eq!(message, b"third commit\n");
+
+        assert_eq!(repo.read_object("1e3e965").unwrap().0, head);
+        assert_eq!(repo.read_object("HEAD~1").unwrap().0, parent);
+        let (_, tag) = repo.read_object("v1.0").unwrap();
+        assert_eq!(tag.object_type(), ObjectType::Tag);
+        assert!(repo.read_object("missing").is_err());
+
+        let packed = existing_test_repo("packed_objects");
+        let repo = Repository::new(packed.root()).unwrap();
+        let (sha1, object) = repo.read_object("HEAD").unwrap();
+        assert_eq!(object.object_type(), ObjectType::Commit);
+        assert_eq!(object.sha1(), sha1);
+    }
+
+    #[test]
+    fn read_packed_objects() {
+        let test_dir = existing_test_repo("packed_objects");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        assert_eq!(repo.loose_objects().unwrap().count(), 0);
+
+        let blob = "06fcdd77c9348567c50638b30d406500f521c304";
+        let mut content = Vec::new();
+        repo.open_object_reader(blob)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content.len(), 23);
+        assert_eq!(repo.object_type(blob).unwrap(), ObjectType::Blob);
+
+        let head = repo.resolve_ref("HEAD").unwrap();
+        assert_eq!(repo.walk_commits(&head).count(), 3);
+    }
+
+    #[test]
+    fn mmap_loose_objects() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let mut count = 0;
+        for sha1 in repo.loose_objects().unwrap() {
+            let map = repo.mmap_object(&sha1).unwrap();
+            let mapped = Object::deserialize_zlib_with(&map[..], repo.hash_algorithm()).unwrap();
+            let mut mapped_data = Vec::new();
+            mapped.serialize(&mut mapped_data).unwrap();
+            let mut buffered_data = Vec::new();
+            repo.load_object(&sha1)
+                .unwrap()
+                .serialize(&mut buffered_data)
+                .unwrap();
+            assert_eq!(mapped_data, buffered_data);
+            count += 1;
+        }
+        assert!(count > 0);
+
+        assert!(matches!(
+            repo.mmap_object("d1b3c0beb7a7dffd3a2a4e1cbb07c5b2a7c6e928"),
+            Err(GitSyncError::ObjectNotFound { .. })
+        ));
+        let empty = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+        let path = repo.path(Repository::hash_to_object(empty));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, b"").unwrap();
+        assert!(matches!(
+            repo.mmap_object(empty),
+            Err(GitSyncError::InvalidObjectHeader(_))
+        ));
+    }
+
+    #[test]
+    fn cache_objects() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap().with_cache(1024);
+        let head = repo.resolve_ref("HEAD").unwrap();
+        let commit = repo.load_object(&head).unwrap();
+
+        // the second read must not touch the object file
+        fs::remove_file(repo.path(Repository::hash_to_object(&head))).unwrap();
+        let cached = repo.load_object(&head).unwrap();
+        assert_eq!(cached.hash(repo.hash_algorithm()), head);
+        assert_eq!(repo.object_type(&head).unwrap(), commit.object_type());
+
+        let uncached = Repository::new(test_dir.root()).unwrap();
+        assert!(matches!(
+            uncached.load_object(&head),
+            Err(GitSyncError::ObjectNotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn check_object_existence() {
+        let test_dir = existing_test_repo("packed_objects");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        assert!(repo.has_object("06fcdd77c9348567c50638b30d406500f521c304"));
+        assert!(repo.has_object("06FCDD77"));
+        assert!(!repo.has_object("06fcdd77c9348567c50638b30d406500f521c305"));
+        assert!(!repo.has_object("d1b3c0beb7a7dffd3a2a4e1cbb07c5b2a7c6e928"));
+        assert!(!repo.has_object("not hex"));
+
+        let blob = Object::Blob {
+            data: b"loose\n".to_vec(),
+        };
+        let sha1 = blob.save(&repo).unwrap();
+        assert!(repo.has_object(&sha1));
+        assert!(repo.has_object(&sha1[..7]));
+    }
+
+    #[test]
+    fn read_ref_delta_objects() {
+        let test_dir = existing_test_repo("delta_pack");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let blob = "ee0ba3222acb4c84cbd8ddcd92ed4cfea7c90ec7";
+        let mut expected = Vec::new();
+        repo.open_object_reader(blob)
+            .unwrap()
+            .read_to_end(&mut expected)
+            .unwrap();
+
+        // only keep the pack with ref deltas, whose bases are in the same pack
+        let objects = test_dir.root().join(".git/objects");
+        for dir in fs::read_dir(&objects).unwrap() {
+            let dir = dir.unwrap().path();
+            if dir.file_name().unwrap() != "pack" {
+                fs::remove_dir_all(dir).unwrap();
+            }
+        }
+        let pack = objects.join("pack/pack-9d9506ca781239bd0307c9f2bf8a402b45c27a65");
+        fs::remove_file(pack.with_extension("idx")).unwrap();
+        assert_eq!(repo.loose_objects().unwrap().count(), 0);
+
+        let mut content = Vec::new();
+        repo.open_object_reader(blob)
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, expected);
+        let head = repo.resolve_ref("HEAD").unwrap();
+        assert_eq!(repo.read_tree_recursive(&head).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn reject_ref_delta_cycles() {
+        // two ref deltas which are each other's base
+        let test_dir = existing_test_repo("delta_cycle");
+        let repo = Repository::new(test_dir.root()).unwrap();
+        let cycle = repo.resolve_ref("refs/tags/cycle").unwrap();
+        assert!(matches!(
+            repo.load_object(&cycle),
+            Err(GitSyncError::InvalidPack(_))
+        ));
+    }
+
+    #[test]
+    fn find_from_git_dir_env() {
+        let repo_path = test_dir("find_from_git_dir_env");
+        let worktree_path = test_dir("find_from_git_work_tree_env");
+        Repository::create_at(repo_path.root()).unwrap();
+
+        let git_dir = fs::canonicalize(repo_path.root().join(".git")).unwrap();
+        let worktree = fs::canonicalize(worktree_path.root()).unwrap();
+
+        {
+            let _env = set_env(&[
+                ("GIT_DIR", Some(git_dir.as_os_str())),
+                ("GIT_WORK_TREE", Some(worktree.as_os_str())),
+            ]);
+            let repo = Repository::find(RepoKind::Git).unwrap();
+            assert_eq!(repo.gitdir_root(), git_dir);
+            assert_eq!(repo.worktree_root().unwrap(), worktree);
+        }
+
+        {
+            let _env = set_env(&[
+                ("GIT_DIR", Some(git_dir.as_os_str())),
+                ("GIT_WORK_TREE", None),
+            ]);
+            let repo = Repository::find(RepoKind::Git).unwrap();
+            assert_eq!(repo.gitdir_root(), git_dir);
+            assert_eq!(
+                repo.worktree_root().unwrap(),
+                std::env::current_dir().unwrap()
+            );
+        }
+
+        {
+            let _env = set_env(&[
+                ("GIT_DIR", Some(worktree.as_os_str())),
+                ("GIT_WORK_TREE", None),
+            ]);
+            assert!(Repository::find(RepoKind::Git).is_none());
+        }
     }
 
     #[test]
@@ -264,7 +6372,7 @@ mod test {
             "./Cargo.toml",
             "src/./",
         ] {
-            Repository::find_from(start_path).expect(&format!(
+            Repository::find_from(start_path, RepoKind::Git).expect(&format!(
                 "Could not find git repo staring at {start_path:?}"
             ));
         }
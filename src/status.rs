@@ -0,0 +1,290 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::ErrorKind,
+    path::Path,
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    repository::{bytes_path, file_blob, file_mode, os_str_bytes},
+    GitSyncError, IgnoreRules, Index, IndexEntry, IndexTime, RepoKind, Repository, Result,
+};
+
+/// how a path differs between two states, e.g. HEAD and the index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Modified,
+    Deleted,
+}
+
+/// the differences between HEAD, the index and the worktree, created by
+/// [Repository::status].
+///
+/// All paths are relative to the worktree root and sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatusReport {
+    /// changes between HEAD and the index
+    pub staged: Vec<(Vec<u8>, Change)>,
+    /// changes between the index and the worktree. Files are never
+    /// [Change::Added] here, see `untracked`
+    pub unstaged: Vec<(Vec<u8>, Change)>,
+    /// files in the worktree that are not in the index. Directories without
+    /// any tracked files are listed once with a trailing `/`
+    pub untracked: Vec<Vec<u8>>,
+}
+
+impl StatusReport {
+    pub(crate) fn new(repo: &Repository) -> Result<Self> {
+        let root = repo.worktree_root()?;
+        let index = repo.read_index()?;
+
+        let head = match repo.resolve_ref("HEAD") {
+            Ok(head) => repo.read_tree_recursive(&head)?,
+            Err(GitSyncError::RefNotFound { .. }) => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        let head: BTreeMap<&[u8], (u32, &[u8])> = head
+            .iter()
+            .map(|entry| {
+                let mode = u32::from_str_radix(&entry.mode, 8).unwrap_or_default();
+                (entry.name.as_slice(), (mode, entry.sha.as_slice()))
+            })
+            .collect();
+        let staged_entries: BTreeMap<&[u8], &IndexEntry> = index
+            .entries
+            .iter()
+            .filter(|entry| entry.stage() == 0)
+            .map(|entry| (entry.path.as_slice(), entry))
+            .collect();
+
+        let mut report = StatusReport::default();
+
+        let paths: BTreeSet<&[u8]> = head.keys().chain(staged_entries.keys()).copied().collect();
+        for path in paths {
+            let change = match (head.get(path), staged_entries.get(path)) {
+                (None, Some(_)) => Change::Added,
+                (Some(_), None) => Change::Deleted,
+                (Some(&(mode, sha)), Some(entry)) if entry.mode != mode || entry.sha != sha => {
+                    Change::Modified
+                }
+                _ => continue,
+            };
+            report.staged.push((path.to_vec(), change));
+        }
+
+        let index_time = index_mtime(&repo.gitdir_root().join("index"))?;
+        let mut unmerged = None;
+        for entry in &index.entries {
+            if entry.stage() != 0 {
+                // report each conflicted path once
+                if unmerged != Some(&entry.path) {
+                    report.unstaged.push((entry.path.clone(), Change::Modified));
+                    unmerged = Some(&entry.path);
+                }
+                continue;
+            }
+            if let Some(change) = worktree_change(repo, root, entry, index_time)? {
+                report.unstaged.push((entry.path.clone(), change));
+            }
+        }
+
+        let ignore = IgnoreRules::load(repo)?;
+        collect_untracked(&index, &ignore, root, &[], &mut report.untracked)?;
+
+        Ok(report)
+    }
+
+    /// whether there are no changes at all
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty() && self.unstaged.is_empty() && self.untracked.is_empty()
+    }
+}
+
+/// the modification time of the index file, if it exists
+fn index_mtime(path: &Path) -> Result<Option<IndexTime>> {
+    let modified = match fs::metadata(path) {
+        Ok(metadata) => metadata.modified()?,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok(Some(IndexTime {
+        secs: since_epoch.as_secs() as u32,
+        nanos: since_epoch.subsec_nanos(),
+    }))
+}
+
+/// compares the index `entry` with the file in the worktree.
+///
+/// Matching stat data is only trusted if the file was modified before the
+/// index was written. Otherwise the file could have changed in the same
+/// time stamp granularity after it was staged ("racy git"), so its content
+/// is hashed.
+fn worktree_change(
+    repo: &Repository,
+    root: &Path,
+    entry: &IndexEntry,
+    index_time: Option<IndexTime>,
+) -> Result<Option<Change>> {
+    let path = root.join(bytes_path(&entry.path));
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(metadata) if metadata.is_dir() => return Ok(Some(Change::Deleted)),
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Some(Change::Deleted)),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mode = file_mode(&metadata);
+    if entry.mode != mode {
+        return Ok(Some(Change::Modified));
+    }
+
+    let before_index = index_time.is_some_and(|index_time| {
+        (entry.mtime.secs, entry.mtime.nanos) < (index_time.secs, index_time.nanos)
+    });
+    if before_index && entry.matches_metadata(&metadata) {
+        return Ok(None);
+    }
+
+    let sha1 = file_blob(&path, &metadata)?.hash(repo.hash_algorithm());
+    if sha1 == entry.sha1() {
+        Ok(None)
+    } else {
+        Ok(Some(Change::Modified))
+    }
+}
+
+/// adds the files below `dir` that are neither in the index nor ignored to
+/// `untracked`
+fn collect_untracked(
+    index: &Index,
+    ignore: &IgnoreRules,
+    dir: &Path,
+    name: &[u8],
+    untracked: &mut Vec<Vec<u8>>,
+) -> Result<()> {
+    let mut children = Vec::new();
+    for dir_entry in fs::read_dir(dir)? {
+        let dir_entry = dir_entry?;
+        if RepoKind::is_dir_name(&dir_entry.file_name()) {
+            continue;
+        }
+        let mut child = name.to_vec();
+        if !child.is_empty() {
+            child.push(b'/');
+        }
+        child.extend(os_str_bytes(&dir_entry.file_name()));
+        children.push((child, dir_entry.path(), dir_entry.file_type()?.is_dir()));
+    }
+    children.sort();
+
+    for (child, path, is_dir) in children {
+        let tracked = index.contains(&child);
+        let tracked_below = index.contains_below(&child);
+        if ignore.matches(&child, is_dir) && !tracked && !tracked_below {
+            continue;
+        }
+        if !is_dir {
+            if !tracked {
+                untracked.push(child);
+            }
+        } else if tracked_below {
+            collect_untracked(index, ignore, &path, &child, untracked)?;
+        } else if contains_files(ignore, &path, &child)? {
+            let mut child = child;
+            child.push(b'/');
+            untracked.push(child);
+        }
+    }
+    Ok(())
+}
+
+/// whether there is any file below the directory `path` that is not
+/// ignored. `name` is the path relative to the worktree root
+fn contains_files(ignore: &IgnoreRules, path: &Path, name: &[u8]) -> Result<bool> {
+    for dir_entry in fs::read_dir(path)? {
+        let dir_entry = dir_entry?;
+        let child = [name, b"/", &os_str_bytes(&dir_entry.file_name())].concat();
+        let is_dir = dir_entry.file_type()?.is_dir();
+        if ignore.matches(&child, is_dir) {
+            continue;
+        }
+        if !is_dir || contains_files(ignore, &dir_entry.path(), &child)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use test_dir::DirBuilder;
+
+    use super::{Change, StatusReport};
+    use crate::test_utils::{existing_test_repo, test_dir};
+    use crate::Repository;
+
+    #[test]
+    fn status_of_fixture() {
+        let test_dir = existing_test_repo("status_changes");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let report = repo.status().unwrap();
+        assert_eq!(
+            report,
+            StatusReport {
+                staged: vec![
+                    (b"b.txt".to_vec(), Change::Modified),
+                    (b"new.txt".to_vec(), Change::Added),
+                ],
+                unstaged: vec![
+                    (b"a.txt".to_vec(), Change::Modified),
+                    (b"dir/c.txt".to_vec(), Change::Deleted),
+                ],
+                untracked: vec![b"extra/".to_vec(), b"untracked.txt".to_vec()],
+            }
+        );
+    }
+
+    #[test]
+    fn status_after_add() {
+        let repo_path = test_dir("status_after_add");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        assert!(repo.status().unwrap().is_clean());
+
+        fs::write(repo_path.root().join("foo.txt"), "hello\n").unwrap();
+        assert_eq!(repo.status().unwrap().untracked, [b"foo.txt".to_vec()]);
+
+        repo.add(&["foo.txt"]).unwrap();
+        let report = repo.status().unwrap();
+        assert_eq!(report.staged, [(b"foo.txt".to_vec(), Change::Added)]);
+        assert!(report.unstaged.is_empty());
+        assert!(report.untracked.is_empty());
+    }
+
+    #[test]
+    fn status_hides_ignored_files() {
+        let repo_path = test_dir("status_hides_ignored_files");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+
+        fs::write(
+            root.join(".gitignore"),
+            "*.log
+build/
+",
+        )
+        .unwrap();
+        fs::write(root.join("debug.log"), "").unwrap();
+        fs::create_dir_all(root.join("build")).unwrap();
+        fs::write(root.join("build/out"), "").unwrap();
+        fs::create_dir_all(root.join("logs")).unwrap();
+        fs::write(root.join("logs/a.log"), "").unwrap();
+
+        assert_eq!(repo.status().unwrap().untracked, [b".gitignore".to_vec()]);
+    }
+}
@@ -1,15 +1,23 @@
 use std::{
+    env,
+    ffi::{OsStr, OsString},
     fs,
-    path::PathBuf,
-    sync::Mutex,
-    thread::sleep,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    thread::{self, sleep},
     time::{Duration, SystemTime},
 };
 
 use fs_extra::dir::{self, CopyOptions};
 use test_dir::{DirBuilder, TestDir};
 
+use crate::transport::{read_pkt, write_pkt, Packet};
+use crate::Repository;
+
 static TEST_DIR_MUTEX: Mutex<()> = Mutex::new(());
+static ENV_MUTEX: Mutex<()> = Mutex::new(());
 
 pub fn test_dir(name: &str) -> TestDir {
     let _lock = TEST_DIR_MUTEX.lock();
@@ -36,8 +44,8 @@ pub fn existing_test_repo(name: &str) -> TestDir {
     )
     .expect("Failed to copy existing repo to temp test location");
 
-    for git_dir in dir::get_dir_content(test_dir.root())
-        .expect("Failed to read created test dir")
+    let content = dir::get_dir_content(test_dir.root()).expect("Failed to read created test dir");
+    for git_dir in content
         .directories
         .iter()
         .filter(|it| it.as_str().ends_with("git-sync-repo"))
@@ -48,9 +56,205 @@ pub fn existing_test_repo(name: &str) -> TestDir {
         fs::rename(from, to).expect("Faileld to move git-sync-repo to .git in temp test dir");
     }
 
+    // `.git` files can't be committed either
+    for git_file in content
+        .files
+        .iter()
+        .filter(|it| it.as_str().ends_with("git-sync-file"))
+    {
+        let from = PathBuf::from(&git_file);
+        let to = from.with_file_name(".git");
+        fs::rename(from, to).expect("Failed to move git-sync-file to .git in temp test dir");
+    }
+
     test_dir
 }
 
+/// restores the environment variables changed by [set_env] on drop
+pub struct EnvGuard {
+    previous: Vec<(String, Option<OsString>)>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        for (key, value) in &self.previous {
+            match value {
+                Some(value) => env::set_var(key, value),
+                None => env::remove_var(key),
+            }
+        }
+    }
+}
+
+/// sets (or removes for `None`) environment variables until the returned
+/// guard is dropped.
+///
+/// Only one guard can exist at a time, so tests don't see each others
+/// environment.
+pub fn set_env(vars: &[(&str, Option<&OsStr>)]) -> EnvGuard {
+    let lock = ENV_MUTEX.lock().unwrap_or_else(|err| err.into_inner());
+    let previous = vars
+        .iter()
+        .map(|(key, value)| {
+            let previous = env::var_os(key);
+            match value {
+                Some(value) => env::set_var(key, value),
+                None => env::remove_var(key),
+            }
+            (key.to_string(), previous)
+        })
+        .collect();
+    EnvGuard {
+        previous,
+        _lock: lock,
+    }
+}
+
+/// serves the repository at `path` over git's smart http protocol version 2
+/// and returns its url.
+///
+/// `ls-refs` lists the refs of the repository and every `fetch` is answered
+/// with its only pack, regardless of the wants and haves. Pushes are
+/// accepted with version 0 of `git-receive-pack`.
+pub fn serve_http(path: &Path) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/repo", listener.local_addr().unwrap());
+    let path = path.to_path_buf();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+            handle_http(&path, stream);
+        }
+    });
+    url
+}
+
+fn handle_http(path: &Path, mut stream: TcpStream) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        if header.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap();
+            }
+        }
+    }
+    let mut request = vec![0; content_length];
+    reader.read_exact(&mut request).unwrap();
+
+    let repo = Repository::new(path).unwrap();
+    let mut body = Vec::new();
+    let content_type = if request_line.contains("service=git-receive-pack") {
+        write_pkt(&mut body, b"# service=git-receive-pack\n");
+        body.extend_from_slice(b"0000");
+        let caps = "report-status delete-refs ofs-delta";
+        let refs: Vec<_> = repo
+            .refs()
+            .unwrap()
+            .into_iter()
+            .filter(|(name, _)| name != "HEAD")
+            .collect();
+        if refs.is_empty() {
+            let line = format!("{} capabilities^{{}}\0{caps}\n", repo.null_sha1());
+            write_pkt(&mut body, line.as_bytes());
+        }
+        for (i, (name, sha1)) in refs.iter().enumerate() {
+            let line = if i == 0 {
+                format!("{sha1} {name}\0{caps}\n")
+            } else {
+                format!("{sha1} {name}\n")
+            };
+            write_pkt(&mut body, line.as_bytes());
+        }
+        body.extend_from_slice(b"0000");
+        "application/x-git-receive-pack-advertisement"
+    } else if request_line.contains("/git-receive-pack ") {
+        let mut reader = request.as_slice();
+        let mut commands = Vec::new();
+        while let Packet::Data(line) = read_pkt(&mut reader).unwrap() {
+            let line = String::from_utf8(line).unwrap();
+            let command = line.split('\0').next().unwrap().trim_end().to_owned();
+            commands.push(command);
+        }
+        if !reader.is_empty() {
+            repo.store_pack(reader).unwrap();
+        }
+        write_pkt(&mut body, b"unpack ok\n");
+        for command in commands {
+            let mut parts = command.split(' ');
+            let (old, new, name) = (
+                parts.next().unwrap(),
+                parts.next().unwrap(),
+                parts.next().unwrap(),
+            );
+            let line = match repo.update_ref(name, new, Some(old)) {
+                Ok(()) => format!("ok {name}\n"),
+                Err(err) => format!("ng {name} {err}\n"),
+            };
+            write_pkt(&mut body, line.as_bytes());
+        }
+        body.extend_from_slice(b"0000");
+        "application/x-git-receive-pack-result"
+    } else if request_line.starts_with("GET ") {
+        write_pkt(&mut body, b"# service=git-upload-pack\n");
+        body.extend_from_slice(b"0000");
+        for line in [
+            "version 2",
+            "agent=test",
+            "ls-refs",
+            "fetch",
+            "object-format=sha1",
+        ] {
+            write_pkt(&mut body, format!("{line}\n").as_bytes());
+        }
+        body.extend_from_slice(b"0000");
+        "application/x-git-upload-pack-advertisement"
+    } else if request.windows(15).any(|w| w == b"command=ls-refs") {
+        for (name, sha1) in repo.refs().unwrap() {
+            let mut line = format!("{sha1} {name}");
+            if let Some(target) = repo.read_symbolic_ref(&name).unwrap() {
+                line.push_str(&format!(" symref-target:{target}"));
+            }
+            if let Ok(crate::Object::Tag { object, .. }) = repo.load_object(&sha1) {
+                line.push_str(&format!(" peeled:{object}"));
+            }
+            write_pkt(&mut body, format!("{line}\n").as_bytes());
+        }
+        body.extend_from_slice(b"0000");
+        "application/x-git-upload-pack-result"
+    } else {
+        let pack = fs::read_dir(repo.path("objects/pack"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|ext| ext == "pack"))
+            .unwrap();
+        write_pkt(&mut body, b"packfile\n");
+        for chunk in fs::read(pack).unwrap().chunks(1000) {
+            write_pkt(&mut body, &[&[1], chunk].concat());
+        }
+        body.extend_from_slice(b"0000");
+        "application/x-git-upload-pack-result"
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )
+    .unwrap();
+    stream.write_all(&body).unwrap();
+}
+
 fn get_sys_time_in_secs() -> u64 {
     match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
         Ok(n) => n.as_secs(),
@@ -0,0 +1,268 @@
+use crate::{GitSyncError, Result};
+
+const DAY: i64 = 24 * 60 * 60;
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// parses a time stamp as stored in commits and tags, e.g.
+/// `1700000000 +0100`, into seconds since the unix epoch and the timezone
+/// offset in minutes east of UTC
+pub fn parse_git_time(time: &str) -> Result<(i64, i32)> {
+    let invalid = || GitSyncError::InvalidDate(time.to_owned());
+    let (seconds, tz) = time.split_once(' ').ok_or_else(invalid)?;
+    let seconds = seconds.parse().map_err(|_| invalid())?;
+    let tz = parse_offset(tz)
+        .filter(|_| tz.len() == 5)
+        .ok_or_else(invalid)?;
+    Ok((seconds, tz))
+}
+
+/// formats a time stamp like [parse_git_time] expects it, e.g.
+/// `1700000000 -0830` for an offset of -510 minutes
+pub fn format_git_time(seconds: i64, tz_offset: i32) -> String {
+    let sign = if tz_offset < 0 { '-' } else { '+' };
+    let offset = tz_offset.abs();
+    format!("{seconds} {sign}{:02}{:02}", offset / 60, offset % 60)
+}
+
+/// parses a date in one of the formats git accepts for `GIT_AUTHOR_DATE` and
+/// `GIT_COMMITTER_DATE`:
+///
+/// - git's own format, see [parse_git_time], optionally with a leading `@`
+/// - `@<seconds>` in UTC
+/// - ISO 8601, e.g. `2023-11-14T22:13:20+01:00` or `2023-11-14 22:13:20`
+/// - RFC 2822, e.g. `Tue, 14 Nov 2023 22:13:20 +0100`
+///
+/// Dates without a timezone are in UTC.
+pub fn parse_human_date(date: &str) -> Result<(i64, i32)> {
+    let date = date.trim();
+    let unix = date.strip_prefix('@');
+    let parsed = parse_git_time(unix.unwrap_or(date))
+        .ok()
+        .or_else(|| Some((unix?.parse().ok()?, 0)))
+        .or_else(|| parse_iso_date(date))
+        .or_else(|| parse_rfc2822_date(date));
+    parsed.ok_or_else(|| GitSyncError::InvalidDate(date.to_owned()))
+}
+
+/// parses a date like git does for `HEAD@{<date>}` and returns it as seconds
+/// since the unix epoch. `now` is the current time, used for relative dates.
+///
+/// Accepted are the formats of [parse_human_date], `now`, `yesterday` and
+/// relative dates like `3.days.ago` or `2 hours ago`.
+pub(crate) fn parse_date(date: &str, now: i64) -> Option<i64> {
+    if let Ok((seconds, _)) = parse_human_date(date) {
+        return Some(seconds);
+    }
+    match date.trim() {
+        "now" => Some(now),
+        "yesterday" => Some(now - DAY),
+        date => parse_relative_date(date, now),
+    }
+}
+
+/// `YYYY-MM-DD`, optionally followed by ` HH:MM[:SS]` or `THH:MM[:SS]` and
+/// a timezone like `Z`, `+01:00` or `+0100`
+fn parse_iso_date(date: &str) -> Option<(i64, i32)> {
+    let (day, time) = match date.split_once([' ', 'T']) {
+        Some((day, time)) => (day, Some(time.trim_start())),
+        None => (date, None),
+    };
+
+    let mut parts = day.splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    let days = days_from_civil(year, month, day)?;
+
+    let (seconds, tz) = match time {
+        Some(time) => {
+            let tz_start = time.find(['Z', '+', '-']).unwrap_or(time.len());
+            let (clock, tz) = time.split_at(tz_start);
+            let tz = match tz {
+                "" | "Z" => 0,
+                tz => parse_offset(&tz.replace(':', ""))?,
+            };
+            (parse_clock(clock.trim_end())?, tz)
+        }
+        None => (0, 0),
+    };
+    Some((timestamp(days, seconds, tz)?, tz))
+}
+
+/// `[<weekday>, ]DD Mon YYYY HH:MM[:SS] +HHMM`, as used in emails
+fn parse_rfc2822_date(date: &str) -> Option<(i64, i32)> {
+    let date = date.split_once(", ").map_or(date, |(_, date)| date);
+    let words: Vec<&str> = date.split_whitespace().collect();
+    let [day, month, year, clock, tz] = words[..] else {
+        return None;
+    };
+    let month = MONTHS
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(month))?;
+    let days = days_from_civil(year.parse().ok()?, month as i64 + 1, day.parse().ok()?)?;
+    let tz = parse_offset(tz).filter(|_| tz.len() == 5)?;
+    Some((timestamp(days, parse_clock(clock)?, tz)?, tz))
+}
+
+/// the seconds since the unix epoch of `seconds` after midnight of the day
+/// `days` after 1970-01-01 in the timezone `tz`, unless they overflow
+fn timestamp(days: i64, seconds: i64, tz: i32) -> Option<i64> {
+    days.checked_mul(DAY)?
+        .checked_add(seconds)?
+        .checked_sub(i64::from(tz) * 60)
+}
+
+/// `HH:MM[:SS]` as seconds since midnight
+fn parse_clock(clock: &str) -> Option<i64> {
+    let mut parts = clock.splitn(3, ':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next()?.parse().ok()?;
+    let seconds: i64 = parts.next().map_or(Some(0), |s| s.parse().ok())?;
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    Some(hours * 3600 + minutes * 60 + seconds)
+}
+
+/// a timezone offset like `+0100`, `-0830` or `+01` in minutes east of UTC
+fn parse_offset(tz: &str) -> Option<i32> {
+    let sign = match tz.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let digits = &tz[1..];
+    if !matches!(digits.len(), 2 | 4) || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits.get(2..).map_or(Some(0), |m| m.parse().ok())?;
+    if minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// `<n> <unit> ago` with `.` or spaces between the words, e.g. `2.weeks.ago`
+fn parse_relative_date(date: &str, now: i64) -> Option<i64> {
+    let words: Vec<&str> = date
+        .split(['.', ' '])
+        .filter(|word| !word.is_empty())
+        .collect();
+    let [n, unit, "ago"] = words[..] else {
+        return None;
+    };
+    let n: i64 = n.parse().ok()?;
+    let unit = match unit.strip_suffix('s').unwrap_or(unit) {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => DAY,
+        "week" => 7 * DAY,
+        "month" => 30 * DAY,
+        "year" => 365 * DAY,
+        _ => return None,
+    };
+    now.checked_sub(n.checked_mul(unit)?)
+}
+
+/// the number of days from 1970-01-01 to the date in the proleptic
+/// gregorian calendar, if it is a valid date that does not overflow
+fn days_from_civil(year: i64, month: i64, day: i64) -> Option<i64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // shift the year to start in march, so the leap day is at its end
+    let year = if month <= 2 {
+        year.checked_sub(1)?
+    } else {
+        year
+    };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era.checked_mul(146_097)?.checked_add(day_of_era - 719_468)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_git_time, parse_date, parse_git_time, parse_human_date};
+
+    #[test]
+    fn round_trip_git_time() {
+        for (seconds, tz) in [
+            (1_700_000_000, 60),
+            (1_700_000_000, -510),
+            (0, 0),
+            (1_234_567_890, -30),
+            (-86_400, 345),
+        ] {
+            let formatted = format_git_time(seconds, tz);
+            assert_eq!(parse_git_time(&formatted).unwrap(), (seconds, tz));
+        }
+        assert_eq!(format_git_time(1_700_000_000, -510), "1700000000 -0830");
+        assert_eq!(format_git_time(1_700_000_000, -30), "1700000000 -0030");
+
+        for invalid in [
+            "1700000000",
+            "1700000000 0100",
+            "x +0100",
+            "1 +01",
+            "1 +0160",
+        ] {
+            assert!(parse_git_time(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_human_dates() {
+        let now = 1_700_000_000;
+        assert_eq!(parse_human_date("1700000000 -0830").unwrap(), (now, -510));
+        assert_eq!(parse_human_date("@1700000000 +0100").unwrap(), (now, 60));
+        assert_eq!(parse_human_date("@1700000000").unwrap(), (now, 0));
+
+        assert_eq!(parse_human_date("1970-01-01").unwrap(), (0, 0));
+        assert_eq!(parse_human_date("2000-03-01").unwrap(), (951_868_800, 0));
+        assert_eq!(parse_human_date("2023-11-14 22:13:20").unwrap(), (now, 0));
+        assert_eq!(parse_human_date("2023-11-14T22:13:20Z").unwrap(), (now, 0));
+        assert_eq!(
+            parse_human_date("2023-11-14T23:13:20+01:00").unwrap(),
+            (now, 60)
+        );
+        assert_eq!(
+            parse_human_date("2023-11-14 13:43:20 -0830").unwrap(),
+            (now, -510)
+        );
+        assert_eq!(
+            parse_human_date("Tue, 14 Nov 2023 23:13:20 +0100").unwrap(),
+            (now, 60)
+        );
+        assert_eq!(
+            parse_human_date("14 Nov 2023 13:43:20 -0830").unwrap(),
+            (now, -510)
+        );
+
+        for invalid in ["2023-13-01", "14 Foo 2023 00:00 +0000", "last tuesday"] {
+            assert!(parse_human_date(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_dates() {
+        let now = 1_700_000_000;
+        assert_eq!(parse_date("2023-11-14 22:13:20", now), Some(now));
+        assert_eq!(parse_date("now", now), Some(now));
+        assert_eq!(parse_date("yesterday", now), Some(now - 86_400));
+        assert_eq!(parse_date("2.hours.ago", now), Some(now - 7200));
+        assert_eq!(parse_date("1 week ago", now), Some(now - 604_800));
+
+        assert_eq!(parse_date("2.fortnights.ago", now), None);
+        assert_eq!(parse_date("tomorrow", now), None);
+        assert_eq!(parse_date("99999999999999 years ago", now), None);
+        assert_eq!(parse_date(&format!("{} seconds ago", i64::MIN), now), None);
+        assert_eq!(parse_date("9223372036854775807-01-01", now), None);
+    }
+}
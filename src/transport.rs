@@ -0,0 +1,1073 @@
+use std::{
+    collections::HashSet,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use crate::{write_delta_pack, write_pack, GitSyncError, Object, Repository, Result};
+
+/// a ref advertised by a remote repository
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteRef {
+    /// the full name, e.g. `refs/heads/main` or `HEAD`
+    pub name: String,
+    pub sha1: String,
+    /// the ref a symbolic ref like `HEAD` points to
+    pub symref_target: Option<String>,
+    /// the object an annotated tag points to
+    pub peeled: Option<String>,
+}
+
+/// a remote-tracking ref changed by [Repository::fetch]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchedRef {
+    /// the full name of the remote-tracking ref, e.g.
+    /// `refs/remotes/origin/main`
+    pub name: String,
+    /// `None` if the ref was created
+    pub old: Option<String>,
+    pub new: String,
+}
+
+/// a connection to a remote repository, see [connect]
+pub trait Transport {
+    /// lists `HEAD`, the branches and the tags of the remote, with the
+    /// target of `HEAD` and the peeled objects of annotated tags
+    fn ls_refs(&self) -> Result<Vec<RemoteRef>>;
+
+    /// copies `wants` and everything reachable from them into `repo`.
+    ///
+    /// `haves` are commits `repo` already has, whose history is not copied
+    /// again.
+    fn fetch(&self, repo: &Repository, wants: &[String], haves: &[String]) -> Result<()>;
+
+    /// sends the objects the remote is missing and points its ref `name` to
+    /// the local commit `new`. Returns the sha1 the ref pointed to before,
+    /// `None` if it is new.
+    ///
+    /// Unless `force` is set, only fast-forwards are allowed.
+    fn push(&self, repo: &Repository, name: &str, new: &str, force: bool)
+        -> Result<Option<String>>;
+}
+
+/// opens a connection to the remote repository at `url`, depending on its
+/// scheme.
+///
+/// `http://` and `https://` urls use [HttpTransport]. `file://` urls and
+/// urls without a scheme are paths to a local repository and use
+/// [LocalTransport].
+pub(crate) fn connect(url: &str) -> Result<Box<dyn Transport>> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        return Ok(Box::new(HttpTransport::connect(url)?));
+    }
+    if let Some(path) = url.strip_prefix("file://") {
+        return Ok(Box::new(LocalTransport::open(path)?));
+    }
+    match url.split_once("://") {
+        Some((scheme, _)) => Err(GitSyncError::Transport(format!(
+            "unsupported url scheme {scheme:?}"
+        ))),
+        None => Ok(Box::new(LocalTransport::open(url)?)),
+    }
+}
+
+/// a connection to a remote repository over http(s), speaking version 2 of
+/// git's smart protocol with `git-upload-pack`
+#[derive(Debug)]
+pub struct HttpTransport {
+    /// the url of the repository without a trailing `/`
+    url: String,
+    agent: ureq::Agent,
+    /// the capabilities the server advertised
+    capabilities: Vec<String>,
+}
+
+/// a single pkt-line of the smart protocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Packet {
+    /// `0000`, ends a message
+    Flush,
+    /// `0001`, separates the sections of a message
+    Delim,
+    /// `0002`, ends a response in stateless connections
+    ResponseEnd,
+    Data(Vec<u8>),
+}
+
+const USER_AGENT: &str = concat!("gitsync/", env!("CARGO_PKG_VERSION"));
+
+/// the longest pkt-line, including its 4 byte length
+const MAX_PKT_LEN: usize = 65520;
+
+impl HttpTransport {
+    /// requests the capabilities of the repository at `url` and checks that
+    /// it speaks protocol version 2
+    pub fn connect(url: &str) -> Result<Self> {
+        let url = url.trim_end_matches('/').to_owned();
+        let agent = ureq::AgentBuilder::new().user_agent(USER_AGENT).build();
+
+        let response = agent
+            .get(&format!("{url}/info/refs?service=git-upload-pack"))
+            .set("Git-Protocol", "version=2")
+            .call()
+            .map_err(transport_error)?;
+        let mut reader = BufReader::new(response.into_reader());
+
+        // servers send the service line of protocol version 0 first, even
+        // if they answer with version 2
+        let mut first = read_pkt(&mut reader)?;
+        if let Packet::Data(line) = &first {
+            if line.starts_with(b"# service=") {
+                expect_flush(&mut reader)?;
+                first = read_pkt(&mut reader)?;
+            }
+        }
+        if first != Packet::Data(b"version 2\n".to_vec()) {
+            return Err(GitSyncError::Transport(
+                "server does not support protocol version 2".to_owned(),
+            ));
+        }
+
+        let mut capabilities = Vec::new();
+        while let Packet::Data(line) = read_pkt(&mut reader)? {
+            capabilities.push(pkt_str(&line)?.to_owned());
+        }
+        let transport = Self {
+            url,
+            agent,
+            capabilities,
+        };
+        for command in ["ls-refs", "fetch"] {
+            if !transport.has_capability(command) {
+                return Err(GitSyncError::Transport(format!(
+                    "server does not support {command:?}"
+                )));
+            }
+        }
+        Ok(transport)
+    }
+
+    /// whether the server advertised `capability`, with or without a value
+    pub fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|advertised| {
+            advertised == capability
+                || advertised
+                    .strip_prefix(capability)
+                    .is_some_and(|value| value.starts_with('='))
+        })
+    }
+
+    /// requests a pack containing `wants` and everything reachable from them,
+    /// except for what is reachable from `haves`, and returns the raw pack.
+    ///
+    /// The negotiation ends after a single round, the server sends a pack
+    /// based on all `haves` right away.
+    pub fn fetch_pack(&self, wants: &[String], haves: &[String]) -> Result<Vec<u8>> {
+        let mut args = vec!["no-progress".to_owned(), "ofs-delta".to_owned()];
+        args.extend(wants.iter().map(|want| format!("want {want}")));
+        args.extend(haves.iter().map(|have| format!("have {have}")));
+        args.push("done".to_owned());
+        let mut reader = self.command("fetch", &args)?;
+
+        // the response consists of sections separated by delim packets.
+        // After `done` the server skips the acknowledgments, the pack is
+        // always the last section
+        loop {
+            let header = match read_pkt(&mut reader)? {
+                Packet::Data(header) => header,
+                packet => {
+                    return Err(GitSyncError::Transport(format!(
+                        "expected a section header, got {packet:?}"
+                    )))
+                }
+            };
+            if pkt_str(&header)? == "packfile" {
+                break;
+            }
+            while let Packet::Data(_) = read_pkt(&mut reader)? {}
+        }
+
+        let mut pack = Vec::new();
+        while let Packet::Data(data) = read_pkt(&mut reader)? {
+            // the first byte is the sideband channel
+            match data.split_first() {
+                Some((1, data)) => pack.extend_from_slice(data),
+                Some((2, _)) => {}
+                Some((3, message)) => {
+                    return Err(GitSyncError::Transport(
+                        String::from_utf8_lossy(message).trim_end().to_owned(),
+                    ))
+                }
+                _ => {
+                    return Err(GitSyncError::Transport(
+                        "invalid sideband packet".to_owned(),
+                    ))
+                }
+            }
+        }
+        Ok(pack)
+    }
+
+    /// the refs `git-receive-pack` advertises as `(name, sha1)` pairs, and
+    /// its capabilities
+    fn receive_pack_refs(&self) -> Result<(Vec<(String, String)>, Vec<String>)> {
+        let response = self
+            .agent
+            .get(&format!("{}/info/refs?service=git-receive-pack", self.url))
+            .call()
+            .map_err(transport_error)?;
+        let mut reader = BufReader::new(response.into_reader());
+
+        let mut packet = read_pkt(&mut reader)?;
+        if let Packet::Data(line) = &packet {
+            if line.starts_with(b"# service=") {
+                expect_flush(&mut reader)?;
+                packet = read_pkt(&mut reader)?;
+            }
+        }
+
+        let mut refs = Vec::new();
+        let mut capabilities = Vec::new();
+        while let Packet::Data(line) = packet {
+            // the capabilities follow the first ref after a NUL byte
+            let mut parts = line.splitn(2, |&b| b == 0);
+            let line = pkt_str(parts.next().unwrap_or_default())?;
+            if let Some(advertised) = parts.next() {
+                capabilities = pkt_str(advertised)?
+                    .split(' ')
+                    .filter(|capability| !capability.is_empty())
+                    .map(str::to_owned)
+                    .collect();
+            }
+            let Some((sha1, name)) = line.split_once(' ') else {
+                return Err(GitSyncError::Transport(format!("invalid ref {line:?}")));
+            };
+            // an empty repository advertises its capabilities without refs
+            if name != "capabilities^{}" {
+                refs.push((name.to_owned(), sha1.to_owned()));
+            }
+            packet = read_pkt(&mut reader)?;
+        }
+        Ok((refs, capabilities))
+    }
+
+    /// sends `command` with `args` to `git-upload-pack` and returns the
+    /// response
+    fn command(&self, command: &str, args: &[impl AsRef<str>]) -> Result<impl Read> {
+        let mut body = Vec::new();
+        write_pkt(&mut body, format!("command={command}\n").as_bytes());
+        write_pkt(&mut body, format!("agent={USER_AGENT}\n").as_bytes());
+        body.extend_from_slice(b"0001");
+        for arg in args {
+            write_pkt(&mut body, format!("{}\n", arg.as_ref()).as_bytes());
+        }
+        body.extend_from_slice(b"0000");
+
+        let response = self
+            .agent
+            .post(&format!("{}/git-upload-pack", self.url))
+            .set("Git-Protocol", "version=2")
+            .set("Content-Type", "application/x-git-upload-pack-request")
+            .set("Accept", "application/x-git-upload-pack-result")
+            .send_bytes(&body)
+            .map_err(transport_error)?;
+        Ok(BufReader::new(response.into_reader()))
+    }
+}
+
+impl Transport for HttpTransport {
+    fn ls_refs(&self) -> Result<Vec<RemoteRef>> {
+        let args = [
+            "peel",
+            "symrefs",
+            "ref-prefix HEAD",
+            "ref-prefix refs/heads/",
+            "ref-prefix refs/tags/",
+        ];
+        let mut reader = self.command("ls-refs", &args)?;
+
+        let mut refs = Vec::new();
+        while let Packet::Data(line) = read_pkt(&mut reader)? {
+            let line = pkt_str(&line)?;
+            let mut parts = line.split(' ');
+            let (Some(sha1), Some(name)) = (parts.next(), parts.next()) else {
+                return Err(GitSyncError::Transport(format!("invalid ref {line:?}")));
+            };
+            let mut remote_ref = RemoteRef {
+                name: name.to_owned(),
+                sha1: sha1.to_owned(),
+                symref_target: None,
+                peeled: None,
+            };
+            for attribute in parts {
+                if let Some(target) = attribute.strip_prefix("symref-target:") {
+                    remote_ref.symref_target = Some(target.to_owned());
+                } else if let Some(peeled) = attribute.strip_prefix("peeled:") {
+                    remote_ref.peeled = Some(peeled.to_owned());
+                }
+            }
+            refs.push(remote_ref);
+        }
+        Ok(refs)
+    }
+
+    /// fetches a pack, see [Self::fetch_pack], and stores it in `repo`
+    fn fetch(&self, repo: &Repository, wants: &[String], haves: &[String]) -> Result<()> {
+        repo.store_pack(&self.fetch_pack(wants, haves)?)?;
+        Ok(())
+    }
+
+    /// pushes with version 0 of `git-receive-pack`, protocol version 2 does
+    /// not support pushing
+    fn push(
+        &self,
+        repo: &Repository,
+        name: &str,
+        new: &str,
+        force: bool,
+    ) -> Result<Option<String>> {
+        let (refs, capabilities) = self.receive_pack_refs()?;
+        let old = refs
+            .into_iter()
+            .find(|(refname, _)| refname == name)
+            .map(|(_, sha1)| sha1);
+        let objects = push_objects(repo, name, old.as_deref(), new, force)?
+            .iter()
+            .map(|sha1| {
+                let object = repo.load_object(sha1)?;
+                let mut data = Vec::new();
+                object.serialize(&mut data)?;
+                Ok((object.object_type(), data))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // deltas against earlier entries need the `ofs-delta` capability
+        let pack = if capabilities
+            .iter()
+            .any(|capability| capability == "ofs-delta")
+        {
+            write_delta_pack(&objects, repo.hash_algorithm(), &repo.delta_options())
+        } else {
+            write_pack(&objects, repo.hash_algorithm())
+        };
+
+        let mut body = Vec::new();
+        let old_sha1 = old.clone().unwrap_or_else(|| repo.null_sha1());
+        let command = format!("{old_sha1} {new} {name}\0report-status agent={USER_AGENT}\n");
+        write_pkt(&mut body, command.as_bytes());
+        body.extend_from_slice(b"0000");
+        body.extend_from_slice(&pack);
+
+        let response = self
+            .agent
+            .post(&format!("{}/git-receive-pack", self.url))
+            .set("Content-Type", "application/x-git-receive-pack-request")
+            .set("Accept", "application/x-git-receive-pack-result")
+            .send_bytes(&body)
+            .map_err(transport_error)?;
+        let mut reader = BufReader::new(response.into_reader());
+
+        while let Packet::Data(line) = read_pkt(&mut reader)? {
+            let line = pkt_str(&line)?;
+            if let Some(status) = line.strip_prefix("unpack ") {
+                if status != "ok" {
+                    return Err(GitSyncError::Transport(format!(
+                        "remote failed to unpack: {status}"
+                    )));
+                }
+            } else if let Some(reason) = line
+                .strip_prefix("ng ")
+                .and_then(|rest| rest.strip_prefix(name))
+            {
+                return Err(GitSyncError::Transport(format!(
+                    "remote rejected {name}:{reason}"
+                )));
+            }
+        }
+        Ok(old)
+    }
+}
+
+/// a "connection" to a repository on the local file system, whose objects
+/// are copied directly
+#[derive(Debug)]
+pub struct LocalTransport {
+    remote: Repository,
+}
+
+impl LocalTransport {
+    /// opens the repository at `path`, either a worktree or a bare
+    /// repository
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self {
+            remote: Repository::new(path.as_ref())?,
+        })
+    }
+}
+
+impl Transport for LocalTransport {
+    fn ls_refs(&self) -> Result<Vec<RemoteRef>> {
+        let mut refs = Vec::new();
+        for (name, sha1) in self.remote.refs()? {
+            let listed =
+                name == "HEAD" || name.starts_with("refs/heads/") || name.starts_with("refs/tags/");
+            if !listed {
+                continue;
+            }
+            let symref_target = self.remote.read_symbolic_ref(&name)?;
+            let peeled = match self.remote.load_object(&sha1)? {
+                Object::Tag { object, .. } => Some(object),
+                _ => None,
+            };
+            refs.push(RemoteRef {
+                name,
+                sha1,
+                symref_target,
+                peeled,
+            });
+        }
+        Ok(refs)
+    }
+
+    /// copies the missing objects as loose objects. `haves` are not needed,
+    /// objects already in `repo` are skipped along with everything reachable
+    /// from them.
+    ///
+    /// Objects are saved after everything they reference, so a fetch that
+    /// fails halfway never leaves an object whose dependencies are missing.
+    fn fetch(&self, repo: &Repository, wants: &[String], _haves: &[String]) -> Result<()> {
+        // objects are pushed again with their content once their
+        // dependencies are queued, and saved when they are popped the
+        // second time
+        let mut pending: Vec<(String, Option<Object>)> =
+            wants.iter().map(|sha1| (sha1.clone(), None)).collect();
+        let mut seen = HashSet::new();
+        while let Some((sha1, loaded)) = pending.pop() {
+            if let Some(object) = loaded {
+                object.save(repo)?;
+                continue;
+            }
+            if !seen.insert(sha1.clone()) || repo.has_object(&sha1) {
+                continue;
+            }
+            let object = self.remote.load_object(&sha1)?;
+            let dependencies: Vec<String> = match &object {
+                Object::Commit { tree, parents, .. } => std::iter::once(tree.clone())
+                    .chain(parents.iter().cloned())
+                    .collect(),
+                Object::Tree { entries } => entries
+                    .iter()
+                    // submodule commits are not part of this repository
+                    .filter(|entry| entry.mode != "160000")
+                    .map(|entry| entry.sha1())
+                    .collect(),
+                Object::Tag { object, .. } => vec![object.clone()],
+                Object::Blob { .. } => Vec::new(),
+            };
+            pending.push((sha1, Some(object)));
+            pending.extend(dependencies.into_iter().map(|sha1| (sha1, None)));
+        }
+        Ok(())
+    }
+
+    fn push(
+        &self,
+        repo: &Repository,
+        name: &str,
+        new: &str,
+        force: bool,
+    ) -> Result<Option<String>> {
+        // like git, the checked out branch of a worktree is not updated
+        // behind its back
+        if !self.remote.is_bare() && self.remote.read_symbolic_ref("HEAD")?.as_deref() == Some(name)
+        {
+            return Err(GitSyncError::BranchCheckedOut {
+                name: name.to_owned(),
+            });
+        }
+
+        let old = self
+            .remote
+            .refs()?
+            .into_iter()
+            .find(|(refname, _)| refname == name);
+        let old = old.map(|(_, sha1)| sha1);
+        for sha1 in push_objects(repo, name, old.as_deref(), new, force)? {
+            repo.load_object(&sha1)?.save(&self.remote)?;
+        }
+        let expected = old.clone().unwrap_or_else(|| self.remote.null_sha1());
+        self.remote.update_ref(name, new, Some(&expected))?;
+        Ok(old)
+    }
+}
+
+/// the objects the remote needs to update its ref `name` from `old` to the
+/// local commit `new`: everything reachable from `new` that is not
+/// reachable from `old`.
+///
+/// Fails with [GitSyncError::NonFastForward] if `old` is not an ancestor of
+/// `new`, unless `force` is set.
+fn push_objects(
+    repo: &Repository,
+    name: &str,
+    old: Option<&str>,
+    new: &str,
+    force: bool,
+) -> Result<Vec<String>> {
+    // without the old commit it can't be an ancestor
+    let known_old = old.filter(|old| repo.has_object(old));
+    let fast_forward = match (old, known_old) {
+        (_, Some(old)) => is_ancestor(repo, old, new)?,
+        (Some(_), None) => false,
+        (None, _) => true,
+    };
+    if !fast_forward && !force {
+        return Err(GitSyncError::NonFastForward {
+            name: name.to_owned(),
+        });
+    }
+
+    let known = match known_old {
+        Some(old) => reachable_objects(repo, old, &HashSet::new())?,
+        None => Vec::new(),
+    };
+    reachable_objects(repo, new, &known.into_iter().collect())
+}
+
+/// whether the commit `ancestor` is reachable from the commit `sha1`
+fn is_ancestor(repo: &Repository, ancestor: &str, sha1: &str) -> Result<bool> {
+    for commit in repo.walk_commits(sha1) {
+        if commit?.0 == ancestor {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// the objects reachable from `start`, without descending into the objects
+/// in `exclude`
+fn reachable_objects(
+    repo: &Repository,
+    start: &str,
+    exclude: &HashSet<String>,
+) -> Result<Vec<String>> {
+    let mut reachable = exclude.clone();
+    repo.mark_reachable(vec![start.to_owned()], &mut reachable)?;
+    Ok(reachable.difference(exclude).cloned().collect())
+}
+
+/// pushes the local branch `branch` to the branch of the same name on
+/// `remote`, see [Repository::push]
+pub(crate) fn push_branch(
+    repo: &Repository,
+    remote: &str,
+    branch: &str,
+    force: bool,
+) -> Result<Option<String>> {
+    let refname = format!("refs/heads/{branch}");
+    let new = repo.resolve_ref(&refname)?;
+
+    let configured = repo.config().get_str("remote", Some(remote), "url");
+    let url = configured.unwrap_or(remote);
+    let old = connect(url)?.push(repo, &refname, &new, force)?;
+
+    if configured.is_some() {
+        repo.update_ref_with_message(
+            &format!("refs/remotes/{remote}/{branch}"),
+            &new,
+            None,
+            "update by push",
+        )?;
+    }
+    Ok(old)
+}
+
+/// fetches the branches of the configured `remote` into
+/// `refs/remotes/<remote>/*`, see [Repository::fetch]
+pub(crate) fn fetch_remote(repo: &Repository, remote: &str) -> Result<Vec<FetchedRef>> {
+    let url = repo
+        .config()
+        .get_str("remote", Some(remote), "url")
+        .ok_or_else(|| GitSyncError::RemoteNotFound {
+            name: remote.to_owned(),
+        })?;
+    let transport = connect(url)?;
+
+    let branches: Vec<(String, String)> = transport
+        .ls_refs()?
+        .into_iter()
+        .filter_map(|remote_ref| {
+            let branch = remote_ref.name.strip_prefix("refs/heads/")?;
+            Some((format!("refs/remotes/{remote}/{branch}"), remote_ref.sha1))
+        })
+        .collect();
+
+    let mut wants: Vec<String> = branches
+        .iter()
+        .map(|(_, sha1)| sha1.clone())
+        .filter(|sha1| !repo.has_object(sha1))
+        .collect();
+    wants.sort();
+    wants.dedup();
+    if !wants.is_empty() {
+        // everything the local refs point to is complete, so the remote
+        // does not need to send their history again
+        let mut haves: Vec<String> = repo.refs()?.into_iter().map(|(_, sha1)| sha1).collect();
+        haves.sort();
+        haves.dedup();
+        transport.fetch(repo, &wants, &haves)?;
+    }
+
+    let mut fetched = Vec::new();
+    for (name, new) in branches {
+        let old = repo
+            .refs()?
+            .into_iter()
+            .find(|(refname, _)| *refname == name);
+        let old = old.map(|(_, sha1)| sha1);
+        if old.as_ref() == Some(&new) {
+            continue;
+        }
+        repo.update_ref_with_message(&name, &new, None, &format!("fetch: {remote}"))?;
+        fetched.push(FetchedRef { name, old, new });
+    }
+    Ok(fetched)
+}
+
+/// clones the repository at `url` into a new repository at `path`, see
+/// [Repository::clone_remote]
+pub(crate) fn clone_remote(url: &str, path: &Path) -> Result<Repository> {
+    let repo = Repository::create_at(path)?;
+    let transport = connect(url)?;
+    let refs = transport.ls_refs()?;
+
+    let mut wants: Vec<String> = refs.iter().map(|r| r.sha1.clone()).collect();
+    wants.sort();
+    wants.dedup();
+    // an empty remote has nothing to fetch
+    if !wants.is_empty() {
+        transport.fetch(&repo, &wants, &[])?;
+    }
+
+    let message = format!("clone: from {url}");
+    for remote_ref in &refs {
+        if let Some(branch) = remote_ref.name.strip_prefix("refs/heads/") {
+            let tracking = format!("refs/remotes/origin/{branch}");
+            repo.update_ref_with_message(&tracking, &remote_ref.sha1, None, &message)?;
+        } else if remote_ref.name.starts_with("refs/tags/") {
+            repo.update_ref_with_message(&remote_ref.name, &remote_ref.sha1, None, &message)?;
+        }
+    }
+
+    let mut config = repo.config().clone();
+    config.set("remote", Some("origin"), "url", url);
+    config.set(
+        "remote",
+        Some("origin"),
+        "fetch",
+        "+refs/heads/*:refs/remotes/origin/*",
+    );
+    let head = refs.iter().find(|r| r.name == "HEAD");
+    let branch = head
+        .and_then(|head| head.symref_target.as_deref())
+        .and_then(|target| target.strip_prefix("refs/heads/"));
+    if let Some(branch) = branch {
+        repo.write_symbolic_ref(
+            "refs/remotes/origin/HEAD",
+            &format!("refs/remotes/origin/{branch}"),
+        )?;
+        config.set("branch", Some(branch), "remote", "origin");
+        config.set(
+            "branch",
+            Some(branch),
+            "merge",
+            &format!("refs/heads/{branch}"),
+        );
+    }
+    config.write_to_file(repo.path("config"))?;
+    // reopen to pick up the new config
+    let repo = Repository::new(path)?;
+
+    match (head, branch) {
+        (Some(head), Some(branch)) => {
+            repo.update_ref_with_message(
+                &format!("refs/heads/{branch}"),
+                &head.sha1,
+                Some(&repo.null_sha1()),
+                &message,
+            )?;
+            repo.switch(branch, false)?;
+        }
+        (Some(head), None) => repo.switch(&head.sha1, false)?,
+        // HEAD stays on the unborn default branch
+        (None, _) => {}
+    }
+    Ok(repo)
+}
+
+/// appends `data` as a pkt-line to `out`
+pub(crate) fn write_pkt(out: &mut Vec<u8>, data: &[u8]) {
+    assert!(data.len() + 4 <= MAX_PKT_LEN, "pkt-line is too long");
+    out.extend_from_slice(format!("{:04x}", data.len() + 4).as_bytes());
+    out.extend_from_slice(data);
+}
+
+/// reads the next pkt-line. `ERR` packets are turned into errors
+pub(crate) fn read_pkt(reader: &mut impl Read) -> Result<Packet> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let len = std::str::from_utf8(&len)
+        .ok()
+        .and_then(|len| usize::from_str_radix(len, 16).ok())
+        .ok_or_else(|| GitSyncError::Transport(format!("invalid pkt-line length {len:?}")))?;
+
+    match len {
+        0 => Ok(Packet::Flush),
+        1 => Ok(Packet::Delim),
+        2 => Ok(Packet::ResponseEnd),
+        3 => Err(GitSyncError::Transport(
+            "invalid pkt-line length 3".to_owned(),
+        )),
+        len if len > MAX_PKT_LEN => Err(GitSyncError::Transport(format!(
+            "pkt-line of length {len} is too long"
+        ))),
+        len => {
+            let mut data = vec![0; len - 4];
+            reader.read_exact(&mut data)?;
+            if let Some(message) = data.strip_prefix(b"ERR ") {
+                return Err(GitSyncError::Transport(
+                    String::from_utf8_lossy(message).trim_end().to_owned(),
+                ));
+            }
+            Ok(Packet::Data(data))
+        }
+    }
+}
+
+fn expect_flush(reader: &mut impl Read) -> Result<()> {
+    match read_pkt(reader)? {
+        Packet::Flush => Ok(()),
+        packet => Err(GitSyncError::Transport(format!(
+            "expected a flush packet, got {packet:?}"
+        ))),
+    }
+}
+
+/// a text pkt-line without its trailing newline
+fn pkt_str(line: &[u8]) -> Result<&str> {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    std::str::from_utf8(line)
+        .map_err(|_| GitSyncError::Transport(format!("pkt-line {line:?} is not utf-8")))
+}
+
+fn transport_error(err: ureq::Error) -> GitSyncError {
+    GitSyncError::Transport(err.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use test_dir::DirBuilder;
+
+    use std::fs;
+
+    use super::{
+        connect, read_pkt, write_pkt, FetchedRef, HttpTransport, LocalTransport, Packet, Transport,
+    };
+    use crate::test_utils::{existing_test_repo, serve_http, test_dir};
+    use crate::{
+        GitSyncError, HashAlgorithm, InitOptions, Object, PackIndex, Repository, Signature,
+    };
+
+    #[test]
+    fn pkt_lines() {
+        let mut data = Vec::new();
+        write_pkt(&mut data, b"version 2\n");
+        data.extend_from_slice(b"00010000");
+        assert_eq!(&data[..4], b"000e");
+
+        let mut reader = data.as_slice();
+        assert_eq!(
+            read_pkt(&mut reader).unwrap(),
+            Packet::Data(b"version 2\n".to_vec())
+        );
+        assert_eq!(read_pkt(&mut reader).unwrap(), Packet::Delim);
+        assert_eq!(read_pkt(&mut reader).unwrap(), Packet::Flush);
+        assert!(read_pkt(&mut &b"000bERR no\n"[..]).is_err());
+        assert!(read_pkt(&mut &b"zzzz"[..]).is_err());
+    }
+
+    #[test]
+    fn fetch_over_http() {
+        let remote = existing_test_repo("packed_objects");
+        let url = serve_http(remote.root());
+
+        let transport = HttpTransport::connect(&url).unwrap();
+        let refs = transport.ls_refs().unwrap();
+        let head = refs.iter().find(|r| r.name == "HEAD").unwrap();
+        assert_eq!(head.sha1, "1e3e965f92c57b26cb86b4313edca25125678074");
+        assert_eq!(head.symref_target.as_deref(), Some("refs/heads/master"));
+        let tag = refs.iter().find(|r| r.name == "refs/tags/v1.0").unwrap();
+        assert_eq!(tag.sha1, "28d0e451b11758598f5e97c62bd8aa8c1061cba5");
+        assert_eq!(
+            tag.peeled.as_deref(),
+            Some("25a7b65d7c4c638b508f306909cb719db11cb7c2")
+        );
+
+        let mut wants: Vec<String> = refs.into_iter().map(|r| r.sha1).collect();
+        wants.sort();
+        wants.dedup();
+        let pack = transport.fetch_pack(&wants, &[]).unwrap();
+
+        let local_dir = test_dir("fetch_over_http");
+        let repo = Repository::create_at(local_dir.root()).unwrap();
+        repo.store_pack(&pack).unwrap();
+
+        let index = PackIndex::open(
+            remote
+                .root()
+                .join(".git/objects/pack/pack-3af1e45a1a333b30392b7a6a1906476cd1f15f6c.idx"),
+            HashAlgorithm::Sha1,
+        )
+        .unwrap();
+        for n in 0..index.object_count() {
+            let sha1 = index.sha1(n);
+            assert!(repo.has_object(&sha1), "{sha1} is missing");
+            assert_eq!(repo.load_object(&sha1).unwrap().sha1(), sha1);
+        }
+    }
+
+    #[test]
+    fn clone_over_http() {
+        let remote = existing_test_repo("packed_objects");
+        let url = serve_http(remote.root());
+
+        let local_dir = test_dir("clone_over_http");
+        let path = local_dir.root().join("clone");
+        let repo = Repository::clone_remote(&url, &path).unwrap();
+
+        assert_eq!(repo.current_branch().unwrap().as_deref(), Some("master"));
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074";
+        assert_eq!(repo.resolve_ref("HEAD").unwrap(), head);
+        assert_eq!(
+            repo.resolve_ref("refs/remotes/origin/master").unwrap(),
+            head
+        );
+        assert_eq!(repo.resolve_ref("origin").unwrap(), head);
+        assert_eq!(
+            repo.resolve_ref("refs/tags/v1.0").unwrap(),
+            "28d0e451b11758598f5e97c62bd8aa8c1061cba5"
+        );
+        assert_eq!(
+            repo.config().get_str("remote", Some("origin"), "url"),
+            Some(url.as_str())
+        );
+        assert_eq!(
+            repo.config().get_str("branch", Some("master"), "merge"),
+            Some("refs/heads/master")
+        );
+
+        for file in ["a.txt", "b.txt", "dir/c.txt"] {
+            assert_eq!(
+                fs::read(path.join(file)).unwrap(),
+                fs::read(remote.root().join(file)).unwrap(),
+                "{file}"
+            );
+        }
+        assert!(repo.status().unwrap().is_clean());
+    }
+
+    #[test]
+    fn clone_from_file_url() {
+        let remote = existing_test_repo("packed_objects");
+        let local_dir = test_dir("clone_from_file_url");
+        let path = local_dir.root().join("clone");
+        let url = format!("file://{}", remote.root().display());
+        let repo = Repository::clone_remote(&url, &path).unwrap();
+
+        let index = PackIndex::open(
+            remote
+                .root()
+                .join(".git/objects/pack/pack-3af1e45a1a333b30392b7a6a1906476cd1f15f6c.idx"),
+            HashAlgorithm::Sha1,
+        )
+        .unwrap();
+        let mut expected: Vec<String> = (0..index.object_count()).map(|n| index.sha1(n)).collect();
+        expected.sort();
+        let copied: Vec<String> = repo.loose_objects().unwrap().collect();
+        assert_eq!(copied, expected);
+
+        assert_eq!(repo.current_branch().unwrap().as_deref(), Some("master"));
+        assert_eq!(
+            fs::read(path.join("dir/c.txt")).unwrap(),
+            fs::read(remote.root().join("dir/c.txt")).unwrap()
+        );
+    }
+
+    #[test]
+    fn connect_by_scheme() {
+        let remote = existing_test_repo("packed_objects");
+        let path = remote.root().display().to_string();
+        for url in [path.clone(), format!("file://{path}")] {
+            let refs = connect(&url).unwrap().ls_refs().unwrap();
+            assert!(refs.iter().any(|r| r.name == "refs/heads/master"));
+        }
+        assert!(connect("ssh://example.com/repo").is_err());
+    }
+
+    fn push_to(source: &Repository, url: &str, label: &str) {
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074";
+        assert_eq!(source.push(url, "master", false).unwrap(), None, "{label}");
+        let Object::Commit { tree, .. } = source.load_object(head).unwrap() else {
+            panic!("HEAD is not a commit");
+        };
+        let author = Signature::parse("Test User <test@example.com> 1700000300 +0000").unwrap();
+
+        let next = source
+            .commit_tree(&tree, &[head.to_owned()], &author, &author, "fourth\n")
+            .unwrap();
+        source
+            .update_ref("refs/heads/master", &next, Some(head))
+            .unwrap();
+        assert_eq!(
+            source.push(url, "master", false).unwrap().as_deref(),
+            Some(head),
+            "{label}"
+        );
+
+        // a commit that does not build on the remote branch
+        let other = source
+            .commit_tree(&tree, &[head.to_owned()], &author, &author, "other\n")
+            .unwrap();
+        source
+            .update_ref("refs/heads/master", &other, Some(&next))
+            .unwrap();
+        assert!(
+            matches!(
+                source.push(url, "master", false),
+                Err(GitSyncError::NonFastForward { .. })
+            ),
+            "{label}"
+        );
+        assert_eq!(
+            source.push(url, "master", true).unwrap().as_deref(),
+            Some(next.as_str()),
+            "{label}"
+        );
+    }
+
+    #[test]
+    fn push_to_bare_repo() {
+        let options = InitOptions {
+            bare: true,
+            ..InitOptions::default()
+        };
+        for label in ["local", "http"] {
+            let source_dir = existing_test_repo("packed_objects");
+            let source = Repository::new(source_dir.root()).unwrap();
+            let remote_dir = test_dir("push_to_bare_repo");
+            let remote_path = remote_dir.root().join("remote.git");
+            Repository::create_with(remote_path.as_path(), &options).unwrap();
+
+            let url = match label {
+                "local" => remote_path.display().to_string(),
+                _ => serve_http(&remote_path),
+            };
+            push_to(&source, &url, label);
+
+            let remote = Repository::new(remote_path.as_path()).unwrap();
+            let tip = source.resolve_ref("refs/heads/master").unwrap();
+            assert_eq!(remote.resolve_ref("refs/heads/master").unwrap(), tip);
+            // the whole history arrived
+            assert_eq!(remote.walk_commits(&tip).count(), 4);
+        }
+    }
+
+    #[test]
+    fn failed_fetch_keeps_history_complete() {
+        let test_dir = test_dir("failed_fetch_keeps_history_complete");
+        let remote_path = test_dir.root().join("remote");
+        let remote = Repository::create_at(remote_path.as_path()).unwrap();
+        let local = Repository::create_at(test_dir.root().join("local")).unwrap();
+        let signature = Signature::parse("Test User <test@example.com> 1700000000 +0100").unwrap();
+
+        fs::write(remote_path.join("a.txt"), "first\n").unwrap();
+        remote.add(&["a.txt"]).unwrap();
+        let first = remote.commit("first\n", &signature, &signature).unwrap();
+        fs::write(remote_path.join("b.txt"), "second\n").unwrap();
+        remote.add(&["b.txt"]).unwrap();
+        let second = remote.commit("second\n", &signature, &signature).unwrap();
+
+        // the fetch fails at the blob of the first commit
+        let blob = Object::Blob {
+            data: b"first\n".to_vec(),
+        };
+        fs::remove_file(remote.loose_object_path(&blob.sha1())).unwrap();
+        let transport = LocalTransport::open(&remote_path).unwrap();
+        assert!(transport.fetch(&local, &[second.clone()], &[]).is_err());
+        assert!(!local.has_object(&first));
+        assert!(!local.has_object(&second));
+
+        blob.save(&remote).unwrap();
+        transport.fetch(&local, &[second.clone()], &[]).unwrap();
+        for sha1 in remote.loose_objects().unwrap() {
+            assert!(local.has_object(&sha1), "{sha1}");
+        }
+    }
+
+    #[test]
+    fn fetch_updates_remote_tracking_refs() {
+        let options = InitOptions {
+            initial_branch: "master".to_owned(),
+            bare: true,
+            ..InitOptions::default()
+        };
+        let source_dir = existing_test_repo("packed_objects");
+        let source = Repository::new(source_dir.root()).unwrap();
+        let test_dir = test_dir("fetch_updates_remote_tracking_refs");
+        let remote_path = test_dir.root().join("remote.git");
+        Repository::create_with(remote_path.as_path(), &options).unwrap();
+        let url = remote_path.display().to_string();
+        source.push(&url, "master", false).unwrap();
+
+        let local = Repository::clone_remote(&url, test_dir.root().join("clone")).unwrap();
+        assert!(local.fetch("origin").unwrap().is_empty());
+
+        let head = "1e3e965f92c57b26cb86b4313edca25125678074";
+        let Object::Commit { tree, .. } = source.load_object(head).unwrap() else {
+            panic!("HEAD is not a commit");
+        };
+        let author = Signature::parse("Test User <test@example.com> 1700000300 +0000").unwrap();
+        let next = source
+            .commit_tree(&tree, &[head.to_owned()], &author, &author, "fourth\n")
+            .unwrap();
+        source
+            .update_ref("refs/heads/master", &next, Some(head))
+            .unwrap();
+        source.push(&url, "master", false).unwrap();
+        source.update_ref("refs/heads/side", head, None).unwrap();
+        source.push(&url, "side", false).unwrap();
+
+        let fetched = local.fetch("origin").unwrap();
+        assert_eq!(
+            fetched,
+            [
+                FetchedRef {
+                    name: "refs/remotes/origin/master".to_owned(),
+                    old: Some(head.to_owned()),
+                    new: next.clone(),
+                },
+                FetchedRef {
+                    name: "refs/remotes/origin/side".to_owned(),
+                    old: None,
+                    new: head.to_owned(),
+                },
+            ]
+        );
+        assert!(local.has_object(&next));
+        // local branches are not touched
+        assert_eq!(local.resolve_ref("refs/heads/master").unwrap(), head);
+        assert!(matches!(
+            local.fetch("upstream"),
+            Err(GitSyncError::RemoteNotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,336 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashSet},
+    fs,
+    path::PathBuf,
+};
+
+use crate::{
+    repository::path_bytes, GitSyncError, IgnoreRules, Object, ObjectType, RepoKind, Repository,
+    Result,
+};
+
+/// iterator over the history of a commit, created by
+/// [Repository::walk_commits].
+///
+/// Commits are yielded newest first by committer time. Every commit is only
+/// yielded once, even if it is reachable through multiple merge parents.
+pub struct CommitWalk<'r> {
+    repo: &'r Repository,
+    /// commits the walk starts at, queued on the first call to `next`
+    starts: Vec<String>,
+    queue: BinaryHeap<QueuedCommit>,
+    seen: HashSet<String>,
+    /// insertion counter, so commits with the same time keep their order
+    next_seq: u64,
+    first_parent: bool,
+    failed: bool,
+}
+
+struct QueuedCommit {
+    time: i64,
+    seq: Reverse<u64>,
+    sha1: String,
+    commit: Object,
+}
+
+impl PartialEq for QueuedCommit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedCommit {}
+
+impl PartialOrd for QueuedCommit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedCommit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.time, self.seq).cmp(&(other.time, other.seq))
+    }
+}
+
+impl<'r> CommitWalk<'r> {
+    pub(crate) fn new(repo: &'r Repository, start: &str) -> Self {
+        Self::from_starts(repo, vec![start.to_owned()])
+    }
+
+    /// walks the combined history of all `starts`
+    pub(crate) fn from_starts(repo: &'r Repository, starts: Vec<String>) -> Self {
+        Self {
+            repo,
+            starts,
+            queue: BinaryHeap::new(),
+            seen: HashSet::new(),
+            next_seq: 0,
+            first_parent: false,
+            failed: false,
+        }
+    }
+
+    /// only follows the first parent of merge commits
+    pub fn first_parent(mut self) -> Self {
+        self.first_parent = true;
+        self
+    }
+
+    /// loads the commit `sha1` and queues it, unless it was already seen
+    fn push(&mut self, sha1: String) -> Result<()> {
+        if !self.seen.insert(sha1.clone()) {
+            return Ok(());
+        }
+
+        let commit = self.repo.load_object(&sha1)?;
+        let Object::Commit { committer, .. } = &commit else {
+            return Err(GitSyncError::UnexpectedObjectType {
+                sha1,
+                expected: ObjectType::Commit,
+            });
+        };
+
+        self.queue.push(QueuedCommit {
+            time: committer.time,
+            seq: Reverse(self.next_seq),
+            sha1,
+            commit,
+        });
+        self.next_seq += 1;
+        Ok(())
+    }
+
+    fn advance(&mut self) -> Result<Option<(String, Object)>> {
+        for start in std::mem::take(&mut self.starts) {
+            self.push(start)?;
+        }
+
+        let Some(QueuedCommit { sha1, commit, .. }) = self.queue.pop() else {
+            return Ok(None);
+        };
+
+        if let Object::Commit { parents, .. } = &commit {
+            let parents = if self.first_parent {
+                &parents[..parents.len().min(1)]
+            } else {
+                &parents[..]
+            };
+            for parent in parents {
+                self.push(parent.clone())?;
+            }
+        }
+
+        Ok(Some((sha1, commit)))
+    }
+}
+
+impl Iterator for CommitWalk<'_> {
+    type Item = Result<(String, Object)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let next = self.advance();
+        self.failed = next.is_err();
+        next.transpose()
+    }
+}
+
+/// iterator over the files in the worktree, created by
+/// [Repository::walk_worktree].
+///
+/// Yields the paths of all files and symlinks relative to the worktree root,
+/// sorted by name within each directory. The git dir and ignored paths are
+/// skipped without descending into them. Symlinks to directories are yielded
+/// like files and not followed.
+pub struct WorktreeWalk {
+    root: PathBuf,
+    ignore: IgnoreRules,
+    /// relative paths that still have to be visited, the next one last
+    pending: Vec<(PathBuf, bool)>,
+    /// the error that prevented the walk from starting
+    error: Option<GitSyncError>,
+}
+
+impl WorktreeWalk {
+    pub(crate) fn new(repo: &Repository) -> Self {
+        let start = repo
+            .worktree_root()
+            .map(|root| root.to_path_buf())
+            .and_then(|root| Ok((root, IgnoreRules::load(repo)?)));
+        match start {
+            Ok((root, ignore)) => Self {
+                root,
+                ignore,
+                pending: vec![(PathBuf::new(), true)],
+                error: None,
+            },
+            Err(err) => Self {
+                root: PathBuf::new(),
+                ignore: IgnoreRules::default(),
+                pending: Vec::new(),
+                error: Some(err),
+            },
+        }
+    }
+
+    /// queues the entries of the directory `dir` that are not ignored
+    fn read_dir(&mut self, dir: PathBuf) -> Result<()> {
+        let mut children = Vec::new();
+        for dir_entry in fs::read_dir(self.root.join(&dir))? {
+            let dir_entry = dir_entry?;
+            if RepoKind::is_dir_name(&dir_entry.file_name()) {
+                continue;
+            }
+            // the file type of a symlink is not followed
+            let is_dir = dir_entry.file_type()?.is_dir();
+            let path = dir.join(dir_entry.file_name());
+            if !self.ignore.matches(&path_bytes(&path), is_dir) {
+                children.push((path, is_dir));
+            }
+        }
+        children.sort();
+        self.pending.extend(children.into_iter().rev());
+        Ok(())
+    }
+}
+
+impl Iterator for WorktreeWalk {
+    type Item = Result<PathBuf>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(err) = self.error.take() {
+            return Some(Err(err));
+        }
+        while let Some((path, is_dir)) = self.pending.pop() {
+            if !is_dir {
+                return Some(Ok(path));
+            }
+            if let Err(err) = self.read_dir(path) {
+                self.pending.clear();
+                return Some(Err(err));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::PathBuf};
+
+    use test_dir::DirBuilder;
+
+    use crate::test_utils::{existing_test_repo, test_dir};
+    use crate::{Object, Repository, Signature};
+
+    #[test]
+    fn walk_linear_history() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let head = repo.resolve_ref("HEAD").unwrap();
+        let commits: Vec<String> = repo
+            .walk_commits(&head)
+            .map(|commit| commit.unwrap().0)
+            .collect();
+
+        assert_eq!(
+            commits,
+            vec![
+                "1e3e965f92c57b26cb86b4313edca25125678074",
+                "09ed861f6cd57e64a23811b30686bb4919a41674",
+                "25a7b65d7c4c638b508f306909cb719db11cb7c2",
+            ]
+        );
+    }
+
+    #[test]
+    fn walk_merge_history() {
+        let repo_path = test_dir("walk_merge_history");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+
+        let tree = Object::Tree { entries: vec![] }.save(&repo).unwrap();
+        let commit = |parents: &[&String], time: i64| {
+            let signature = Signature {
+                name: "Test User".to_owned(),
+                email: "test@example.com".to_owned(),
+                time,
+                tz_offset: 0,
+                raw_time: None,
+            };
+            Object::Commit {
+                tree: tree.clone(),
+                parents: parents.iter().map(|parent| parent.to_string()).collect(),
+                author: signature.clone(),
+                committer: signature,
+                encoding: None,
+                extra_headers: Vec::new(),
+                gpgsig: None,
+                message: format!("commit at {time}\n").into_bytes(),
+            }
+            .save(&repo)
+            .unwrap()
+        };
+
+        //   root - left ---- merge
+        //      \            /
+        //       `--- right -
+        let root = commit(&[], 100);
+        let left = commit(&[&root], 200);
+        let right = commit(&[&root], 300);
+        let merge = commit(&[&left, &right], 400);
+
+        let all: Vec<String> = repo
+            .walk_commits(&merge)
+            .map(|commit| commit.unwrap().0)
+            .collect();
+        assert_eq!(all, [merge.clone(), right, left.clone(), root.clone()]);
+
+        let first_parent: Vec<String> = repo
+            .walk_commits(&merge)
+            .first_parent()
+            .map(|commit| commit.unwrap().0)
+            .collect();
+        assert_eq!(first_parent, [merge, left, root]);
+    }
+
+    #[test]
+    fn walk_requires_commits() {
+        let test_dir = existing_test_repo("linear_history");
+        let repo = Repository::new(test_dir.root()).unwrap();
+
+        let mut walk = repo.walk_commits("3d8a457a40a1b21da38ebcb0ab009e4e997a5bb9");
+        assert!(walk.next().unwrap().is_err());
+        assert!(walk.next().is_none());
+    }
+
+    #[test]
+    fn walk_worktree_skips_ignored() {
+        let repo_path = test_dir("walk_worktree_skips_ignored");
+        let repo = Repository::create_at(repo_path.root()).unwrap();
+        let root = repo_path.root();
+
+        fs::write(root.join(".gitignore"), "target/\n").unwrap();
+        fs::create_dir_all(root.join("target/debug")).unwrap();
+        fs::write(root.join("target/debug/app"), "").unwrap();
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "").unwrap();
+        fs::write(root.join("README"), "").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(".", root.join("src/loop")).unwrap();
+
+        let paths: Vec<PathBuf> = repo.walk_worktree().map(|path| path.unwrap()).collect();
+        let mut expected = vec![
+            PathBuf::from(".gitignore"),
+            PathBuf::from("README"),
+            PathBuf::from("src/main.rs"),
+        ];
+        #[cfg(unix)]
+        expected.insert(2, PathBuf::from("src/loop"));
+        assert_eq!(paths, expected);
+    }
+}